@@ -0,0 +1,50 @@
+// How fast output goes through the parser onto the grid, the part of
+// drawing a frame that doesn't need a GPU. colored_log.out is a capture of
+// a colored log being cat'ed, the same one `rush --bench-render` replays.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use rush::term::Terminal;
+
+const COLORED_LOG: &[u8] = include_bytes!("colored_log.out");
+
+// What the PTY hands over in one read at most
+const READ_SIZE: usize = 65536;
+
+fn process(term: &mut Terminal, output: &[u8]) {
+    for chunk in output.chunks(READ_SIZE) {
+        term.process(chunk);
+    }
+}
+
+fn parsing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("process");
+    group.throughput(Throughput::Bytes(COLORED_LOG.len() as u64));
+    group.bench_function("colored_log", |b| {
+        b.iter_batched_ref(|| Terminal::new(24, 80), |term| process(term, COLORED_LOG), BatchSize::SmallInput)
+    });
+
+    let plain: Vec<u8> = (0..COLORED_LOG.len() / 64).flat_map(|i| format!("{:>62}\r\n", i).into_bytes()).collect();
+    group.throughput(Throughput::Bytes(plain.len() as u64));
+    group.bench_function("plain_text", |b| {
+        b.iter_batched_ref(|| Terminal::new(24, 80), |term| process(term, &plain), BatchSize::SmallInput)
+    });
+
+    // Scrolling a full screen region, as vim and less do
+    let scrolling: Vec<u8> = (0..COLORED_LOG.len() / 64)
+        .flat_map(|i| format!("\x1b[2;23r\x1b[23;1H\n\x1b[33m{:>60}\x1b[0m", i).into_bytes())
+        .collect();
+    group.throughput(Throughput::Bytes(scrolling.len() as u64));
+    group.bench_function("scroll_region", |b| {
+        b.iter_batched_ref(|| Terminal::new(24, 80), |term| process(term, &scrolling), BatchSize::SmallInput)
+    });
+    group.finish();
+}
+
+fn reading(c: &mut Criterion) {
+    let mut term = Terminal::new(50, 200);
+    process(&mut term, COLORED_LOG);
+    c.bench_function("screen_text", |b| b.iter(|| term.screen_text()));
+}
+
+criterion_group!(benches, parsing, reading);
+criterion_main!(benches);
@@ -3,11 +3,16 @@
 out vec4 FragColor;
 
 in vec2 TexCoords;
+in vec4 Color;
 
 uniform sampler2D text;
+// Blending in sRGB thins out light text on dark backgrounds and thickens
+// dark text on light ones. Coverage is raised to a power that undoes
+// that, more than 1 for dark text and less than 1 for light text.
+uniform float gamma;
 
 void main() {
-    vec4 sampled = texture(text, TexCoords);
-    FragColor = vec4(sampled.r, sampled.r, sampled.r, 1.0);
-    //FragColor = vec4(1.0, 1.0, 1.0, 1.0);
+    float luminance = dot(Color.rgb, vec3(0.2126, 0.7152, 0.0722));
+    float coverage = pow(texture(text, TexCoords).r, pow(gamma, 1.0 - 2.0 * luminance));
+    FragColor = vec4(Color.rgb, Color.a * coverage);
 }
@@ -0,0 +1,33 @@
+// The parser on its own: arbitrary bytes, split at an arbitrary point so
+// sequences and UTF-8 cut across two reads are covered too
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rush::parser::{Params, Parser, Perform};
+
+struct Sink;
+
+impl Perform for Sink {
+    fn print(&mut self, _c: char) {}
+
+    fn execute(&mut self, _byte: u8) {}
+
+    fn csi_dispatch(&mut self, params: &Params, _intermediates: &[u8], _action: char) {
+        for param in params.iter() {
+            let _ = param[0];
+        }
+    }
+
+    fn esc_dispatch(&mut self, _intermediates: &[u8], _byte: u8) {}
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&split, data)) = data.split_first() else {
+        return;
+    };
+    let (first, second) = data.split_at((split as usize).min(data.len()));
+    let mut parser = Parser::new();
+    parser.advance(&mut Sink, first);
+    parser.advance(&mut Sink, second);
+});
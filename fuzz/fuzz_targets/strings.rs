@@ -0,0 +1,23 @@
+// OSC, DCS and APC payloads, wrapped in their introducer and terminator so
+// the fuzzer spends its time inside the handlers rather than finding the
+// way in: titles, colors, hyperlinks, XTGETTCAP and kitty graphics
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rush::term::Terminal;
+
+const INTRODUCERS: [&[u8]; 5] = [b"\x1b]", b"\x1bP", b"\x1bP+q", b"\x1b_G", b"\x1b]8;"];
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&kind, payload)) = data.split_first() else {
+        return;
+    };
+    let mut term = Terminal::new(24, 80);
+    term.process(INTRODUCERS[kind as usize % INTRODUCERS.len()]);
+    term.process(payload);
+    term.process(b"\x1b\\");
+    // Print something so a link or image left open lands on a cell
+    term.process(b"text\r\n");
+    term.take_responses();
+});
@@ -0,0 +1,44 @@
+// The whole terminal: arbitrary output from a program, with the window
+// resized now and then, must never panic or leave the cursor or the grid
+// out of shape
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rush::term::Terminal;
+
+// Bytes fed between chances to resize
+const CHUNK: usize = 64;
+// A chunk starting with this resizes to the size in its next two bytes
+const RESIZE: u8 = 0xff;
+
+fn size(rows: u8, cols: u8) -> (usize, usize) {
+    (rows as usize % 64 + 1, cols as usize % 256 + 1)
+}
+
+fn check(term: &Terminal) {
+    assert!(term.cursor.row < term.rows, "cursor row {} of {}", term.cursor.row, term.rows);
+    assert!(term.cursor.col < term.cols, "cursor column {} of {}", term.cursor.col, term.cols);
+    for row in 0..term.rows {
+        assert_eq!(term.visible_row(row).len(), term.cols, "row {} has the wrong width", row);
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let [rows, cols, data @ ..] = data else {
+        return;
+    };
+    let (rows, cols) = size(*rows, *cols);
+    let mut term = Terminal::new(rows, cols);
+    for chunk in data.chunks(CHUNK) {
+        if let [RESIZE, rows, cols, ..] = chunk {
+            let (rows, cols) = size(*rows, *cols);
+            term.resize(rows, cols);
+        } else {
+            term.process(chunk);
+        }
+        check(&term);
+    }
+    term.take_responses();
+    let _ = term.screen_text();
+});
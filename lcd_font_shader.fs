@@ -0,0 +1,21 @@
+#version 330 core
+
+// Dual source blending: the color is mixed into what's below per
+// subpixel, by the coverage in the second output
+layout (location = 0, index = 0) out vec4 FragColor;
+layout (location = 0, index = 1) out vec4 Coverage;
+
+in vec2 TexCoords;
+in vec4 Color;
+
+uniform sampler2D text;
+// See font_shader.fs
+uniform float gamma;
+
+void main() {
+    float luminance = dot(Color.rgb, vec3(0.2126, 0.7152, 0.0722));
+    vec3 coverage = pow(texture(text, TexCoords).rgb, vec3(pow(gamma, 1.0 - 2.0 * luminance))) * Color.a;
+    float alpha = max(coverage.r, max(coverage.g, coverage.b));
+    FragColor = vec4(Color.rgb, alpha);
+    Coverage = vec4(coverage, alpha);
+}
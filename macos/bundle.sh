@@ -0,0 +1,26 @@
+#!/bin/sh
+# Build rush.app in target/release: the release binary, Info.plist and an
+# icon made from logo.png. Runs on macOS, which has sips and iconutil.
+set -eu
+
+cd "$(dirname "$0")/.."
+cargo build --release "$@"
+
+version=$(sed -n 's/^version = "\(.*\)"/\1/p' Cargo.toml | head -n 1)
+app=target/release/rush.app
+rm -rf "$app"
+mkdir -p "$app/Contents/MacOS" "$app/Contents/Resources"
+cp target/release/rush "$app/Contents/MacOS/rush"
+sed "s/@VERSION@/$version/" macos/Info.plist > "$app/Contents/Info.plist"
+
+iconset=target/release/rush.iconset
+rm -rf "$iconset"
+mkdir -p "$iconset"
+for size in 16 32 128 256 512; do
+    sips -z $size $size logo.png --out "$iconset/icon_${size}x${size}.png" > /dev/null
+    sips -z $((size * 2)) $((size * 2)) logo.png --out "$iconset/icon_${size}x${size}@2x.png" > /dev/null
+done
+iconutil -c icns "$iconset" -o "$app/Contents/Resources/rush.icns"
+rm -rf "$iconset"
+
+echo "Built $app"
@@ -1,6 +1,8 @@
 #version 330 core
 out vec4 FragColor;
 
+in vec4 Color;
+
 void main() {
-    FragColor = vec4(1.0f, 1.0f, 1.0f, 1.0f);
+    FragColor = Color;
 }
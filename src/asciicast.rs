@@ -0,0 +1,328 @@
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::term::Terminal;
+
+/// Something that happened during a recorded session
+#[derive(Clone, PartialEq, Debug)]
+pub enum Event {
+    // Text the program wrote
+    Output(String),
+    Resize { cols: usize, rows: usize },
+}
+
+impl Event {
+    /// Replay the event on `term`
+    pub fn apply(&self, term: &mut Terminal) {
+        match self {
+            Event::Output(text) => term.process(text.as_bytes()),
+            Event::Resize { cols, rows } => term.resize(*rows, *cols),
+        }
+    }
+}
+
+/// Why a recording couldn't be played
+#[derive(Debug, thiserror::Error)]
+pub enum CastError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("line {line}: {reason}")]
+    Invalid { line: usize, reason: &'static str },
+}
+
+/// A session recorded in the asciicast v2 format of asciinema: a JSON
+/// header with the size of the terminal, then one JSON array per line for
+/// each event with the seconds since the start
+pub struct Cast {
+    pub cols: usize,
+    pub rows: usize,
+    pub events: Vec<(f64, Event)>,
+}
+
+impl Cast {
+    pub fn open(path: &Path) -> Result<Cast, CastError> {
+        Cast::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Cast, CastError> {
+        let mut lines = text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+        let invalid = |line: usize, reason| CastError::Invalid { line: line + 1, reason };
+
+        let (number, header) = lines.next().ok_or(invalid(0, "the header is missing"))?;
+        let Some(Json::Object(header)) = parse_json(header) else {
+            return Err(invalid(number, "the header isn't a JSON object"));
+        };
+        let field = |name: &str| header.iter().find(|(key, _)| key == name).map(|(_, value)| value);
+        if field("version") != Some(&Json::Number(2.0)) {
+            return Err(invalid(number, "only version 2 recordings can be played"));
+        }
+        let (Some(&Json::Number(cols)), Some(&Json::Number(rows))) = (field("width"), field("height")) else {
+            return Err(invalid(number, "the header has no width and height"));
+        };
+
+        let mut events = Vec::new();
+        for (number, line) in lines {
+            let Some(Json::Array(fields)) = parse_json(line) else {
+                return Err(invalid(number, "an event isn't a JSON array"));
+            };
+            let [Json::Number(time), Json::String(kind), Json::String(data)] = &fields[..] else {
+                return Err(invalid(number, "an event isn't [time, type, data]"));
+            };
+            let event = match kind.as_str() {
+                "o" => Event::Output(data.clone()),
+                "r" => {
+                    let size = data.split_once('x');
+                    let Some((Ok(cols), Ok(rows))) = size.map(|(cols, rows)| (cols.parse(), rows.parse())) else {
+                        return Err(invalid(number, "a resize isn't COLSxROWS"));
+                    };
+                    Event::Resize { cols, rows }
+                }
+                // Input, markers and whatever later versions add don't
+                // change what's on the screen
+                _ => continue,
+            };
+            events.push((*time, event));
+        }
+        Ok(Cast { cols: cols as usize, rows: rows as usize, events })
+    }
+}
+
+/// Writes a session as it happens in the asciicast v2 format. Every event
+/// is written straight away, a crash loses nothing recorded before it.
+pub struct Recorder<W: Write> {
+    out: W,
+    start: Instant,
+    // The start of a UTF-8 sequence the next read completes, events are
+    // JSON strings and can't hold half a character
+    partial: Vec<u8>,
+}
+
+impl Recorder<File> {
+    pub fn create(path: &Path, cols: usize, rows: usize) -> io::Result<Recorder<File>> {
+        Recorder::new(File::create(path)?, cols, rows)
+    }
+}
+
+impl<W: Write> Recorder<W> {
+    /// Start the recording of a terminal of `cols` by `rows` with its header
+    pub fn new(mut out: W, cols: usize, rows: usize) -> io::Result<Recorder<W>> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        writeln!(out, r#"{{"version": 2, "width": {}, "height": {}, "timestamp": {}}}"#, cols, rows, timestamp)?;
+        Ok(Recorder { out, start: Instant::now(), partial: Vec::new() })
+    }
+
+    /// Record bytes the program wrote
+    pub fn output(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.partial.extend_from_slice(bytes);
+        let complete = match std::str::from_utf8(&self.partial) {
+            // Cut at the character the read ended in the middle of
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            _ => self.partial.len(),
+        };
+        if complete == 0 {
+            return Ok(());
+        }
+        let text = String::from_utf8_lossy(&self.partial[..complete]).into_owned();
+        self.partial.drain(..complete);
+        self.event("o", &text)
+    }
+
+    /// Record that the terminal changed size
+    pub fn resize(&mut self, cols: usize, rows: usize) -> io::Result<()> {
+        self.event("r", &format!("{}x{}", cols, rows))
+    }
+
+    fn event(&mut self, kind: &str, data: &str) -> io::Result<()> {
+        let time = self.start.elapsed().as_secs_f64();
+        let line = format!("[{:.6}, \"{}\", {}]\n", time, kind, json_string(data));
+        self.out.write_all(line.as_bytes())
+    }
+}
+
+/// Hands out the events of a recording as their time comes, counting from
+/// when it was created
+pub struct Player {
+    cast: Cast,
+    next: usize,
+    start: Instant,
+}
+
+impl Player {
+    pub fn new(cast: Cast) -> Player {
+        Player { cast, next: 0, start: Instant::now() }
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cast.cols
+    }
+
+    pub fn rows(&self) -> usize {
+        self.cast.rows
+    }
+
+    /// The events that are due and weren't handed out yet
+    pub fn due(&mut self) -> &[(f64, Event)] {
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let first = self.next;
+        while self.cast.events.get(self.next).is_some_and(|(time, _)| *time <= elapsed) {
+            self.next += 1;
+        }
+        &self.cast.events[first..self.next]
+    }
+
+    /// How long until the next event, `None` once they were all played
+    pub fn until_next(&self) -> Option<Duration> {
+        let (time, _) = self.cast.events.get(self.next)?;
+        Some(Duration::from_secs_f64(time.max(0.0)).saturating_sub(self.start.elapsed()))
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// Just enough JSON to read recordings with
+#[derive(PartialEq, Debug)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+fn parse_json(text: &str) -> Option<Json> {
+    let mut parser = JsonParser { text, pos: 0 };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    (parser.pos == text.len()).then_some(value)
+}
+
+struct JsonParser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl JsonParser<'_> {
+    fn rest(&self) -> &str {
+        &self.text[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start_matches([' ', '\t', '\n', '\r']).len();
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        let found = self.rest().starts_with(token);
+        if found {
+            self.pos += token.len();
+        }
+        found
+    }
+
+    fn value(&mut self) -> Option<Json> {
+        self.skip_whitespace();
+        match self.rest().chars().next()? {
+            '{' => self.list("{", "}", |parser| {
+                let key = parser.string()?;
+                parser.eat(":").then_some(())?;
+                Some((key, parser.value()?))
+            })
+            .map(Json::Object),
+            '[' => self.list("[", "]", JsonParser::value).map(Json::Array),
+            '"' => self.string().map(Json::String),
+            _ if self.eat("null") => Some(Json::Null),
+            _ if self.eat("true") => Some(Json::Bool(true)),
+            _ if self.eat("false") => Some(Json::Bool(false)),
+            _ => {
+                let rest = self.rest();
+                let numeric = |c: char| matches!(c, '0'..='9' | '-' | '+' | '.' | 'e' | 'E');
+                let len = rest.find(|c: char| !numeric(c)).unwrap_or(rest.len());
+                let number = rest[..len].parse().ok()?;
+                self.pos += len;
+                Some(Json::Number(number))
+            }
+        }
+    }
+
+    // Comma separated items between `open` and `close`
+    fn list<T>(&mut self, open: &str, close: &str, mut item: impl FnMut(&mut Self) -> Option<T>) -> Option<Vec<T>> {
+        self.eat(open).then_some(())?;
+        let mut items = Vec::new();
+        if self.eat(close) {
+            return Some(items);
+        }
+        loop {
+            items.push(item(self)?);
+            if self.eat(close) {
+                return Some(items);
+            }
+            self.eat(",").then_some(())?;
+        }
+    }
+
+    fn string(&mut self) -> Option<String> {
+        self.eat("\"").then_some(())?;
+        let mut string = String::new();
+        let mut chars = self.rest().char_indices();
+        loop {
+            let (i, c) = chars.next()?;
+            match c {
+                '"' => {
+                    self.pos += i + 1;
+                    return Some(string);
+                }
+                '\\' => {
+                    let c = match chars.next()?.1 {
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'u' => {
+                            let unit = hex_unit(&mut chars)?;
+                            // Characters outside the BMP come as a surrogate pair
+                            let code = if (0xd800..0xdc00).contains(&unit) {
+                                let (_, '\\') = chars.next()? else { return None };
+                                let (_, 'u') = chars.next()? else { return None };
+                                let low = hex_unit(&mut chars)?;
+                                0x10000 + ((unit - 0xd800) << 10) + low.checked_sub(0xdc00)?
+                            } else {
+                                unit
+                            };
+                            char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+                        }
+                        c => c,
+                    };
+                    string.push(c);
+                }
+                c => string.push(c),
+            }
+        }
+    }
+}
+
+// The four hex digits of a \u escape
+fn hex_unit(chars: &mut impl Iterator<Item = (usize, char)>) -> Option<u32> {
+    let digits: String = (0..4).map(|_| chars.next().map(|(_, c)| c)).collect::<Option<_>>()?;
+    u32::from_str_radix(&digits, 16).ok()
+}
@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+
+/// Something a key binding does
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    Copy,
+    Paste,
+    IncreaseFontSize,
+    DecreaseFontSize,
+    ResetFontSize,
+    ScrollLineUp,
+    ScrollLineDown,
+    ScrollPageUp,
+    ScrollPageDown,
+    ScrollToTop,
+    ScrollToBottom,
+    Search,
+    CopyMode,
+    Hints,
+    ScrollToPreviousPrompt,
+    ScrollToNextPrompt,
+    SelectLastCommandOutput,
+    // On the clipboard, in the format `export_format` in the config says
+    ExportScreen,
+    ExportSelection,
+    ExportLastCommandOutput,
+    // Into `pipe_command` in a new window
+    PipeScreen,
+    PipeScrollback,
+    PipeLastCommandOutput,
+    // The next of the themes in the config, by name
+    NextTheme,
+    // A PNG of the window in `screenshot_directory`
+    Screenshot,
+    SpawnNewWindow,
+    // Hides a window of `rush --daemon`, its shell keeps running
+    Detach,
+    ClearScrollback,
+    TogglePerfHud,
+    ToggleFullscreen,
+    ToggleAlwaysOnTop,
+    Quit,
+    // Removes a default binding so the key goes to the application
+    None,
+}
+
+impl Action {
+    fn from_name(name: &str) -> Option<Action> {
+        Some(match name {
+            "Copy" => Action::Copy,
+            "Paste" => Action::Paste,
+            "IncreaseFontSize" => Action::IncreaseFontSize,
+            "DecreaseFontSize" => Action::DecreaseFontSize,
+            "ResetFontSize" => Action::ResetFontSize,
+            "ScrollLineUp" => Action::ScrollLineUp,
+            "ScrollLineDown" => Action::ScrollLineDown,
+            "ScrollPageUp" => Action::ScrollPageUp,
+            "ScrollPageDown" => Action::ScrollPageDown,
+            "ScrollToTop" => Action::ScrollToTop,
+            "ScrollToBottom" => Action::ScrollToBottom,
+            "Search" => Action::Search,
+            "CopyMode" => Action::CopyMode,
+            "Hints" => Action::Hints,
+            "ScrollToPreviousPrompt" => Action::ScrollToPreviousPrompt,
+            "ScrollToNextPrompt" => Action::ScrollToNextPrompt,
+            "SelectLastCommandOutput" => Action::SelectLastCommandOutput,
+            "ExportScreen" => Action::ExportScreen,
+            "ExportSelection" => Action::ExportSelection,
+            "ExportLastCommandOutput" => Action::ExportLastCommandOutput,
+            "PipeScreen" => Action::PipeScreen,
+            "PipeScrollback" => Action::PipeScrollback,
+            "PipeLastCommandOutput" => Action::PipeLastCommandOutput,
+            "NextTheme" => Action::NextTheme,
+            "Screenshot" => Action::Screenshot,
+            "SpawnNewWindow" => Action::SpawnNewWindow,
+            "Detach" => Action::Detach,
+            "ClearScrollback" => Action::ClearScrollback,
+            "TogglePerfHud" => Action::TogglePerfHud,
+            "ToggleFullscreen" => Action::ToggleFullscreen,
+            "ToggleAlwaysOnTop" => Action::ToggleAlwaysOnTop,
+            "Quit" => Action::Quit,
+            "None" => Action::None,
+            _ => return None,
+        })
+    }
+}
+
+// Used unless the config binds the same chord to something else
+const DEFAULT_BINDINGS: &[(&str, &str)] = &[
+    ("ctrl+shift+c", "Copy"),
+    ("ctrl+shift+v", "Paste"),
+    ("ctrl+equal", "IncreaseFontSize"),
+    ("ctrl+shift+equal", "IncreaseFontSize"),
+    ("ctrl+kp_add", "IncreaseFontSize"),
+    ("ctrl+minus", "DecreaseFontSize"),
+    ("ctrl+kp_subtract", "DecreaseFontSize"),
+    ("ctrl+0", "ResetFontSize"),
+    ("ctrl+shift+up", "ScrollLineUp"),
+    ("ctrl+shift+down", "ScrollLineDown"),
+    ("shift+page_up", "ScrollPageUp"),
+    ("shift+page_down", "ScrollPageDown"),
+    ("ctrl+shift+home", "ScrollToTop"),
+    ("ctrl+shift+end", "ScrollToBottom"),
+    ("ctrl+shift+f", "Search"),
+    ("ctrl+shift+space", "CopyMode"),
+    ("ctrl+shift+e", "Hints"),
+    ("ctrl+shift+z", "ScrollToPreviousPrompt"),
+    ("ctrl+shift+x", "ScrollToNextPrompt"),
+    ("ctrl+shift+g", "SelectLastCommandOutput"),
+    ("ctrl+shift+h", "PipeScrollback"),
+    ("ctrl+shift+n", "SpawnNewWindow"),
+    ("ctrl+shift+d", "Detach"),
+    ("ctrl+shift+k", "ClearScrollback"),
+    ("ctrl+shift+s", "Screenshot"),
+    ("ctrl+shift+t", "NextTheme"),
+    ("ctrl+shift+f12", "TogglePerfHud"),
+    ("f11", "ToggleFullscreen"),
+    ("ctrl+shift+f11", "ToggleAlwaysOnTop"),
+    ("ctrl+shift+q", "Quit"),
+];
+
+// The shortcuts Mac apps use, on top of the defaults above
+const MACOS_BINDINGS: &[(&str, &str)] = &[
+    ("cmd+c", "Copy"),
+    ("cmd+v", "Paste"),
+    ("cmd+equal", "IncreaseFontSize"),
+    ("cmd+shift+equal", "IncreaseFontSize"),
+    ("cmd+minus", "DecreaseFontSize"),
+    ("cmd+0", "ResetFontSize"),
+    ("cmd+f", "Search"),
+    ("cmd+n", "SpawnNewWindow"),
+    ("cmd+k", "ClearScrollback"),
+    ("cmd+q", "Quit"),
+];
+
+// Lock keys don't change what a chord means
+const CHORD_MODIFIERS: glfw::Modifiers = glfw::Modifiers::Control
+    .union(glfw::Modifiers::Shift)
+    .union(glfw::Modifiers::Alt)
+    .union(glfw::Modifiers::Super);
+
+/// Key chords and the actions they're bound to, read from the
+/// `keybindings:` section of the config, e.g. `ctrl+shift+c: Copy`
+pub struct Bindings {
+    bindings: Vec<(glfw::Key, glfw::Modifiers, Action)>,
+}
+
+impl Bindings {
+    pub fn from_config(config: &HashMap<String, String>) -> Bindings {
+        let mut bindings = Bindings { bindings: Vec::new() };
+        for (chord, action) in DEFAULT_BINDINGS {
+            bindings.bind(chord, action);
+        }
+        if cfg!(target_os = "macos") {
+            for (chord, action) in MACOS_BINDINGS {
+                bindings.bind(chord, action);
+            }
+        }
+        for (key, action) in config {
+            if let Some(chord) = key.strip_prefix("keybindings.") {
+                bindings.bind(chord, action);
+            }
+        }
+        bindings
+    }
+
+    fn bind(&mut self, chord: &str, action: &str) {
+        let Some((key, modifiers)) = parse_chord(chord) else {
+            tracing::warn!("Invalid key chord in keybindings: {}", chord);
+            return;
+        };
+        let Some(action) = Action::from_name(action) else {
+            tracing::warn!("Unknown action in keybindings: {}", action);
+            return;
+        };
+        self.bindings.retain(|&(k, m, _)| (k, m) != (key, modifiers));
+        self.bindings.push((key, modifiers, action));
+    }
+
+    /// The action bound to a key pressed with the given modifiers
+    pub fn action(&self, key: glfw::Key, modifiers: glfw::Modifiers) -> Option<Action> {
+        let modifiers = modifiers & CHORD_MODIFIERS;
+        self.bindings
+            .iter()
+            .find(|&&(k, m, _)| k == key && m == modifiers)
+            .map(|&(_, _, action)| action)
+            .filter(|&action| action != Action::None)
+    }
+}
+
+/// Parse a chord like `ctrl+shift+c`: any number of modifiers and a key,
+/// joined with `+`
+fn parse_chord(chord: &str) -> Option<(glfw::Key, glfw::Modifiers)> {
+    let chord = chord.to_lowercase();
+    let mut parts: Vec<&str> = chord.split('+').map(str::trim).collect();
+    let key = parse_key(parts.pop()?)?;
+
+    let mut modifiers = glfw::Modifiers::empty();
+    for part in parts {
+        modifiers |= match part {
+            "ctrl" | "control" => glfw::Modifiers::Control,
+            "shift" => glfw::Modifiers::Shift,
+            "alt" | "option" => glfw::Modifiers::Alt,
+            "super" | "cmd" | "command" => glfw::Modifiers::Super,
+            _ => return None,
+        };
+    }
+    Some((key, modifiers))
+}
+
+fn parse_key(name: &str) -> Option<glfw::Key> {
+    use glfw::Key;
+
+    let key = match name {
+        "a" => Key::A,
+        "b" => Key::B,
+        "c" => Key::C,
+        "d" => Key::D,
+        "e" => Key::E,
+        "f" => Key::F,
+        "g" => Key::G,
+        "h" => Key::H,
+        "i" => Key::I,
+        "j" => Key::J,
+        "k" => Key::K,
+        "l" => Key::L,
+        "m" => Key::M,
+        "n" => Key::N,
+        "o" => Key::O,
+        "p" => Key::P,
+        "q" => Key::Q,
+        "r" => Key::R,
+        "s" => Key::S,
+        "t" => Key::T,
+        "u" => Key::U,
+        "v" => Key::V,
+        "w" => Key::W,
+        "x" => Key::X,
+        "y" => Key::Y,
+        "z" => Key::Z,
+        "0" => Key::Num0,
+        "1" => Key::Num1,
+        "2" => Key::Num2,
+        "3" => Key::Num3,
+        "4" => Key::Num4,
+        "5" => Key::Num5,
+        "6" => Key::Num6,
+        "7" => Key::Num7,
+        "8" => Key::Num8,
+        "9" => Key::Num9,
+        "f1" => Key::F1,
+        "f2" => Key::F2,
+        "f3" => Key::F3,
+        "f4" => Key::F4,
+        "f5" => Key::F5,
+        "f6" => Key::F6,
+        "f7" => Key::F7,
+        "f8" => Key::F8,
+        "f9" => Key::F9,
+        "f10" => Key::F10,
+        "f11" => Key::F11,
+        "f12" => Key::F12,
+        "space" => Key::Space,
+        "escape" | "esc" => Key::Escape,
+        "enter" | "return" => Key::Enter,
+        "tab" => Key::Tab,
+        "backspace" => Key::Backspace,
+        "insert" => Key::Insert,
+        "delete" => Key::Delete,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "page_up" | "pageup" => Key::PageUp,
+        "page_down" | "pagedown" => Key::PageDown,
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        // `+` separates the parts of a chord, so symbols go by name
+        "minus" | "-" => Key::Minus,
+        "equal" | "=" => Key::Equal,
+        "comma" | "," => Key::Comma,
+        "period" | "." => Key::Period,
+        "slash" | "/" => Key::Slash,
+        "backslash" | "\\" => Key::Backslash,
+        "semicolon" | ";" => Key::Semicolon,
+        "apostrophe" | "'" => Key::Apostrophe,
+        "grave" | "`" => Key::GraveAccent,
+        "left_bracket" | "[" => Key::LeftBracket,
+        "right_bracket" | "]" => Key::RightBracket,
+        "kp_add" => Key::KpAdd,
+        "kp_subtract" => Key::KpSubtract,
+        _ => return None,
+    };
+    Some(key)
+}
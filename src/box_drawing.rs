@@ -0,0 +1,354 @@
+// Box drawing (U+2500–U+257F) and block element (U+2580–U+259F) characters
+// are drawn as rectangles instead of font glyphs, so borders join up
+// seamlessly between cells and don't depend on the font covering them. So
+// are the scan lines and the diamond of the DEC special graphics set, which
+// few fonts have.
+
+/// A filled quad in pixels relative to the cell, (0, 0) being its top left
+/// corner. Corners are top left, top right, bottom left, bottom right.
+pub struct Shape {
+    pub corners: [(f32, f32); 4],
+    pub alpha: f32,
+}
+
+pub fn is_box_drawing(c: char) -> bool {
+    matches!(c, '\u{2500}'..='\u{259f}' | '\u{23ba}'..='\u{23bd}' | '\u{25c6}')
+}
+
+// Line weights
+const NONE: u8 = 0;
+const LIGHT: u8 = 1;
+const HEAVY: u8 = 2;
+const DOUBLE: u8 = 3;
+
+// Weight of the up, right, down and left arms of U+2500 to U+257F.
+// Dashed lines, diagonals and arcs are handled separately.
+const LINES: [[u8; 4]; 128] = [
+    [0, 1, 0, 1], [0, 2, 0, 2], [1, 0, 1, 0], [2, 0, 2, 0], // ─ ━ │ ┃
+    [0; 4], [0; 4], [0; 4], [0; 4], // ┄ ┅ ┆ ┇
+    [0; 4], [0; 4], [0; 4], [0; 4], // ┈ ┉ ┊ ┋
+    [0, 1, 1, 0], [0, 2, 1, 0], [0, 1, 2, 0], [0, 2, 2, 0], // ┌ ┍ ┎ ┏
+    [0, 0, 1, 1], [0, 0, 1, 2], [0, 0, 2, 1], [0, 0, 2, 2], // ┐ ┑ ┒ ┓
+    [1, 1, 0, 0], [1, 2, 0, 0], [2, 1, 0, 0], [2, 2, 0, 0], // └ ┕ ┖ ┗
+    [1, 0, 0, 1], [1, 0, 0, 2], [2, 0, 0, 1], [2, 0, 0, 2], // ┘ ┙ ┚ ┛
+    [1, 1, 1, 0], [1, 2, 1, 0], [2, 1, 1, 0], [1, 1, 2, 0], // ├ ┝ ┞ ┟
+    [2, 1, 2, 0], [2, 2, 1, 0], [1, 2, 2, 0], [2, 2, 2, 0], // ┠ ┡ ┢ ┣
+    [1, 0, 1, 1], [1, 0, 1, 2], [2, 0, 1, 1], [1, 0, 2, 1], // ┤ ┥ ┦ ┧
+    [2, 0, 2, 1], [2, 0, 1, 2], [1, 0, 2, 2], [2, 0, 2, 2], // ┨ ┩ ┪ ┫
+    [0, 1, 1, 1], [0, 1, 1, 2], [0, 2, 1, 1], [0, 2, 1, 2], // ┬ ┭ ┮ ┯
+    [0, 1, 2, 1], [0, 1, 2, 2], [0, 2, 2, 1], [0, 2, 2, 2], // ┰ ┱ ┲ ┳
+    [1, 1, 0, 1], [1, 1, 0, 2], [1, 2, 0, 1], [1, 2, 0, 2], // ┴ ┵ ┶ ┷
+    [2, 1, 0, 1], [2, 1, 0, 2], [2, 2, 0, 1], [2, 2, 0, 2], // ┸ ┹ ┺ ┻
+    [1, 1, 1, 1], [1, 1, 1, 2], [1, 2, 1, 1], [1, 2, 1, 2], // ┼ ┽ ┾ ┿
+    [2, 1, 1, 1], [1, 1, 2, 1], [2, 1, 2, 1], [2, 1, 1, 2], // ╀ ╁ ╂ ╃
+    [2, 2, 1, 1], [1, 1, 2, 2], [1, 2, 2, 1], [2, 2, 1, 2], // ╄ ╅ ╆ ╇
+    [1, 2, 2, 2], [2, 1, 2, 2], [2, 2, 2, 1], [2, 2, 2, 2], // ╈ ╉ ╊ ╋
+    [0; 4], [0; 4], [0; 4], [0; 4], // ╌ ╍ ╎ ╏
+    [0, 3, 0, 3], [3, 0, 3, 0], [0, 3, 1, 0], [0, 1, 3, 0], // ═ ║ ╒ ╓
+    [0, 3, 3, 0], [0, 0, 1, 3], [0, 0, 3, 1], [0, 0, 3, 3], // ╔ ╕ ╖ ╗
+    [1, 3, 0, 0], [3, 1, 0, 0], [3, 3, 0, 0], [1, 0, 0, 3], // ╘ ╙ ╚ ╛
+    [3, 0, 0, 1], [3, 0, 0, 3], [1, 3, 1, 0], [3, 1, 3, 0], // ╜ ╝ ╞ ╟
+    [3, 3, 3, 0], [1, 0, 1, 3], [3, 0, 3, 1], [3, 0, 3, 3], // ╠ ╡ ╢ ╣
+    [0, 3, 1, 3], [0, 1, 3, 1], [0, 3, 3, 3], [1, 3, 0, 3], // ╤ ╥ ╦ ╧
+    [3, 1, 0, 1], [3, 3, 0, 3], [1, 3, 1, 3], [3, 1, 3, 1], // ╨ ╩ ╪ ╫
+    [3, 3, 3, 3], [0; 4], [0; 4], [0; 4], // ╬ ╭ ╮ ╯
+    [0; 4], [0; 4], [0; 4], [0; 4], // ╰ ╱ ╲ ╳
+    [0, 0, 0, 1], [1, 0, 0, 0], [0, 1, 0, 0], [0, 0, 1, 0], // ╴ ╵ ╶ ╷
+    [0, 0, 0, 2], [2, 0, 0, 0], [0, 2, 0, 0], [0, 0, 2, 0], // ╸ ╹ ╺ ╻
+    [0, 2, 0, 1], [1, 0, 2, 0], [0, 1, 0, 2], [2, 0, 1, 0], // ╼ ╽ ╾ ╿
+];
+
+/// Add the shapes making up `c` in a cell of the given size in pixels to
+/// `shapes`
+pub fn shapes(c: char, width: f32, height: f32, shapes: &mut Vec<Shape>) {
+    let mut builder = Builder {
+        shapes,
+        width,
+        height,
+        // Light lines are an eighth of the cell wide, but never thinner
+        // than a pixel
+        light: (width / 8.0).round().max(1.0),
+    };
+
+    match c as u32 {
+        // Dashed lines: ┄ ┅ ┆ ┇ (three dashes) ┈ ┉ ┊ ┋ (four dashes)
+        code @ 0x2504..=0x250b => {
+            let dashes = if code < 0x2508 { 3 } else { 4 };
+            let vertical = code & 2 != 0;
+            let weight = if code & 1 == 0 { LIGHT } else { HEAVY };
+            builder.dashes(dashes, vertical, weight);
+        }
+        // ╌ ╍ ╎ ╏
+        code @ 0x254c..=0x254f => {
+            let vertical = code & 2 != 0;
+            let weight = if code & 1 == 0 { LIGHT } else { HEAVY };
+            builder.dashes(2, vertical, weight);
+        }
+        // Rounded corners ╭ ╮ ╯ ╰, given by the corner the arc curves around
+        0x256d => builder.arc(1.0, 1.0),
+        0x256e => builder.arc(0.0, 1.0),
+        0x256f => builder.arc(0.0, 0.0),
+        0x2570 => builder.arc(1.0, 0.0),
+        // ╱ ╲ ╳
+        0x2571 => builder.diagonal((0.0, height), (width, 0.0)),
+        0x2572 => builder.diagonal((0.0, 0.0), (width, height)),
+        0x2573 => {
+            builder.diagonal((0.0, height), (width, 0.0));
+            builder.diagonal((0.0, 0.0), (width, height));
+        }
+        code @ 0x2500..=0x257f => builder.lines(LINES[(code - 0x2500) as usize]),
+        code @ 0x2580..=0x259f => builder.block(code),
+        // ⎺ ⎻ ⎼ ⎽, scan lines 1, 3, 7 and 9 of the nine of a VT100 cell
+        code @ 0x23ba..=0x23bd => {
+            let scan = [0.0, 2.0, 6.0, 8.0][(code - 0x23ba) as usize];
+            let light = builder.light;
+            builder.hline(0.0, width, light / 2.0 + scan / 8.0 * (height - light), light);
+        }
+        // ◆
+        0x25c6 => builder.diamond(),
+        _ => {}
+    }
+}
+
+struct Builder<'a> {
+    shapes: &'a mut Vec<Shape>,
+    width: f32,
+    height: f32,
+    light: f32,
+}
+
+impl Builder<'_> {
+    /// An axis aligned rectangle between two corners, snapped to whole
+    /// pixels so neighbouring cells line up exactly
+    fn rect(&mut self, x0: f32, y0: f32, x1: f32, y1: f32, alpha: f32) {
+        let (x0, y0, x1, y1) = (x0.round(), y0.round(), x1.round(), y1.round());
+        if x1 <= x0 || y1 <= y0 {
+            return;
+        }
+        self.shapes.push(Shape {
+            corners: [(x0, y0), (x1, y0), (x0, y1), (x1, y1)],
+            alpha,
+        });
+    }
+
+    fn thickness(&self, weight: u8) -> f32 {
+        match weight {
+            NONE => 0.0,
+            LIGHT => self.light,
+            HEAVY => self.light * 2.0,
+            // Both strokes of a double line and the gap between them
+            _ => self.light * 3.0,
+        }
+    }
+
+    fn hline(&mut self, x0: f32, x1: f32, center_y: f32, thickness: f32) {
+        let top = (center_y - thickness / 2.0).round();
+        self.rect(x0, top, x1, top + thickness, 1.0);
+    }
+
+    fn vline(&mut self, y0: f32, y1: f32, center_x: f32, thickness: f32) {
+        let left = (center_x - thickness / 2.0).round();
+        self.rect(left, y0, left + thickness, y1, 1.0);
+    }
+
+    /// Draw the arms of a line junction. `arms` are the weights of the up,
+    /// right, down and left arms.
+    fn lines(&mut self, arms: [u8; 4]) {
+        let [up, right, down, left] = arms;
+        let (cx, cy) = ((self.width / 2.0).floor(), (self.height / 2.0).floor());
+        // Double lines are two light strokes this far either side of the center
+        let gap = self.light;
+
+        let vertical = self.thickness(up).max(self.thickness(down));
+        let horizontal = self.thickness(left).max(self.thickness(right));
+
+        for (weight, toward_start, opposite) in [(left, true, right), (right, false, left)] {
+            if weight == NONE {
+                continue;
+            }
+            let strokes: &[f32] = if weight == DOUBLE { &[-gap, gap] } else { &[0.0] };
+            for &offset in strokes {
+                let side = if offset < 0.0 { up } else { down };
+                let reach = self.reach(weight, side, [up, down], opposite, vertical);
+                let thickness = if weight == DOUBLE { self.light } else { self.thickness(weight) };
+                if toward_start {
+                    self.hline(0.0, cx + reach, cy + offset, thickness);
+                } else {
+                    self.hline(cx - reach, self.width, cy + offset, thickness);
+                }
+            }
+        }
+
+        for (weight, toward_start, opposite) in [(up, true, down), (down, false, up)] {
+            if weight == NONE {
+                continue;
+            }
+            let strokes: &[f32] = if weight == DOUBLE { &[-gap, gap] } else { &[0.0] };
+            for &offset in strokes {
+                let side = if offset < 0.0 { left } else { right };
+                let reach = self.reach(weight, side, [left, right], opposite, horizontal);
+                let thickness = if weight == DOUBLE { self.light } else { self.thickness(weight) };
+                if toward_start {
+                    self.vline(0.0, cy + reach, cx + offset, thickness);
+                } else {
+                    self.vline(cy - reach, self.height, cx + offset, thickness);
+                }
+            }
+        }
+    }
+
+    /// How far past the center of the cell a stroke of an arm extends so
+    /// it joins the perpendicular arms. `side` is the perpendicular arm on
+    /// the same side as the stroke, `across` the thickest perpendicular arm.
+    fn reach(&self, weight: u8, side: u8, perpendicular: [u8; 2], opposite: u8, across: f32) -> f32 {
+        let gap = self.light;
+        let half = self.light / 2.0;
+        // The strokes of a perpendicular double line
+        let near = -gap + half;
+        let far = gap + half;
+
+        if !perpendicular.contains(&DOUBLE) {
+            return across / 2.0;
+        }
+        if weight == DOUBLE {
+            // Inner strokes stop at the nearest stroke of the other line,
+            // outer ones go round the corner to the farthest
+            return if side == DOUBLE { near } else { far };
+        }
+        if opposite != NONE {
+            // Crossing straight through
+            across / 2.0
+        } else if perpendicular.contains(&NONE) {
+            // A corner, joining the outer stroke
+            far
+        } else {
+            near
+        }
+    }
+
+    fn dashes(&mut self, count: usize, vertical: bool, weight: u8) {
+        let thickness = self.thickness(weight);
+        let length = if vertical { self.height } else { self.width };
+        let segment = length / count as f32;
+        let gap = segment / 3.0;
+        for i in 0..count {
+            let start = i as f32 * segment + gap / 2.0;
+            let end = (i + 1) as f32 * segment - gap / 2.0;
+            if vertical {
+                self.vline(start, end, (self.width / 2.0).floor(), thickness);
+            } else {
+                self.hline(start, end, (self.height / 2.0).floor(), thickness);
+            }
+        }
+    }
+
+    /// A quarter ellipse joining the middles of the two edges that meet at
+    /// the corner `(corner_x, corner_y)`, given as 0 or 1 of the cell size
+    fn arc(&mut self, corner_x: f32, corner_y: f32) {
+        const SEGMENTS: usize = 8;
+        let center = (corner_x * self.width, corner_y * self.height);
+        // Step from the corner towards the middle of the cell
+        let dir_x = if corner_x == 0.0 { 1.0 } else { -1.0 };
+        let dir_y = if corner_y == 0.0 { 1.0 } else { -1.0 };
+        let (radius_x, radius_y) = ((self.width / 2.0).floor(), (self.height / 2.0).floor());
+        let radius_x = if corner_x == 0.0 { radius_x } else { self.width - radius_x };
+        let radius_y = if corner_y == 0.0 { radius_y } else { self.height - radius_y };
+
+        let point = |i: usize| {
+            let t = i as f32 / SEGMENTS as f32 * std::f32::consts::FRAC_PI_2;
+            (center.0 + dir_x * radius_x * t.cos(), center.1 + dir_y * radius_y * t.sin())
+        };
+        for i in 0..SEGMENTS {
+            self.diagonal(point(i), point(i + 1));
+        }
+    }
+
+    /// A light stroke between two arbitrary points
+    fn diagonal(&mut self, from: (f32, f32), to: (f32, f32)) {
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        let length = (dx * dx + dy * dy).sqrt();
+        if length == 0.0 {
+            return;
+        }
+        // Half the stroke either side of the line
+        let (nx, ny) = (-dy / length * self.light / 2.0, dx / length * self.light / 2.0);
+        self.shapes.push(Shape {
+            corners: [
+                (from.0 + nx, from.1 + ny),
+                (to.0 + nx, to.1 + ny),
+                (from.0 - nx, from.1 - ny),
+                (to.0 - nx, to.1 - ny),
+            ],
+            alpha: 1.0,
+        });
+    }
+
+    /// A diamond as wide as the cell, in its middle
+    fn diamond(&mut self) {
+        let (cx, cy) = (self.width / 2.0, self.height / 2.0);
+        let radius = self.width / 2.0;
+        self.shapes.push(Shape {
+            corners: [(cx, cy - radius), (cx + radius, cy), (cx - radius, cy), (cx, cy + radius)],
+            alpha: 1.0,
+        });
+    }
+
+    fn block(&mut self, code: u32) {
+        let (w, h) = (self.width, self.height);
+        match code {
+            // ▀
+            0x2580 => self.rect(0.0, 0.0, w, h / 2.0, 1.0),
+            // ▁ to █, lower eighths
+            0x2581..=0x2588 => {
+                let eighths = (code - 0x2580) as f32;
+                self.rect(0.0, h - h * eighths / 8.0, w, h, 1.0);
+            }
+            // ▉ to ▏, left eighths
+            0x2589..=0x258f => {
+                let eighths = (0x2590 - code) as f32;
+                self.rect(0.0, 0.0, w * eighths / 8.0, h, 1.0);
+            }
+            // ▐
+            0x2590 => self.rect(w / 2.0, 0.0, w, h, 1.0),
+            // ░ ▒ ▓
+            0x2591..=0x2593 => self.rect(0.0, 0.0, w, h, (code - 0x2590) as f32 / 4.0),
+            // ▔
+            0x2594 => self.rect(0.0, 0.0, w, h / 8.0, 1.0),
+            // ▕
+            0x2595 => self.rect(w - w / 8.0, 0.0, w, h, 1.0),
+            // Quadrants ▖ to ▟
+            0x2596..=0x259f => {
+                const UPPER_LEFT: u8 = 1;
+                const UPPER_RIGHT: u8 = 2;
+                const LOWER_LEFT: u8 = 4;
+                const LOWER_RIGHT: u8 = 8;
+                let quadrants = [
+                    LOWER_LEFT,
+                    LOWER_RIGHT,
+                    UPPER_LEFT,
+                    UPPER_LEFT | LOWER_LEFT | LOWER_RIGHT,
+                    UPPER_LEFT | LOWER_RIGHT,
+                    UPPER_LEFT | UPPER_RIGHT | LOWER_LEFT,
+                    UPPER_LEFT | UPPER_RIGHT | LOWER_RIGHT,
+                    UPPER_RIGHT,
+                    UPPER_RIGHT | LOWER_LEFT,
+                    UPPER_RIGHT | LOWER_LEFT | LOWER_RIGHT,
+                ][(code - 0x2596) as usize];
+                let (mx, my) = (w / 2.0, h / 2.0);
+                if quadrants & UPPER_LEFT != 0 {
+                    self.rect(0.0, 0.0, mx, my, 1.0);
+                }
+                if quadrants & UPPER_RIGHT != 0 {
+                    self.rect(mx, 0.0, w, my, 1.0);
+                }
+                if quadrants & LOWER_LEFT != 0 {
+                    self.rect(0.0, my, mx, h, 1.0);
+                }
+                if quadrants & LOWER_RIGHT != 0 {
+                    self.rect(mx, my, w, h, 1.0);
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use crate::color::Rgb;
+
+/// A frame drawn on the CPU, for when there's no GPU to draw with. Pixels
+/// are 0RGB, one `u32` each, in rows from the top.
+pub struct Canvas {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u32>,
+    // Only pixels in here are drawn to: left, top, right and bottom edges
+    clip: (usize, usize, usize, usize),
+}
+
+impl Canvas {
+    pub fn new(width: usize, height: usize) -> Canvas {
+        Canvas { width, height, pixels: vec![0; width * height], clip: (0, 0, width, height) }
+    }
+
+    /// Change the size, which loses what was drawn and the clip rectangle
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+        self.pixels.clear();
+        self.pixels.resize(width * height, 0);
+        self.clip = (0, 0, width, height);
+    }
+
+    /// Only draw to the `width` by `height` rectangle at `(x, y)` from now
+    /// on
+    pub fn set_clip(&mut self, (x, y): (usize, usize), width: usize, height: usize) {
+        self.clip = (x.min(self.width), y.min(self.height), (x + width).min(self.width), (y + height).min(self.height));
+    }
+
+    /// Fill the whole canvas, whatever the clip rectangle
+    pub fn clear(&mut self, color: Rgb) {
+        self.pixels.fill(pack(color));
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Rgb {
+        let pixel = self.pixels[y * self.width + x];
+        ((pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8)
+    }
+
+    /// Blend `color` into the pixels whose centers are inside a triangle
+    /// with its corners in pixels. Pixels on an edge two triangles share
+    /// are drawn by only one of them, so translucent quads come out even.
+    pub fn fill_triangle(&mut self, [a, mut b, mut c]: [(f32, f32); 3], color: [f32; 4]) {
+        let area = edge(a, b, c);
+        if area == 0.0 || !area.is_finite() {
+            return;
+        }
+        // The same winding for every triangle, neighbours then go along a
+        // shared edge in opposite directions
+        if area < 0.0 {
+            std::mem::swap(&mut b, &mut c);
+        }
+
+        let (left, top, right, bottom) = self.clip;
+        let x0 = a.0.min(b.0).min(c.0).floor().max(left as f32) as usize;
+        let y0 = a.1.min(b.1).min(c.1).floor().max(top as f32) as usize;
+        let x1 = (a.0.max(b.0).max(c.0).ceil().max(0.0) as usize).min(right);
+        let y1 = (a.1.max(b.1).max(c.1).ceil().max(0.0) as usize).min(bottom);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let p = (x as f32 + 0.5, y as f32 + 0.5);
+                if inside(b, c, p) && inside(c, a, p) && inside(a, b, p) {
+                    let pixel = &mut self.pixels[y * self.width + x];
+                    *pixel = blend(*pixel, color, 1.0);
+                }
+            }
+        }
+    }
+
+    /// Blend `color` into the pixels under a mask of coverage values,
+    /// `width` of them a row, with its top left corner at `(x, y)`
+    pub fn blend_mask(&mut self, (x, y): (i32, i32), width: usize, mask: &[u8], color: [f32; 4]) {
+        if width == 0 {
+            return;
+        }
+        let (left, top, right, bottom) = self.clip;
+        for (row, coverage) in mask.chunks_exact(width).enumerate() {
+            let py = y + row as i32;
+            if py < top as i32 || py >= bottom as i32 {
+                continue;
+            }
+            for (col, &coverage) in coverage.iter().enumerate() {
+                let px = x + col as i32;
+                if coverage == 0 || px < left as i32 || px >= right as i32 {
+                    continue;
+                }
+                let pixel = &mut self.pixels[py as usize * self.width + px as usize];
+                *pixel = blend(*pixel, color, coverage as f32 / 255.0);
+            }
+        }
+    }
+}
+
+fn pack((r, g, b): Rgb) -> u32 {
+    (r as u32) << 16 | (g as u32) << 8 | b as u32
+}
+
+// Twice the signed area of the triangle a, b, p: positive with p on the
+// inner side of the edge from a to b
+fn edge(a: (f32, f32), b: (f32, f32), p: (f32, f32)) -> f32 {
+    (b.0 - a.0) * (p.1 - a.1) - (b.1 - a.1) * (p.0 - a.0)
+}
+
+// Pixels right on an edge belong to the triangle going along it in one
+// direction and not the other
+fn inside(a: (f32, f32), b: (f32, f32), p: (f32, f32)) -> bool {
+    let w = edge(a, b, p);
+    w > 0.0 || (w == 0.0 && (b.1 > a.1 || (b.1 == a.1 && b.0 > a.0)))
+}
+
+fn blend(pixel: u32, color: [f32; 4], coverage: f32) -> u32 {
+    let alpha = color[3] * coverage;
+    let channel = |shift: u32, value: f32| {
+        let dst = ((pixel >> shift) & 0xff) as f32;
+        (dst + (value * 255.0 - dst) * alpha).round().clamp(0.0, 255.0) as u8
+    };
+    pack((channel(16, color[0]), channel(8, color[1]), channel(0, color[2])))
+}
@@ -0,0 +1,195 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use rush::export::{Format, Region};
+use rush::policy::Feature;
+use tracing::level_filters::LevelFilter;
+
+/// A GPU accelerated terminal emulator
+#[derive(Parser, Debug, Clone)]
+#[command(name = "rush", version)]
+pub struct Cli {
+    /// Run this program, and its arguments, instead of the shell
+    #[arg(short = 'e', long, num_args = 1.., allow_hyphen_values = true, value_name = "COMMAND")]
+    pub command: Vec<String>,
+
+    /// Start the shell in this directory
+    #[arg(long, value_name = "DIR")]
+    pub working_directory: Option<PathBuf>,
+
+    /// Read the config from this file instead of the default location
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Window title
+    #[arg(long, default_value = "rush")]
+    pub title: String,
+
+    /// Open the window this many columns wide and lines tall, instead of
+    /// the size in the config or the one the last window closed with
+    #[arg(long, value_name = "COLSxLINES", value_parser = parse_size)]
+    pub geometry: Option<(usize, usize)>,
+
+    /// WM_CLASS on X11 and app id on Wayland, for window manager rules;
+    /// window.class in the config, rush by default
+    #[arg(long)]
+    pub class: Option<String>,
+
+    /// Print the default config and exit
+    #[arg(long)]
+    pub print_default_config: bool,
+
+    /// Run without a window and print the screen once the program exits
+    #[arg(long)]
+    pub headless: bool,
+
+    /// Columns and lines of the screen with --headless
+    #[arg(long, value_name = "COLSxLINES", default_value = "80x24", value_parser = parse_size)]
+    pub headless_size: (usize, usize),
+
+    /// Host every window in this process and take commands from `rush msg`
+    /// on a control socket
+    #[arg(long)]
+    pub daemon: bool,
+
+    /// Slide the dropdown window of a running `rush --daemon` in or out,
+    /// for a desktop shortcut to run
+    #[arg(long, conflicts_with = "daemon")]
+    pub toggle: bool,
+
+    /// Let programs in the window use FEATURE with escape sequences,
+    /// whatever the security section of the config says: clipboard_write,
+    /// title or notifications
+    #[arg(long, value_name = "FEATURE")]
+    pub allow: Vec<Feature>,
+
+    /// Keep programs in the window from using FEATURE, like --allow. For
+    /// sessions on machines you don't trust: rush --deny clipboard_write
+    /// -e ssh HOST
+    #[arg(long, value_name = "FEATURE")]
+    pub deny: Vec<Feature>,
+
+    /// Record the session to this file, in the asciicast v2 format of
+    /// asciinema
+    #[arg(long, value_name = "FILE")]
+    pub record: Option<PathBuf>,
+
+    /// Play an asciicast recording instead of running a program
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["command", "record"])]
+    pub play: Option<PathBuf>,
+
+    /// Replay this capture of a program's output, like the ones
+    /// --dump-output saves, as fast as frames can be drawn and report how
+    /// fast that was
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["command", "play", "headless", "daemon"])]
+    pub bench_render: Option<PathBuf>,
+
+    /// Save everything the program writes, byte for byte, to a timestamped
+    /// file in this directory, to attach to bug reports
+    #[arg(long, value_name = "DIR")]
+    pub dump_output: Option<PathBuf>,
+
+    /// Also list the escape sequences rush didn't understand, in a file
+    /// next to the dump
+    #[arg(long, requires = "dump_output")]
+    pub dump_annotate: bool,
+
+    /// Log messages this severe and worse: off, error, warn, info, debug
+    /// or trace
+    #[arg(long, value_name = "LEVEL", default_value = "warn")]
+    pub log_level: LevelFilter,
+
+    /// Append the log to this file instead of writing it to stderr
+    #[arg(long, value_name = "FILE")]
+    pub log_file: Option<PathBuf>,
+
+    /// Draw with opengl, wgpu or software, instead of what the `renderer`
+    /// key of the config says
+    #[arg(long, value_name = "BACKEND")]
+    pub renderer: Option<Backend>,
+
+    #[command(subcommand)]
+    pub subcommand: Option<Subcommand>,
+}
+
+fn parse_size(size: &str) -> Result<(usize, usize), String> {
+    let invalid = || format!("expected COLSxLINES, like 80x24, not {:?}", size);
+    let (cols, rows) = size.split_once('x').ok_or_else(invalid)?;
+    let cols = cols.parse().map_err(|_| invalid())?;
+    let rows = rows.parse().map_err(|_| invalid())?;
+    Ok((cols, rows))
+}
+
+/// What the window is drawn with
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Backend {
+    OpenGl,
+    // Only in builds with the wgpu feature
+    Wgpu,
+    // On the CPU, also what rush falls back to without OpenGL
+    Software,
+}
+
+impl std::str::FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "opengl" => Ok(Backend::OpenGl),
+            "wgpu" => Ok(Backend::Wgpu),
+            "software" => Ok(Backend::Software),
+            _ => Err(format!("expected opengl, wgpu or software, not {:?}", s)),
+        }
+    }
+}
+
+#[derive(clap::Subcommand, Debug, Clone)]
+pub enum Subcommand {
+    /// Send a command to a running `rush --daemon`
+    Msg {
+        #[command(subcommand)]
+        message: Message,
+    },
+}
+
+/// What `rush msg` can ask the daemon to do
+#[derive(clap::Subcommand, Debug, Clone)]
+pub enum Message {
+    /// Open a new window
+    NewWindow {
+        /// Start the shell in this directory
+        #[arg(long, value_name = "DIR")]
+        cwd: Option<PathBuf>,
+    },
+    /// Type text into the focused window
+    SendText { text: String },
+    /// Slide the dropdown window in or out, opening it the first time
+    Toggle,
+    /// Close the focused window but keep its shell running in the daemon,
+    /// for `attach` to open it again
+    Detach,
+    /// Open a detached session again, the last one detached unless SESSION
+    /// says which
+    Attach {
+        /// The process id of its shell, as `sessions` lists it
+        session: Option<i32>,
+    },
+    /// List the daemon's sessions: their shell's process id, whether
+    /// they're detached, and their title
+    Sessions,
+    /// Save the focused window's screen, scrollback, selection or last
+    /// command's output to a file
+    Export {
+        /// screen, scrollback, selection or last-output
+        #[arg(long, default_value = "screen")]
+        region: Region,
+        /// text, ansi or html
+        #[arg(long, default_value = "text")]
+        format: Format,
+        file: PathBuf,
+    },
+    /// Save a PNG of the focused window
+    Screenshot { file: PathBuf },
+    /// Switch every window to a theme from the config
+    Theme { name: String },
+}
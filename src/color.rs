@@ -0,0 +1,173 @@
+use crate::term::{Cell, Color, Flags};
+
+pub type Rgb = (u8, u8, u8);
+
+// xterm's default 16 colors
+const ANSI_COLORS: [Rgb; 16] = [
+    (0x00, 0x00, 0x00),
+    (0xcd, 0x00, 0x00),
+    (0x00, 0xcd, 0x00),
+    (0xcd, 0xcd, 0x00),
+    (0x00, 0x00, 0xee),
+    (0xcd, 0x00, 0xcd),
+    (0x00, 0xcd, 0xcd),
+    (0xe5, 0xe5, 0xe5),
+    (0x7f, 0x7f, 0x7f),
+    (0xff, 0x00, 0x00),
+    (0x00, 0xff, 0x00),
+    (0xff, 0xff, 0x00),
+    (0x5c, 0x5c, 0xff),
+    (0xff, 0x00, 0xff),
+    (0x00, 0xff, 0xff),
+    (0xff, 0xff, 0xff),
+];
+
+/// The 256 color palette plus the default colors
+#[derive(Clone, PartialEq)]
+pub struct Palette {
+    colors: [Rgb; 256],
+    pub foreground: Rgb,
+    pub background: Rgb,
+    // The cursor takes the color of the text under it when unset
+    pub cursor: Option<Rgb>,
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        let mut colors = [(0, 0, 0); 256];
+        colors[..16].copy_from_slice(&ANSI_COLORS);
+
+        // 6x6x6 color cube
+        let level = |n: usize| if n == 0 { 0 } else { (55 + n * 40) as u8 };
+        for i in 0..216 {
+            colors[16 + i] = (level(i / 36), level(i / 6 % 6), level(i % 6));
+        }
+
+        // Grayscale ramp
+        for i in 0..24 {
+            let gray = (8 + i * 10) as u8;
+            colors[232 + i] = (gray, gray, gray);
+        }
+
+        Palette {
+            colors,
+            foreground: (0xff, 0xff, 0xff),
+            background: (0x00, 0x00, 0x00),
+            cursor: None,
+        }
+    }
+}
+
+impl Palette {
+    pub fn indexed(&self, index: u8) -> Rgb {
+        self.colors[index as usize]
+    }
+
+    pub fn set_indexed(&mut self, index: u8, rgb: Rgb) {
+        self.colors[index as usize] = rgb;
+    }
+
+    /// Resolve a cell color, `default` is used for `Color::Default`
+    pub fn resolve(&self, color: Color, default: Rgb) -> Rgb {
+        match color {
+            Color::Default => default,
+            Color::Indexed(index) => self.indexed(index),
+            Color::Rgb(r, g, b) => (r, g, b),
+        }
+    }
+}
+
+impl Palette {
+    /// The foreground and background a cell is drawn with, once reverse
+    /// video and faint have been applied
+    pub fn cell_colors(&self, cell: &Cell) -> (Rgb, Rgb) {
+        let mut fg = self.resolve(cell.fg, self.foreground);
+        let mut bg = self.resolve(cell.bg, self.background);
+        if cell.flags.contains(Flags::INVERSE) {
+            std::mem::swap(&mut fg, &mut bg);
+        }
+        if cell.flags.contains(Flags::DIM) {
+            // Two thirds of the way from the background to the foreground
+            let dim = |f: u8, b: u8| ((f as u16 * 2 + b as u16) / 3) as u8;
+            fg = (dim(fg.0, bg.0), dim(fg.1, bg.1), dim(fg.2, bg.2));
+        }
+        (fg, bg)
+    }
+}
+
+/// Parse a `#rrggbb` color
+pub fn parse_hex(s: &str) -> Option<Rgb> {
+    let hex = s.strip_prefix('#')?;
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some((channel(0)?, channel(2)?, channel(4)?))
+}
+
+/// Convert to the normalized RGBA OpenGL wants
+pub fn to_gl(rgb: Rgb) -> [f32; 4] {
+    [rgb.0 as f32 / 255.0, rgb.1 as f32 / 255.0, rgb.2 as f32 / 255.0, 1.0]
+}
+
+/// Parse an X11 color spec as OSC 4 and 10-12 carry them: `rgb:r/g/b` with
+/// one to four hex digits a channel, scaled to eight bits, or `#rgb` with
+/// one to four digits a channel, of which the high eight bits count
+pub fn parse_spec(spec: &str) -> Option<Rgb> {
+    let hex = |digits: &str| {
+        if digits.is_empty() || digits.len() > 4 || !digits.is_ascii() {
+            return None;
+        }
+        u32::from_str_radix(digits, 16).ok()
+    };
+    if let Some(channels) = spec.strip_prefix("rgb:") {
+        // The length is checked before it's used to shift
+        let channel = |digits: &str| {
+            let value = hex(digits)?;
+            let max = (1 << (4 * digits.len())) - 1;
+            Some((value * 255 / max) as u8)
+        };
+        let mut channels = channels.split('/');
+        let rgb = (channel(channels.next()?)?, channel(channels.next()?)?, channel(channels.next()?)?);
+        return channels.next().is_none().then_some(rgb);
+    }
+    let digits = spec.strip_prefix('#')?;
+    if digits.is_empty() || digits.len() % 3 != 0 || !digits.is_ascii() {
+        return None;
+    }
+    let n = digits.len() / 3;
+    let channel = |i: usize| {
+        let value = hex(&digits[i * n..(i + 1) * n])?;
+        Some(if n == 1 { value << 4 } else { value >> (4 * n - 8) } as u8)
+    };
+    Some((channel(0)?, channel(1)?, channel(2)?))
+}
+
+/// Format a color the way xterm answers color queries
+pub fn format_spec(rgb: Rgb) -> String {
+    // Repeating the byte scales it to sixteen bits
+    format!("rgb:{0:02x}{0:02x}/{1:02x}{1:02x}/{2:02x}{2:02x}", rgb.0, rgb.1, rgb.2)
+}
+
+/// How much two colors stand apart, as the WCAG contrast ratio: from 1 for
+/// the same brightness to 21 for black and white
+pub fn contrast(a: Rgb, b: Rgb) -> f32 {
+    let (a, b) = (luminance(a), luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+/// Black or white, whichever stands out more against `rgb`
+pub fn contrasting(rgb: Rgb) -> Rgb {
+    const BLACK: Rgb = (0, 0, 0);
+    const WHITE: Rgb = (0xff, 0xff, 0xff);
+    if contrast(rgb, BLACK) >= contrast(rgb, WHITE) { BLACK } else { WHITE }
+}
+
+// Relative luminance, from the linear values of the sRGB channels
+fn luminance((r, g, b): Rgb) -> f32 {
+    let linear = |channel: u8| {
+        let c = channel as f32 / 255.0;
+        if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+    };
+    0.2126 * linear(r) + 0.7152 * linear(g) + 0.0722 * linear(b)
+}
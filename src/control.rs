@@ -0,0 +1,274 @@
+use std::ffi::OsString;
+use std::fs::DirBuilder;
+use std::io::{self, Read, Write};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::cli::Message;
+
+// How long a client gets to send its message before it is dropped
+const READ_TIMEOUT: Duration = Duration::from_secs(1);
+// How long the daemon waits for a client to take its reply
+const WRITE_TIMEOUT: Duration = Duration::from_millis(100);
+// How long `rush msg` waits for the daemon to get to its message
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where `rush --daemon` listens: `$XDG_RUNTIME_DIR/rush.sock`, or a
+/// socket in a directory in /tmp named after the user when that isn't set
+pub fn socket_path() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("rush.sock"),
+        None => fallback_dir().join("rush.sock"),
+    }
+}
+
+fn fallback_dir() -> PathBuf {
+    PathBuf::from(format!("/tmp/rush-{}", unsafe { nix::libc::getuid() }))
+}
+
+/// Make sure nobody else can get at the socket. `$XDG_RUNTIME_DIR` is the
+/// user's own, but anyone can write to /tmp: another user could put their
+/// socket where the daemon's is expected and read the text sent to it. So
+/// there the socket goes in a directory only the user can get into.
+fn check_socket_dir(create: bool) -> io::Result<()> {
+    if std::env::var_os("XDG_RUNTIME_DIR").is_some() {
+        return Ok(());
+    }
+    let dir = fallback_dir();
+    if create {
+        match DirBuilder::new().mode(0o700).create(&dir) {
+            Err(e) if e.kind() != io::ErrorKind::AlreadyExists => return Err(e),
+            _ => {}
+        }
+    }
+    // A symlink could point to anywhere, it's not followed
+    let metadata = std::fs::symlink_metadata(&dir)?;
+    let uid = unsafe { nix::libc::getuid() };
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+        let reason = format!("{} isn't a directory only this user can access", dir.display());
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied, reason));
+    }
+    Ok(())
+}
+
+fn connect() -> io::Result<UnixStream> {
+    check_socket_dir(false)?;
+    UnixStream::connect(socket_path())
+}
+
+/// Send a message to the daemon, one message per connection
+pub fn send(message: &Message) -> io::Result<()> {
+    let mut stream = connect()?;
+    stream.write_all(&encode(message))?;
+    stream.shutdown(std::net::Shutdown::Write)
+}
+
+/// Send a message to the daemon and wait for its reply, empty for the
+/// messages that have none. Not for the daemon's own windows, which the
+/// daemon only gets to after they're done.
+pub fn request(message: &Message) -> io::Result<String> {
+    let mut stream = connect()?;
+    stream.write_all(&encode(message))?;
+    stream.shutdown(std::net::Shutdown::Write)?;
+    stream.set_read_timeout(Some(REPLY_TIMEOUT))?;
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+    Ok(reply)
+}
+
+/// Where the daemon answers a message, the client stops waiting for a
+/// reply once it's dropped
+pub struct Reply(UnixStream);
+
+impl Reply {
+    pub fn send(mut self, text: &str) {
+        if let Err(e) = self.0.write_all(text.as_bytes()) {
+            tracing::warn!("Failed to reply on the control socket: {}", e);
+        }
+    }
+}
+
+/// The daemon's end of the control socket
+pub struct Server {
+    listener: UnixListener,
+    path: PathBuf,
+    // Connections whose message hasn't all arrived yet
+    clients: Vec<Client>,
+}
+
+impl Server {
+    pub fn bind() -> io::Result<Server> {
+        check_socket_dir(true)?;
+        let path = socket_path();
+        if path.exists() {
+            // A socket nobody answers on was left behind by a daemon that
+            // didn't get to clean up
+            if UnixStream::connect(&path).is_ok() {
+                return Err(io::Error::new(io::ErrorKind::AddrInUse, "a rush daemon is already running"));
+            }
+            std::fs::remove_file(&path)?;
+        }
+        let listener = UnixListener::bind(&path)?;
+        // Polled once per frame, it must not block the render loop
+        listener.set_nonblocking(true)?;
+        Ok(Server { listener, path, clients: Vec::new() })
+    }
+
+    /// The messages that finished arriving since the last call. Clients
+    /// are read without blocking, a slow one is picked up again next time.
+    pub fn poll(&mut self) -> Vec<(Message, Reply)> {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => match stream.set_nonblocking(true) {
+                    Ok(()) => self.clients.push(Client { stream, bytes: Vec::new(), connected: Instant::now() }),
+                    Err(e) => tracing::warn!("Failed to set up a control connection: {}", e),
+                },
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    tracing::warn!("Failed to accept a control connection: {}", e);
+                    break;
+                }
+            }
+        }
+
+        let mut messages = Vec::new();
+        for mut client in std::mem::take(&mut self.clients) {
+            match client.read() {
+                Ok(true) => match client.message() {
+                    Ok(Some(message)) => messages.push((message, Reply(client.stream))),
+                    // Checking whether the daemon is up connects without a message
+                    Ok(None) => {}
+                    Err(e) => tracing::warn!("Failed to read from the control socket: {}", e),
+                },
+                Ok(false) if client.connected.elapsed() < READ_TIMEOUT => self.clients.push(client),
+                Ok(false) => tracing::warn!("Dropped a control connection that didn't send its message in time"),
+                Err(e) => tracing::warn!("Failed to read from the control socket: {}", e),
+            }
+        }
+        messages
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// A connection to the daemon and what it sent so far
+struct Client {
+    stream: UnixStream,
+    bytes: Vec<u8>,
+    connected: Instant,
+}
+
+impl Client {
+    /// Read what has arrived, and return whether the client is done
+    /// sending. It shuts down its end once the message is written.
+    fn read(&mut self) -> io::Result<bool> {
+        let mut buf = [0; 4096];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => return Ok(true),
+                Ok(n) => self.bytes.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(false),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// The message that arrived, with the stream set up for the reply
+    fn message(&self) -> io::Result<Option<Message>> {
+        if self.bytes.is_empty() {
+            return Ok(None);
+        }
+        let message =
+            decode(&self.bytes).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid message"))?;
+        // A client that doesn't take its reply mustn't hold up the windows
+        self.stream.set_nonblocking(false)?;
+        self.stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        Ok(Some(message))
+    }
+}
+
+// A message is its name followed by its arguments, separated by NUL bytes
+// so text and paths can hold anything else
+
+fn encode(message: &Message) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    match message {
+        Message::NewWindow { cwd } => {
+            bytes.extend_from_slice(b"new-window");
+            if let Some(cwd) = cwd {
+                // Relative to the client, the daemon may be somewhere else
+                let cwd = std::env::current_dir().map(|dir| dir.join(cwd)).unwrap_or_else(|_| cwd.clone());
+                bytes.push(0);
+                bytes.extend_from_slice(cwd.as_os_str().as_bytes());
+            }
+        }
+        Message::SendText { text } => {
+            bytes.extend_from_slice(b"send-text\0");
+            bytes.extend_from_slice(text.as_bytes());
+        }
+        Message::Toggle => bytes.extend_from_slice(b"toggle"),
+        Message::Detach => bytes.extend_from_slice(b"detach"),
+        Message::Attach { session } => {
+            bytes.extend_from_slice(b"attach");
+            if let Some(session) = session {
+                bytes.extend_from_slice(format!("\0{}", session).as_bytes());
+            }
+        }
+        Message::Sessions => bytes.extend_from_slice(b"sessions"),
+        Message::Screenshot { file } => {
+            let file = std::env::current_dir().map(|dir| dir.join(file)).unwrap_or_else(|_| file.clone());
+            bytes.extend_from_slice(b"screenshot\0");
+            bytes.extend_from_slice(file.as_os_str().as_bytes());
+        }
+        Message::Theme { name } => {
+            bytes.extend_from_slice(b"theme\0");
+            bytes.extend_from_slice(name.as_bytes());
+        }
+        Message::Export { region, format, file } => {
+            let file = std::env::current_dir().map(|dir| dir.join(file)).unwrap_or_else(|_| file.clone());
+            bytes.extend_from_slice(format!("export\0{}\0{}\0", region.name(), format.name()).as_bytes());
+            bytes.extend_from_slice(file.as_os_str().as_bytes());
+        }
+    }
+    bytes
+}
+
+fn decode(bytes: &[u8]) -> Option<Message> {
+    let mut parts = bytes.splitn(2, |&b| b == 0);
+    let message = match (parts.next()?, parts.next()) {
+        (b"new-window", cwd) => Message::NewWindow {
+            cwd: cwd.map(|cwd| PathBuf::from(OsString::from_vec(cwd.to_vec()))),
+        },
+        (b"send-text", Some(text)) => Message::SendText {
+            text: String::from_utf8_lossy(text).into_owned(),
+        },
+        (b"toggle", None) => Message::Toggle,
+        (b"detach", None) => Message::Detach,
+        (b"attach", session) => Message::Attach {
+            session: match session {
+                Some(session) => Some(std::str::from_utf8(session).ok()?.parse().ok()?),
+                None => None,
+            },
+        },
+        (b"sessions", None) => Message::Sessions,
+        (b"theme", Some(name)) => Message::Theme { name: String::from_utf8_lossy(name).into_owned() },
+        (b"screenshot", Some(file)) => Message::Screenshot { file: PathBuf::from(OsString::from_vec(file.to_vec())) },
+        (b"export", Some(args)) => {
+            let mut args = args.splitn(3, |&b| b == 0);
+            let region = std::str::from_utf8(args.next()?).ok()?.parse().ok()?;
+            let format = std::str::from_utf8(args.next()?).ok()?.parse().ok()?;
+            let file = PathBuf::from(OsString::from_vec(args.next()?.to_vec()));
+            Message::Export { region, format, file }
+        }
+        _ => return None,
+    };
+    Some(message)
+}
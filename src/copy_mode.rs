@@ -0,0 +1,165 @@
+use crate::selection::{Point, Selection};
+use crate::term::Terminal;
+
+/// Keyboard driven copy mode: a cursor moved over the screen and the
+/// scrollback with vi keys, selecting text to yank to the clipboard
+#[derive(Default)]
+pub struct CopyMode {
+    pub active: bool,
+    pub cursor: Point,
+    // Also where text dragged over with the mouse is kept, outside of copy
+    // mode
+    pub selection: Option<Selection>,
+}
+
+impl CopyMode {
+    pub fn new() -> CopyMode {
+        CopyMode::default()
+    }
+
+    /// Enter copy mode with the cursor where the terminal's cursor is
+    pub fn enter(&mut self, term: &mut Terminal) {
+        self.active = true;
+        self.selection = None;
+        self.cursor = Point {
+            line: term.scrollback_len() + term.cursor.row,
+            col: term.cursor.col,
+        };
+        term.scroll_to_line(self.cursor.line);
+    }
+
+    /// Enter copy mode with `selection` made, ready to be yanked
+    pub fn select(&mut self, selection: Selection, term: &mut Terminal) {
+        self.active = true;
+        self.selection = Some(selection);
+        self.cursor = selection.end;
+        term.scroll_to_line(selection.end.line);
+        term.scroll_to_line(selection.anchor.line);
+    }
+
+    pub fn exit(&mut self) {
+        *self = CopyMode::default();
+    }
+
+    /// Handle a key event in copy mode. Returns the text to put on the
+    /// clipboard when something was yanked.
+    pub fn handle_key(
+        &mut self,
+        key: glfw::Key,
+        action: glfw::Action,
+        text: Option<char>,
+        term: &mut Terminal,
+    ) -> Option<String> {
+        if action == glfw::Action::Release {
+            return None;
+        }
+        let last_line = term.total_lines() - 1;
+        let last_col = term.cols - 1;
+        let cursor = &mut self.cursor;
+
+        match (key, text) {
+            (glfw::Key::Escape, _) | (_, Some('q')) => {
+                self.exit();
+                return None;
+            }
+            (glfw::Key::Left, _) | (_, Some('h')) => cursor.col = cursor.col.saturating_sub(1),
+            (glfw::Key::Down, _) | (_, Some('j')) => cursor.line = (cursor.line + 1).min(last_line),
+            (glfw::Key::Up, _) | (_, Some('k')) => cursor.line = cursor.line.saturating_sub(1),
+            (glfw::Key::Right, _) | (_, Some('l')) => cursor.col = (cursor.col + 1).min(last_col),
+            (glfw::Key::Home, _) | (_, Some('0')) => cursor.col = 0,
+            (glfw::Key::End, _) | (_, Some('$')) => cursor.col = last_occupied_col(term, cursor.line),
+            (_, Some('g')) => cursor.line = 0,
+            (_, Some('G')) => cursor.line = last_line,
+            (_, Some('w')) => *cursor = next_word(term, *cursor),
+            (_, Some('b')) => *cursor = previous_word(term, *cursor),
+            (_, Some('v')) => {
+                self.selection = match self.selection {
+                    Some(_) => None,
+                    None => Some(Selection::new(*cursor)),
+                };
+            }
+            // Switches between a selection of text and a block, like
+            // rectangle-toggle in tmux
+            (_, Some('r')) => match &mut self.selection {
+                Some(selection) => selection.block = !selection.block,
+                None => self.selection = Some(Selection { block: true, ..Selection::new(*cursor) }),
+            },
+            (_, Some('y')) => {
+                let text = self.selection?.text(term);
+                self.exit();
+                return Some(text);
+            }
+            _ => {}
+        }
+
+        if let Some(selection) = &mut self.selection {
+            selection.end = self.cursor;
+        }
+        term.scroll_to_line(self.cursor.line);
+        None
+    }
+}
+
+fn is_blank(term: &Terminal, point: Point) -> bool {
+    term.line(point.line).get(point.col).is_none_or(|cell| cell.c == ' ')
+}
+
+fn last_occupied_col(term: &Terminal, line: usize) -> usize {
+    let row = term.line(line);
+    row.iter().rposition(|cell| cell.c != ' ').unwrap_or(0)
+}
+
+fn step_forward(term: &Terminal, point: Point) -> Option<Point> {
+    if point.col + 1 < term.cols {
+        Some(Point { col: point.col + 1, ..point })
+    } else if point.line + 1 < term.total_lines() {
+        Some(Point { line: point.line + 1, col: 0 })
+    } else {
+        None
+    }
+}
+
+fn step_back(term: &Terminal, point: Point) -> Option<Point> {
+    if point.col > 0 {
+        Some(Point { col: point.col - 1, ..point })
+    } else if point.line > 0 {
+        Some(Point { line: point.line - 1, col: term.cols - 1 })
+    } else {
+        None
+    }
+}
+
+/// The start of the next word, words being anything between blanks
+fn next_word(term: &Terminal, mut point: Point) -> Point {
+    while !is_blank(term, point) {
+        match step_forward(term, point) {
+            Some(next) => point = next,
+            None => return point,
+        }
+    }
+    while is_blank(term, point) {
+        match step_forward(term, point) {
+            Some(next) => point = next,
+            None => return point,
+        }
+    }
+    point
+}
+
+/// The start of the word before the cursor, or of the one it is in
+fn previous_word(term: &Terminal, mut point: Point) -> Point {
+    point = match step_back(term, point) {
+        Some(previous) => previous,
+        None => return point,
+    };
+    while is_blank(term, point) {
+        match step_back(term, point) {
+            Some(previous) => point = previous,
+            None => return point,
+        }
+    }
+    while let Some(previous) = step_back(term, point).filter(|&p| !is_blank(term, p)) {
+        point = previous;
+    }
+    point
+}
@@ -0,0 +1,40 @@
+use crate::render::Cursor;
+use crate::term::{Row, Terminal};
+
+/// Which rows of the view changed since the last frame, found by comparing
+/// them with what that frame showed. The cursor moving damages the row it
+/// left and the row it went to.
+#[derive(Default)]
+pub struct Damage {
+    // The rows as the last frame showed them, empty before the first
+    last: Vec<Row>,
+    last_cursor: Option<Cursor>,
+}
+
+impl Damage {
+    pub fn new() -> Damage {
+        Damage::default()
+    }
+
+    /// The rows of the view that changed since the last call, top to
+    /// bottom. Every row is damaged on the first call and after a resize.
+    pub fn update(&mut self, term: &Terminal) -> Vec<usize> {
+        let resized = self.last.len() != term.rows;
+        if resized {
+            self.last = vec![Row::new(); term.rows];
+        }
+        let cursor = Cursor::of(term, true);
+        let mut damaged = Vec::new();
+        for (row, last) in self.last.iter_mut().enumerate() {
+            let current = term.visible_row(row);
+            let cursor_moved =
+                cursor != self.last_cursor && [cursor, self.last_cursor].iter().flatten().any(|c| c.row == row);
+            if resized || cursor_moved || last[..] != current[..] {
+                *last = current.into_owned();
+                damaged.push(row);
+            }
+        }
+        self.last_cursor = cursor;
+        damaged
+    }
+}
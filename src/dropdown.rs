@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::error::RushError;
+
+// How long sliding in or out takes
+const SLIDE_TIME: Duration = Duration::from_millis(150);
+
+/// The window `rush --toggle` shows and hides, hanging from the top of a
+/// monitor like the console of a game. Its settings are the `dropdown:`
+/// section of the config. Wayland doesn't let windows place themselves,
+/// there it only appears and disappears.
+pub struct Dropdown {
+    // Of the monitor's work area, from 0 to 1
+    height: f32,
+    // The monitor's name, the primary monitor when none is given
+    monitor: Option<String>,
+    animate: bool,
+    // Where the window hangs from and its size: x and y of the work area,
+    // width and height
+    area: (i32, i32, i32, i32),
+    // When the slide going on started and whether it is sliding in
+    slide: Option<(Instant, bool)>,
+}
+
+impl Dropdown {
+    pub fn from_config(config: &HashMap<String, String>, reduced_motion: bool) -> Result<Dropdown, RushError> {
+        let percent: f32 = crate::config_value(config, "dropdown.height", 40.0)?;
+        Ok(Dropdown {
+            height: (percent / 100.0).clamp(0.05, 1.0),
+            monitor: config.get("dropdown.monitor").cloned(),
+            animate: !reduced_motion,
+            area: (0, 0, 1, 1),
+            slide: None,
+        })
+    }
+
+    /// Slide the window in, or out when it's in front already
+    pub fn toggle(&mut self, glfw: &mut glfw::Glfw, window: &mut glfw::PWindow) {
+        let sliding_in = matches!(self.slide, Some((_, true)));
+        let shown = window.is_visible() && !matches!(self.slide, Some((_, false)));
+        if shown && (window.is_focused() || sliding_in) {
+            self.slide = Some((Instant::now(), false));
+        } else {
+            self.place(glfw, window);
+            window.show();
+            window.focus();
+            self.slide = Some((Instant::now(), true));
+        }
+        self.step(window);
+    }
+
+    /// Move the window along while it slides, and hide it once it's out
+    pub fn step(&mut self, window: &mut glfw::PWindow) {
+        let Some((start, sliding_in)) = self.slide else {
+            return;
+        };
+        let done = !self.animate || start.elapsed() >= SLIDE_TIME;
+        let t = if done { 1.0 } else { start.elapsed().as_secs_f32() / SLIDE_TIME.as_secs_f32() };
+        // Quick at first and slowing down at the end
+        let eased = 1.0 - (1.0 - t) * (1.0 - t);
+        let shown = if sliding_in { eased } else { 1.0 - eased };
+        let (x, y, _, height) = self.area;
+        window.set_pos(x, y - ((1.0 - shown) * height as f32).round() as i32);
+        if done {
+            self.slide = None;
+            if !sliding_in {
+                window.hide();
+            }
+        }
+    }
+
+    /// Fit the window to the top of the monitor, which may have changed
+    /// size or been unplugged since the last time
+    fn place(&mut self, glfw: &mut glfw::Glfw, window: &mut glfw::PWindow) {
+        let area = glfw.with_connected_monitors(|_, monitors| {
+            let named = self.monitor.as_ref().and_then(|name| {
+                let found = monitors.iter().find(|monitor| monitor.get_name().as_ref() == Some(name));
+                if found.is_none() {
+                    tracing::warn!("No monitor named {}, the dropdown opens on the primary one", name);
+                }
+                found
+            });
+            // GLFW lists the primary monitor first
+            let monitor = named.or(monitors.first())?;
+            Some(monitor.get_workarea())
+        });
+        let Some((x, y, width, work_height)) = area else {
+            return;
+        };
+        let height = (work_height as f32 * self.height).round().max(1.0) as i32;
+        self.area = (x, y, width, height);
+        window.set_decorated(false);
+        window.set_floating(true);
+        window.set_size(width, height);
+    }
+}
@@ -0,0 +1,62 @@
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A copy of everything the program wrote, byte for byte, so what it drew
+/// can be replayed with `cat` to reproduce rendering bugs. Annotations, when
+/// asked for, go to a file next to it: one line per sequence the terminal
+/// didn't understand, after the offset of the end of the read it was in.
+pub struct Dump {
+    pub path: PathBuf,
+    output: File,
+    annotations: Option<File>,
+    // Bytes written to the output so far
+    offset: u64,
+}
+
+impl Dump {
+    /// Start `rush-<UTC time>-<pid>.out` in `dir`, and `.log` for the
+    /// annotations
+    pub fn create(dir: &Path, annotate: bool) -> io::Result<Dump> {
+        let name = format!("rush-{}-{}", timestamp(SystemTime::now()), std::process::id());
+        let path = dir.join(&name).with_extension("out");
+        let output = File::create(&path)?;
+        let annotations = match annotate {
+            true => Some(File::create(dir.join(name).with_extension("log"))?),
+            false => None,
+        };
+        Ok(Dump { path, output, annotations, offset: 0 })
+    }
+
+    /// Append `bytes` read from the PTY, and what the terminal noted as
+    /// unhandled while processing them
+    pub fn write(&mut self, bytes: &[u8], unhandled: &[String]) -> io::Result<()> {
+        self.output.write_all(bytes)?;
+        self.offset += bytes.len() as u64;
+        if let Some(annotations) = &mut self.annotations {
+            for message in unhandled {
+                writeln!(annotations, "{}: {}", self.offset, message)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// 20261016-142501, sorts in the order the dumps were made
+pub fn timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, secs) = (secs / 86400, secs % 86400);
+    // Days since the epoch to a civil date, from Howard Hinnant's
+    // date algorithms, with years starting in March so leap days come last
+    let days = days as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}{:02}{:02}-{:02}{:02}{:02}", year, month, day, secs / 3600, secs / 60 % 60, secs % 60)
+}
@@ -0,0 +1,63 @@
+use std::path::PathBuf;
+
+use crate::asciicast::CastError;
+use crate::shader::ShaderError;
+
+/// Why rush couldn't start, phrased for the person running it
+#[derive(Debug, thiserror::Error)]
+pub enum RushError {
+    #[error("Could not read the config file {}: {source}", .path.display())]
+    ConfigRead {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("`{0}` is missing from the config")]
+    MissingConfigKey(&'static str),
+    #[error("Invalid value for `{key}` in the config: {value:?}, {reason}")]
+    InvalidConfigValue { key: String, value: String, reason: String },
+    // An invalid value, and where in the config file it is
+    #[error("{}:{line}:{column}: {source}", .path.display())]
+    ConfigDiagnostic {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        source: Box<RushError>,
+    },
+    #[error("Could not initialize FreeType, error code {0}")]
+    FreeTypeInit(i32),
+    #[error("Could not find the font family {family:?}: {reason}")]
+    FontNotFound { family: String, reason: String },
+    #[error("Could not load the font {path}, FreeType error code {code}")]
+    FontLoad { path: String, code: i32 },
+    #[error("Could not rasterize {character:?} from the font, FreeType error code {code}")]
+    GlyphLoad { character: char, code: i32 },
+    #[error(transparent)]
+    Shader(#[from] ShaderError),
+    #[error("Could not initialize GLFW: {0}")]
+    Glfw(String),
+    #[error("Could not record to {}: {source}", .path.display())]
+    Record {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Could not play {}: {source}", .path.display())]
+    Play { path: PathBuf, source: CastError },
+    #[error("Could not create the output dump in {}: {source}", .dir.display())]
+    Dump {
+        dir: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Could not listen on {}: {source}", .path.display())]
+    ControlSocket {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Could not create a window")]
+    WindowCreation,
+    #[error("This build of rush can't draw with wgpu, it was built without the wgpu feature")]
+    WgpuUnavailable,
+    #[error("Could not draw with wgpu: {0}")]
+    Wgpu(String),
+    #[error("Could not draw into the window without OpenGL: {0}")]
+    Software(String),
+}
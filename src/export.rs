@@ -0,0 +1,225 @@
+use crate::color::{Palette, Rgb};
+use crate::selection::Selection;
+use crate::term::{Cell, Color, Flags, Terminal};
+
+/// What part of the terminal to export
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Region {
+    // What the window shows, scrolled back or not
+    Screen,
+    // Every line kept, the screen and the scrollback above it
+    Scrollback,
+    Selection,
+    // Needs shell integration to know where it is
+    LastCommandOutput,
+}
+
+impl Region {
+    pub fn name(self) -> &'static str {
+        match self {
+            Region::Screen => "screen",
+            Region::Scrollback => "scrollback",
+            Region::Selection => "selection",
+            Region::LastCommandOutput => "last-output",
+        }
+    }
+}
+
+impl std::str::FromStr for Region {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "screen" => Ok(Region::Screen),
+            "scrollback" => Ok(Region::Scrollback),
+            "selection" => Ok(Region::Selection),
+            "last-output" => Ok(Region::LastCommandOutput),
+            _ => Err(format!("expected screen, scrollback, selection or last-output, not {:?}", s)),
+        }
+    }
+}
+
+/// How exported text keeps its colors and attributes
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Format {
+    // Without them, as it is copied
+    Text,
+    // As SGR escape sequences, for `cat` or `less -R` to show
+    Ansi,
+    // As a `<pre>` with the colors inline, for web pages and mail
+    Html,
+}
+
+impl Format {
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Text => "text",
+            Format::Ansi => "ansi",
+            Format::Html => "html",
+        }
+    }
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "ansi" => Ok(Format::Ansi),
+            "html" => Ok(Format::Html),
+            _ => Err(format!("expected text, ansi or html, not {:?}", s)),
+        }
+    }
+}
+
+/// The selected text in `format`, a line per line of the terminal
+pub fn export(term: &Terminal, selection: &Selection, format: Format) -> String {
+    match format {
+        Format::Text => selection.text(term),
+        Format::Ansi => {
+            let lines = runs(term, selection, sgr);
+            let lines = lines.into_iter().map(|runs| {
+                let mut line: String = runs.iter().map(|(style, text)| format!("{}{}", style, text)).collect();
+                // Leave the terminal it is shown in as it was
+                if runs.last().is_some_and(|(style, _)| style != PLAIN) {
+                    line.push_str(PLAIN);
+                }
+                line
+            });
+            lines.collect::<Vec<_>>().join("\n")
+        }
+        Format::Html => {
+            let palette = &term.palette;
+            let (fg, bg) = (hex(palette.foreground), hex(palette.background));
+            let mut html = format!("<pre style=\"color:{};background-color:{};font-family:monospace\">", fg, bg);
+            for (i, runs) in runs(term, selection, |cell| css(palette, cell)).into_iter().enumerate() {
+                if i > 0 {
+                    html.push('\n');
+                }
+                for (css, text) in runs {
+                    if css.is_empty() {
+                        html.push_str(&escape_html(&text));
+                    } else {
+                        html.push_str(&format!("<span style=\"{}\">{}</span>", css, escape_html(&text)));
+                    }
+                }
+            }
+            html.push_str("</pre>\n");
+            html
+        }
+    }
+}
+
+// Each selected line in runs of cells with the same style, as `style`
+// writes it, and their text. Blanks at the end of a line with nothing
+// drawn in them are left out, as when the text is copied.
+fn runs(term: &Terminal, selection: &Selection, style: impl Fn(&Cell) -> String) -> Vec<Vec<(String, String)>> {
+    let mut lines = Vec::new();
+    for cells in selection.lines(term) {
+        let used = cells.iter().rposition(|cell| !is_blank(cell)).map_or(0, |last| last + 1);
+        let mut runs: Vec<(String, String)> = Vec::new();
+        for cell in &cells[..used] {
+            if cell.flags.contains(Flags::WIDE_CHAR_SPACER) {
+                continue;
+            }
+            let cell_style = style(cell);
+            match runs.last_mut() {
+                Some((run_style, text)) if *run_style == cell_style => text.push(cell.c),
+                _ => runs.push((cell_style, cell.c.to_string())),
+            }
+            runs.last_mut().expect("Run just added to").1.extend(cell.zerowidth());
+        }
+        lines.push(runs);
+    }
+    lines
+}
+
+// A space nothing but the default background was drawn in
+fn is_blank(cell: &Cell) -> bool {
+    cell.c == ' '
+        && cell.zerowidth().is_empty()
+        && cell.bg == Color::Default
+        && !cell.flags.intersects(Flags::INVERSE | Flags::ALL_UNDERLINES | Flags::STRIKEOUT)
+}
+
+// What text without colors or attributes starts with
+const PLAIN: &str = "\x1b[0m";
+
+// The SGR sequence that gives text the style of `cell`
+fn sgr(cell: &Cell) -> String {
+    const ATTRIBUTES: [(Flags, &str); 10] = [
+        (Flags::BOLD, "1"),
+        (Flags::DIM, "2"),
+        (Flags::ITALIC, "3"),
+        (Flags::UNDERLINE, "4"),
+        (Flags::DOUBLE_UNDERLINE, "21"),
+        (Flags::UNDERCURL, "4:3"),
+        (Flags::BLINK, "5"),
+        (Flags::INVERSE, "7"),
+        (Flags::HIDDEN, "8"),
+        (Flags::STRIKEOUT, "9"),
+    ];
+    let mut params = vec!["0".to_string()];
+    for (flag, param) in ATTRIBUTES {
+        if cell.flags.contains(flag) {
+            params.push(param.to_string());
+        }
+    }
+    for (color, base) in [(cell.fg, 30), (cell.bg, 40)] {
+        match color {
+            Color::Default => {}
+            Color::Indexed(index @ 0..=7) => params.push((base + index as u16).to_string()),
+            Color::Indexed(index @ 8..=15) => params.push((base + 60 + index as u16 - 8).to_string()),
+            Color::Indexed(index) => params.push(format!("{};5;{}", base + 8, index)),
+            Color::Rgb(r, g, b) => params.push(format!("{};2;{};{};{}", base + 8, r, g, b)),
+        }
+    }
+    format!("\x1b[{}m", params.join(";"))
+}
+
+// The CSS that gives text the style of `cell`, empty for the style of the
+// whole `<pre>`
+fn css(palette: &Palette, cell: &Cell) -> String {
+    let (fg, bg) = palette.cell_colors(cell);
+    let mut css = Vec::new();
+    if cell.flags.contains(Flags::HIDDEN) {
+        css.push(format!("color:{}", hex(bg)));
+    } else if fg != palette.foreground {
+        css.push(format!("color:{}", hex(fg)));
+    }
+    if bg != palette.background {
+        css.push(format!("background-color:{}", hex(bg)));
+    }
+    if cell.flags.contains(Flags::BOLD) {
+        css.push("font-weight:bold".to_string());
+    }
+    if cell.flags.contains(Flags::ITALIC) {
+        css.push("font-style:italic".to_string());
+    }
+    match (cell.flags.intersects(Flags::ALL_UNDERLINES), cell.flags.contains(Flags::STRIKEOUT)) {
+        (true, true) => css.push("text-decoration:underline line-through".to_string()),
+        (true, false) => css.push("text-decoration:underline".to_string()),
+        (false, true) => css.push("text-decoration:line-through".to_string()),
+        (false, false) => {}
+    }
+    css.join(";")
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn hex((r, g, b): Rgb) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
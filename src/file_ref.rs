@@ -0,0 +1,80 @@
+use std::ops::Range;
+
+use regex::Regex;
+
+use crate::term::{Flags, Terminal};
+
+/// `path:line` and `path:line:column`, as compilers, linters and `grep -n`
+/// print them. The file name needs an extension, so times and ports don't
+/// pass for references.
+pub const FILE_REF_PATTERN: &str = r"(?x)
+    (?P<path>(?:(?:~|\.{1,2})?/)?(?:[\w.\-@+]+/)*[\w.\-@+]+\.\w+)
+    :(?P<line>[0-9]+)(?::(?P<column>[0-9]+))?
+";
+
+/// A reference to a line of a file, where it is on the screen
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FileRef {
+    pub path: String,
+    pub line: u32,
+    pub column: Option<u32>,
+    // The columns of the row it takes up
+    pub cols: Range<usize>,
+}
+
+impl FileRef {
+    /// The editor command `template` for this reference, a word per
+    /// argument with `{file}`, `{line}` and `{column}` filled in. `path` is
+    /// where the file is found, relative paths are relative to the shell.
+    pub fn command(&self, template: &str, path: &str) -> Vec<String> {
+        let line = self.line.to_string();
+        let column = self.column.unwrap_or(1).to_string();
+        let words = template.split_whitespace();
+        words.map(|word| word.replace("{file}", path).replace("{line}", &line).replace("{column}", &column)).collect()
+    }
+}
+
+/// Finds file references in rows of the screen
+pub struct FileRefs {
+    regex: Regex,
+}
+
+impl FileRefs {
+    pub fn new() -> FileRefs {
+        FileRefs { regex: Regex::new(FILE_REF_PATTERN).expect("Invalid file reference pattern") }
+    }
+
+    /// The reference in row `row` of the view covering column `col`
+    pub fn at(&self, term: &Terminal, row: usize, col: usize) -> Option<FileRef> {
+        let cells = term.visible_row(row);
+        let mut text = String::new();
+        // The column each byte of `text` came from
+        let mut byte_cols = Vec::new();
+        for (col, cell) in cells.iter().enumerate() {
+            if !cell.flags.contains(Flags::WIDE_CHAR_SPACER) {
+                text.push(cell.c);
+                byte_cols.resize(text.len(), col);
+            }
+        }
+        byte_cols.push(cells.len());
+        self.regex.captures_iter(&text).find_map(|captures| {
+            let found = captures.get(0)?;
+            let cols = byte_cols[found.start()]..byte_cols[found.end()];
+            if !cols.contains(&col) {
+                return None;
+            }
+            Some(FileRef {
+                path: captures["path"].to_string(),
+                line: captures["line"].parse().ok()?,
+                column: captures.name("column").and_then(|column| column.as_str().parse().ok()),
+                cols,
+            })
+        })
+    }
+}
+
+impl Default for FileRefs {
+    fn default() -> FileRefs {
+        FileRefs::new()
+    }
+}
@@ -0,0 +1,47 @@
+use std::process::Command;
+
+use crate::error::RushError;
+
+/// The file of the font fontconfig picks for `family` in `style` ("Bold",
+/// "Italic", "Bold Italic", ...). When the family isn't installed
+/// fontconfig substitutes another font, which is used with a warning.
+pub fn find(family: &str, style: Option<&str>) -> Result<String, RushError> {
+    let mut pattern = escape(family);
+    if let Some(style) = style {
+        pattern.push_str(":style=");
+        pattern.push_str(&escape(style));
+    }
+
+    let not_found = |reason: String| RushError::FontNotFound { family: family.to_string(), reason };
+    let output = Command::new("fc-match")
+        .arg("--format=%{family}\n%{file}\n")
+        .arg(&pattern)
+        .output()
+        .map_err(|e| not_found(format!("could not run fc-match: {}", e)))?;
+    if !output.status.success() {
+        return Err(not_found(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+    }
+
+    let output = String::from_utf8_lossy(&output.stdout);
+    let mut lines = output.lines();
+    let (Some(families), Some(file)) = (lines.next(), lines.next()) else {
+        return Err(not_found("fc-match found no font".to_string()));
+    };
+    // A font can have several names, in different languages
+    if !families.split(',').any(|name| name.eq_ignore_ascii_case(family)) {
+        tracing::warn!("The font family {:?} isn't installed, using {:?} instead", family, families);
+    }
+    Ok(file.to_string())
+}
+
+// Dashes, colons and commas separate the parts of a fontconfig pattern
+fn escape(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for c in name.chars() {
+        if matches!(c, '\\' | '-' | ':' | ',') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
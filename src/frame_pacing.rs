@@ -0,0 +1,60 @@
+use std::time::{Duration, Instant};
+
+// How long the loop sleeps waiting for events when nothing needs drawing.
+// Program output doesn't wake the loop up, so this is also how late it can
+// show up on an idle screen.
+const IDLE_WAIT: Duration = Duration::from_millis(4);
+
+/// Decides when a window draws: only after something changed, and not
+/// more often than `max_fps` frames a second
+pub struct FramePacing {
+    // Shortest time between two frames, none without a cap
+    min_interval: Option<Duration>,
+    last_frame: Option<Instant>,
+    redraw: bool,
+}
+
+impl FramePacing {
+    /// `max_fps` of 0 leaves the frame rate to vsync
+    pub fn new(max_fps: u32) -> FramePacing {
+        FramePacing {
+            min_interval: (max_fps > 0).then(|| Duration::from_secs(1) / max_fps),
+            last_frame: None,
+            redraw: true,
+        }
+    }
+
+    /// Something changed and the next frame should show it
+    pub fn request_redraw(&mut self) {
+        self.redraw = true;
+    }
+
+    /// Whether a frame should be drawn now
+    pub fn due(&self) -> bool {
+        self.redraw && self.until_allowed() == Duration::ZERO
+    }
+
+    /// A frame was drawn
+    pub fn drawn(&mut self) {
+        self.redraw = false;
+        self.last_frame = Some(Instant::now());
+    }
+
+    /// How long the loop can wait for events before this window needs it
+    /// again
+    pub fn wait(&self) -> Duration {
+        if self.redraw {
+            self.until_allowed()
+        } else {
+            IDLE_WAIT
+        }
+    }
+
+    // Until the cap lets the next frame be drawn
+    fn until_allowed(&self) -> Duration {
+        match (self.min_interval, self.last_frame) {
+            (Some(interval), Some(last)) => interval.saturating_sub(last.elapsed()),
+            _ => Duration::ZERO,
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use gl::types::*;
+use std::ffi::{c_void, CStr};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// A kind of context to ask GLFW for
+#[derive(Clone, Copy, Debug)]
+pub struct Profile {
+    pub es: bool,
+    pub major: u32,
+    pub minor: u32,
+}
+
+/// Tried in order until one gives a window: the 3.3 core profile the
+/// shaders are written for, then OpenGL ES 3.0 for drivers and devices
+/// that only do ES
+pub const PROFILES: [Profile; 2] = [
+    Profile { es: false, major: 3, minor: 3 },
+    Profile { es: true, major: 3, minor: 0 },
+];
+
+// Set once the driver reports errors through the debug callback, polling
+// glGetError is pointless then
+static DEBUG_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// Ask for `profile` in the windows GLFW creates next
+pub fn hint(glfw: &mut glfw::Glfw, profile: Profile) {
+    glfw.window_hint(glfw::WindowHint::ContextVersion(profile.major, profile.minor));
+    if profile.es {
+        glfw.window_hint(glfw::WindowHint::ClientApi(glfw::ClientApiHint::OpenGlEs));
+        glfw.window_hint(glfw::WindowHint::OpenGlProfile(glfw::OpenGlProfileHint::Any));
+        glfw.window_hint(glfw::WindowHint::OpenGlForwardCompat(false));
+    } else {
+        glfw.window_hint(glfw::WindowHint::ClientApi(glfw::ClientApiHint::OpenGl));
+        glfw.window_hint(glfw::WindowHint::OpenGlProfile(glfw::OpenGlProfileHint::Core));
+        // macOS only hands out core profiles that are forward compatible
+        glfw.window_hint(glfw::WindowHint::OpenGlForwardCompat(true));
+    }
+    // Debug contexts are slower, only worth it while developing
+    glfw.window_hint(glfw::WindowHint::OpenGlDebugContext(cfg!(debug_assertions)));
+}
+
+/// Whether the current context is OpenGL ES, the functions must be loaded
+pub fn is_gles() -> bool {
+    unsafe {
+        let version = gl::GetString(gl::VERSION);
+        !version.is_null() && CStr::from_ptr(version as *const _).to_bytes().starts_with(b"OpenGL ES")
+    }
+}
+
+/// The shaders are written against GLSL 3.30 core. ES 3.0 understands
+/// them once told its own version and a default float precision.
+pub fn adapt_shader(source: String) -> String {
+    if !is_gles() {
+        return source;
+    }
+    let body = match source.strip_prefix("#version 330 core") {
+        Some(body) => body,
+        None => &source,
+    };
+    format!("#version 300 es\nprecision highp float;{}", body)
+}
+
+/// Report GL errors through KHR_debug as they happen, when the driver
+/// supports it. Returns whether it does.
+pub fn enable_debug_output() -> bool {
+    if !gl::DebugMessageCallback::is_loaded() {
+        return false;
+    }
+    unsafe {
+        gl::Enable(gl::DEBUG_OUTPUT);
+        // Report from within the call that caused it, so a breakpoint in
+        // the callback shows where
+        gl::Enable(gl::DEBUG_OUTPUT_SYNCHRONOUS);
+        gl::DebugMessageCallback(Some(debug_callback), std::ptr::null());
+    }
+    DEBUG_OUTPUT.store(true, Ordering::Relaxed);
+    true
+}
+
+/// Whether errors are already reported by the debug callback
+pub fn debug_output() -> bool {
+    DEBUG_OUTPUT.load(Ordering::Relaxed)
+}
+
+extern "system" fn debug_callback(
+    source: GLenum,
+    kind: GLenum,
+    id: GLuint,
+    severity: GLenum,
+    length: GLsizei,
+    message: *const GLchar,
+    _user_param: *mut c_void,
+) {
+    // Drivers are chatty about buffer placement and the like
+    if severity == gl::DEBUG_SEVERITY_NOTIFICATION {
+        return;
+    }
+    let message = unsafe { std::slice::from_raw_parts(message as *const u8, length.max(0) as usize) };
+    let message = format!(
+        "GL {} ({} severity, from {}, id {}): {}",
+        kind_name(kind),
+        severity_name(severity),
+        source_name(source),
+        id,
+        String::from_utf8_lossy(message).trim_end()
+    );
+    if kind == gl::DEBUG_TYPE_ERROR || severity == gl::DEBUG_SEVERITY_HIGH {
+        tracing::error!("{}", message);
+    } else {
+        tracing::warn!("{}", message);
+    }
+}
+
+fn kind_name(kind: GLenum) -> &'static str {
+    match kind {
+        gl::DEBUG_TYPE_ERROR => "error",
+        gl::DEBUG_TYPE_DEPRECATED_BEHAVIOR => "deprecated behavior",
+        gl::DEBUG_TYPE_UNDEFINED_BEHAVIOR => "undefined behavior",
+        gl::DEBUG_TYPE_PORTABILITY => "portability issue",
+        gl::DEBUG_TYPE_PERFORMANCE => "performance issue",
+        _ => "message",
+    }
+}
+
+fn severity_name(severity: GLenum) -> &'static str {
+    match severity {
+        gl::DEBUG_SEVERITY_HIGH => "high",
+        gl::DEBUG_SEVERITY_MEDIUM => "medium",
+        _ => "low",
+    }
+}
+
+fn source_name(source: GLenum) -> &'static str {
+    match source {
+        gl::DEBUG_SOURCE_API => "the API",
+        gl::DEBUG_SOURCE_WINDOW_SYSTEM => "the window system",
+        gl::DEBUG_SOURCE_SHADER_COMPILER => "the shader compiler",
+        gl::DEBUG_SOURCE_APPLICATION => "the application",
+        _ => "elsewhere",
+    }
+}
@@ -0,0 +1,50 @@
+use std::collections::HashMap;
+
+// Characters below this are stored by index, they're what most cells hold
+const DIRECT: usize = 128;
+
+/// Rasterized glyphs by the character they're for. ASCII is indexed
+/// directly so the lookups done for every cell of every frame don't hash,
+/// the long tail goes in a map.
+pub struct GlyphStore<T> {
+    ascii: [Option<T>; DIRECT],
+    others: HashMap<char, T>,
+}
+
+impl<T> GlyphStore<T> {
+    pub fn new() -> GlyphStore<T> {
+        GlyphStore {
+            ascii: std::array::from_fn(|_| None),
+            others: HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, c: char) -> Option<&T> {
+        match self.ascii.get(c as usize) {
+            Some(glyph) => glyph.as_ref(),
+            None => self.others.get(&c),
+        }
+    }
+
+    /// The glyph for `c`, or the one for `fallback` when there is none
+    pub fn get_or(&self, c: char, fallback: char) -> Option<&T> {
+        self.get(c).or_else(|| self.get(fallback))
+    }
+
+    pub fn insert(&mut self, c: char, glyph: T) {
+        match self.ascii.get_mut(c as usize) {
+            Some(slot) => *slot = Some(glyph),
+            None => {
+                self.others.insert(c, glyph);
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ascii.iter().flatten().count() + self.others.len()
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.ascii.iter().flatten().chain(self.others.values())
+    }
+}
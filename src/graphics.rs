@@ -0,0 +1,409 @@
+use std::collections::HashMap;
+use std::io::Read;
+
+use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+use base64::Engine;
+
+// Payloads are base64, chunks may or may not be padded
+const BASE64: GeneralPurpose = GeneralPurpose::new(
+    &base64::alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+// Decoded pixel data kept for all images together, the oldest images are
+// dropped past this
+const STORAGE_LIMIT: usize = 320 << 20;
+// Largest single image accepted, in pixels a side
+const MAX_IMAGE_SIDE: u32 = 10_000;
+
+/// An image transmitted by the application, decoded to RGBA
+pub struct Image {
+    pub id: u32,
+    // The number the application asked for with `I`, if any
+    pub number: u32,
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+    // Changes every time the image is (re)transmitted, so the renderer
+    // knows its texture is stale
+    pub generation: u64,
+}
+
+/// An image shown on the grid. `line` counts from the oldest line in the
+/// scrollback like the rest of the terminal, so images scroll with the text.
+pub struct Placement {
+    pub image_id: u32,
+    pub placement_id: u32,
+    pub line: usize,
+    pub col: usize,
+    // Cells covered
+    pub cols: usize,
+    pub rows: usize,
+    // Pixel offset of the image within its first cell
+    pub offset: (u32, u32),
+    // The part of the image shown: x, y, width, height in image pixels
+    pub source: (u32, u32, u32, u32),
+    // Size drawn on screen in pixels
+    pub width: u32,
+    pub height: u32,
+    // Negative values go under the text
+    pub z: i32,
+}
+
+impl Placement {
+    pub fn intersects(&self, line: usize, col: usize) -> bool {
+        (self.line..self.line + self.rows).contains(&line) && (self.col..self.col + self.cols).contains(&col)
+    }
+}
+
+/// The keys of an APC G command, named after what they mean rather than
+/// the single letters the protocol uses
+#[derive(Clone)]
+pub struct Command {
+    pub action: u8,
+    pub quiet: u32,
+    pub format: u32,
+    pub medium: u8,
+    pub compressed: bool,
+    // Pixel size of raw RGB(A) data
+    pub data_width: u32,
+    pub data_height: u32,
+    // Byte range to read when transmitting from a file
+    pub data_size: usize,
+    pub data_offset: usize,
+    pub image_id: u32,
+    pub image_number: u32,
+    pub placement_id: u32,
+    pub source_x: u32,
+    pub source_y: u32,
+    pub source_width: u32,
+    pub source_height: u32,
+    pub cell_x_offset: u32,
+    pub cell_y_offset: u32,
+    pub cols: u32,
+    pub rows: u32,
+    pub z: i32,
+    pub move_cursor: bool,
+    pub more: bool,
+    pub delete: u8,
+}
+
+impl Default for Command {
+    fn default() -> Command {
+        Command {
+            action: b't',
+            quiet: 0,
+            format: 32,
+            medium: b'd',
+            compressed: false,
+            data_width: 0,
+            data_height: 0,
+            data_size: 0,
+            data_offset: 0,
+            image_id: 0,
+            image_number: 0,
+            placement_id: 0,
+            source_x: 0,
+            source_y: 0,
+            source_width: 0,
+            source_height: 0,
+            cell_x_offset: 0,
+            cell_y_offset: 0,
+            cols: 0,
+            rows: 0,
+            z: 0,
+            move_cursor: true,
+            more: false,
+            delete: b'a',
+        }
+    }
+}
+
+impl Command {
+    /// Parse the `key=value,...` control data of a command
+    pub fn parse(control: &[u8]) -> Result<Command, Error> {
+        let mut command = Command::default();
+        for pair in control.split(|&b| b == b',').filter(|pair| !pair.is_empty()) {
+            let [key, b'=', value @ ..] = pair else {
+                return Err(Error::invalid("Malformed control data"));
+            };
+            let number = || -> Result<u32, Error> {
+                std::str::from_utf8(value)
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .ok_or_else(|| Error::invalid(format!("Invalid value for {}", *key as char)))
+            };
+            let letter = || value.first().copied().ok_or_else(|| Error::invalid("Empty value"));
+            match key {
+                b'a' => command.action = letter()?,
+                b'q' => command.quiet = number()?,
+                b'f' => command.format = number()?,
+                b't' => command.medium = letter()?,
+                b'o' => command.compressed = letter()? == b'z',
+                b's' => command.data_width = number()?,
+                b'v' => command.data_height = number()?,
+                b'S' => command.data_size = number()? as usize,
+                b'O' => command.data_offset = number()? as usize,
+                b'i' => command.image_id = number()?,
+                b'I' => command.image_number = number()?,
+                b'p' => command.placement_id = number()?,
+                b'x' => command.source_x = number()?,
+                b'y' => command.source_y = number()?,
+                b'w' => command.source_width = number()?,
+                b'h' => command.source_height = number()?,
+                b'X' => command.cell_x_offset = number()?,
+                b'Y' => command.cell_y_offset = number()?,
+                b'c' => command.cols = number()?,
+                b'r' => command.rows = number()?,
+                b'z' => {
+                    command.z = std::str::from_utf8(value)
+                        .ok()
+                        .and_then(|v| v.parse().ok())
+                        .ok_or_else(|| Error::invalid("Invalid z-index"))?
+                }
+                b'C' => command.move_cursor = number()? == 0,
+                b'm' => command.more = number()? == 1,
+                b'd' => command.delete = letter()?,
+                // Animation and unicode placeholder keys aren't supported
+                // and don't change how the rest is understood
+                _ => {}
+            }
+        }
+        Ok(command)
+    }
+
+    /// The reply to this command, if the application wants one
+    pub fn response(&self, result: &Result<(), Error>) -> Option<Vec<u8>> {
+        if self.image_id == 0 && self.image_number == 0 {
+            return None;
+        }
+        let message = match result {
+            Ok(()) if self.quiet >= 1 => return None,
+            Ok(()) => "OK".to_string(),
+            Err(_) if self.quiet >= 2 => return None,
+            Err(e) => format!("{}:{}", e.code, e.message),
+        };
+
+        let mut keys = format!("i={}", self.image_id);
+        if self.image_number != 0 {
+            keys.push_str(&format!(",I={}", self.image_number));
+        }
+        if self.placement_id != 0 {
+            keys.push_str(&format!(",p={}", self.placement_id));
+        }
+        Some(format!("\x1b_G{};{}\x1b\\", keys, message).into_bytes())
+    }
+}
+
+/// A failed command, reported back with a POSIX style error code
+#[derive(Debug)]
+pub struct Error {
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl Error {
+    pub fn new(code: &'static str, message: impl Into<String>) -> Error {
+        Error { code, message: message.into() }
+    }
+
+    pub fn invalid(message: impl Into<String>) -> Error {
+        Error::new("EINVAL", message)
+    }
+}
+
+/// Images and where they are shown
+#[derive(Default)]
+pub struct Graphics {
+    pub images: HashMap<u32, Image>,
+    pub placements: Vec<Placement>,
+    // A command whose payload is being sent in chunks, and the base64
+    // received so far
+    loading: Option<(Command, Vec<u8>)>,
+    next_generation: u64,
+}
+
+impl Graphics {
+    /// Collect the chunks of a transmission. Returns the command and its
+    /// whole payload once the last chunk arrived.
+    pub fn receive(&mut self, command: Command, payload: &[u8]) -> Option<(Command, Vec<u8>)> {
+        // Chunks after the first only carry `m`, the rest of the keys come
+        // from the first one
+        let (first, mut data) = match self.loading.take() {
+            Some((first, data)) => (first, data),
+            None => (command.clone(), Vec::new()),
+        };
+        data.extend_from_slice(payload);
+        if command.more {
+            self.loading = Some((first, data));
+            None
+        } else {
+            Some((first, data))
+        }
+    }
+
+    /// An id for an image the application only gave a number, or no id at
+    /// all
+    pub fn free_id(&self) -> u32 {
+        (1..=u32::MAX).rev().find(|id| !self.images.contains_key(id)).unwrap_or(u32::MAX)
+    }
+
+    /// The id of the newest image with the given number
+    pub fn id_for_number(&self, number: u32) -> Option<u32> {
+        self.images
+            .values()
+            .filter(|image| image.number == number)
+            .max_by_key(|image| image.generation)
+            .map(|image| image.id)
+    }
+
+    pub fn add_image(&mut self, id: u32, number: u32, width: u32, height: u32, rgba: Vec<u8>) {
+        self.next_generation += 1;
+        let image = Image { id, number, width, height, rgba, generation: self.next_generation };
+        // Retransmitting an image replaces it, and what showed it
+        if self.images.insert(id, image).is_some() {
+            self.placements.retain(|p| p.image_id != id);
+        }
+
+        while self.images.values().map(|image| image.rgba.len()).sum::<usize>() > STORAGE_LIMIT {
+            let oldest = self.images.values().min_by_key(|image| image.generation).map(|image| image.id);
+            match oldest {
+                Some(oldest) if oldest != id => {
+                    self.images.remove(&oldest);
+                    self.placements.retain(|p| p.image_id != oldest);
+                }
+                _ => break,
+            }
+        }
+    }
+
+    pub fn add_placement(&mut self, placement: Placement) {
+        // A placement id given again moves the existing placement
+        if placement.placement_id != 0 {
+            self.placements
+                .retain(|p| p.image_id != placement.image_id || p.placement_id != placement.placement_id);
+        }
+        self.placements.push(placement);
+    }
+
+    /// Remove the placements matching `pred`. With `free`, images left
+    /// without a placement are dropped as well, along with `also_free`.
+    pub fn delete(&mut self, free: bool, also_free: Option<u32>, pred: impl Fn(&Placement) -> bool) {
+        let mut affected: Vec<u32> = also_free.into_iter().collect();
+        self.placements.retain(|p| {
+            if pred(p) {
+                affected.push(p.image_id);
+                false
+            } else {
+                true
+            }
+        });
+        if free {
+            for id in affected {
+                if !self.placements.iter().any(|p| p.image_id == id) {
+                    self.images.remove(&id);
+                }
+            }
+        }
+    }
+
+    /// The oldest `count` scrollback lines were dropped, which shifts every
+    /// line index down
+    pub fn lines_dropped(&mut self, count: usize) {
+        self.placements.retain_mut(|p| {
+            if p.line < count {
+                return false;
+            }
+            p.line -= count;
+            true
+        });
+    }
+
+    pub fn clear(&mut self) {
+        *self = Graphics::default();
+    }
+}
+
+/// Get the pixel data a command carries, from the payload itself or from
+/// the file it names
+pub fn load_data(command: &Command, payload: &[u8]) -> Result<Vec<u8>, Error> {
+    let payload = BASE64
+        .decode(payload)
+        .map_err(|e| Error::invalid(format!("Invalid base64: {}", e)))?;
+
+    let data = match command.medium {
+        b'd' => payload,
+        b'f' | b't' => {
+            let path = String::from_utf8(payload).map_err(|_| Error::invalid("Invalid file name"))?;
+            let data = read_file(&path, command.data_offset, command.data_size)?;
+            // Temporary files are removed once read, only those clearly
+            // meant for this so nothing else can be deleted this way
+            if command.medium == b't' && path.contains("tty-graphics-protocol") {
+                let _ = std::fs::remove_file(&path);
+            }
+            data
+        }
+        _ => return Err(Error::invalid("Unsupported transmission medium")),
+    };
+
+    if !command.compressed {
+        return Ok(data);
+    }
+    let mut inflated = Vec::new();
+    flate2::read::ZlibDecoder::new(&data[..])
+        .read_to_end(&mut inflated)
+        .map_err(|e| Error::invalid(format!("Invalid zlib data: {}", e)))?;
+    Ok(inflated)
+}
+
+fn read_file(path: &str, offset: usize, size: usize) -> Result<Vec<u8>, Error> {
+    let data = std::fs::read(path).map_err(|e| Error::new("EBADF", format!("{}: {}", path, e)))?;
+    let start = offset.min(data.len());
+    let end = if size == 0 { data.len() } else { (start + size).min(data.len()) };
+    Ok(data[start..end].to_vec())
+}
+
+/// Decode pixel data to RGBA, returning its width and height as well
+pub fn decode(command: &Command, data: &[u8]) -> Result<(u32, u32, Vec<u8>), Error> {
+    let (width, height, rgba) = match command.format {
+        100 => decode_png(data)?,
+        24 | 32 => {
+            let (width, height) = (command.data_width, command.data_height);
+            let channels = command.format as usize / 8;
+            let expected = width as usize * height as usize * channels;
+            if width == 0 || height == 0 || data.len() < expected {
+                return Err(Error::new("ENODATA", "Not enough pixel data"));
+            }
+            let rgba = if channels == 4 {
+                data[..expected].to_vec()
+            } else {
+                data[..expected].chunks_exact(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect()
+            };
+            (width, height, rgba)
+        }
+        format => return Err(Error::invalid(format!("Unknown format {}", format))),
+    };
+    if width > MAX_IMAGE_SIDE || height > MAX_IMAGE_SIDE {
+        return Err(Error::invalid("Image too large"));
+    }
+    Ok((width, height, rgba))
+}
+
+fn decode_png(data: &[u8]) -> Result<(u32, u32, Vec<u8>), Error> {
+    let invalid = |e: png::DecodingError| Error::new("EBADPNG", e.to_string());
+    let mut decoder = png::Decoder::new(std::io::Cursor::new(data));
+    decoder.set_transformations(png::Transformations::normalize_to_color8() | png::Transformations::ALPHA);
+    let mut reader = decoder.read_info().map_err(invalid)?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).map_err(invalid)?;
+    buf.truncate(info.buffer_size());
+
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => buf,
+        png::ColorType::GrayscaleAlpha => buf.chunks_exact(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+        png::ColorType::Rgb => buf.chunks_exact(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect(),
+        png::ColorType::Grayscale => buf.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+        png::ColorType::Indexed => return Err(Error::new("EBADPNG", "Unexpanded palette")),
+    };
+    Ok((info.width, info.height, rgba))
+}
@@ -0,0 +1,71 @@
+use std::time::{Duration, Instant};
+
+use crate::pty::{self, Pty};
+use crate::term::Terminal;
+
+// How long to sleep when the program has nothing to say
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// A terminal with a program running in it and no window: its output goes
+/// through the parser into the grid, which can be looked at in `term`
+pub struct Headless {
+    pub term: Terminal,
+    pty: Pty,
+    exited: bool,
+}
+
+impl Headless {
+    pub fn spawn(command: &pty::Command, rows: usize, cols: usize) -> Headless {
+        let term = Terminal::new(rows, cols);
+        let pty = Pty::spawn(command, term.rows, term.cols, term.cell_size);
+        Headless { term, pty, exited: false }
+    }
+
+    /// Type `bytes` into the program
+    pub fn write(&mut self, bytes: &[u8]) {
+        self.pty.write(bytes);
+    }
+
+    /// Process whatever the program has written so far and answer its
+    /// queries. Returns whether there was anything.
+    pub fn pump(&mut self) -> bool {
+        let _span = tracing::trace_span!("read_pty").entered();
+        let mut buffer = [0; 65536];
+        let mut read_any = false;
+        self.pty.flush();
+        while !self.exited {
+            match self.pty.read(&mut buffer) {
+                Some(0) => self.exited = true,
+                Some(n) => {
+                    self.term.process(&buffer[..n]);
+                    read_any = true;
+                }
+                None => break,
+            }
+        }
+        let responses = self.term.take_responses();
+        if !responses.is_empty() {
+            self.pty.write(&responses);
+        }
+        read_any
+    }
+
+    /// Keep processing output until the program exits, or `timeout` has
+    /// passed. Returns whether it exited.
+    pub fn run(&mut self, timeout: Option<Duration>) -> bool {
+        let start = Instant::now();
+        while !self.exited {
+            if timeout.is_some_and(|timeout| start.elapsed() >= timeout) {
+                break;
+            }
+            if !self.pump() {
+                std::thread::sleep(POLL_INTERVAL);
+            }
+        }
+        self.exited
+    }
+
+    pub fn exited(&self) -> bool {
+        self.exited
+    }
+}
@@ -0,0 +1,142 @@
+use regex::{Regex, RegexSet};
+
+use crate::color::{self, Rgb};
+use crate::term::{Cell, Color, Flags, Row, Terminal};
+
+/// How text matching a rule is drawn, on top of its own style
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Style {
+    pub fg: Option<Rgb>,
+    pub bg: Option<Rgb>,
+    pub flags: Flags,
+}
+
+impl std::str::FromStr for Style {
+    type Err = String;
+
+    /// Words separated by spaces: a `#rrggbb` color, `on` and a background
+    /// color, and attributes
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut style = Style::default();
+        let mut words = s.split_whitespace();
+        while let Some(word) = words.next() {
+            let parse_color = |word: &str| color::parse_hex(word).ok_or(format!("{:?} isn't a #rrggbb color", word));
+            match word {
+                "on" => style.bg = Some(parse_color(words.next().unwrap_or(""))?),
+                "bold" => style.flags.insert(Flags::BOLD),
+                "italic" => style.flags.insert(Flags::ITALIC),
+                "dim" => style.flags.insert(Flags::DIM),
+                "underline" => style.flags.insert(Flags::UNDERLINE),
+                "undercurl" => style.flags.insert(Flags::UNDERCURL),
+                "strikeout" => style.flags.insert(Flags::STRIKEOUT),
+                "inverse" => style.flags.insert(Flags::INVERSE),
+                _ if word.starts_with('#') => style.fg = Some(parse_color(word)?),
+                _ => return Err(format!("unknown style {:?}", word)),
+            }
+        }
+        Ok(style)
+    }
+}
+
+/// Text to restyle wherever it is on the screen
+#[derive(Clone, Debug)]
+pub struct Rule {
+    pub regex: Regex,
+    pub style: Style,
+}
+
+impl std::str::FromStr for Rule {
+    type Err = String;
+
+    /// The style, then the regex between slashes:
+    /// `#ff5555 bold /\b(ERROR|FATAL)\b/`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (style, rest) = s.split_once('/').ok_or("the regex is missing, it goes between slashes")?;
+        let pattern = rest.strip_suffix('/').ok_or("the regex doesn't end with a slash")?;
+        let regex = Regex::new(pattern).map_err(|e| e.to_string())?;
+        Ok(Rule { regex, style: style.parse()? })
+    }
+}
+
+/// Restyles the text of the rows in view that matches the rules, for the
+/// frame being drawn only. Nothing is done to output as it arrives, so
+/// however much of it there is the cost stays that of a screenful a frame.
+pub struct Highlighter {
+    rules: Vec<Rule>,
+    // Tells which rules match a row in one pass, most rows match none
+    any: RegexSet,
+}
+
+/// Restyled copies of the rows in view that have matches, drawn in place
+/// of the terminal's own
+#[derive(Default, Debug)]
+pub struct Restyled(Vec<(usize, Row)>);
+
+impl Restyled {
+    /// The row at `index` in view as it is drawn, if a rule restyled it
+    pub fn row(&self, index: usize) -> Option<&[Cell]> {
+        self.0.iter().find(|(restyled, _)| *restyled == index).map(|(_, row)| &row[..])
+    }
+}
+
+impl Highlighter {
+    /// Later rules win where matches overlap
+    pub fn new(rules: Vec<Rule>) -> Highlighter {
+        let any = RegexSet::new(rules.iter().map(|rule| rule.regex.as_str())).expect("Rules are valid regexes");
+        Highlighter { rules, any }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Restyle the matches in view. The terminal is left as it is, the
+    /// rows with matches are copied.
+    pub fn apply(&self, term: &Terminal) -> Restyled {
+        let mut restyled = Vec::new();
+        if self.is_empty() {
+            return Restyled(restyled);
+        }
+        let mut text = String::new();
+        // The column each character of `text` starts at, by byte offset
+        let mut columns = Vec::new();
+        for index in 0..term.rows {
+            let row = term.visible_row(index);
+            text.clear();
+            columns.clear();
+            for (col, cell) in row.iter().enumerate() {
+                if !cell.flags.contains(Flags::WIDE_CHAR_SPACER) {
+                    columns.push((text.len(), col));
+                    text.push(cell.c);
+                    text.extend(cell.zerowidth());
+                }
+            }
+            let matching = self.any.matches(&text);
+            if !matching.matched_any() {
+                continue;
+            }
+            let mut row = row.into_owned();
+            for rule in matching.iter().map(|i| &self.rules[i]) {
+                for found in rule.regex.find_iter(&text).filter(|found| !found.is_empty()) {
+                    let column = |offset: usize| match columns.binary_search_by_key(&offset, |&(start, _)| start) {
+                        Ok(i) => columns[i].1,
+                        // Inside a character's combining marks
+                        Err(i) => columns.get(i).map_or(row.len(), |&(_, col)| col),
+                    };
+                    let (start, end) = (column(found.start()), column(found.end()));
+                    for cell in &mut row[start..end] {
+                        if let Some((r, g, b)) = rule.style.fg {
+                            cell.fg = Color::Rgb(r, g, b);
+                        }
+                        if let Some((r, g, b)) = rule.style.bg {
+                            cell.bg = Color::Rgb(r, g, b);
+                        }
+                        cell.flags.insert(rule.style.flags);
+                    }
+                }
+            }
+            restyled.push((index, row));
+        }
+        Restyled(restyled)
+    }
+}
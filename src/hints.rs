@@ -0,0 +1,159 @@
+use regex::Regex;
+
+use crate::selection::{Point, TRAILING_PUNCTUATION, URL_AND_PATH_PATTERN};
+use crate::span::SpanKind;
+use crate::term::{Flags, Terminal};
+
+// Letters used for labels, the easiest to reach first
+const ALPHABET: &[u8] = b"asdfghjklqwertyuiopzxcvbnm";
+
+/// Something on the screen that can be picked with a hint
+pub struct Hint {
+    pub label: String,
+    pub line: usize,
+    pub start: usize,
+    pub target: String,
+}
+
+pub enum HintAction {
+    Open(String),
+    Copy(String),
+}
+
+/// Kitty style hints: every URL, path and hyperlink on the screen gets a
+/// short label, typing it opens the target, or copies it when typed with
+/// Shift held
+pub struct Hints {
+    pub active: bool,
+    pub hints: Vec<Hint>,
+    // The part of a label typed so far
+    pub typed: String,
+    regex: Regex,
+}
+
+impl Hints {
+    pub fn new() -> Hints {
+        Hints {
+            active: false,
+            hints: Vec::new(),
+            typed: String::new(),
+            regex: Regex::new(URL_AND_PATH_PATTERN).expect("Invalid hints pattern"),
+        }
+    }
+
+    pub fn start(&mut self, term: &Terminal) {
+        self.hints.clear();
+        self.typed.clear();
+
+        let mut targets = Vec::new();
+        let first_line = term.first_visible_line();
+        for row in 0..term.rows {
+            let line = first_line + row;
+            let cells = term.visible_row(row);
+
+            // OSC 8 links, one target per run of cells with the same link
+            let mut col = 0;
+            while col < cells.len() {
+                let Some(span) = term.hyperlink_span(Point { line, col }) else {
+                    col += 1;
+                    continue;
+                };
+                if let SpanKind::Hyperlink(link) = span.kind {
+                    targets.push((line, col, link.uri.clone()));
+                }
+                col = span.end.col;
+            }
+
+            let mut text = String::new();
+            // The column each byte of `text` came from
+            let mut byte_cols = Vec::new();
+            for (col, cell) in cells.iter().enumerate() {
+                if !cell.flags.contains(Flags::WIDE_CHAR_SPACER) && cell.hyperlink().is_none() {
+                    text.push(cell.c);
+                    byte_cols.resize(text.len(), col);
+                }
+            }
+            for found in self.regex.find_iter(&text) {
+                let target = found.as_str().trim_end_matches(TRAILING_PUNCTUATION);
+                if !target.is_empty() {
+                    targets.push((line, byte_cols[found.start()], target.to_string()));
+                }
+            }
+        }
+
+        if targets.is_empty() {
+            return;
+        }
+
+        // Every label is the same length so none is a prefix of another.
+        // The targets nearest the bottom, where the prompt is, get the
+        // shortest to type.
+        let mut length = 1;
+        while ALPHABET.len().pow(length) < targets.len() {
+            length += 1;
+        }
+        targets.sort_by_key(|&(line, col, _)| std::cmp::Reverse((line, col)));
+        for (i, (line, start, target)) in targets.into_iter().enumerate() {
+            self.hints.push(Hint { label: label(i, length), line, start, target });
+        }
+        self.active = true;
+    }
+
+    pub fn exit(&mut self) {
+        self.active = false;
+        self.hints.clear();
+        self.typed.clear();
+    }
+
+    /// Handle a key event while hints are shown. Returns what to do once a
+    /// whole label has been typed.
+    pub fn handle_key(&mut self, key: glfw::Key, action: glfw::Action, text: Option<char>) -> Option<HintAction> {
+        if action == glfw::Action::Release {
+            return None;
+        }
+        match key {
+            glfw::Key::Escape => self.exit(),
+            glfw::Key::Backspace => {
+                self.typed.pop();
+            }
+            _ => {
+                let c = text.filter(char::is_ascii_alphabetic)?;
+                self.typed.push(c.to_ascii_lowercase());
+                if !self.hints.iter().any(|hint| hint.label.starts_with(&self.typed)) {
+                    self.typed.pop();
+                    return None;
+                }
+
+                let hint = self.hints.iter().find(|hint| hint.label == self.typed)?;
+                let target = hint.target.clone();
+                self.exit();
+                return Some(if c.is_ascii_uppercase() {
+                    HintAction::Copy(target)
+                } else {
+                    HintAction::Open(target)
+                });
+            }
+        }
+        None
+    }
+
+    /// The hints still matching what has been typed
+    pub fn visible(&self) -> impl Iterator<Item = &Hint> {
+        self.hints.iter().filter(|hint| hint.label.starts_with(&self.typed))
+    }
+}
+
+impl Default for Hints {
+    fn default() -> Hints {
+        Hints::new()
+    }
+}
+
+fn label(mut index: usize, length: u32) -> String {
+    let mut label = Vec::new();
+    for _ in 0..length {
+        label.push(ALPHABET[index % ALPHABET.len()]);
+        index /= ALPHABET.len();
+    }
+    String::from_utf8(label).unwrap()
+}
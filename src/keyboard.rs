@@ -0,0 +1,282 @@
+// Key event encoding, both the legacy xterm style and the kitty progressive
+// enhancement protocol (https://sw.kovidgoyal.net/kitty/keyboard-protocol/)
+
+// Progressive enhancement flags, as set by `CSI > flags u`
+pub const DISAMBIGUATE: u8 = 1;
+pub const REPORT_EVENT_TYPES: u8 = 2;
+pub const REPORT_ALTERNATE_KEYS: u8 = 4;
+pub const REPORT_ALL_KEYS: u8 = 8;
+pub const REPORT_TEXT: u8 = 16;
+pub const ALL_FLAGS: u8 = 31;
+
+/// What the application has asked the keys to send
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Modes {
+    /// The keyboard enhancement flags
+    pub flags: u8,
+    /// DECCKM, the cursor keys send SS3 sequences
+    pub app_cursor: bool,
+    /// DECKPAM, the keypad sends SS3 sequences instead of its characters
+    pub app_keypad: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum KeyCode {
+    // Keys identified by a unicode codepoint, encoded as `CSI code ; mods u`
+    Codepoint(u32),
+    // Keys with a legacy encoding of `CSI number ; mods final`
+    Functional(u32, char),
+}
+
+const ESCAPE: u32 = 27;
+const ENTER: u32 = 13;
+const TAB: u32 = 9;
+const BACKSPACE: u32 = 127;
+
+fn key_code(key: glfw::Key, text: Option<char>, flags: u8) -> Option<KeyCode> {
+    use glfw::Key;
+    // Keypad keys are told apart from the main keys only when every key is
+    // reported
+    if let Some((_, number)) = keypad_key(key).filter(|_| flags & REPORT_ALL_KEYS != 0) {
+        return Some(KeyCode::Codepoint(number));
+    }
+    let code = match key {
+        Key::Escape => KeyCode::Codepoint(ESCAPE),
+        Key::Enter | Key::KpEnter => KeyCode::Codepoint(ENTER),
+        Key::Tab => KeyCode::Codepoint(TAB),
+        Key::Backspace => KeyCode::Codepoint(BACKSPACE),
+        Key::Insert => KeyCode::Functional(2, '~'),
+        Key::Delete => KeyCode::Functional(3, '~'),
+        Key::PageUp => KeyCode::Functional(5, '~'),
+        Key::PageDown => KeyCode::Functional(6, '~'),
+        Key::Up => KeyCode::Functional(1, 'A'),
+        Key::Down => KeyCode::Functional(1, 'B'),
+        Key::Right => KeyCode::Functional(1, 'C'),
+        Key::Left => KeyCode::Functional(1, 'D'),
+        Key::Home => KeyCode::Functional(1, 'H'),
+        Key::End => KeyCode::Functional(1, 'F'),
+        Key::F1 => KeyCode::Functional(1, 'P'),
+        Key::F2 => KeyCode::Functional(1, 'Q'),
+        Key::F3 => KeyCode::Functional(1, 'R'),
+        Key::F4 => KeyCode::Functional(1, 'S'),
+        Key::F5 => KeyCode::Functional(15, '~'),
+        Key::F6 => KeyCode::Functional(17, '~'),
+        Key::F7 => KeyCode::Functional(18, '~'),
+        Key::F8 => KeyCode::Functional(19, '~'),
+        Key::F9 => KeyCode::Functional(20, '~'),
+        Key::F10 => KeyCode::Functional(21, '~'),
+        Key::F11 => KeyCode::Functional(23, '~'),
+        Key::F12 => KeyCode::Functional(24, '~'),
+        // Text keys are identified by their unshifted character
+        _ => {
+            let c = text?;
+            KeyCode::Codepoint(c.to_lowercase().next().unwrap_or(c) as u32)
+        }
+    };
+    Some(code)
+}
+
+/// The SS3 final character a keypad key sends in application keypad mode
+/// and its number in the kitty protocol
+fn keypad_key(key: glfw::Key) -> Option<(char, u32)> {
+    use glfw::Key;
+    let key = match key {
+        Key::Kp0 => ('p', 57399),
+        Key::Kp1 => ('q', 57400),
+        Key::Kp2 => ('r', 57401),
+        Key::Kp3 => ('s', 57402),
+        Key::Kp4 => ('t', 57403),
+        Key::Kp5 => ('u', 57404),
+        Key::Kp6 => ('v', 57405),
+        Key::Kp7 => ('w', 57406),
+        Key::Kp8 => ('x', 57407),
+        Key::Kp9 => ('y', 57408),
+        Key::KpDecimal => ('n', 57409),
+        Key::KpDivide => ('o', 57410),
+        Key::KpMultiply => ('j', 57411),
+        Key::KpSubtract => ('m', 57412),
+        Key::KpAdd => ('k', 57413),
+        Key::KpEnter => ('M', 57414),
+        Key::KpEqual => ('X', 57415),
+        _ => return None,
+    };
+    Some(key)
+}
+
+/// The modifier parameter, 1 + the modifier bits
+fn modifier_param(modifiers: glfw::Modifiers, include_locks: bool) -> u32 {
+    let mut bits = 0;
+    if modifiers.contains(glfw::Modifiers::Shift) {
+        bits |= 1;
+    }
+    if modifiers.contains(glfw::Modifiers::Alt) {
+        bits |= 2;
+    }
+    if modifiers.contains(glfw::Modifiers::Control) {
+        bits |= 4;
+    }
+    if modifiers.contains(glfw::Modifiers::Super) {
+        bits |= 8;
+    }
+    if include_locks {
+        if modifiers.contains(glfw::Modifiers::CapsLock) {
+            bits |= 64;
+        }
+        if modifiers.contains(glfw::Modifiers::NumLock) {
+            bits |= 128;
+        }
+    }
+    bits + 1
+}
+
+/// Encode a key event for the application. `text` is the character the
+/// key produces with the current modifiers, if any. `modes` are the
+/// keyboard enhancement flags and key modes the application has enabled.
+pub fn encode(
+    key: glfw::Key,
+    action: glfw::Action,
+    modifiers: glfw::Modifiers,
+    text: Option<char>,
+    modes: Modes,
+) -> Option<Vec<u8>> {
+    let flags = modes.flags;
+    let code = key_code(key, text, flags)?;
+    let release = action == glfw::Action::Release;
+    if release && flags & REPORT_EVENT_TYPES == 0 {
+        return None;
+    }
+
+    let ctrl_alt_super = modifiers.intersects(
+        glfw::Modifiers::Control | glfw::Modifiers::Alt | glfw::Modifiers::Super,
+    );
+    let legacy_control_key = matches!(code, KeyCode::Codepoint(ENTER | TAB | BACKSPACE));
+
+    // Enter, Tab and Backspace releases would confuse programs that only
+    // opted into disambiguation, so they are only sent when every key is
+    if release && legacy_control_key && flags & REPORT_ALL_KEYS == 0 {
+        return None;
+    }
+
+    let use_csi_u = match code {
+        _ if flags & REPORT_ALL_KEYS != 0 => true,
+        _ if release => true,
+        KeyCode::Functional(..) => false,
+        KeyCode::Codepoint(ESCAPE) => flags & DISAMBIGUATE != 0,
+        KeyCode::Codepoint(_) if legacy_control_key => {
+            flags & DISAMBIGUATE != 0 && modifier_param(modifiers, false) > 1
+        }
+        KeyCode::Codepoint(_) => flags & DISAMBIGUATE != 0 && ctrl_alt_super,
+    };
+
+    if !use_csi_u {
+        // Modified keypad keys keep sending what they would otherwise
+        match keypad_key(key) {
+            Some((ss3, _)) if modes.app_keypad && modifier_param(modifiers, false) == 1 => {
+                return Some(format!("\x1bO{}", ss3).into_bytes());
+            }
+            _ => return Some(encode_legacy(code, modifiers, text, modes.app_cursor)),
+        }
+    }
+
+    let event_type = match action {
+        _ if flags & REPORT_EVENT_TYPES == 0 => 1,
+        glfw::Action::Press => 1,
+        glfw::Action::Repeat => 2,
+        glfw::Action::Release => 3,
+    };
+    let mods = modifier_param(modifiers, true);
+
+    let (number, final_char) = match code {
+        KeyCode::Codepoint(c) => (c, 'u'),
+        // `CSI R` is also a cursor position report, so the protocol gives
+        // F3 a number of its own
+        KeyCode::Functional(1, 'R') => (13, '~'),
+        KeyCode::Functional(number, final_char) => (number, final_char),
+    };
+
+    let mut seq = format!("\x1b[{}", number);
+    if let (KeyCode::Codepoint(c), Some(shifted)) = (code, text) {
+        if flags & REPORT_ALTERNATE_KEYS != 0
+            && modifiers.contains(glfw::Modifiers::Shift)
+            && shifted as u32 != c
+        {
+            seq.push_str(&format!(":{}", shifted as u32));
+        }
+    }
+
+    let report_text = flags & REPORT_TEXT != 0 && !release && !ctrl_alt_super && !legacy_control_key;
+    let text = text.filter(|c| report_text && !c.is_control());
+    if mods > 1 || event_type > 1 || text.is_some() {
+        seq.push_str(&format!(";{}", mods));
+        if event_type > 1 {
+            seq.push_str(&format!(":{}", event_type));
+        }
+    }
+    if let Some(text) = text {
+        seq.push_str(&format!(";{}", text as u32));
+    }
+    // `CSI 1 A` is written as just `CSI A`
+    if number == 1 && final_char != 'u' && seq == "\x1b[1" {
+        seq.truncate(2);
+    }
+    seq.push(final_char);
+
+    Some(seq.into_bytes())
+}
+
+fn encode_legacy(code: KeyCode, modifiers: glfw::Modifiers, text: Option<char>, app_cursor: bool) -> Vec<u8> {
+    let shift = modifiers.contains(glfw::Modifiers::Shift);
+    let alt = modifiers.contains(glfw::Modifiers::Alt);
+    let ctrl = modifiers.contains(glfw::Modifiers::Control);
+    let mods = modifier_param(modifiers, false);
+
+    let mut bytes = Vec::new();
+    match code {
+        KeyCode::Functional(number, final_char) => {
+            if mods > 1 {
+                bytes.extend(format!("\x1b[{};{}{}", number, mods, final_char).bytes());
+            } else if final_char == '~' {
+                bytes.extend(format!("\x1b[{}~", number).bytes());
+            } else if matches!(final_char, 'P'..='S') || (app_cursor && matches!(final_char, 'A'..='D' | 'H' | 'F')) {
+                // F1 to F4 are sent as SS3 sequences, and so are the
+                // cursor keys in application cursor mode
+                bytes.extend(format!("\x1bO{}", final_char).bytes());
+            } else {
+                bytes.extend(format!("\x1b[{}", final_char).bytes());
+            }
+            return bytes;
+        }
+        KeyCode::Codepoint(TAB) if shift => return b"\x1b[Z".to_vec(),
+        KeyCode::Codepoint(BACKSPACE) if ctrl => bytes.push(0x08),
+        KeyCode::Codepoint(c @ (ESCAPE | ENTER | TAB | BACKSPACE)) => bytes.push(c as u8),
+        KeyCode::Codepoint(c) => {
+            let c = text.unwrap_or(char::from_u32(c).unwrap_or(' '));
+            match control_character(c) {
+                Some(control) if ctrl => bytes.push(control),
+                _ => bytes.extend(c.encode_utf8(&mut [0; 4]).as_bytes()),
+            }
+        }
+    }
+
+    // Alt sends the key prefixed with ESC
+    if alt {
+        bytes.insert(0, 0x1b);
+    }
+    bytes
+}
+
+/// The C0 control character Ctrl+`c` produces
+fn control_character(c: char) -> Option<u8> {
+    match c {
+        'a'..='z' => Some(c as u8 - b'a' + 1),
+        'A'..='Z' => Some(c as u8 - b'A' + 1),
+        ' ' | '@' | '2' => Some(0x00),
+        '[' | '3' => Some(0x1b),
+        '\\' | '4' => Some(0x1c),
+        ']' | '5' => Some(0x1d),
+        '^' | '6' => Some(0x1e),
+        '_' | '/' | '7' => Some(0x1f),
+        '8' => Some(0x7f),
+        _ => None,
+    }
+}
@@ -0,0 +1,26 @@
+// The terminal core: the escape sequence parser, the cell grid it drives
+// and the PTY the program runs on. None of it needs a window or a GL
+// context, so it runs headless and can be tested on its own.
+
+pub mod asciicast;
+pub mod bindings;
+pub mod canvas;
+pub mod color;
+pub mod damage;
+pub mod export;
+pub mod file_ref;
+pub mod graphics;
+pub mod headless;
+pub mod highlight;
+pub mod keyboard;
+pub mod parser;
+pub mod policy;
+pub mod pty;
+pub mod render;
+pub mod selection;
+pub mod shell_integration;
+pub mod span;
+pub mod spill;
+pub mod term;
+pub mod terminfo;
+pub mod window_geometry;
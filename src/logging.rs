@@ -0,0 +1,28 @@
+use std::fs::OpenOptions;
+use std::path::Path;
+use std::sync::Mutex;
+
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::fmt::format::FmtSpan;
+
+/// Send what is logged at `level` and above to stderr, or appended to
+/// `file`. At trace level how long every span took is logged as it closes,
+/// that's PTY reads, parsing and each render pass.
+pub fn init(level: LevelFilter, file: Option<&Path>) {
+    let span_events = if level == LevelFilter::TRACE { FmtSpan::CLOSE } else { FmtSpan::NONE };
+    let builder = tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_span_events(span_events)
+        .with_target(false);
+    let Some(path) = file else {
+        builder.with_writer(std::io::stderr).init();
+        return;
+    };
+    match OpenOptions::new().create(true).append(true).open(path) {
+        Ok(file) => builder.with_ansi(false).with_writer(Mutex::new(file)).init(),
+        Err(e) => {
+            builder.with_writer(std::io::stderr).init();
+            tracing::error!("Could not open the log file {}, logging to stderr: {}", path.display(), e);
+        }
+    }
+}
@@ -1,30 +1,232 @@
 #![allow(dead_code)]
 
+mod box_drawing;
+mod cli;
+mod control;
+mod dropdown;
+mod error;
+mod font_lookup;
+mod frame_pacing;
+mod gl_context;
+mod glyph_store;
+mod copy_mode;
+mod dump;
+mod hints;
+mod logging;
+mod mouse;
+mod open;
+mod paste;
+mod perf_hud;
+mod primary;
+mod scrollbar;
+mod search;
 mod shader;
+mod shader_watch;
+mod shaping;
+mod signals;
+mod smooth_scroll;
+mod software_renderer;
+mod theme;
 mod yaml_parser;
+#[cfg(feature = "wgpu")]
+mod wgpu_renderer;
+
+// The terminal core lives in the library so it can run without a window
+use rush::{asciicast, color, export, graphics, highlight, keyboard, pty, render, selection, span, term, terminfo};
+use rush::window_geometry::WindowGeometry;
 
 extern crate freetype;
 extern crate gl;
-extern crate gl_loader;
 extern crate glfw;
 extern crate nalgebra_glm;
 
+use asciicast::{Cast, Player, Recorder};
+use rush::bindings::{Action, Bindings};
+use clap::Parser;
+use cli::{Backend, Cli, Message, Subcommand};
+use copy_mode::CopyMode;
+use dropdown::Dropdown;
+use dump::Dump;
+use error::RushError;
 use freetype::freetype as ft;
+use hints::{HintAction, Hints};
+use rush::damage::Damage;
+use rush::headless::Headless;
+use rush::file_ref::{FileRef, FileRefs};
+use rush::highlight::Highlighter;
+use rush::policy::{Feature, Permission, Policy};
+use mouse::MouseState;
+use nix::unistd::Pid;
+use frame_pacing::FramePacing;
+use perf_hud::PerfHud;
+use primary::PrimarySelection;
+use pty::Pty;
+use scrollbar::ScrollbarFade;
+use search::Search;
 use shader::Shader;
+use shader_watch::ShaderWatcher;
+use shaping::Shaper;
+use signals::{SignalEvent, SignalEvents};
+use theme::{ColorSchemeWatcher, Themes};
+use smooth_scroll::SmoothScroll;
+use software_renderer::SoftwareRenderer;
 use glfw::Context;
-use std::cell::RefCell;
-use std::collections::HashMap;
+use glyph_store::GlyphStore;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::ffi::CString;
 use std::os::raw::c_void;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::time::{Duration, Instant};
+use render::Cursor;
+use term::{Hyperlink, Terminal};
+use tracing::{debug, error, info, trace_span, warn};
+
+/// How glyph edges are smoothed, `text_antialiasing` in the config
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Antialiasing {
+    Grayscale,
+    // Each of the red, green and blue subpixels of an LCD gets its own
+    // coverage, sharper on standard DPI monitors with RGB subpixels
+    Subpixel,
+}
+
+impl std::str::FromStr for Antialiasing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "grayscale" => Ok(Antialiasing::Grayscale),
+            "subpixel" => Ok(Antialiasing::Subpixel),
+            _ => Err(format!("expected grayscale or subpixel, not {:?}", s)),
+        }
+    }
+}
+
+/// Who draws the title bar and borders on Wayland, `wayland_decorations`
+/// in the config
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum WaylandDecorations {
+    // libdecor draws them to fit the desktop, where it is installed
+    Client,
+    // The compositor draws them, where it supports xdg-decoration
+    Server,
+}
+
+impl std::str::FromStr for WaylandDecorations {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "client" => Ok(WaylandDecorations::Client),
+            "server" => Ok(WaylandDecorations::Server),
+            _ => Err(format!("expected client or server, not {:?}", s)),
+        }
+    }
+}
+
+/// What happens to a window once its shell exits, `exit_behavior` in the
+/// config
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum ExitBehavior {
+    Close,
+    // The window stays open with how the shell ended written under its
+    // output, until Enter or Escape is pressed
+    Hold,
+    // Hold only when the shell failed or was killed
+    HoldOnFailure,
+}
+
+impl std::str::FromStr for ExitBehavior {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "close" => Ok(ExitBehavior::Close),
+            "hold" => Ok(ExitBehavior::Hold),
+            "hold-on-failure" => Ok(ExitBehavior::HoldOnFailure),
+            _ => Err(format!("expected close, hold or hold-on-failure, not {:?}", s)),
+        }
+    }
+}
+
+/// How strongly outlines are fitted to the pixel grid, `font_hinting` in
+/// the config
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Hinting {
+    None,
+    // Vertically only, keeps the shapes of the font
+    Slight,
+    Full,
+}
+
+impl std::str::FromStr for Hinting {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Hinting::None),
+            "slight" => Ok(Hinting::Slight),
+            "full" => Ok(Hinting::Full),
+            _ => Err(format!("expected none, slight or full, not {:?}", s)),
+        }
+    }
+}
+
+/// How FreeType loads and renders glyphs
+#[derive(Clone, Copy, Debug)]
+struct GlyphOptions {
+    antialiasing: Antialiasing,
+    hinting: Hinting,
+    // Use FreeType's autohinter even for fonts that have their own hints
+    autohint: bool,
+    // Measure cells with the hinted advance, rounded to whole pixels,
+    // rather than the font's own
+    integer_metrics: bool,
+}
+
+impl GlyphOptions {
+    fn from_config(config: &HashMap<String, String>) -> Result<GlyphOptions, RushError> {
+        Ok(GlyphOptions {
+            antialiasing: config_value(config, "text_antialiasing", Antialiasing::Grayscale)?,
+            hinting: config_value(config, "font_hinting", Hinting::Full)?,
+            autohint: config_value(config, "font_autohint", false)?,
+            integer_metrics: config_value(config, "font_integer_metrics", true)?,
+        })
+    }
+
+    fn load_flags(self) -> i32 {
+        let target = match (self.hinting, self.antialiasing) {
+            (Hinting::None, _) => ft::FT_LOAD_NO_HINTING as i32,
+            (Hinting::Slight, _) => (ft::FT_Render_Mode::FT_RENDER_MODE_LIGHT as i32) << 16,
+            (Hinting::Full, Antialiasing::Grayscale) => (ft::FT_Render_Mode::FT_RENDER_MODE_NORMAL as i32) << 16,
+            (Hinting::Full, Antialiasing::Subpixel) => (ft::FT_Render_Mode::FT_RENDER_MODE_LCD as i32) << 16,
+        };
+        let autohint = if self.autohint { ft::FT_LOAD_FORCE_AUTOHINT as i32 } else { 0 };
+        target | autohint
+    }
+
+    fn render_mode(self) -> ft::FT_Render_Mode {
+        match self.antialiasing {
+            Antialiasing::Grayscale => ft::FT_Render_Mode::FT_RENDER_MODE_NORMAL,
+            Antialiasing::Subpixel => ft::FT_Render_Mode::FT_RENDER_MODE_LCD,
+        }
+    }
 
-use nix::pty::forkpty;
-use nix::pty::ForkptyResult;
-use std::os::unix::io::RawFd;
-use std::process::Command;
-use nix::unistd::read;
+    /// The width of a cell for the glyph just loaded, in whole pixels
+    fn advance(self, glyph: &ft::FT_GlyphSlotRec) -> i64 {
+        if self.integer_metrics {
+            glyph.advance.x >> 6
+        } else {
+            // 16.16 fixed point, not rounded by hinting
+            (glyph.linearHoriAdvance + 0xffff) >> 16
+        }
+    }
+}
 
+#[derive(Clone, Copy, Default)]
 struct Character {
     texture_id: u32,
     size: (i32, i32),
@@ -45,228 +247,715 @@ impl std::fmt::Display for Grid {
     }
 }
 
+/// Spacing around and inside cells from the config, in pixels before
+/// scaling for the monitor
+#[derive(Clone, Copy)]
+struct CellGeometry {
+    padding_x: f32,
+    padding_y: f32,
+    // Multiplies the font's line height
+    line_height: f32,
+    // Added to the width of every cell, may be negative
+    letter_spacing: f32,
+}
+
+impl CellGeometry {
+    fn from_config(config: &HashMap<String, String>) -> Result<CellGeometry, RushError> {
+        Ok(CellGeometry {
+            padding_x: config_value(config, "padding_x", 0.0)?,
+            padding_y: config_value(config, "padding_y", 0.0)?,
+            line_height: config_value(config, "line_height", 1.0)?,
+            letter_spacing: config_value(config, "letter_spacing", 0.0)?,
+        })
+    }
+}
+
 struct WindowState {
+    // Framebuffer size in pixels, which is larger than the window size on
+    // HiDPI screens
     width: f32,
     height: f32,
+    // Framebuffer pixels per window coordinate, mouse positions come in
+    // window coordinates
+    pixel_ratio: f32,
+    // The content scale of the monitor the window is on, fonts are
+    // rasterized this much larger
+    scale: f32,
+    geometry: CellGeometry,
     grid: Grid,
-    // Keep one big buffer of the entire screen contents
-    // Cells for each character need not be kept in memory
-    // They can be derived from their location in the string
-    buffer: String,
-    // The index at which to begin rendering the buffer,
-    // if the buffer is larger than the number of cells,
-    // the first n buffer elements should not be rendered,
-    // where n is the difference between the buffer size and
-    // the size of the grid
-    // For example,
-    // if we have a 10x10 grid, that allows 100 characters.
-    // if our buffer has 110 characters, only the last 100 characters
-    // should be rendered. So n here is 10, 110 - 100
-    display_offset: usize,
-    next_cell: (usize, usize),
 }
 
 impl WindowState {
-    fn new(width: f32, height: f32, char_dimensions: CharacterDimensions) -> WindowState {
-        let cell_width = char_dimensions.width as f32;
-        let cell_height = char_dimensions.height as f32;
-        WindowState {
+    fn new(width: f32, height: f32, scale: f32, geometry: CellGeometry, char_dimensions: CharacterDimensions) -> WindowState {
+        let mut ws = WindowState {
             width,
             height,
+            pixel_ratio: 1.0,
+            scale,
+            geometry,
             grid: Grid {
-                cell_width,
-                cell_height,
-                rows: height as usize / cell_height as usize,
-                cols: width as usize / cell_width as usize,
+                cell_width: 1.0,
+                cell_height: 1.0,
+                rows: 1,
+                cols: 1,
             },
-            buffer: String::new(),
-            display_offset: 0,
-            next_cell: (0, 0),
-        }
+        };
+        ws.set_cell_size(char_dimensions);
+        ws
     }
 
-    fn advance(&mut self) {
-        if self.next_cell.1 == self.grid.cols - 1 {
-            self.next_cell = (self.next_cell.0 + 1, 0);
-        } else {
-            self.next_cell = (self.next_cell.0, self.next_cell.1 + 1);
-        }
+    fn set_cell_size(&mut self, char_dimensions: CharacterDimensions) {
+        let geometry = &self.geometry;
+        let width = char_dimensions.width as f32 + geometry.letter_spacing * self.scale;
+        let height = char_dimensions.height as f32 * geometry.line_height;
+        self.grid.cell_width = width.round().max(1.0);
+        self.grid.cell_height = height.round().max(1.0);
+        self.update_size(self.width, self.height);
+    }
 
+    fn update_size(&mut self, width: f32, height: f32) {
+        self.width = width;
+        self.height = height;
+        let (padding_x, padding_y) = self.padding();
+        self.grid.rows = ((self.height - 2.0 * padding_y) / self.grid.cell_height).max(1.0) as usize;
+        self.grid.cols = ((self.width - 2.0 * padding_x) / self.grid.cell_width).max(1.0) as usize;
     }
 
-    fn backspace(&mut self) {
-        self.buffer.pop();
-        if self.display_offset > 0 && self.next_cell.1 == 0 {
-            self.display_offset -= self.grid.cols;
-        }
+    /// The framebuffer size that fits `cols` by `rows` cells and the
+    /// padding around them
+    fn size_for(&self, cols: usize, rows: usize) -> (f32, f32) {
+        let (padding_x, padding_y) = self.padding();
+        (cols as f32 * self.grid.cell_width + 2.0 * padding_x, rows as f32 * self.grid.cell_height + 2.0 * padding_y)
     }
 
-    fn scroll(&mut self) {
-        // just make the buffer begin rendering at 
-        // ncols * rows_scrolled
-        // So if we scroll down 2 rows,
-        // the buffer should begin rendering at buffer[2 * ncols]
-        // idk how to explain why this works with words but it works in my head
-        // so thats good enough, it's because opengl doesn't have a concept of scrolling,
-        // we have to replicate scrolling in terms of what the screen contents should be
-        // after we scroll n rows, if we scroll 1 row, the last row of the screen should be blank,
-        // and the top row of the screen should disappear.
-        self.display_offset += self.grid.cols;
+    fn padding(&self) -> (f32, f32) {
+        (self.geometry.padding_x * self.scale, self.geometry.padding_y * self.scale)
     }
 
-    fn reset_cell(&mut self) {
-        self.next_cell = (0, 0);
+    /// Where the grid is drawn in the framebuffer: x, y from the bottom
+    /// left corner like OpenGL wants it, width and height
+    fn grid_viewport(&self) -> (i32, i32, i32, i32) {
+        let (padding_x, padding_y) = self.padding();
+        let width = self.grid.cols as f32 * self.grid.cell_width;
+        let height = self.grid.rows as f32 * self.grid.cell_height;
+        let y = self.height - padding_y - height;
+        (padding_x as i32, y as i32, width as i32, height as i32)
     }
 
-    fn update_size(&mut self, width: f32, height: f32) {
-        self.width = width;
-        self.height = height;
-        self.grid.rows = (self.height / self.grid.cell_height) as usize;
-        self.grid.cols = (self.width / self.grid.cell_width) as usize;
+    /// Whether a point in window coordinates is on the grid rather than
+    /// in the padding around it
+    fn in_grid(&self, x: f64, y: f64) -> bool {
+        let (padding_x, padding_y) = self.padding();
+        let x = x as f32 * self.pixel_ratio - padding_x;
+        let y = y as f32 * self.pixel_ratio - padding_y;
+        let width = self.grid.cols as f32 * self.grid.cell_width;
+        let height = self.grid.rows as f32 * self.grid.cell_height;
+        (0.0..width).contains(&x) && (0.0..height).contains(&y)
+    }
+
+    /// How far a point in window coordinates is above the grid, negative,
+    /// or below it, in pixels; 0 beside the grid's rows
+    fn vertical_overshoot(&self, y: f64) -> f32 {
+        let (_, padding_y) = self.padding();
+        let y = y as f32 * self.pixel_ratio - padding_y;
+        let height = self.grid.rows as f32 * self.grid.cell_height;
+        if y < 0.0 {
+            y
+        } else {
+            (y - height).max(0.0)
+        }
     }
 
-    fn get_next_cell(&self) -> (usize, usize) {
-        self.next_cell
+    /// The grid cell under a point in window coordinates
+    fn cell_at(&self, x: f64, y: f64) -> (usize, usize) {
+        let (padding_x, padding_y) = self.padding();
+        let x = x.max(0.0) as f32 * self.pixel_ratio - padding_x;
+        let y = y.max(0.0) as f32 * self.pixel_ratio - padding_y;
+        let row = (y.max(0.0) / self.grid.cell_height) as usize;
+        let col = (x.max(0.0) / self.grid.cell_width) as usize;
+        (row.min(self.grid.rows - 1), col.min(self.grid.cols - 1))
     }
 }
 
 struct AppState {
     ts: TerminalState,
     ws: Rc<RefCell<WindowState>>,
-    renderer: Renderer,
+    renderer: WindowRenderer,
+    // Before scaling for the monitor's content scale
+    font_size_px: u32,
+    // The size from the config, what ResetFontSize goes back to
+    default_font_size_px: u32,
+    term: Terminal,
+    pty: Pty,
+    search: Search,
+    copy_mode: CopyMode,
+    hints: Hints,
+    file_refs: FileRefs,
+    prefs: Preferences,
+    smooth_scroll: SmoothScroll,
+    scrollbar: ScrollbarFade,
+    // Whether the window is hosted by `rush --daemon`, which opens new
+    // windows in the same process
+    daemon: bool,
+    // Set on the daemon's window that `rush --toggle` slides in and out
+    dropdown: Option<Dropdown>,
+    // Save where the window was when it closes, for the next one
+    remember_geometry: bool,
+    // Where the next frame drawn is saved, once one is asked for
+    screenshot_to: Option<PathBuf>,
+    primary: PrimarySelection,
+    // A paste waiting for the user to confirm it
+    pending_paste: Option<String>,
+    // The job in the foreground of the PTY, when the shell runs one, and
+    // the name of its program
+    foreground_job: Option<(Pid, String)>,
+    // The program that was running when closing the window was asked for,
+    // while waiting for the user to confirm it
+    pending_close: Option<String>,
+    // What was wrong with the config, shown until a key is pressed
+    config_errors: Vec<String>,
+    // The name of the theme the colors are from, if they're from one
+    theme: Option<String>,
+    // When the window was hidden with its shell left running, for
+    // `rush msg attach` to bring it back
+    detached: Option<Instant>,
+    // The shell closed the PTY, it has exited or is about to
+    pty_closed: bool,
+    // How the shell ended, once it has been reaped
+    exit_status: Option<pty::ExitStatus>,
+    // Where the PTY output is copied with --dump-output
+    dump: Option<Dump>,
+    // The session being recorded with --record
+    recorder: Option<Recorder<std::fs::File>>,
+    // The recording shown with --play, in place of the program's output
+    player: Option<Player>,
+    perf_hud: PerfHud,
+    // The rows that changed from one frame to the next, which are all the
+    // OpenGL renderer redraws when nothing is over the cells
+    damage: Damage,
+    pacing: FramePacing,
+    // Whether blinking text was shown in the last frame
+    blink_visible: bool,
+}
+
+/// What the config says about how a window behaves, the settings that
+/// can be read again while it's open
+struct Preferences {
+    bindings: Bindings,
+    // Put the selection on the clipboard whenever it changes, not only
+    // when it is yanked
+    copy_on_select: bool,
+    // What a double click selects
+    word_rules: selection::WordRules,
+    // What the export actions put on the clipboard
+    export_format: export::Format,
+    // The shell command the pipe actions run, and how the text it's given
+    // keeps its colors
+    pipe_command: String,
+    pipe_format: export::Format,
+    // Where Screenshot saves pictures of the window
+    screenshot_directory: PathBuf,
+    // Ask before pasting text that would run commands or is very large
+    confirm_paste: bool,
+    // Ask before closing the window while a job is running in it
+    confirm_close: bool,
+    exit_behavior: ExitBehavior,
+    hide_mouse_when_typing: bool,
+    // Restyles text on the screen that matches the rules in `highlight`
+    highlights: Highlighter,
+    // What opens a file at a line on ctrl+click, the file's editor in a
+    // new window when not set
+    editor: Option<String>,
+    themes: Themes,
+}
+
+impl Preferences {
+    fn from_config(config: &HashMap<String, String>) -> Result<Preferences, RushError> {
+        Ok(Preferences {
+            bindings: Bindings::from_config(config),
+            copy_on_select: config_value(config, "copy_on_select", false)?,
+            word_rules: selection::WordRules::new(
+                config.get("word_characters").map_or(selection::DEFAULT_WORD_CHARACTERS, String::as_str),
+            ),
+            export_format: config_value(config, "export_format", export::Format::Html)?,
+            pipe_command: config.get("pipe_command").map_or("less -R +G", String::as_str).to_string(),
+            pipe_format: config_value(config, "pipe_format", export::Format::Ansi)?,
+            screenshot_directory: screenshot_directory(config),
+            confirm_paste: config_value(config, "confirm_paste", true)?,
+            confirm_close: config_value(config, "confirm_close", true)?,
+            exit_behavior: config_value(config, "exit_behavior", ExitBehavior::Close)?,
+            hide_mouse_when_typing: config_value(config, "hide_mouse_when_typing", true)?,
+            highlights: highlights(config)?,
+            editor: config.get("editor").cloned(),
+            themes: Themes::from_config(config)?,
+        })
+    }
 }
 
+/// The rules under `highlight`, in the order of their names
+fn highlights(config: &HashMap<String, String>) -> Result<Highlighter, RushError> {
+    let mut rules: Vec<_> =
+        config.iter().filter_map(|(key, value)| Some((key.strip_prefix("highlight.")?, value))).collect();
+    rules.sort();
+    let rules = rules.into_iter().map(|(name, rule)| {
+        rule.parse::<highlight::Rule>().map_err(|reason| RushError::InvalidConfigValue {
+            key: format!("highlight.{}", name),
+            value: rule.clone(),
+            reason,
+        })
+    });
+    Ok(Highlighter::new(rules.collect::<Result<_, _>>()?))
+}
+
+// Where a window's events arrive
+type Events = glfw::GlfwReceiver<(f64, glfw::WindowEvent)>;
+
 struct TerminalState {
     window: Rc<RefCell<glfw::PWindow>>,
-    events: glfw::GlfwReceiver<(f64, glfw::WindowEvent)>,
+    events: Events,
     glfw: glfw::Glfw,
     cursor_pos: (usize, usize), // Note that cursor_pos is always the location
+    mouse: MouseState,
+    focused: bool,
+    // The title from the command line, used until the application sets
+    // one, and what the window's title was last set to
+    title: String,
+    shown_title: String,
+    // Where the window was and its size before it went fullscreen, to go
+    // back to
+    windowed_geometry: Option<(i32, i32, i32, i32)>,
+    // Where the window last was and its size while it was neither
+    // maximized nor fullscreen, saved for the next window when it closes
+    restored_geometry: Option<(i32, i32, i32, i32)>,
 }
 
-struct Renderer {
-    font_size_px: u32,
+struct GlRenderer {
     font_shader: Shader,
-    font_characters: Rc<RefCell<HashMap<char, Character>>>,
+    // Draws the glyphs instead of the font shader with subpixel
+    // antialiasing, not available on OpenGL ES
+    lcd_shader: Option<Shader>,
+    glyph_options: GlyphOptions,
+    // How much the grid fades out while the window doesn't have focus,
+    // from 0 to 1
+    unfocused_dim: f32,
+    cursor_style: render::CursorStyle,
+    // The highlight behind selected text, and the color of the text itself
+    // when the config gives one
+    selection_background: [f32; 4],
+    selection_foreground: Option<color::Rgb>,
+    // How much coverage is corrected for blending in sRGB, 1 is none
+    text_gamma: f32,
+    font_characters: Rc<RefCell<GlyphStore<Character>>>,
+    // Characters drawn that the font doesn't have, so it isn't asked again
+    missing_characters: RefCell<HashSet<char>>,
+    // Kept open so glyphs can be rasterized on demand
+    ft_face: ft::FT_Face,
+    // Only set when ligatures are enabled in the config
+    shaper: Option<Shaper>,
+    // Glyphs rasterized by glyph index for the shaper
+    shaped_glyphs: RefCell<HashMap<u32, Character>>,
+    // Textured quads for glyphs and images
     font_vao: u32,
     font_vbo: u32,
-    cursor_shader: Shader,
-    cursor_vao: u32,
-    cursor_vbo: u32,
-    ebo: u32,
+    // Bytes of storage the vertex buffers have, they only grow
+    font_vbo_capacity: Cell<usize>,
+    // Solid colored quads, batched into one draw per frame
+    rect_shader: Shader,
+    rect_vao: u32,
+    rect_vbo: u32,
+    rect_vbo_capacity: Cell<usize>,
+    // Textures of kitty graphics images by image id, with the generation
+    // of the image they were made from
+    image_shader: Shader,
+    image_textures: RefCell<HashMap<u32, (u64, u32)>>,
+    // Applies to the default background only, colored backgrounds stay
+    // opaque
+    background_opacity: f32,
+    // Size in pixels of the part of the framebuffer the grid is drawn in,
+    // glyph sizes are measured against it
+    viewport: (f32, f32),
+    // Only when the shaders come from `shader_dir`
+    shader_watcher: Option<ShaderWatcher>,
+    // Draws issued this frame and when it was done, before the wait for
+    // the swap, for the performance overlay
+    draw_calls: Cell<u32>,
+    drawn_at: Cell<Instant>,
+    scratch: RefCell<Scratch>,
+    // Frames are drawn into it and copied to the window, so the next one
+    // can be drawn over it
+    retained: RefCell<RetainedFrame>,
+}
+
+impl GlRenderer {
+    /// Rasterize the font again at `font_size_px` into new textures, and
+    /// return the size of a cell
+    fn load_font(&self, font_size_px: f32) -> Result<CharacterDimensions, RushError> {
+        let antialiasing = self.glyph_options.antialiasing;
+        let upload = |glyph: &ft::FT_GlyphSlotRec| unsafe { upload_glyph(glyph, antialiasing) };
+        let (chars, max_width, max_height) = load_font_chars(self.ft_face, font_size_px, self.glyph_options, upload)?;
+        let mut characters = self.font_characters.borrow_mut();
+        let mut shaped_glyphs = self.shaped_glyphs.borrow_mut();
+        for character in characters.values().chain(shaped_glyphs.values()) {
+            unsafe { gl::DeleteTextures(1, &character.texture_id) };
+        }
+        shaped_glyphs.clear();
+        self.missing_characters.borrow_mut().clear();
+        *characters = chars;
+        self.retained.borrow_mut().setup = None;
+        Ok(CharacterDimensions { width: max_width as u32, height: max_height as u32 })
+    }
+
+    /// Rasterize the glyphs for `chars` that aren't loaded yet, at the size
+    /// the font was last loaded at. Characters the font doesn't have are
+    /// drawn as '?'.
+    fn load_characters(&self, chars: impl IntoIterator<Item = char>) {
+        let mut characters = self.font_characters.borrow_mut();
+        let mut missing = self.missing_characters.borrow_mut();
+        for c in chars {
+            if c == ' ' || box_drawing::is_box_drawing(c) || characters.get(c).is_some() || missing.contains(&c) {
+                continue;
+            }
+            match unsafe { ft::FT_Get_Char_Index(self.ft_face, c as ft::FT_ULong) } {
+                0 => {
+                    missing.insert(c);
+                }
+                index => match unsafe { load_glyph_by_index(self.ft_face, index, self.glyph_options) } {
+                    Some(character) => characters.insert(c, character),
+                    None => {
+                        missing.insert(c);
+                    }
+                },
+            }
+        }
+    }
+}
+
+/// What the window draws with, `renderer` in the config
+// There is only one per window, boxing any of them isn't worth it
+#[allow(clippy::large_enum_variant)]
+enum WindowRenderer {
+    OpenGl(GlRenderer),
+    #[cfg(feature = "wgpu")]
+    Wgpu(wgpu_renderer::WgpuRenderer),
+    // Without a GPU, when OpenGL isn't available
+    Software(SoftwareRenderer),
+}
+
+impl WindowRenderer {
+    /// Draws issued for the last frame
+    fn draw_calls(&self) -> u32 {
+        match self {
+            WindowRenderer::OpenGl(renderer) => renderer.draw_calls.get(),
+            #[cfg(feature = "wgpu")]
+            WindowRenderer::Wgpu(renderer) => renderer.draw_calls,
+            // Nothing is handed to a GPU
+            WindowRenderer::Software(_) => 0,
+        }
+    }
+
+    /// When the last frame was done, before waiting for it to be shown
+    fn drawn_at(&self) -> Instant {
+        match self {
+            WindowRenderer::OpenGl(renderer) => renderer.drawn_at.get(),
+            #[cfg(feature = "wgpu")]
+            WindowRenderer::Wgpu(renderer) => renderer.drawn_at,
+            WindowRenderer::Software(renderer) => renderer.drawn_at,
+        }
+    }
+
+    /// Glyphs rasterized so far
+    fn cached_glyphs(&self) -> usize {
+        match self {
+            WindowRenderer::OpenGl(renderer) => renderer.font_characters.borrow().len() + renderer.shaped_glyphs.borrow().len(),
+            #[cfg(feature = "wgpu")]
+            WindowRenderer::Wgpu(renderer) => renderer.cached_glyphs(),
+            WindowRenderer::Software(renderer) => renderer.cached_glyphs(),
+        }
+    }
+
+    /// Rasterize the font again at `font_size_px`, and return the size of
+    /// a cell
+    fn load_font(&mut self, font_size_px: f32) -> Result<CharacterDimensions, RushError> {
+        match self {
+            WindowRenderer::OpenGl(renderer) => renderer.load_font(font_size_px),
+            #[cfg(feature = "wgpu")]
+            WindowRenderer::Wgpu(renderer) => renderer.load_font(font_size_px),
+            WindowRenderer::Software(renderer) => renderer.load_font(font_size_px),
+        }
+    }
+
+    /// Pick up changes to the files in `shader_dir`, returns whether there
+    /// were any. Only the OpenGL shaders can be edited.
+    fn reload_shaders(&mut self) -> bool {
+        match self {
+            WindowRenderer::OpenGl(renderer) => reload_shaders(renderer),
+            #[cfg(feature = "wgpu")]
+            WindowRenderer::Wgpu(_) => false,
+            WindowRenderer::Software(_) => false,
+        }
+    }
+
+    /// Draw into the padded area the grid covers. Everything is laid out in
+    /// normalized device coordinates, so cells line up with the viewport.
+    fn set_viewport(&mut self, ws: &WindowState) {
+        match self {
+            WindowRenderer::OpenGl(renderer) => {
+                let (x, y, width, height) = ws.grid_viewport();
+                unsafe { gl::Viewport(x, y, width, height) };
+                renderer.viewport = (width as f32, height as f32);
+            }
+            #[cfg(feature = "wgpu")]
+            WindowRenderer::Wgpu(renderer) => renderer.resize(ws),
+            WindowRenderer::Software(renderer) => renderer.resize(ws),
+        }
+    }
+
+    /// Make the window's OpenGL context the one drawn with, the other
+    /// renderers don't have one
+    fn make_current(&self, window: &RefCell<glfw::PWindow>) {
+        match self {
+            WindowRenderer::OpenGl(_) => window.borrow_mut().make_current(),
+            #[cfg(feature = "wgpu")]
+            WindowRenderer::Wgpu(_) => {}
+            WindowRenderer::Software(_) => {}
+        }
+    }
+
+    /// Wait for the GPU to be done with every frame it was handed
+    fn finish(&self) {
+        match self {
+            WindowRenderer::OpenGl(_) => unsafe { gl::Finish() },
+            #[cfg(feature = "wgpu")]
+            WindowRenderer::Wgpu(renderer) => renderer.finish(),
+            // Frames are done when they're presented
+            WindowRenderer::Software(_) => {}
+        }
+    }
 }
 
+/// What the config says about drawing, whatever it is drawn with
+struct RenderSettings {
+    glyph_options: GlyphOptions,
+    // Applies to the default background only
+    background_opacity: f32,
+    text_gamma: f32,
+    unfocused_dim: f32,
+    cursor: render::CursorStyle,
+    vsync: bool,
+}
+
+// Floats per vertex in the rect batch: x, y, r, g, b, a
+const RECT_VERTEX_SIZE: usize = 6;
+
+// Floats per vertex in a glyph batch: x, y, u, v, r, g, b, a
+const GLYPH_VERTEX_SIZE: usize = 8;
+
+// Codepoints rasterized up front: ASCII, and the combining diacritical
+// marks so they can be drawn on top of their base character. The rest of
+// the font is rasterized the first time it's drawn.
+const GLYPH_RANGES: [std::ops::Range<u32>; 2] = [0..127, 0x300..0x370];
+
+// Smallest and largest font sizes the font size actions go to, in pixels
+const MIN_FONT_SIZE: u32 = 6;
+const MAX_FONT_SIZE: u32 = 400;
+
+// Seconds for blinking text to go through one on/off cycle
+const BLINK_PERIOD: f64 = 1.0;
+
+// Lines a second the view scrolls while a selection is dragged past the
+// top or bottom of the grid, for every row's height it is past it
+const AUTOSCROLL_RATE: f64 = 15.0;
+
+// Seconds between two clicks on the same cell for them to be a double click
+const DOUBLE_CLICK_TIME: f64 = 0.4;
+
+// --bench-render goes through the capture this many times, feeding the
+// terminal this much of it before each frame, about what a read from a
+// busy PTY returns
+const BENCH_PASSES: usize = 10;
+const BENCH_READ_SIZE: usize = 4096;
+
+// Translucent so the text stays readable on top
+const SEARCH_MATCH_COLOR: [f32; 4] = [1.0, 0.85, 0.0, 0.35];
+const SEARCH_CURRENT_COLOR: [f32; 4] = [1.0, 0.5, 0.0, 0.7];
+const SEARCH_BAR_COLOR: [f32; 4] = [0.2, 0.2, 0.2, 1.0];
+const SELECTION_COLOR: [f32; 4] = [0.3, 0.5, 0.9, 0.45];
+const COPY_MODE_CURSOR_COLOR: [f32; 4] = [0.6, 0.8, 1.0, 0.7];
+const HINT_LABEL_COLOR: [f32; 4] = [1.0, 0.8, 0.2, 1.0];
+const HINT_TEXT_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
 struct CharacterDimensions {
     width: u32,
     height: u32
 }
 
-fn init_freetype_lib() -> ft::FT_Library {
+fn init_freetype_lib() -> Result<ft::FT_Library, RushError> {
     let mut lib: ft::FT_Library = std::ptr::null_mut();
-    unsafe {
-        let err = ft::FT_Init_FreeType(&mut lib);
-        if err != 0 {
-            panic!(
-                "Could not initialize FreeType library. ERROR CODE {:?}",
-                lib
-            );
-        }
+    let error = unsafe { ft::FT_Init_FreeType(&mut lib) };
+    if error != 0 {
+        return Err(RushError::FreeTypeInit(error));
     }
 
-    lib
+    Ok(lib)
 }
 
-fn create_ft_face(lib: ft::FT_Library, font_path: &std::ffi::CStr) -> ft::FT_Face {
+fn create_ft_face(lib: ft::FT_Library, font_path: &str) -> Result<ft::FT_Face, RushError> {
+    let font_error = |code| RushError::FontLoad { path: font_path.to_string(), code };
+    // FreeType reports a path it can't open as FT_Err_Cannot_Open_Resource
+    let c_font_path = CString::new(font_path).map_err(|_| font_error(1))?;
     let mut face: ft::FT_Face = std::ptr::null_mut();
-    let error = unsafe { ft::FT_New_Face(lib, font_path.as_ptr(), 0, &mut face) };
+    let error = unsafe { ft::FT_New_Face(lib, c_font_path.as_ptr(), 0, &mut face) };
     if error != 0 {
-        panic!("Could not create font face. ERROR CODE: {:?}", error);
+        return Err(font_error(error));
     }
 
-    face
+    Ok(face)
 }
 
-fn load_font_chars(lib: ft::FT_Library, face: ft::FT_Face, font_size_px: u32) -> (HashMap<char, Character>, i64, i64) {
-    let mut characters = HashMap::new();
+/// Rasterize the glyphs kept loaded at `font_size_px` and measure the
+/// cells, `upload` puts each rendered glyph where the backend draws it from.
+/// The size can be fractional, at a content scale of 1.25 for one.
+fn load_font_chars<T>(
+    face: ft::FT_Face,
+    font_size_px: f32,
+    options: GlyphOptions,
+    mut upload: impl FnMut(&ft::FT_GlyphSlotRec) -> T,
+) -> Result<(GlyphStore<T>, i64, i64), RushError> {
+    let mut characters = GlyphStore::new();
     let mut max_advance = 0; // used to calculate the width of cells
     let mut max_height = 0;
     unsafe {
-        ft::FT_Set_Pixel_Sizes(face, 0, font_size_px);
-
-        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+        // In 26.6 fixed point, at 72 DPI points are pixels
+        let size = (font_size_px * 64.0).round() as ft::FT_F26Dot6;
+        ft::FT_Set_Char_Size(face, 0, size, 72, 72);
 
-        for c in 0..127 {
-            let error = ft::FT_Load_Char(face, c, ft::FT_LOAD_RENDER as i32);
+        for c in GLYPH_RANGES.iter().cloned().flatten() {
+            let mut error = ft::FT_Load_Char(face, c as ft::FT_ULong, options.load_flags());
+            if error == 0 {
+                error = ft::FT_Render_Glyph((*face).glyph, options.render_mode());
+            }
             if error != 0 {
-                panic!("Could not load character. ERROR CODE: {:?}", error);
+                return Err(RushError::GlyphLoad { character: char::from_u32(c).unwrap(), code: error });
             }
 
-            // Generate texture
-            let mut texture: u32 = 0;
             let glyph = &*(*face).glyph;
             let metrics = (*(*face).size).metrics;
             if (metrics.height >> 6) > max_height {
                 max_height = metrics.height >> 6;
             }
-            if glyph.advance.x > max_advance {
-                max_advance = glyph.advance.x >> 6;
-            }
+            max_advance = max_advance.max(options.advance(glyph));
 
+            characters.insert(char::from_u32(c).unwrap(), upload(glyph));
+        }
+    };
 
-            gl::GenTextures(1, &mut texture);
-            gl::BindTexture(gl::TEXTURE_2D, texture);
-            gl::TexImage2D(
-                gl::TEXTURE_2D,
-                0, gl::RED.try_into().unwrap(),
-                glyph.bitmap.width.try_into().unwrap(),
-                glyph.bitmap.rows.try_into().unwrap(),
-                0,
-                gl::RED,
-                gl::UNSIGNED_BYTE,
-                glyph.bitmap.buffer as *const _,
-            );
+    Ok((characters, max_advance, max_height))
+}
 
-            // Set texture options
-            gl::TexParameteri(
-                gl::TEXTURE_2D,
-                gl::TEXTURE_WRAP_S,
-                gl::CLAMP_TO_EDGE.try_into().unwrap(),
-            );
-            gl::TexParameteri(
-                gl::TEXTURE_2D,
-                gl::TEXTURE_WRAP_T,
-                gl::CLAMP_TO_EDGE.try_into().unwrap(),
-            );
-            gl::TexParameteri(
-                gl::TEXTURE_2D,
-                gl::TEXTURE_MIN_FILTER,
-                gl::LINEAR.try_into().unwrap(),
-            );
-            gl::TexParameteri(
-                gl::TEXTURE_2D,
-                gl::TEXTURE_MAG_FILTER,
-                gl::LINEAR.try_into().unwrap(),
-            );
+/// Rasterize a glyph by its index in the font rather than by codepoint,
+/// which is what the shaper hands back. A glyph FreeType can't load is
+/// logged and left out, one bad glyph shouldn't take the terminal down.
+unsafe fn load_glyph_by_index(face: ft::FT_Face, glyph_id: u32, options: GlyphOptions) -> Option<Character> {
+    let mut error = ft::FT_Load_Glyph(face, glyph_id, options.load_flags());
+    if error == 0 {
+        error = ft::FT_Render_Glyph((*face).glyph, options.render_mode());
+    }
+    if error != 0 {
+        error!("Could not rasterize glyph {} from the font, FreeType error code {}", glyph_id, error);
+        return None;
+    }
+    Some(upload_glyph(&*(*face).glyph, options.antialiasing))
+}
 
-            // Store character for later use
-            let character: Character = Character {
-                texture_id: texture,
-                size: (
-                    glyph.bitmap.width.try_into().unwrap(),
-                    glyph.bitmap.rows.try_into().unwrap(),
-                ),
-                bearing: (glyph.bitmap_left, glyph.bitmap_top),
-                advance: glyph.advance.x,
-            };
+/// Copy a rendered FreeType glyph into a texture, one channel of coverage
+/// or, with subpixel antialiasing, one for each subpixel
+unsafe fn upload_glyph(glyph: &ft::FT_GlyphSlotRec, antialiasing: Antialiasing) -> Character {
+    let bitmap = &glyph.bitmap;
+    let lcd = bitmap.pixel_mode == ft::FT_Pixel_Mode::FT_PIXEL_MODE_LCD as u8;
+    // LCD bitmaps are three subpixels wide per pixel
+    let width = if lcd { bitmap.width / 3 } else { bitmap.width };
 
-            characters.insert(char::from(c as u8), character);
-        }
-        gl::BindTexture(gl::TEXTURE_2D, 0);
+    // Generate texture
+    gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+    let mut texture: u32 = 0;
+    gl::GenTextures(1, &mut texture);
+    gl::BindTexture(gl::TEXTURE_2D, texture);
+    if antialiasing == Antialiasing::Subpixel {
+        let pixels = subpixel_coverage(bitmap, width as usize, lcd);
+        gl::TexImage2D(
+            gl::TEXTURE_2D,
+            0, gl::RGB.try_into().unwrap(),
+            width.try_into().unwrap(),
+            bitmap.rows.try_into().unwrap(),
+            0,
+            gl::RGB,
+            gl::UNSIGNED_BYTE,
+            pixels.as_ptr() as *const _,
+        );
+    } else {
+        gl::TexImage2D(
+            gl::TEXTURE_2D,
+            0, gl::RED.try_into().unwrap(),
+            bitmap.width.try_into().unwrap(),
+            bitmap.rows.try_into().unwrap(),
+            0,
+            gl::RED,
+            gl::UNSIGNED_BYTE,
+            bitmap.buffer as *const _,
+        );
+    }
 
-        ft::FT_Done_Face(face);
-        ft::FT_Done_Library(lib);
-    };
+    // Set texture options
+    gl::TexParameteri(
+        gl::TEXTURE_2D,
+        gl::TEXTURE_WRAP_S,
+        gl::CLAMP_TO_EDGE.try_into().unwrap(),
+    );
+    gl::TexParameteri(
+        gl::TEXTURE_2D,
+        gl::TEXTURE_WRAP_T,
+        gl::CLAMP_TO_EDGE.try_into().unwrap(),
+    );
+    gl::TexParameteri(
+        gl::TEXTURE_2D,
+        gl::TEXTURE_MIN_FILTER,
+        gl::LINEAR.try_into().unwrap(),
+    );
+    gl::TexParameteri(
+        gl::TEXTURE_2D,
+        gl::TEXTURE_MAG_FILTER,
+        gl::LINEAR.try_into().unwrap(),
+    );
+    gl::BindTexture(gl::TEXTURE_2D, 0);
 
-    (characters, max_advance, max_height)
+    // Store character for later use
+    Character {
+        texture_id: texture,
+        size: (
+            width.try_into().unwrap(),
+            glyph.bitmap.rows.try_into().unwrap(),
+        ),
+        bearing: (glyph.bitmap_left, glyph.bitmap_top),
+        advance: glyph.advance.x,
+    }
+}
+
+/// Tightly packed RGB coverage of a glyph bitmap. Rows of LCD bitmaps
+/// can be padded, and glyphs FreeType couldn't render for an LCD (bitmap
+/// fonts, say) get the same coverage on every subpixel.
+unsafe fn subpixel_coverage(bitmap: &ft::FT_Bitmap, width: usize, lcd: bool) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity(width * bitmap.rows as usize * 3);
+    for row in 0..bitmap.rows as usize {
+        let start = bitmap.buffer.offset(row as isize * bitmap.pitch as isize);
+        if lcd {
+            pixels.extend_from_slice(std::slice::from_raw_parts(start, width * 3));
+        } else {
+            for &coverage in std::slice::from_raw_parts(start, width) {
+                pixels.extend_from_slice(&[coverage; 3]);
+            }
+        }
+    }
+    pixels
 }
 
 unsafe fn make_text_vao_vbo() -> (u32, u32) {
@@ -281,179 +970,1238 @@ unsafe fn make_text_vao_vbo() -> (u32, u32) {
     gl::GenBuffers(1, &mut vbo);
     gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
 
-    // Fill VBO with geometry data
-    gl::BufferData(
-        gl::ARRAY_BUFFER,
-        (std::mem::size_of::<f32>() * 4 * 5) as isize,
-        std::ptr::null(),
-        gl::STATIC_DRAW,
-    );
+    let stride = (GLYPH_VERTEX_SIZE * std::mem::size_of::<f32>()) as i32;
 
-    // Set the position attribute (3 floats per vertex for position)
+    // Position, 2 floats
+    gl::VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, stride, std::ptr::null());
+    gl::EnableVertexAttribArray(0);
+
+    // Texture coordinates, 2 floats after the position
     gl::VertexAttribPointer(
-        0,
-        3,
+        1,
+        2,
         gl::FLOAT,
         gl::FALSE,
-        5 * std::mem::size_of::<f32>() as i32,
-        // Byte offset. The position comes first at the beginning of the array, thus null for no
-        // offset
-        std::ptr::null(),
+        stride,
+        (2 * std::mem::size_of::<f32>()) as *const _,
     );
-    gl::EnableVertexAttribArray(0);
+    gl::EnableVertexAttribArray(1);
 
-    // Set texture coordinates attribute
+    // Color, 4 floats after the texture coordinates
     gl::VertexAttribPointer(
-        1,
         2,
+        4,
         gl::FLOAT,
         gl::FALSE,
-        5 * std::mem::size_of::<f32>() as i32,
-        // Byte offset to first element. We have 5 floats, first 3 x, y, z, last 2 2d texture
-        // coords x, y. Texture coords start at index 3.
-        (3 * std::mem::size_of::<f32>()) as *const _, // byte offset to first element
+        stride,
+        (4 * std::mem::size_of::<f32>()) as *const _,
     );
-    gl::EnableVertexAttribArray(1);
+    gl::EnableVertexAttribArray(2);
+
+    gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+    gl::BindVertexArray(0);
 
     (vao, vbo)
 }
 
-fn make_cursor_vao_vbo_ebo() -> (u32, u32, u32) {
+unsafe fn make_rect_vao_vbo() -> (u32, u32) {
     let mut vao: u32 = 0;
     let mut vbo: u32 = 0;
-    let mut ebo: u32 = 0;
 
-    let vertices: [f32; 12] = [
-        -0.5, 0.5, 0.0, // top left
-        0.5, 0.5, 0.0, // top right
-        -0.5, -0.5, 0.0, // bottom left
-        0.5, -0.5, 0.0, // bottom right
-    ];
+    gl::GenVertexArrays(1, &mut vao);
+    gl::BindVertexArray(vao);
+
+    gl::GenBuffers(1, &mut vbo);
+    gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
 
-    let indices: [u32; 6] = [0, 1, 2, 1, 2, 3];
+    let stride = (RECT_VERTEX_SIZE * std::mem::size_of::<f32>()) as i32;
 
-    unsafe {
-        // Create and bind VAO
-        gl::GenVertexArrays(1, &mut vao);
-        gl::BindVertexArray(vao);
-
-        // Create and bind VBO
-        gl::GenBuffers(1, &mut vbo);
-        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
-
-        // Fill VBO with geometry data
-        gl::BufferData(
-            gl::ARRAY_BUFFER,
-            (std::mem::size_of::<f32>() * vertices.len()) as isize,
-            vertices.as_ptr() as *const c_void,
-            gl::STATIC_DRAW,
-        );
+    // Position, 2 floats
+    gl::VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, stride, std::ptr::null());
+    gl::EnableVertexAttribArray(0);
 
-        gl::VertexAttribPointer(
-            0,
-            3,
-            gl::FLOAT,
-            gl::FALSE,
-            3 * std::mem::size_of::<f32>() as i32,
-            std::ptr::null(),
-        );
-        gl::EnableVertexAttribArray(0);
-
-        // Create and bind EBO
-        gl::GenBuffers(1, &mut ebo);
-        gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ebo);
-
-        // Fill EBO with indices data
-        gl::BufferData(
-            gl::ELEMENT_ARRAY_BUFFER,
-            (std::mem::size_of::<u32>() * indices.len()) as isize,
-            indices.as_ptr() as *const c_void,
-            gl::STATIC_DRAW,
-        );
+    // Color, 4 floats after the position
+    gl::VertexAttribPointer(
+        1,
+        4,
+        gl::FLOAT,
+        gl::FALSE,
+        stride,
+        (2 * std::mem::size_of::<f32>()) as *const _,
+    );
+    gl::EnableVertexAttribArray(1);
+
+    gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+    gl::BindVertexArray(0);
+
+    (vao, vbo)
+}
+
+/// Add a quad to a rect batch. Corners are top left, top right, bottom
+/// left, bottom right in normalized device coordinates.
+fn push_quad(vertices: &mut Vec<f32>, corners: [(f32, f32); 4], color: [f32; 4]) {
+    for i in [0, 1, 2, 1, 2, 3] {
+        let (x, y) = corners[i];
+        vertices.extend_from_slice(&[x, y, color[0], color[1], color[2], color[3]]);
+    }
+}
+
+/// Add an axis aligned rectangle, `(x, y)` being its bottom left corner
+fn push_rect(vertices: &mut Vec<f32>, x: f32, y: f32, width: f32, height: f32, color: [f32; 4]) {
+    push_quad(
+        vertices,
+        [(x, y + height), (x + width, y + height), (x, y), (x + width, y)],
+        color,
+    );
+}
 
-        // gl::BindBuffer(gl::ARRAY_BUFFER, 0);
-        // gl::BindVertexArray(0);
+/// Add one period of a sine wave across `width`, built from short segments
+fn push_undercurl(vertices: &mut Vec<f32>, x: f32, center_y: f32, width: f32, amplitude: f32, thickness: f32, color: [f32; 4]) {
+    const SEGMENTS: usize = 8;
+    let point = |i: usize| {
+        let t = i as f32 / SEGMENTS as f32;
+        (x + t * width, center_y + amplitude * (t * std::f32::consts::TAU).sin())
+    };
+
+    let half = thickness / 2.0;
+    for i in 0..SEGMENTS {
+        let (x0, y0) = point(i);
+        let (x1, y1) = point(i + 1);
+        push_quad(vertices, [(x0, y0 + half), (x1, y1 + half), (x0, y0 - half), (x1, y1 - half)], color);
     }
+}
 
-    (vao, vbo, ebo)
+/// Upload `vertices` to the buffer bound to GL_ARRAY_BUFFER, whose storage
+/// is `capacity` bytes. The storage only ever grows, and is orphaned before
+/// it is written so the driver hands out fresh memory rather than waiting
+/// for earlier draws still reading the old contents.
+unsafe fn stream_upload(capacity: &Cell<usize>, vertices: &[f32]) {
+    let size = std::mem::size_of_val(vertices);
+    if size > capacity.get() {
+        capacity.set(size.next_power_of_two());
+    }
+    gl::BufferData(gl::ARRAY_BUFFER, capacity.get() as isize, std::ptr::null(), gl::STREAM_DRAW);
+    gl::BufferSubData(gl::ARRAY_BUFFER, 0, size as isize, vertices.as_ptr() as *const c_void);
 }
 
-fn render_screen_buffer(renderer: &Renderer, ws: Rc<RefCell<WindowState>>) {
-    let mut ws = ws.borrow_mut();
-    ws.reset_cell();
-    renderer.font_shader.use_shader();
+fn draw_rects(renderer: &GlRenderer, vertices: &[f32]) {
+    if vertices.is_empty() {
+        return;
+    }
 
+    renderer.rect_shader.use_shader();
     unsafe {
-        // Enable blending
-        gl::Enable(gl::BLEND);
-        gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
-
-        let characters = renderer.font_characters.borrow();
-        let buf = ws.buffer.clone();
-
-        if buf[ws.display_offset..].len() + 1 > ws.grid.rows * ws.grid.cols {
-            ws.scroll();
-        }
-        
-        for c in buf[ws.display_offset..].chars() {
-            let ftchar = characters.get(&c).unwrap();
-            
-            let (vertices, indices) = calculate_textured_quad_vertices(
-                ws.get_next_cell(),
-                ftchar,
-                800.0,
-                600.0,
-                ws.grid.rows,
-                ws.grid.cols
-            );
-            set_renderer_vertices(renderer.font_vao, renderer.font_vbo, &vertices, &indices);
+        gl::BindVertexArray(renderer.rect_vao);
+        gl::BindBuffer(gl::ARRAY_BUFFER, renderer.rect_vbo);
+        stream_upload(&renderer.rect_vbo_capacity, vertices);
+        gl::DrawArrays(gl::TRIANGLES, 0, (vertices.len() / RECT_VERTEX_SIZE) as i32);
+        renderer.draw_calls.set(renderer.draw_calls.get() + 1);
+        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        gl::BindVertexArray(0);
+    }
+}
 
-            // Set the active texture
-            gl::ActiveTexture(gl::TEXTURE0);
+/// What is drawn over the terminal's contents
+struct Overlays<'a> {
+    search: &'a Search,
+    copy_mode: &'a CopyMode,
+    hints: &'a Hints,
+    // The hyperlink under the mouse, underlined wherever it appears
+    hovered_link: Option<&'a Hyperlink>,
+    // The row and columns of the file reference under the mouse while
+    // ctrl is held
+    hovered_file_ref: Option<(usize, std::ops::Range<usize>)>,
+    // A paste waiting to be confirmed
+    pending_paste: Option<&'a str>,
+    // The program running in the window it was asked to close
+    pending_close: Option<&'a str>,
+    // What was wrong with the config when the window opened
+    config_errors: &'a [String],
+    // What the performance overlay says, while it's shown
+    perf_hud: Option<&'a str>,
+}
 
-            // Bind the VAO
-            gl::BindVertexArray(renderer.font_vao);
+impl Overlays<'_> {
+    /// Whether nothing is drawn over the cells
+    fn is_empty(&self) -> bool {
+        !self.search.open
+            && !self.copy_mode.active
+            && self.copy_mode.selection.is_none()
+            && !self.hints.active
+            && self.hovered_link.is_none()
+            && self.hovered_file_ref.is_none()
+            && self.pending_paste.is_none()
+            && self.pending_close.is_none()
+            && self.config_errors.is_empty()
+            && self.perf_hud.is_none()
+    }
 
-            // Bind texture
-            gl::BindTexture(gl::TEXTURE_2D, ftchar.texture_id);
+    fn selected(&self, point: selection::Point) -> bool {
+        self.copy_mode.selection.is_some_and(|s| s.contains(point))
+    }
+}
 
-            // Bind the buffer
-            gl::BindBuffer(gl::ARRAY_BUFFER, renderer.font_vbo);
-            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, renderer.ebo);
+/// Textured quads sharing one vertex buffer, drawn with one call per
+/// texture
+#[derive(Default)]
+struct GlyphBatch {
+    quads: Vec<(u32, [f32; 6 * GLYPH_VERTEX_SIZE])>,
+    // Built when drawing, kept to be reused: the texture and index of each
+    // quad in drawing order, their vertices in that order, and the
+    // texture, first vertex and vertex count of each draw
+    order: Vec<(u32, u32)>,
+    vertices: Vec<f32>,
+    draws: Vec<(u32, i32, i32)>,
+}
 
-            // check_gl_errors();
+impl GlyphBatch {
+    fn clear(&mut self) {
+        self.quads.clear();
+    }
 
-            gl::DrawElements(gl::TRIANGLES, 6, gl::UNSIGNED_INT, std::ptr::null());
-            ws.advance();
+    /// Add a quad, `(x, y)` being its bottom left corner and `uv` the top
+    /// left and bottom right texture coordinates
+    fn push(&mut self, texture: u32, (x, y, width, height): (f32, f32, f32, f32), uv: [f32; 4], color: [f32; 4]) {
+        let [u0, v0, u1, v1] = uv;
+        let corners = [(x, y + height, u0, v0), (x + width, y + height, u1, v0), (x, y, u0, v1), (x + width, y, u1, v1)];
+        let mut quad = [0.0; 6 * GLYPH_VERTEX_SIZE];
+        for (vertex, i) in quad.chunks_exact_mut(GLYPH_VERTEX_SIZE).zip([0, 1, 2, 1, 2, 3]) {
+            let (x, y, u, v) = corners[i];
+            vertex.copy_from_slice(&[x, y, u, v, color[0], color[1], color[2], color[3]]);
         }
+        self.quads.push((texture, quad));
+    }
+
+    /// Add a glyph in the cell at `(row, col)` spanning `columns` columns,
+    /// with the grid `viewport` pixels in size
+    fn push_glyph(&mut self, viewport: (f32, f32), ftchar: &Character, color: [f32; 4], cell: (usize, usize), columns: usize, term: &Terminal) {
+        let quad = glyph_quad(cell, columns, ftchar, viewport.0, viewport.1, term.rows, term.cols);
+        self.push(ftchar.texture_id, quad, [0.0, 0.0, 1.0, 1.0], color);
     }
 }
 
-fn init_opengl() {
-    gl_loader::init_gl();
-    gl::load_with(|symbol| gl_loader::get_proc_address(symbol) as *const _);
+/// Vertex data built for each pass, kept from frame to frame so that
+/// once the buffers have grown to what a frame needs drawing doesn't
+/// allocate
+#[derive(Default)]
+struct Scratch {
+    rects: Vec<f32>,
+    batch: GlyphBatch,
+    shapes: Vec<box_drawing::Shape>,
+    // What each row of the view was last drawn with
+    rows: Vec<RowVertices>,
 }
 
-fn check_gl_errors() {
-    let err = unsafe { gl::GetError() };
-    if err != gl::NO_ERROR {
-        println!("GL error: {:?}", err);
-    } else {
-        // println!("No GL errors");
+/// The vertices of a row's cells, kept so that a frame redrawing some of
+/// the rows only builds the ones that changed again
+#[derive(Default)]
+struct RowVertices {
+    backgrounds: Vec<f32>,
+    // Box drawing characters and text decorations, then the glyphs
+    rects: Vec<f32>,
+    glyphs: Vec<(u32, [f32; 6 * GLYPH_VERTEX_SIZE])>,
+}
+
+/// Draw the rectangles `pass` adds to `vertices`
+fn draw_rect_pass(renderer: &GlRenderer, vertices: &mut Vec<f32>, pass: impl FnOnce(&mut Vec<f32>)) {
+    vertices.clear();
+    pass(vertices);
+    draw_rects(renderer, vertices);
+}
+
+/// Upload a glyph batch and draw it with the given shader. Quads are
+/// grouped by texture, which only changes the order of quads that don't
+/// share one.
+fn draw_glyphs(renderer: &GlRenderer, shader: &Shader, batch: &mut GlyphBatch) {
+    if batch.quads.is_empty() {
+        return;
+    }
+    let GlyphBatch { quads, order, vertices, draws } = batch;
+    // With the index in the key an unstable sort keeps the order of quads
+    // sharing a texture, without the buffer a stable sort allocates
+    order.clear();
+    order.extend(quads.iter().enumerate().map(|(i, &(texture, _))| (texture, i as u32)));
+    order.sort_unstable();
+
+    vertices.clear();
+    draws.clear();
+    for &(texture, i) in order.iter() {
+        let first = (vertices.len() / GLYPH_VERTEX_SIZE) as i32;
+        vertices.extend_from_slice(&quads[i as usize].1);
+        match draws.last_mut() {
+            Some((last, _, count)) if *last == texture => *count += 6,
+            _ => draws.push((texture, first, 6)),
+        }
+    }
+
+    shader.use_shader();
+    unsafe {
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindVertexArray(renderer.font_vao);
+        gl::BindBuffer(gl::ARRAY_BUFFER, renderer.font_vbo);
+        stream_upload(&renderer.font_vbo_capacity, vertices);
+        for &(texture, first, count) in draws.iter() {
+            gl::BindTexture(gl::TEXTURE_2D, texture);
+            gl::DrawArrays(gl::TRIANGLES, first, count);
+            renderer.draw_calls.set(renderer.draw_calls.get() + 1);
+        }
+        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        gl::BindVertexArray(0);
     }
 }
 
-fn key_to_capital_char(key: glfw::Key) -> Option<char> {
-    match key {
-        glfw::Key::A => Some('A'),
-        glfw::Key::B => Some('B'),
-        glfw::Key::C => Some('C'),
-        glfw::Key::D => Some('D'),
-        glfw::Key::E => Some('E'),
-        glfw::Key::F => Some('F'),
-        glfw::Key::G => Some('G'),
-        glfw::Key::H => Some('H'),
-        glfw::Key::I => Some('I'),
+/// Draw glyphs with the shader for the kind of antialiasing they were
+/// rasterized with
+fn draw_text(renderer: &GlRenderer, batch: &mut GlyphBatch) {
+    match &renderer.lcd_shader {
+        Some(lcd_shader) if renderer.glyph_options.antialiasing == Antialiasing::Subpixel => {
+            lcd_shader.use_shader();
+            lcd_shader.set_float("gamma", renderer.text_gamma);
+            unsafe {
+                gl::BlendFuncSeparate(gl::SRC1_COLOR, gl::ONE_MINUS_SRC1_COLOR, gl::ONE, gl::ONE_MINUS_SRC_ALPHA);
+            }
+            draw_glyphs(renderer, lcd_shader, batch);
+            unsafe {
+                gl::BlendFuncSeparate(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA, gl::ONE, gl::ONE_MINUS_SRC_ALPHA);
+            }
+        }
+        _ => {
+            renderer.font_shader.use_shader();
+            renderer.font_shader.set_float("gamma", renderer.text_gamma);
+            draw_glyphs(renderer, &renderer.font_shader, batch);
+        }
+    }
+}
+
+/// The last frame, kept in a texture to draw the next one over: the back
+/// buffer's contents are undefined after a swap. Frames are drawn into it
+/// and then copied to the window.
+#[derive(Default)]
+struct RetainedFrame {
+    framebuffer: u32,
+    texture: u32,
+    size: (i32, i32),
+    // What the frame was drawn with, `None` when the next has to be drawn
+    // whole
+    setup: Option<FrameSetup>,
+}
+
+/// What a frame with nothing over the cells looks like besides its rows.
+/// One drawn the same way as the last only redraws the rows that changed.
+#[derive(Clone, PartialEq)]
+struct FrameSetup {
+    grid_viewport: (i32, i32, i32, i32),
+    focused: bool,
+    blink_visible: bool,
+    palette: color::Palette,
+}
+
+impl RetainedFrame {
+    /// Draw into the retained frame from here on, at `size` pixels. At a
+    /// new size it starts over blank, to be drawn whole.
+    unsafe fn bind(&mut self, size: (i32, i32)) {
+        let size = (size.0.max(1), size.1.max(1));
+        if self.framebuffer == 0 {
+            gl::GenFramebuffers(1, &mut self.framebuffer);
+            gl::GenTextures(1, &mut self.texture);
+        }
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer);
+        if size != self.size {
+            gl::BindTexture(gl::TEXTURE_2D, self.texture);
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                gl::RGBA8 as i32,
+                size.0,
+                size.1,
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                std::ptr::null(),
+            );
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+            gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, self.texture, 0);
+            self.size = size;
+            self.setup = None;
+        }
+    }
+
+    /// Copy the frame to the window's back buffer, and draw there again
+    unsafe fn show(&self) {
+        let (width, height) = self.size;
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.framebuffer);
+        gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, 0);
+        gl::BlitFramebuffer(0, 0, width, height, 0, 0, width, height, gl::COLOR_BUFFER_BIT, gl::NEAREST);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+    }
+}
+
+/// A frame drawn with OpenGL into a window. Each pass is batched into
+/// its own buffer and drawn in order: cell backgrounds, images under the
+/// text, the selection highlight, the text with its decorations, images
+/// over the text, the cursor, the dimming of an unfocused window, and then
+/// search and hints on top.
+struct GlFrame<'a> {
+    renderer: &'a GlRenderer,
+    overlays: Overlays<'a>,
+    window: &'a RefCell<glfw::PWindow>,
+    // Where the grid is drawn, and how many pixels up it is moved while
+    // scrolling smoothly
+    grid_viewport: (i32, i32, i32, i32),
+    shift: i32,
+    // Set when nothing is drawn over the cells, and the rows of the view
+    // that changed since the last frame
+    setup: Option<FrameSetup>,
+    damaged: &'a [usize],
+    // The rows drawn when the rest of the last frame still holds, found
+    // when the frame begins
+    redraw: Option<std::ops::Range<usize>>,
+}
+
+impl GlFrame<'_> {
+    /// The rows to draw, when the frame doesn't have to be drawn whole:
+    /// from the first damaged row to the last, and the rows either side
+    /// of them, which glyphs reaching past their cells could have drawn over
+    fn redraw_rows(&self, term: &Terminal, last: Option<&FrameSetup>) -> Option<std::ops::Range<usize>> {
+        if self.setup.is_none() || self.setup.as_ref() != last {
+            return None;
+        }
+        match (self.damaged.first(), self.damaged.last()) {
+            (Some(&first), Some(&last)) => Some(first.saturating_sub(1)..(last + 2).min(term.rows)),
+            _ => Some(0..0),
+        }
+    }
+
+    /// The part of the framebuffer `rows` cover
+    fn rows_area(&self, term: &Terminal, rows: &std::ops::Range<usize>) -> (i32, i32, i32, i32) {
+        let (x, y, width, height) = self.grid_viewport;
+        // From the bottom up, as OpenGL counts
+        let edge = |row: usize| y as f32 + height as f32 * (1.0 - row as f32 / term.rows as f32);
+        let (bottom, top) = (edge(rows.end).floor() as i32, edge(rows.start).ceil() as i32);
+        (x, bottom, width, top - bottom)
+    }
+
+    /// Whether the row at `row` has to be built again rather than drawn
+    /// with the vertices it had in the last frame
+    fn stale(&self, row: usize) -> bool {
+        self.redraw.is_none() || self.damaged.iter().any(|&damaged| damaged.abs_diff(row) <= 1)
+    }
+}
+
+impl render::Renderer for GlFrame<'_> {
+    fn begin_frame(&mut self, term: &Terminal) {
+        check_gl_errors();
+        self.renderer.draw_calls.set(0);
+        let (x, y, width, height) = self.grid_viewport;
+        let mut retained = self.renderer.retained.borrow_mut();
+        unsafe {
+            retained.bind(self.window.borrow().get_framebuffer_size());
+            // Only the rows that changed, when the rest of the last frame
+            // still holds
+            self.redraw = self.redraw_rows(term, retained.setup.as_ref());
+            if let Some(rows) = &self.redraw {
+                let (x, y, width, height) = self.rows_area(term, rows);
+                gl::Enable(gl::SCISSOR_TEST);
+                gl::Scissor(x, y, width, height);
+            }
+            retained.setup = self.setup.take();
+            //gl::PolygonMode(gl::FRONT_AND_BACK, gl::LINE);
+            // Premultiplied, which is what compositors expect
+            let alpha = self.renderer.background_opacity;
+            let [r, g, b, _] = color::to_gl(term.palette.background);
+            gl::ClearColor(r * alpha, g * alpha, b * alpha, alpha);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+            if self.shift != 0 {
+                // Keep the moved grid out of the padding
+                gl::Enable(gl::SCISSOR_TEST);
+                gl::Scissor(x, y, width, height);
+                gl::Viewport(x, y + self.shift, width, height);
+            }
+        }
+    }
+
+    fn draw_cells(&mut self, term: &Terminal, frame: &render::Frame) {
+        let renderer = self.renderer;
+        let overlays = &self.overlays;
+        let mut scratch = renderer.scratch.borrow_mut();
+        let scratch = &mut *scratch;
+        let rows = self.redraw.clone().unwrap_or(0..term.rows);
+        trace_span!("build_rows").in_scope(|| {
+            scratch.rows.resize_with(term.rows, RowVertices::default);
+            for row in rows.clone().filter(|&row| self.stale(row)) {
+                build_row(renderer, term, frame, overlays, row, scratch);
+            }
+        });
+        trace_span!("background_pass").in_scope(|| {
+            draw_rect_pass(renderer, &mut scratch.rects, |vertices| {
+                for cached in &scratch.rows[rows.clone()] {
+                    vertices.extend_from_slice(&cached.backgrounds);
+                }
+            })
+        });
+        trace_span!("images_pass").in_scope(|| render_images(renderer, term, true, &mut scratch.batch));
+        trace_span!("selection_pass").in_scope(|| {
+            draw_rect_pass(renderer, &mut scratch.rects, |vertices| {
+                selection_pass(renderer, term, overlays, rows.clone(), vertices)
+            })
+        });
+        trace_span!("text_pass").in_scope(|| {
+            scratch.rects.clear();
+            scratch.batch.clear();
+            for cached in &scratch.rows[rows.clone()] {
+                scratch.rects.extend_from_slice(&cached.rects);
+                scratch.batch.quads.extend_from_slice(&cached.glyphs);
+            }
+            draw_rects(renderer, &scratch.rects);
+            draw_text(renderer, &mut scratch.batch);
+        });
+        trace_span!("images_pass").in_scope(|| render_images(renderer, term, false, &mut scratch.batch));
+    }
+
+    fn draw_cursor(&mut self, term: &Terminal, frame: &render::Frame, cursor: &Cursor) {
+        let renderer = self.renderer;
+        let mut scratch = renderer.scratch.borrow_mut();
+        let scratch = &mut *scratch;
+        let _pass = trace_span!("cursor_pass").entered();
+        draw_rect_pass(renderer, &mut scratch.rects, |vertices| {
+            cursor_pass(renderer.viewport, term, frame, cursor, &renderer.cursor_style, vertices)
+        });
+        if !cursor.hollow && renderer.cursor_style.opaque() {
+            // The text under a block cursor in a color that stands out on it
+            scratch.rects.clear();
+            scratch.batch.clear();
+            let (row, col) = (cursor.row, cursor.col);
+            text_pass(renderer, term, frame, &self.overlays, row..row + 1, Some((row, col)), scratch);
+            draw_rects(renderer, &scratch.rects);
+            draw_text(renderer, &mut scratch.batch);
+        }
+    }
+
+    fn draw_overlays(&mut self, term: &Terminal, frame: &render::Frame) {
+        let renderer = self.renderer;
+        let mut scratch = renderer.scratch.borrow_mut();
+        let scratch = &mut *scratch;
+        if !frame.focused && renderer.unfocused_dim > 0.0 {
+            trace_span!("dim_pass").in_scope(|| {
+                draw_rect_pass(renderer, &mut scratch.rects, |vertices| dim_pass(term, renderer.unfocused_dim, vertices))
+            });
+        }
+        if let Some(scrollbar) = &frame.scrollbar {
+            trace_span!("scrollbar_pass").in_scope(|| {
+                draw_rect_pass(renderer, &mut scratch.rects, |vertices| {
+                    scrollbar_pass(renderer.viewport, term, scrollbar, vertices)
+                })
+            });
+        }
+        trace_span!("overlay_pass").in_scope(|| {
+            scratch.rects.clear();
+            scratch.batch.clear();
+            overlay_pass(renderer, term, &self.overlays, &mut scratch.rects, &mut scratch.batch);
+            draw_rects(renderer, &scratch.rects);
+            draw_text(renderer, &mut scratch.batch);
+        });
+    }
+
+    fn read_pixels(&mut self) -> Option<render::Screenshot> {
+        let (width, height) = self.window.borrow().get_framebuffer_size();
+        let (width, height) = (width.max(0) as usize, height.max(0) as usize);
+        // Minimized
+        if width == 0 || height == 0 {
+            return None;
+        }
+        let mut pixels = vec![0u8; width * height * 4];
+        unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.renderer.retained.borrow().framebuffer);
+            gl::ReadBuffer(gl::COLOR_ATTACHMENT0);
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+            gl::ReadPixels(
+                0,
+                0,
+                width as i32,
+                height as i32,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                pixels.as_mut_ptr() as *mut c_void,
+            );
+        }
+        // OpenGL reads from the bottom up, and a transparent window's
+        // alpha isn't what the picture should have
+        let mut rgba = Vec::with_capacity(pixels.len());
+        for row in pixels.chunks_exact(width * 4).rev() {
+            rgba.extend(row.chunks_exact(4).flat_map(|p| [p[0], p[1], p[2], 255]));
+        }
+        Some(render::Screenshot { width: width as u32, height: height as u32, rgba })
+    }
+
+    fn present(&mut self) {
+        let (x, y, width, height) = self.grid_viewport;
+        unsafe {
+            gl::Viewport(x, y, width, height);
+            gl::Disable(gl::SCISSOR_TEST);
+            self.renderer.retained.borrow().show();
+        }
+        self.renderer.drawn_at.set(Instant::now());
+        self.window.borrow_mut().swap_buffers();
+    }
+}
+
+/// Draw and show a frame of the terminal, with its grid `shift` pixels
+/// up from where it belongs
+fn render(app: &mut AppState, frame: &render::Frame, shift: i32) {
+    let hovered_link = hovered_link(app);
+    let hovered_file_ref = hovered_file_ref(app).map(|(row, file_ref)| (row, file_ref.cols));
+    let _frame = trace_span!("render").entered();
+    let capture = app.screenshot_to.is_some();
+    let restyled = app.prefs.highlights.apply(&app.term);
+    let frame = &render::Frame { restyled: Some(&restyled), ..*frame };
+    let damaged = app.damage.update(&app.term);
+    app.perf_hud.damage(damaged.len(), app.term.rows);
+    let screenshot = match &mut app.renderer {
+        WindowRenderer::OpenGl(renderer) => {
+            let overlays = Overlays {
+                search: &app.search,
+                copy_mode: &app.copy_mode,
+                hints: &app.hints,
+                hovered_link: hovered_link.as_deref(),
+                hovered_file_ref,
+                pending_paste: app.pending_paste.as_deref(),
+                pending_close: app.pending_close.as_deref(),
+                config_errors: &app.config_errors,
+                perf_hud: app.perf_hud.visible.then(|| app.perf_hud.text()),
+            };
+            let grid_viewport = app.ws.borrow().grid_viewport();
+            // Scrolling smoothly, images and whatever is drawn over the
+            // cells take redrawing everything
+            let plain = overlays.is_empty()
+                && frame.scrollbar.is_none()
+                && shift == 0
+                && !capture
+                && app.term.graphics.placements.is_empty();
+            let setup = plain.then(|| FrameSetup {
+                grid_viewport,
+                focused: frame.focused,
+                blink_visible: frame.blink_visible,
+                palette: app.term.palette.clone(),
+            });
+            let mut gl_frame = GlFrame {
+                renderer,
+                overlays,
+                window: &app.ts.window,
+                grid_viewport,
+                shift,
+                setup,
+                damaged: &damaged,
+                redraw: None,
+            };
+            draw_or_capture(&mut gl_frame, &app.term, frame, capture)
+        }
+        #[cfg(feature = "wgpu")]
+        WindowRenderer::Wgpu(renderer) => {
+            renderer.shift = shift;
+            draw_or_capture(renderer, &app.term, frame, capture)
+        }
+        WindowRenderer::Software(renderer) => {
+            renderer.shift = shift;
+            draw_or_capture(renderer, &app.term, frame, capture)
+        }
+    };
+    if let Some(path) = app.screenshot_to.take() {
+        save_screenshot(screenshot, &path);
+    }
+}
+
+fn draw_or_capture(
+    renderer: &mut impl render::Renderer,
+    term: &Terminal,
+    frame: &render::Frame,
+    capture: bool,
+) -> Option<render::Screenshot> {
+    if capture {
+        render::capture(renderer, term, frame)
+    } else {
+        render::draw(renderer, term, frame);
+        None
+    }
+}
+
+fn save_screenshot(screenshot: Option<render::Screenshot>, path: &Path) {
+    let Some(screenshot) = screenshot else {
+        warn!("This renderer can't take screenshots");
+        return;
+    };
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    match screenshot.write_png(path) {
+        Ok(()) => info!("Saved a screenshot to {}", path.display()),
+        Err(e) => error!("Could not save a screenshot to {}: {}", path.display(), e),
+    }
+}
+
+/// The background color laid over the whole grid, fading it out while the
+/// window doesn't have focus
+fn dim_pass(term: &Terminal, amount: f32, vertices: &mut Vec<f32>) {
+    let mut color = color::to_gl(term.palette.background);
+    color[3] = amount;
+    push_rect(vertices, -1.0, -1.0, 2.0, 2.0, color);
+}
+
+/// The scrollbar's thumb over the right edge of the grid, which is
+/// `viewport` pixels big
+fn scrollbar_pass(viewport: (f32, f32), term: &Terminal, scrollbar: &render::Scrollbar, vertices: &mut Vec<f32>) {
+    let Some((top, height)) = render::scrollbar_thumb(term) else {
+        return;
+    };
+    let width = 2.0 * scrollbar.width / viewport.0;
+    // Still big enough to see in a long scrollback
+    let min_height = (2.0 * 2.0 * scrollbar.width / viewport.1).min(2.0);
+    let height = (2.0 * height).max(min_height);
+    let y = (1.0 - 2.0 * top - height).max(-1.0);
+    push_rect(vertices, 1.0 - width, y, width, height, scrollbar.color);
+}
+
+/// Cell backgrounds of `rows`. The default background is what the window
+/// is cleared to, only other colors are drawn, with runs of the same color
+/// merged into one quad.
+fn background_pass(term: &Terminal, frame: &render::Frame, rows: std::ops::Range<usize>, vertices: &mut Vec<f32>) {
+    let palette = &term.palette;
+    let cell_width = 2.0 / term.cols as f32;
+    let cell_height = 2.0 / term.rows as f32;
+
+    for row in rows {
+        let bottom = 1.0 - (row + 1) as f32 * cell_height;
+        let mut run: Option<(usize, color::Rgb)> = None;
+        let cells = frame.row(term, row);
+        for col in 0..=cells.len() {
+            let bg = cells.get(col).map(|cell| palette.cell_colors(cell).1).filter(|&bg| bg != palette.background);
+            match run {
+                Some((_, color)) if bg == Some(color) => continue,
+                Some((start, color)) => {
+                    let x = -1.0 + start as f32 * cell_width;
+                    push_rect(vertices, x, bottom, (col - start) as f32 * cell_width, cell_height, color::to_gl(color));
+                }
+                None => {}
+            }
+            run = bg.map(|bg| (col, bg));
+        }
+    }
+}
+
+/// Search matches, the selection, and the cursor of copy mode in `rows`
+fn selection_pass(
+    renderer: &GlRenderer,
+    term: &Terminal,
+    overlays: &Overlays,
+    rows: std::ops::Range<usize>,
+    vertices: &mut Vec<f32>,
+) {
+    let cell_width = 2.0 / term.cols as f32;
+    let cell_height = 2.0 / term.rows as f32;
+    let copy_mode = overlays.copy_mode;
+
+    for row in rows {
+        let line = term.first_visible_line() + row;
+        let bottom = 1.0 - (row + 1) as f32 * cell_height;
+        for col in 0..term.cols {
+            let x = -1.0 + col as f32 * cell_width;
+            let point = selection::Point { line, col };
+            for span in term.spans.at(point) {
+                if let span::SpanKind::SearchMatch { current } = span.kind {
+                    let color = if current { SEARCH_CURRENT_COLOR } else { SEARCH_MATCH_COLOR };
+                    push_rect(vertices, x, bottom, cell_width, cell_height, color);
+                }
+            }
+            if overlays.selected(point) {
+                push_rect(vertices, x, bottom, cell_width, cell_height, renderer.selection_background);
+            }
+            if copy_mode.active && copy_mode.cursor == point {
+                push_rect(vertices, x, bottom, cell_width, cell_height, COPY_MODE_CURSOR_COLOR);
+            }
+        }
+    }
+}
+
+/// The glyphs of `rows`, and what is drawn as flat rectangles along with
+/// them: box drawing characters, underlines and strikethrough. With
+/// `under_cursor` only that cell is drawn, in the colors it has under a
+/// block cursor.
+fn text_pass(
+    renderer: &GlRenderer,
+    term: &Terminal,
+    frame: &render::Frame,
+    overlays: &Overlays,
+    rows: std::ops::Range<usize>,
+    under_cursor: Option<(usize, usize)>,
+    scratch: &mut Scratch,
+) {
+    let Scratch { rects: vertices, batch, shapes, .. } = scratch;
+    // The text under a block cursor is drawn whether it blinks or not
+    let blink_visible = frame.blink_visible || under_cursor.is_some();
+    for row in rows.clone() {
+        let cells = frame.row(term, row);
+        let chars = cells.iter().flat_map(|cell| std::iter::once(cell.c).chain(cell.zerowidth().iter().copied()));
+        renderer.load_characters(chars);
+    }
+    let characters = renderer.font_characters.borrow();
+
+    for row in rows {
+        let cells = frame.row(term, row);
+        push_shaped_row(batch, renderer, term, overlays, (row, &cells), under_cursor, blink_visible);
+
+        for (col, cell) in cells.iter().enumerate() {
+            if under_cursor.is_some_and(|(_, cursor_col)| col != cursor_col) || !render::text_visible(cell, blink_visible) {
+                continue;
+            }
+            let fg = text_color(renderer, term, overlays, cell, (row, col), under_cursor);
+
+            if renderer.shaper.is_none() && !cell.flags.contains(term::Flags::WIDE_CHAR_SPACER) {
+                let columns = if cell.flags.contains(term::Flags::WIDE_CHAR) { 2 } else { 1 };
+                let color = color::to_gl(fg);
+                if cell.c != ' ' && !box_drawing::is_box_drawing(cell.c) {
+                    if let Some(ftchar) = characters.get_or(cell.c, '?') {
+                        batch.push_glyph(renderer.viewport, ftchar, color, (row, col), columns, term);
+                    }
+                }
+
+                // Combining marks are drawn over the base character in the same cell
+                for mark in cell.zerowidth() {
+                    if let Some(ftchar) = characters.get(*mark) {
+                        batch.push_glyph(renderer.viewport, ftchar, color, (row, col), columns, term);
+                    }
+                }
+            }
+
+            if box_drawing::is_box_drawing(cell.c) {
+                push_box_drawing(vertices, shapes, cell.c, (row, col), term, renderer.viewport, color::to_gl(fg));
+            }
+
+            let hovered = overlays
+                .hovered_link
+                .is_some_and(|hovered| cell.hyperlink().is_some_and(|link| link.is_same(hovered)))
+                || overlays.hovered_file_ref.as_ref().is_some_and(|(r, cols)| *r == row && cols.contains(&col));
+            let flags = if hovered { cell.flags | term::Flags::UNDERLINE } else { cell.flags };
+            push_decorations(vertices, term, cell, flags, (row, col), color::to_gl(fg));
+        }
+    }
+}
+
+/// Build the vertices of the cells of the row at `row` into its place in
+/// the cache
+fn build_row(
+    renderer: &GlRenderer,
+    term: &Terminal,
+    frame: &render::Frame,
+    overlays: &Overlays,
+    row: usize,
+    scratch: &mut Scratch,
+) {
+    scratch.rects.clear();
+    scratch.batch.clear();
+    text_pass(renderer, term, frame, overlays, row..row + 1, None, scratch);
+    let cached = &mut scratch.rows[row];
+    cached.backgrounds.clear();
+    background_pass(term, frame, row..row + 1, &mut cached.backgrounds);
+    cached.rects.clone_from(&scratch.rects);
+    cached.glyphs.clone_from(&scratch.batch.quads);
+}
+
+/// A box drawing character as flat shapes filling the cell at `(row, col)`
+fn push_box_drawing(
+    vertices: &mut Vec<f32>,
+    shapes: &mut Vec<box_drawing::Shape>,
+    c: char,
+    (row, col): (usize, usize),
+    term: &Terminal,
+    (width, height): (f32, f32),
+    color: [f32; 4],
+) {
+    let cell_width = 2.0 / term.cols as f32;
+    let cell_height = 2.0 / term.rows as f32;
+    let cell_width_px = width / term.cols as f32;
+    let cell_height_px = height / term.rows as f32;
+    let x = -1.0 + col as f32 * cell_width;
+    let top = 1.0 - row as f32 * cell_height;
+    let to_ndc = |(px, py): (f32, f32)| (x + px / cell_width_px * cell_width, top - py / cell_height_px * cell_height);
+
+    shapes.clear();
+    box_drawing::shapes(c, cell_width_px, cell_height_px, shapes);
+    for shape in shapes.iter() {
+        let mut color = color;
+        color[3] *= shape.alpha;
+        push_quad(vertices, shape.corners.map(to_ndc), color);
+    }
+}
+
+/// The underlines and strikethrough of the cell at `(row, col)` that
+/// `flags` asks for
+fn push_decorations(
+    vertices: &mut Vec<f32>,
+    term: &Terminal,
+    cell: &term::Cell,
+    flags: term::Flags,
+    (row, col): (usize, usize),
+    fg: [f32; 4],
+) {
+    if !flags.intersects(term::Flags::ALL_UNDERLINES | term::Flags::STRIKEOUT) {
+        return;
+    }
+    let palette = &term.palette;
+    let cell_width = 2.0 / term.cols as f32;
+    let cell_height = 2.0 / term.rows as f32;
+    let thickness = cell_height * 0.06;
+    let x = -1.0 + col as f32 * cell_width;
+    let bottom = 1.0 - (row + 1) as f32 * cell_height;
+    let underline_color = cell
+        .underline_color()
+        .map(|c| color::to_gl(palette.resolve(c, palette.foreground)))
+        .unwrap_or(fg);
+
+    // The baseline sits 20% up the cell, underlines go just below it
+    let underline_y = bottom + cell_height * 0.12;
+    if flags.contains(term::Flags::UNDERLINE) {
+        push_rect(vertices, x, underline_y, cell_width, thickness, underline_color);
+    }
+    if flags.contains(term::Flags::DOUBLE_UNDERLINE) {
+        push_rect(vertices, x, underline_y, cell_width, thickness, underline_color);
+        push_rect(vertices, x, underline_y - thickness * 2.0, cell_width, thickness, underline_color);
+    }
+    if flags.contains(term::Flags::UNDERCURL) {
+        let amplitude = cell_height * 0.04;
+        push_undercurl(vertices, x, underline_y, cell_width, amplitude, thickness, underline_color);
+    }
+    if flags.contains(term::Flags::STRIKEOUT) {
+        push_rect(vertices, x, bottom + cell_height * 0.45, cell_width, thickness, fg);
+    }
+}
+
+fn push_shaped_row(
+    batch: &mut GlyphBatch,
+    renderer: &GlRenderer,
+    term: &Terminal,
+    overlays: &Overlays,
+    (row, cells): (usize, &[term::Cell]),
+    under_cursor: Option<(usize, usize)>,
+    blink_visible: bool,
+) {
+    // Only with ligatures on
+    let Some(shaper) = &renderer.shaper else {
+        return;
+    };
+    // Ligatures are broken up under the cursor so it is clear which
+    // character it is on
+    let cursor_col = if term.cursor_view_row() == Some(row) {
+        Some(term.cursor.col)
+    } else {
+        None
+    };
+
+    let mut shaped_glyphs = renderer.shaped_glyphs.borrow_mut();
+    for glyph in shaper.shape_row(cells, cursor_col) {
+        if under_cursor.is_some_and(|(_, col)| glyph.col != col) {
+            continue;
+        }
+        let cell = &cells[glyph.col];
+        let blank = cell.c == ' ' || box_drawing::is_box_drawing(cell.c);
+        if (blank && cell.zerowidth().is_empty()) || !render::text_visible(cell, blink_visible) {
+            continue;
+        }
+        let fg = color::to_gl(text_color(renderer, term, overlays, cell, (row, glyph.col), under_cursor));
+        // A glyph that couldn't be loaded is remembered as an empty one
+        let ftchar = shaped_glyphs.entry(glyph.glyph_id).or_insert_with(|| {
+            unsafe { load_glyph_by_index(renderer.ft_face, glyph.glyph_id, renderer.glyph_options) }.unwrap_or_default()
+        });
+        batch.push_glyph(renderer.viewport, ftchar, fg, (row, glyph.col), glyph.columns, term);
+    }
+}
+
+/// The color of the text in the cell at `(row, col)`, taking the cursor
+/// and the selection into account
+fn text_color(
+    renderer: &GlRenderer,
+    term: &Terminal,
+    overlays: &Overlays,
+    cell: &term::Cell,
+    (row, col): (usize, usize),
+    block_cursor: Option<(usize, usize)>,
+) -> color::Rgb {
+    let under_cursor = block_cursor == Some((row, col));
+    let point = selection::Point { line: term.first_visible_line() + row, col };
+    match renderer.selection_foreground {
+        Some(color) if !under_cursor && overlays.selected(point) => color,
+        _ if under_cursor => renderer.cursor_style.colors(&term.palette, cell).1,
+        _ => term.palette.cell_colors(cell).0,
+    }
+}
+
+/// A block over the cursor's cell in the colors of `style`, or a one pixel
+/// outline around it
+fn cursor_pass(
+    viewport: (f32, f32),
+    term: &Terminal,
+    frame: &render::Frame,
+    cursor: &Cursor,
+    style: &render::CursorStyle,
+    vertices: &mut Vec<f32>,
+) {
+    let cell = &frame.row(term, cursor.row)[cursor.col];
+    let mut color = color::to_gl(style.colors(&term.palette, cell).0);
+    color[3] = style.opacity;
+    let cell_height = 2.0 / term.rows as f32;
+    let width = 2.0 / term.cols as f32 * cursor.width as f32;
+    let x = -1.0 + cursor.col as f32 * 2.0 / term.cols as f32;
+    let y = 1.0 - (cursor.row + 1) as f32 * cell_height;
+
+    if cursor.hollow {
+        let line_width = 2.0 / viewport.0;
+        let line_height = 2.0 / viewport.1;
+        push_rect(vertices, x, y + cell_height - line_height, width, line_height, color);
+        push_rect(vertices, x, y, width, line_height, color);
+        push_rect(vertices, x, y, line_width, cell_height, color);
+        push_rect(vertices, x + width - line_width, y, line_width, cell_height, color);
+    } else {
+        push_rect(vertices, x, y, width, cell_height, color);
+    }
+}
+
+/// The search bar and hint labels, drawn over everything else
+fn overlay_pass(renderer: &GlRenderer, term: &Terminal, overlays: &Overlays, vertices: &mut Vec<f32>, batch: &mut GlyphBatch) {
+    if overlays.search.open {
+        push_search_bar(vertices, batch, renderer, overlays.search, term);
+    }
+    if overlays.hints.active {
+        push_hints(vertices, batch, renderer, overlays.hints, term);
+    }
+    if let Some(error) = overlays.config_errors.first() {
+        let more = match overlays.config_errors.len() {
+            1 => String::new(),
+            n => format!("and {} more", n - 1),
+        };
+        push_status_bar(vertices, batch, renderer, term, error, &more);
+    }
+    if let Some(text) = overlays.pending_paste {
+        push_status_bar(vertices, batch, renderer, term, &paste::prompt(text), "");
+    }
+    if let Some(program) = overlays.pending_close {
+        let prompt = format!("{} is still running. Close the window? [y/n]", program);
+        push_status_bar(vertices, batch, renderer, term, &prompt, "");
+    }
+    if let Some(text) = overlays.perf_hud {
+        push_perf_hud(vertices, batch, renderer, term, text);
+    }
+}
+
+/// The query and the match count, drawn over the bottom row while searching
+fn push_search_bar(vertices: &mut Vec<f32>, batch: &mut GlyphBatch, renderer: &GlRenderer, search: &Search, term: &Terminal) {
+    let query = format!("Search: {}{}", search.query, if search.editing { "_" } else { "" });
+    let status = match (search.current, search.current_older_line()) {
+        // Not on the screen, the part of its line from the match on is
+        // shown instead
+        (Some(current), Some(line)) => {
+            let room = term.cols.saturating_sub(query.chars().count() + 12);
+            let excerpt: String = line.chars().skip(search.matches[current].start).take(room).collect();
+            format!("{}  {}/{}", excerpt, current + 1, search.matches.len())
+        }
+        (Some(current), None) => format!("{}/{}", current + 1, search.matches.len()),
+        (None, _) if search.query.is_empty() => String::new(),
+        (None, _) => "No matches".to_string(),
+    };
+    push_status_bar(vertices, batch, renderer, term, &query, &status);
+}
+
+/// A bar over the bottom row with `left` and `right` at either end
+fn push_status_bar(vertices: &mut Vec<f32>, batch: &mut GlyphBatch, renderer: &GlRenderer, term: &Terminal, left: &str, right: &str) {
+    push_rect(vertices, -1.0, -1.0, 2.0, 2.0 / term.rows as f32, SEARCH_BAR_COLOR);
+
+    let right_col = term.cols.saturating_sub(right.chars().count());
+    let text = left
+        .chars()
+        .enumerate()
+        .chain(right.chars().enumerate().map(|(i, c)| (right_col + i, c)));
+
+    renderer.load_characters(left.chars().chain(right.chars()));
+    let characters = renderer.font_characters.borrow();
+    let color = color::to_gl(term.palette.foreground);
+    let row = term.rows - 1;
+    for (col, c) in text {
+        if col >= term.cols || c == ' ' {
+            continue;
+        }
+        if let Some(ftchar) = characters.get_or(c, '?') {
+            batch.push_glyph(renderer.viewport, ftchar, color, (row, col), 1, term);
+        }
+    }
+}
+
+/// The performance overlay, in the top right corner
+fn push_perf_hud(vertices: &mut Vec<f32>, batch: &mut GlyphBatch, renderer: &GlRenderer, term: &Terminal, text: &str) {
+    let len = text.chars().count().min(term.cols);
+    let first_col = term.cols - len;
+    let cell_width = 2.0 / term.cols as f32;
+    let cell_height = 2.0 / term.rows as f32;
+    let x = -1.0 + first_col as f32 * cell_width;
+    push_rect(vertices, x, 1.0 - cell_height, len as f32 * cell_width, cell_height, SEARCH_BAR_COLOR);
+
+    let characters = renderer.font_characters.borrow();
+    let color = color::to_gl(term.palette.foreground);
+    for (i, c) in text.chars().take(len).enumerate() {
+        if c == ' ' {
+            continue;
+        }
+        if let Some(ftchar) = characters.get_or(c, '?') {
+            batch.push_glyph(renderer.viewport, ftchar, color, (0, first_col + i), 1, term);
+        }
+    }
+}
+
+/// Hint labels, drawn over the start of their targets
+fn push_hints(vertices: &mut Vec<f32>, batch: &mut GlyphBatch, renderer: &GlRenderer, hints: &Hints, term: &Terminal) {
+    let cell_width = 2.0 / term.cols as f32;
+    let cell_height = 2.0 / term.rows as f32;
+    let first_line = term.first_visible_line();
+    let characters = renderer.font_characters.borrow();
+
+    for hint in hints.visible() {
+        let row = hint.line - first_line;
+        // Only the part of the label left to type is shown
+        for (i, c) in hint.label[hints.typed.len()..].chars().enumerate() {
+            let col = hint.start + i;
+            if col >= term.cols {
+                break;
+            }
+            let x = -1.0 + col as f32 * cell_width;
+            let bottom = 1.0 - (row + 1) as f32 * cell_height;
+            push_rect(vertices, x, bottom, cell_width, cell_height, HINT_LABEL_COLOR);
+            if let Some(ftchar) = characters.get(c) {
+                batch.push_glyph(renderer.viewport, ftchar, HINT_TEXT_COLOR, (row, col), 1, term);
+            }
+        }
+    }
+}
+
+/// Images placed with the kitty graphics protocol, those with a negative
+/// z-index under the text and the rest on top of it
+fn render_images(renderer: &GlRenderer, term: &Terminal, below_text: bool, batch: &mut GlyphBatch) {
+    let graphics = &term.graphics;
+    let mut textures = renderer.image_textures.borrow_mut();
+    // Drop the textures of images that were deleted or retransmitted
+    textures.retain(|id, (generation, texture)| {
+        let current = graphics.images.get(id).is_some_and(|image| image.generation == *generation);
+        if !current {
+            unsafe { gl::DeleteTextures(1, texture) };
+        }
+        current
+    });
+
+    let mut placements: Vec<&graphics::Placement> =
+        graphics.placements.iter().filter(|p| (p.z < 0) == below_text).collect();
+    if placements.is_empty() {
+        return;
+    }
+    placements.sort_by_key(|p| p.z);
+
+    let cell_width = 2.0 / term.cols as f32;
+    let cell_height = 2.0 / term.rows as f32;
+    let pixel_width = cell_width / term.cell_size.0.max(1) as f32;
+    let pixel_height = cell_height / term.cell_size.1.max(1) as f32;
+    let first_line = term.first_visible_line() as isize;
+
+    // Images overlapping each other have to be drawn in z order, so each
+    // gets its own draw rather than being grouped by texture
+    for placement in placements {
+        let row = placement.line as isize - first_line;
+        if row >= term.rows as isize || row + placement.rows as isize <= 0 {
+            continue;
+        }
+        let Some(image) = graphics.images.get(&placement.image_id) else {
+            continue;
+        };
+        let (_, texture) = *textures.entry(image.id).or_insert_with(|| (image.generation, upload_image(image)));
+
+        let left = -1.0 + placement.col as f32 * cell_width + placement.offset.0 as f32 * pixel_width;
+        let top = 1.0 - row as f32 * cell_height - placement.offset.1 as f32 * pixel_height;
+        let width = placement.width as f32 * pixel_width;
+        let height = placement.height as f32 * pixel_height;
+
+        let (x, y, source_width, source_height) = placement.source;
+        let uv = [
+            x as f32 / image.width as f32,
+            y as f32 / image.height as f32,
+            (x + source_width) as f32 / image.width as f32,
+            (y + source_height) as f32 / image.height as f32,
+        ];
+
+        batch.clear();
+        batch.push(texture, (left, top - height, width, height), uv, [1.0; 4]);
+        draw_glyphs(renderer, &renderer.image_shader, batch);
+    }
+}
+
+fn upload_image(image: &graphics::Image) -> u32 {
+    let mut texture: u32 = 0;
+    unsafe {
+        gl::GenTextures(1, &mut texture);
+        gl::BindTexture(gl::TEXTURE_2D, texture);
+        gl::TexImage2D(
+            gl::TEXTURE_2D,
+            0,
+            gl::RGBA as i32,
+            image.width as i32,
+            image.height as i32,
+            0,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            image.rgba.as_ptr() as *const c_void,
+        );
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+    }
+    texture
+}
+
+fn init_opengl(window: &mut glfw::PWindow) {
+    gl::load_with(|symbol| window.get_proc_address(symbol) as *const _);
+    gl_context::enable_debug_output();
+}
+
+fn check_gl_errors() {
+    if gl_context::debug_output() {
+        return;
+    }
+    let err = unsafe { gl::GetError() };
+    if err != gl::NO_ERROR {
+        error!("GL error: {:?}", err);
+    }
+}
+
+/// The character a key types with the given modifiers held
+fn key_text(key: glfw::Key, modifiers: glfw::Modifiers) -> Option<char> {
+    let symbol = key > glfw::Key::Z || key < glfw::Key::A;
+    if symbol {
+        key_to_symbol(key)
+    } else if modifiers.contains(glfw::Modifiers::Shift) && modifiers.contains(glfw::Modifiers::CapsLock) {
+        key_to_char(key)
+    } else if modifiers.contains(glfw::Modifiers::Shift) || modifiers.contains(glfw::Modifiers::CapsLock) {
+        key_to_capital_char(key)
+    } else {
+        key_to_char(key)
+    }
+}
+
+fn key_to_capital_char(key: glfw::Key) -> Option<char> {
+    match key {
+        glfw::Key::A => Some('A'),
+        glfw::Key::B => Some('B'),
+        glfw::Key::C => Some('C'),
+        glfw::Key::D => Some('D'),
+        glfw::Key::E => Some('E'),
+        glfw::Key::F => Some('F'),
+        glfw::Key::G => Some('G'),
+        glfw::Key::H => Some('H'),
+        glfw::Key::I => Some('I'),
         glfw::Key::J => Some('J'),
         glfw::Key::K => Some('K'),
         glfw::Key::L => Some('L'),
@@ -518,6 +2266,22 @@ fn key_to_symbol(key: glfw::Key) -> Option<char> {
         glfw::Key::Num8 => Some('8'),
         glfw::Key::Num9 => Some('9'),
         glfw::Key::Num0 => Some('0'),
+        glfw::Key::Kp1 => Some('1'),
+        glfw::Key::Kp2 => Some('2'),
+        glfw::Key::Kp3 => Some('3'),
+        glfw::Key::Kp4 => Some('4'),
+        glfw::Key::Kp5 => Some('5'),
+        glfw::Key::Kp6 => Some('6'),
+        glfw::Key::Kp7 => Some('7'),
+        glfw::Key::Kp8 => Some('8'),
+        glfw::Key::Kp9 => Some('9'),
+        glfw::Key::Kp0 => Some('0'),
+        glfw::Key::KpDecimal => Some('.'),
+        glfw::Key::KpDivide => Some('/'),
+        glfw::Key::KpMultiply => Some('*'),
+        glfw::Key::KpSubtract => Some('-'),
+        glfw::Key::KpAdd => Some('+'),
+        glfw::Key::KpEqual => Some('='),
         glfw::Key::Semicolon => Some(';'),
         glfw::Key::Comma => Some(','),
         glfw::Key::Period => Some('.'),
@@ -550,81 +2314,26 @@ fn translation_matrix(dx: f32, dy: f32, width: f32, height: f32) -> [[f32; 4]; 4
     ]
 }
 
-fn set_uniform_mat4(s: &Shader, uniform_name: std::ffi::CString, transform: [[f32; 4]; 4]) {
-    let location = unsafe { gl::GetUniformLocation(*s.get_id(), uniform_name.as_ptr()) };
-    unsafe {
-        gl::UniformMatrix4fv(location, 1, gl::FALSE, transform.as_ptr() as *const f32);
-    }
-}
-
-fn render_cursor(s: &Shader, vao: u32) {
-    s.use_shader();
-
-    unsafe {
-        gl::BindVertexArray(vao);
-        gl::DrawElements(gl::TRIANGLES, 6, gl::UNSIGNED_INT, std::ptr::null());
-        gl::BindVertexArray(0);
-    }
-}
-
-fn calculate_cursor_vertices(
-    _window_width: f32,
-    _window_height: f32,
-    nrows: usize,
-    ncols: usize,
-    cell: (usize, usize),
-) -> ([f32; 12], [u32; 6]) {
-    let (row, col) = cell;
-
-    // Calculate cell size in normalized coordinates
-    let cell_width = 2.0 / ncols as f32;
-    let cell_height = 2.0 / nrows as f32;
-
-    // Calculate bottom-left corner in normalized coordinates
-    let x = -1.0 + col as f32 * cell_width;
-    let y = 1.0 - (row + 1) as f32 * cell_height;
-
-    // Create the vertex positions for the cell
-    let vertices = [
-        x,
-        y + cell_height,
-        0.0, // Top left
-        x + cell_width,
-        y + cell_height,
-        0.0, // Top right
-        x,
-        y,
-        0.0, // Bottom left
-        x + cell_width,
-        y,
-        0.0, // Bottom right
-    ];
-
-    // Define the indices for two triangles forming a rectangle
-    let indices = [
-        0, 1, 2, // First triangle
-        1, 2, 3, // Second triangle
-    ];
-
-    (vertices, indices)
-}
-
-fn calculate_textured_quad_vertices(
+/// Where a glyph goes in the cell at `(row, col)`: its bottom left corner,
+/// width and height in normalized device coordinates
+fn glyph_quad(
     cell: (usize, usize),
+    columns: usize,
     character: &Character,
     window_width: f32,
     window_height: f32,
     nrows: usize,
     ncols: usize
-) -> ([f32; 20], [u32; 6]) {
+) -> (f32, f32, f32, f32) {
     let (row, col) = cell;
 
-    // Cell dimensions
-    let cell_width = 2.0 / ncols as f32;
+    // Cell dimensions, wide characters span two columns
+    let column_width = 2.0 / ncols as f32;
+    let cell_width = column_width * columns as f32;
     let cell_height = 2.0 / nrows as f32;
 
     // Top-left corner of the cell
-    let cell_x = -1.0 + col as f32 * cell_width;
+    let cell_x = -1.0 + col as f32 * column_width;
     let cell_y = 1.0 - (row as f32 + 1.0) * cell_height;
 
     let normalized_advance = (character.advance >> 6) as f32 / (window_width * 2.0);
@@ -654,179 +2363,669 @@ fn calculate_textured_quad_vertices(
     // so glyphs that go under the baseline overflow the cell
     let char_y = cell_y + baseline_offset - char_height + (cell_height * 0.2);
 
+    (char_x, char_y, char_width, char_height)
+}
 
-    let vertices = [
-        char_x,
-        char_y + char_height,
-        0.0,
-        0.0,
-        0.0,
-        char_x + char_width,
-        char_y + char_height,
-        0.0,
-        1.0,
-        0.0,
-        char_x,
-        char_y,
-        0.0,
-        0.0,
-        1.0,
-        char_x + char_width,
-        char_y,
-        0.0,
-        1.0,
-        1.0,
-    ];
-
-    let indices = [
-        0, 1, 2, // First triangle
-        1, 2, 3, // Second triangle
-    ];
-
-    (vertices, indices)
-}
-
-fn set_renderer_vertices(vao: u32, vbo: u32, vertices: &[f32], _indices: &[u32]) {
-    unsafe {
-        gl::BindVertexArray(vao);
-        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
-        gl::BufferData(
-            gl::ARRAY_BUFFER,
-            (std::mem::size_of::<f32>() * vertices.len()) as isize,
-            vertices.as_ptr() as *const c_void,
-            gl::STATIC_DRAW,
-        );
+/// Initialize GLFW. On macOS it keeps the working directory it was started
+/// in, rather than moving into the bundle's Resources, and sets up the
+/// default menu bar whose Quit item, and Cmd+Q, ask every window to close.
+/// Hints only count the first time, later windows share the library.
+fn init_glfw_library(decorations: WaylandDecorations) -> Result<glfw::Glfw, glfw::InitError> {
+    // GLFW_WAYLAND_LIBDECOR and GLFW_WAYLAND_DISABLE_LIBDECOR, glfw has no
+    // hint for them. Without libdecor GLFW asks the compositor for server
+    // side decorations, and draws plain ones itself where it has none.
+    if decorations == WaylandDecorations::Server {
+        unsafe { glfw::ffi::glfwInitHint(0x00053001, 0x00038002) };
+    }
+    glfw::init_hint(glfw::InitHint::CocoaChdirResources(false));
+    glfw::init_hint(glfw::InitHint::CocoaMenubar(true));
+    glfw::init_no_callbacks()
+}
 
-        // Unbind
-        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
-        gl::BindVertexArray(0);
+/// How the window is made, from the command line and the config
+struct WindowOptions<'a> {
+    title: &'a str,
+    // WM_CLASS on X11 and the app id on Wayland, what window manager rules
+    // match windows by
+    class: &'a str,
+    transparent: bool,
+    // A title bar and borders, false for a borderless window
+    decorated: bool,
+    always_on_top: bool,
+    wayland_decorations: WaylandDecorations,
+}
+
+impl WindowOptions<'_> {
+    fn from_config<'a>(cli: &'a Cli, config: &'a HashMap<String, String>) -> Result<WindowOptions<'a>, RushError> {
+        Ok(WindowOptions {
+            title: &cli.title,
+            class: cli.class.as_deref().or(config.get("window.class").map(String::as_str)).unwrap_or("rush"),
+            transparent: config_value(config, "background_opacity", 1.0f32)? < 1.0,
+            decorated: config_value(config, "window.decorations", true)?,
+            always_on_top: config_value(config, "window.always_on_top", false)?,
+            wayland_decorations: config_value(config, "wayland_decorations", WaylandDecorations::Client)?,
+        })
     }
 }
 
+// GLFW_WAYLAND_APP_ID, glfw has no hint for it
+const WAYLAND_APP_ID: i32 = 0x00026001;
+
 fn init_glfw(
     window_width: f32,
     window_height: f32,
-) -> (
+    options: &WindowOptions,
+    mut backend: Backend,
+) -> Result<(
     glfw::Glfw,
     glfw::PWindow,
-    glfw::GlfwReceiver<(f64, glfw::WindowEvent)>,
-) {
-    let mut glfw = glfw::init_no_callbacks().unwrap();
-    let (mut window, events) = glfw
-        .create_window(
+    Events,
+    Backend,
+), RushError> {
+    let mut glfw = init_glfw_library(options.wayland_decorations).map_err(|e| RushError::Glfw(format!("{:?}", e)))?;
+    // Make the window bigger on HiDPI monitors where the platform doesn't
+    // do that itself
+    glfw.window_hint(glfw::WindowHint::ScaleToMonitor(true));
+    // A framebuffer of device pixels on Retina displays, the font is
+    // rasterized at the content scale to fill it
+    glfw.window_hint(glfw::WindowHint::CocoaRetinaFramebuffer(true));
+    // Shown once it has been sized to fit the cells
+    glfw.window_hint(glfw::WindowHint::Visible(false));
+    // Needed for the background to be see through with a compositor
+    glfw.window_hint(glfw::WindowHint::TransparentFramebuffer(options.transparent));
+    glfw.window_hint(glfw::WindowHint::Decorated(options.decorated));
+    glfw.window_hint(glfw::WindowHint::Floating(options.always_on_top));
+    glfw.window_hint(glfw::WindowHint::X11ClassName(Some(options.class.to_string())));
+    glfw.window_hint(glfw::WindowHint::X11InstanceName(Some(options.class.to_string())));
+    if let Ok(app_id) = CString::new(options.class) {
+        unsafe { glfw::ffi::glfwWindowHintString(WAYLAND_APP_ID, app_id.as_ptr()) };
+    }
+    let mut created = None;
+    for profile in gl_context::PROFILES.into_iter().filter(|_| backend == Backend::OpenGl) {
+        gl_context::hint(&mut glfw, profile);
+        created = glfw.create_window(
             window_width as u32,
             window_height as u32,
-            "rush",
+            options.title,
             glfw::WindowMode::Windowed,
-        )
-        .expect("Failed to create window.");
+        );
+        if created.is_some() {
+            if profile.es {
+                info!("OpenGL {}.{} core isn't available, using OpenGL ES {}.{}",
+                    gl_context::PROFILES[0].major, gl_context::PROFILES[0].minor, profile.major, profile.minor);
+            }
+            break;
+        }
+    }
+    if backend == Backend::OpenGl && created.is_none() {
+        warn!("Could not create an OpenGL context, drawing in software");
+        backend = Backend::Software;
+    }
+    if backend != Backend::OpenGl {
+        // wgpu and the software renderer bring their own ways of drawing,
+        // the window gets no context
+        glfw.window_hint(glfw::WindowHint::ClientApi(glfw::ClientApiHint::NoApi));
+        // Software frames have no alpha channel to see through
+        glfw.window_hint(glfw::WindowHint::TransparentFramebuffer(options.transparent && backend != Backend::Software));
+        created = glfw.create_window(window_width as u32, window_height as u32, options.title, glfw::WindowMode::Windowed);
+    }
+    let (mut window, events) = created.ok_or(RushError::WindowCreation)?;
 
-    // Make the window's context current
-    window.make_current();
     window.set_key_polling(true);
+    window.set_mouse_button_polling(true);
+    window.set_cursor_pos_polling(true);
+    window.set_scroll_polling(true);
+    window.set_focus_polling(true);
+    window.set_framebuffer_size_polling(true);
+    window.set_pos_polling(true);
+    window.set_size_polling(true);
+    window.set_content_scale_polling(true);
+    window.set_drag_and_drop_polling(true);
+    window.set_refresh_polling(true);
+    window.set_close_polling(true);
     unsafe { 
         glfw::ffi::glfwSetInputMode(glfw::Window::window_ptr(&window), glfw::ffi::LOCK_KEY_MODS, glfw::ffi::TRUE);
     };
-    
-    
-    (glfw, window, events)
+
+    Ok((glfw, window, events, backend))
 }
 
-fn init_glfw_opengl(
-    window_width: f32,
-    window_height: f32,
-) -> (
-    glfw::Glfw,
-    Rc<RefCell<glfw::PWindow>>,
-    glfw::GlfwReceiver<(f64, glfw::WindowEvent)>,
-) {
-    let (glfw, window, events) = init_glfw(window_width, window_height);
-    init_opengl();
+/// Set up OpenGL in `window` and rasterize the font into textures
+fn init_gl_renderer(
+    glfw: &mut glfw::Glfw,
+    window: &mut glfw::PWindow,
+    config: &HashMap<String, String>,
+    settings: &RenderSettings,
+    face: ft::FT_Face,
+    font_path: &str,
+    font_size_px: f32,
+) -> Result<(GlRenderer, CharacterDimensions), RushError> {
+    window.make_current();
+    init_opengl(window);
     unsafe {
-        gl::Viewport(0, 0, window_width as i32, window_height as i32);
+        // Glyphs, shades and translucent overlays are all blended. The
+        // alpha channel adds up rather than being blended like the colors,
+        // so text stays opaque on a translucent background.
+        gl::Enable(gl::BLEND);
+        gl::BlendFuncSeparate(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA, gl::ONE, gl::ONE_MINUS_SRC_ALPHA);
     }
-    (glfw, Rc::new(RefCell::new(window)), events)
+    let shader_dir = config.get("shader_dir").map(PathBuf::from);
+    let shaders = init_shaders(shader_dir.as_deref())?;
+    let mut glyph_options = settings.glyph_options;
+    if glyph_options.antialiasing == Antialiasing::Subpixel && shaders.lcd.is_none() {
+        info!("Subpixel antialiasing isn't available with OpenGL ES, using grayscale");
+        glyph_options.antialiasing = Antialiasing::Grayscale;
+    }
+    // Without vsync frames are only limited by max_fps
+    glfw.set_swap_interval(match settings.vsync {
+        true => glfw::SwapInterval::Sync(1),
+        false => glfw::SwapInterval::None,
+    });
+    let ligatures = config.get("ligatures").map(|v| v == "true").unwrap_or(false);
+    let (font_vao, font_vbo) = unsafe { make_text_vao_vbo() };
+    let (rect_vao, rect_vbo) = unsafe { make_rect_vao_vbo() };
+    let (width, height) = window.get_framebuffer_size();
+
+    let renderer = GlRenderer {
+        font_vao,
+        font_vbo,
+        font_vbo_capacity: Cell::new(0),
+        font_shader: shaders.font,
+        lcd_shader: shaders.lcd,
+        glyph_options,
+        text_gamma: settings.text_gamma,
+        unfocused_dim: settings.unfocused_dim,
+        cursor_style: settings.cursor,
+        selection_background: match config_color(config, "selection_background")? {
+            Some(color) => color::to_gl(color),
+            None => SELECTION_COLOR,
+        },
+        selection_foreground: config_color(config, "selection_foreground")?,
+        font_characters: Rc::new(RefCell::new(GlyphStore::new())),
+        missing_characters: RefCell::new(HashSet::new()),
+        ft_face: face,
+        shaper: if ligatures { Some(Shaper::new(font_path)) } else { None },
+        shaped_glyphs: RefCell::new(HashMap::new()),
+        rect_shader: shaders.rect,
+        rect_vao,
+        rect_vbo,
+        rect_vbo_capacity: Cell::new(0),
+        image_shader: shaders.image,
+        image_textures: RefCell::new(HashMap::new()),
+        background_opacity: settings.background_opacity,
+        viewport: (width as f32, height as f32),
+        shader_watcher: shader_dir.as_deref().map(ShaderWatcher::new),
+        draw_calls: Cell::new(0),
+        drawn_at: Cell::new(Instant::now()),
+        scratch: RefCell::new(Scratch::default()),
+        retained: RefCell::new(RetainedFrame::default()),
+    };
+    let char_dim = renderer.load_font(font_size_px)?;
+    check_gl_errors();
+    Ok((renderer, char_dim))
 }
 
-fn init_shaders(dir: &std::path::Path) -> (Shader, Shader) {
-    let font_shader = Shader::new(
-        dir.join("font_shader.vs").to_str().unwrap(),
-        dir.join("font_shader.fs").to_str().unwrap(),
-    );
+// The shaders rush ships with, so it runs from any directory
+const BUILTIN_SHADERS: [(&str, &str); 6] = [
+    ("font_shader.vs", include_str!("../font_shader.vs")),
+    ("font_shader.fs", include_str!("../font_shader.fs")),
+    ("rect_shader.vs", include_str!("../rect_shader.vs")),
+    ("rect_shader.fs", include_str!("../rect_shader.fs")),
+    ("image_shader.fs", include_str!("../image_shader.fs")),
+    ("lcd_font_shader.fs", include_str!("../lcd_font_shader.fs")),
+];
 
-    let cursor_shader = Shader::new(
-        dir.join("cursor_shader.vs").to_str().unwrap(),
-        dir.join("cursor_shader.fs").to_str().unwrap(),
-    );
+struct Shaders {
+    font: Shader,
+    rect: Shader,
+    image: Shader,
+    // Needs dual source blending, which OpenGL ES doesn't have
+    lcd: Option<Shader>,
+}
 
-    (font_shader, cursor_shader)
+/// Build the programs from the built in shaders, or from the files of the
+/// same names in `dir` when the config sets `shader_dir`
+fn init_shaders(dir: Option<&std::path::Path>) -> Result<Shaders, RushError> {
+    let program = |vertex: &str, fragment: &str| match dir {
+        Some(dir) => Shader::new(&dir.join(vertex), &dir.join(fragment)),
+        None => {
+            let source = |name: &str| {
+                let (_, source) = BUILTIN_SHADERS.iter().find(|(file, _)| *file == name).expect("Unknown built in shader");
+                source.to_string()
+            };
+            Shader::from_source(Path::new(vertex), source(vertex), Path::new(fragment), source(fragment))
+        }
+    };
+    let font_shader = program("font_shader.vs", "font_shader.fs")?;
+    let rect_shader = program("rect_shader.vs", "rect_shader.fs")?;
+    // Images are textured quads like glyphs, only sampled in full color
+    let image_shader = program("font_shader.vs", "image_shader.fs")?;
+
+    let lcd_shader = if gl_context::is_gles() { None } else { Some(program("font_shader.vs", "lcd_font_shader.fs")?) };
+
+    // They all sample the texture bound to unit 0
+    font_shader.use_shader();
+    font_shader.set_int("text", 0);
+    image_shader.use_shader();
+    image_shader.set_int("image", 0);
+    if let Some(lcd_shader) = &lcd_shader {
+        lcd_shader.use_shader();
+        lcd_shader.set_int("text", 0);
+    }
+
+    Ok(Shaders { font: font_shader, rect: rect_shader, image: image_shader, lcd: lcd_shader })
 }
 
 fn init_freetype(
     font_path: &str,
-    font_size_px: u32
-) -> (
+    options: GlyphOptions,
+) -> Result<(
     freetype::freetype::FT_Library,
     freetype::freetype::FT_Face,
-    Rc<RefCell<HashMap<char, Character>>>,
-    CharacterDimensions
-) {
-    let lib = init_freetype_lib();
-    let c_font_path = CString::new(font_path).unwrap();
-    let face = create_ft_face(lib, &c_font_path);
-    let (chars, max_width, max_height)= load_font_chars(lib, face, font_size_px);
-    let char_dim = CharacterDimensions {
-        width: max_width as u32, height: max_height as u32
+), RushError> {
+    let lib = init_freetype_lib()?;
+    if options.antialiasing == Antialiasing::Subpixel {
+        // Spreads each subpixel's coverage over its neighbours, without it
+        // edges get colored fringes
+        unsafe { ft::FT_Library_SetLcdFilter(lib, ft::FT_LcdFilter::FT_LCD_FILTER_DEFAULT) };
+    }
+    let face = create_ft_face(lib, font_path)?;
+
+    Ok((lib, face))
+}
+
+/// Report the values in the config that aren't valid, and put back the
+/// built in ones in their place, so a typo doesn't keep windows from
+/// opening. What is wrong is logged and returned, with where it is.
+fn validate_config(cli: &Cli, config: &mut yaml_parser::Config) -> Vec<String> {
+    let mut errors = Vec::new();
+    while let Err(e) = check_config(cli, config) {
+        let RushError::InvalidConfigValue { key, .. } = &e else {
+            // Not about a value, it stops the window opening later on
+            break;
+        };
+        let key = key.clone();
+        let message = config.locate(e).to_string();
+        error!("{}", message);
+        errors.push(message);
+        config.reset(&key);
+    }
+    errors
+}
+
+/// Read every setting a window reads from the config, the first that
+/// isn't valid is the error
+fn check_config(cli: &Cli, config: &HashMap<String, String>) -> Result<(), RushError> {
+    GlyphOptions::from_config(config)?;
+    CellGeometry::from_config(config)?;
+    WindowOptions::from_config(cli, config)?;
+    Preferences::from_config(config)?;
+    ScrollbarFade::from_config(config)?;
+    Dropdown::from_config(config, false)?;
+    cursor_style(config)?;
+    config_value(config, "renderer", Backend::OpenGl)?;
+    config_value(config, "shell_args", pty::Args::default())?;
+    for key in ["font_size", "window.columns", "window.lines", "max_fps"] {
+        config_value(config, key, 0u32)?;
+    }
+    for key in ["background_opacity", "text_gamma", "unfocused_dim", "scrollback_memory"] {
+        config_value(config, key, 0.0f64)?;
+    }
+    let switches = ["vsync", "scrollback_spill", "window.remember_geometry", "window.start_fullscreen", "window.start_maximized"];
+    for key in switches {
+        config_value(config, key, false)?;
+    }
+    for key in ["selection_background", "selection_foreground"] {
+        config_color(config, key)?;
+    }
+    escape_policy(cli, config)?;
+    Ok(())
+}
+
+/// What programs in the window may do with escape sequences: the
+/// `security` section of the config, then --allow and --deny
+fn escape_policy(cli: &Cli, config: &HashMap<String, String>) -> Result<Policy, RushError> {
+    let mut policy = Policy::default();
+    for feature in Feature::ALL {
+        let permission = config_value(config, &format!("security.{}", feature.name()), Permission::Allow)?;
+        policy.set(feature, permission);
+    }
+    for &feature in &cli.allow {
+        policy.set(feature, Permission::Allow);
+    }
+    for &feature in &cli.deny {
+        policy.set(feature, Permission::Deny);
+    }
+    Ok(policy)
+}
+
+/// A setting parsed from the config, `default` when it isn't set
+fn config_value<T>(config: &HashMap<String, String>, key: &str, default: T) -> Result<T, RushError>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    match config.get(key) {
+        Some(value) => value.parse().map_err(|e: T::Err| RushError::InvalidConfigValue {
+            key: key.to_string(),
+            value: value.clone(),
+            reason: e.to_string(),
+        }),
+        None => Ok(default),
+    }
+}
+
+/// How the config colors the cursor: `cursor.color` is a `#rrggbb` color
+/// or `invert` to swap the colors of the cell under it
+fn cursor_style(config: &HashMap<String, String>) -> Result<render::CursorStyle, RushError> {
+    let color = match config.get("cursor.color").map(String::as_str) {
+        None | Some("invert") => None,
+        Some(_) => config_color(config, "cursor.color")?,
     };
+    Ok(render::CursorStyle {
+        color,
+        text_color: config_color(config, "cursor.text_color")?,
+        opacity: config_value(config, "cursor.opacity", 1.0f32)?.clamp(0.0, 1.0),
+    })
+}
 
-    (lib, face, Rc::new(RefCell::new(chars)), char_dim)
+/// A `#rrggbb` color from the config
+fn config_color(config: &HashMap<String, String>, key: &str) -> Result<Option<color::Rgb>, RushError> {
+    match config.get(key) {
+        Some(value) => color::parse_hex(value).map(Some).ok_or_else(|| RushError::InvalidConfigValue {
+            key: key.to_string(),
+            value: value.clone(),
+            reason: "expected a #rrggbb color".to_string(),
+        }),
+        None => Ok(None),
+    }
 }
 
-#[allow(unused)]
-fn init() -> AppState {
-    let config = yaml_parser::parse_config();
-    let font_size = config.get("font_size").expect("Font size not found in config");
-    let font_size_px: u32 = font_size.parse().expect("Invalid font size");
-    let font_path = config.get("font_path").expect("Font path not found in config");
-    let dir = env::current_dir().expect("Could not get current directory");
-    let (glfw, mut window, events) = init_glfw_opengl(800.0, 600.0);
-    let (font_shader, cursor_shader) = init_shaders(&dir);
-    let (lib, face, characters, char_dim) =
-        init_freetype(font_path, font_size_px);
-    let (font_vao, font_vbo) = unsafe { make_text_vao_vbo() };
-    let (cursor_vao, cursor_vbo, ebo) = make_cursor_vao_vbo_ebo();
+/// The program to run: the one given with `-e`, or the shell in the
+/// config, falling back to the user's login shell
+fn shell_command(cli: &Cli, config: &HashMap<String, String>, window_id: Option<u64>) -> pty::Command {
+    let (program, args) = match cli.command.split_first() {
+        Some((program, args)) => (program.clone(), args.to_vec()),
+        None => {
+            let program = config
+                .get("shell")
+                .cloned()
+                .or_else(|| env::var("SHELL").ok())
+                .unwrap_or_else(|| "/bin/sh".to_string());
+            // Terminals on macOS start login shells, there's no display
+            // manager that ran the profile before
+            let args = match config.get("shell_args").map(|args| args.parse()) {
+                Some(Ok(pty::Args(args))) => args,
+                _ if cfg!(target_os = "macos") => vec!["-l".to_string()],
+                _ => Vec::new(),
+            };
+            (program, args)
+        }
+    };
+
+    let working_directory = cli
+        .working_directory
+        .clone()
+        .or_else(|| config.get("working_directory").map(PathBuf::from))
+        .or_else(launched_from_finder);
+    pty::Command { program, args, working_directory, env: pty::child_env(window_id) }
+}
+
+/// Apps opened from the Finder or the Dock start in `/`, their shells
+/// should start at home
+fn launched_from_finder() -> Option<PathBuf> {
+    if !cfg!(target_os = "macos") || env::current_dir().ok()? != Path::new("/") {
+        return None;
+    }
+    env::var_os("HOME").map(PathBuf::from)
+}
+
+/// Menlo, which every Mac has, when the configured font isn't there; the
+/// default font_path is where Linux distributions put FreeMono
+fn platform_font(font_path: &str) -> &str {
+    const MENLO: &str = "/System/Library/Fonts/Menlo.ttc";
+    if cfg!(target_os = "macos") && !Path::new(font_path).exists() {
+        warn!("{} doesn't exist, using {}", font_path, MENLO);
+        return MENLO;
+    }
+    font_path
+}
+
+/// What runs on the PTY while a recording or a benchmark's capture plays:
+/// something that takes the input and discards it, without echoing it back
+fn idle_command() -> pty::Command {
+    pty::Command {
+        program: "/bin/sh".to_string(),
+        args: vec!["-c".to_string(), "stty raw -echo; exec cat > /dev/null".to_string()],
+        working_directory: None,
+        env: Vec::new(),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn window_id(window: &glfw::PWindow) -> Option<u64> {
+    // Zero when the window isn't an X11 one
+    let id = window.get_x11_window() as u64;
+    (id != 0).then_some(id)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn window_id(_window: &glfw::PWindow) -> Option<u64> {
+    None
+}
 
-    // Set up window callbacks
-    window.borrow_mut().set_framebuffer_size_callback({
-        let font_shader = font_shader.clone();
-        move |_window, width, height| unsafe {
-            gl::Viewport(0, 0, width.into(), height.into());
+/// Whether GLFW made a Wayland window, rather than an X11 one through
+/// XWayland
+fn is_wayland(window: &glfw::PWindow) -> bool {
+    cfg!(target_os = "linux") && window_id(window).is_none()
+}
+
+#[allow(unused)]
+fn init(cli: &Cli) -> Result<AppState, RushError> {
+    let mut config = yaml_parser::parse_config(cli.config.as_deref())?;
+    let config_errors = validate_config(cli, &mut config);
+    config.get("font_size").ok_or(RushError::MissingConfigKey("font_size"))?;
+    let font_size_px = config_value(&config, "font_size", 0u32)?.clamp(MIN_FONT_SIZE, MAX_FONT_SIZE);
+    // A family is looked up through fontconfig, a path is used as is
+    let font_path = match config.get("font_family") {
+        Some(family) => &font_lookup::find(family, config.get("font_style").map(String::as_str))?,
+        None => config.get("font_path").ok_or(RushError::MissingConfigKey("font_family"))?,
+    };
+    let font_path = platform_font(font_path);
+    let smooth_scrolling = config.get("smooth_scrolling").map(|v| v == "true").unwrap_or(false);
+    // Turns off animations whatever else the config asks for
+    let reduced_motion = config.get("reduced_motion").map(|v| v == "true").unwrap_or(false);
+    let settings = RenderSettings {
+        glyph_options: GlyphOptions::from_config(&config)?,
+        background_opacity: config_value(&config, "background_opacity", 1.0f32)?.clamp(0.0, 1.0),
+        text_gamma: config_value(&config, "text_gamma", 1.8f32)?.max(0.1),
+        unfocused_dim: config_value(&config, "unfocused_dim", 0.0f32)?.clamp(0.0, 1.0),
+        cursor: cursor_style(&config)?,
+        vsync: config_value(&config, "vsync", true)?,
+    };
+    let backend = match cli.renderer {
+        Some(backend) => backend,
+        None => config_value(&config, "renderer", Backend::OpenGl)?,
+    };
+    let geometry = CellGeometry::from_config(&config)?;
+    let options = WindowOptions::from_config(cli, &config)?;
+    let (mut glfw, window, events, backend) = init_glfw(800.0, 600.0, &options, backend)?;
+    let window = Rc::new(RefCell::new(window));
+    let scale = content_scale(&mut glfw, &window.borrow());
+    let (lib, face) = init_freetype(font_path, settings.glyph_options)?;
+    let scaled_size_px = scaled_font_size(font_size_px, scale);
+    let (renderer, char_dim) = match backend {
+        Backend::OpenGl => {
+            let (renderer, char_dim) = init_gl_renderer(
+                &mut glfw,
+                &mut window.borrow_mut(),
+                &config,
+                &settings,
+                face,
+                font_path,
+                scaled_size_px,
+            )?;
+            (WindowRenderer::OpenGl(renderer), char_dim)
         }
-    });
+        #[cfg(feature = "wgpu")]
+        Backend::Wgpu => {
+            // The atlas holds one channel of coverage
+            let glyph_options = GlyphOptions { antialiasing: Antialiasing::Grayscale, ..settings.glyph_options };
+            let settings = RenderSettings { glyph_options, ..settings };
+            let (renderer, char_dim) = wgpu_renderer::WgpuRenderer::new(window.clone(), face, scaled_size_px, settings)?;
+            (WindowRenderer::Wgpu(renderer), char_dim)
+        }
+        #[cfg(not(feature = "wgpu"))]
+        Backend::Wgpu => return Err(RushError::WgpuUnavailable),
+        Backend::Software => {
+            // Glyphs are blended with one channel of coverage
+            let glyph_options = GlyphOptions { antialiasing: Antialiasing::Grayscale, ..settings.glyph_options };
+            let settings = RenderSettings { glyph_options, ..settings };
+            let (renderer, char_dim) = SoftwareRenderer::new(window.clone(), face, scaled_size_px, settings)?;
+            (WindowRenderer::Software(renderer), char_dim)
+        }
+    };
 
-    let mut ws = Rc::new(RefCell::new(WindowState::new(800.0, 600.0, char_dim)));
-    let app = AppState {
+    let (width, height) = window.borrow().get_framebuffer_size();
+    let mut ws = Rc::new(RefCell::new(WindowState::new(width as f32, height as f32, scale, geometry, char_dim)));
+    ws.borrow_mut().pixel_ratio = pixel_ratio(&window.borrow());
+    // The window was made at a placeholder size before the cells could be
+    // measured. It opens where the last one closed unless a size is asked
+    // for; the dropdown and windows replaying something always place
+    // themselves.
+    let remember_geometry = config_value(&config, "window.remember_geometry", true)?
+        && !cli.toggle
+        && cli.play.is_none()
+        && cli.bench_render.is_none();
+    let size_given = cli.geometry.is_some() || config.contains_key("window.columns") || config.contains_key("window.lines");
+    let saved_geometry = if remember_geometry && !size_given { WindowGeometry::load() } else { None };
+    {
+        let mut ws = ws.borrow_mut();
+        let mut window = window.borrow_mut();
+        match saved_geometry {
+            Some(saved) => {
+                window.set_size(saved.width, saved.height);
+                window.set_pos(saved.x, saved.y);
+                let ratio = ws.pixel_ratio;
+                ws.update_size(saved.width as f32 * ratio, saved.height as f32 * ratio);
+            }
+            None => {
+                let (columns, lines) = match cli.geometry {
+                    Some(geometry) => geometry,
+                    None => (config_value(&config, "window.columns", 80)?, config_value(&config, "window.lines", 24)?),
+                };
+                let (width, height) = ws.size_for(columns.max(1), lines.max(1));
+                ws.update_size(width, height);
+                let (width, height) = (width / ws.pixel_ratio, height / ws.pixel_ratio);
+                window.set_size(width.ceil() as i32, height.ceil() as i32);
+            }
+        }
+    }
+    let (rows, cols) = (ws.borrow().grid.rows, ws.borrow().grid.cols);
+    let cell_size = (ws.borrow().grid.cell_width as u32, ws.borrow().grid.cell_height as u32);
+    let dump = match &cli.dump_output {
+        Some(dir) => {
+            let dump = Dump::create(dir, cli.dump_annotate)
+                .map_err(|source| RushError::Dump { dir: dir.clone(), source })?;
+            eprintln!("Dumping the output to {}", dump.path.display());
+            Some(dump)
+        }
+        None => None,
+    };
+    let player = match &cli.play {
+        Some(path) => Some(Player::new(
+            Cast::open(path).map_err(|source| RushError::Play { path: path.clone(), source })?,
+        )),
+        None => None,
+    };
+    let recorder = match &cli.record {
+        Some(path) => {
+            Some(Recorder::create(path, cols, rows).map_err(|source| RushError::Record { path: path.clone(), source })?)
+        }
+        None => None,
+    };
+    terminfo::install();
+    let command = match player.is_some() || cli.bench_render.is_some() {
+        true => idle_command(),
+        false => shell_command(cli, &config, window_id(&window.borrow())),
+    };
+    let pty = Pty::spawn(&command, rows, cols, cell_size);
+    // A recording plays at the size it was made at
+    let (rows, cols) = player.as_ref().map_or((rows, cols), |player| (player.rows(), player.cols()));
+    let mut term = Terminal::new(rows, cols);
+    term.cell_size = cell_size;
+    term.unhandled.recording = cli.dump_annotate;
+    term.policy = escape_policy(cli, &config)?;
+    let budget_mib: f64 = config_value(&config, "scrollback_memory", 64.0)?;
+    term.scrollback_budget = (budget_mib.max(0.0) * 1024.0 * 1024.0) as usize;
+    if config_value(&config, "scrollback_spill", false)? {
+        if let Err(e) = term.spill_to_disk() {
+            warn!("Could not keep the scrollback on disk: {}", e);
+        }
+    }
+    let mut app = AppState {
         ts: TerminalState {
             cursor_pos: (0, 0),
             glfw,
             events,
             window: window.to_owned(),
+            mouse: MouseState::new(),
+            focused: true,
+            title: cli.title.clone(),
+            shown_title: cli.title.clone(),
+            windowed_geometry: None,
+            restored_geometry: None,
         },
         ws,
-        renderer: Renderer {
-            font_size_px,
-            font_vao,
-            font_vbo,
-            cursor_vao,
-            cursor_vbo,
-            font_shader,
-            font_characters: characters.clone(),
-            cursor_shader,
-            ebo,
-        },
+        term,
+        pty,
+        search: Search::new(),
+        copy_mode: CopyMode::new(),
+        hints: Hints::new(),
+        file_refs: FileRefs::new(),
+        prefs: Preferences::from_config(&config)?,
+        smooth_scroll: SmoothScroll::new(smooth_scrolling && !reduced_motion),
+        scrollbar: ScrollbarFade::from_config(&config)?,
+        daemon: false,
+        // The daemon opens the dropdown window with --toggle
+        dropdown: if cli.toggle { Some(Dropdown::from_config(&config, reduced_motion)?) } else { None },
+        remember_geometry,
+        screenshot_to: None,
+        primary: PrimarySelection::new(),
+        foreground_job: None,
+        pending_close: None,
+        detached: None,
+        config_errors,
+        theme: None,
+        pty_closed: false,
+        exit_status: None,
+        pending_paste: None,
+        dump,
+        recorder,
+        player,
+        perf_hud: PerfHud::new(),
+        damage: Damage::new(),
+        pacing: FramePacing::new(config_value(&config, "max_fps", 0u32)?),
+        blink_visible: true,
+        renderer,
+        font_size_px,
+        default_font_size_px: font_size_px,
     };
+    app.renderer.set_viewport(&app.ws.borrow());
+    if let Some(theme) = app.prefs.themes.dark.clone() {
+        set_theme(&mut app, &theme);
+    }
+    // The dropdown stays hidden until it slides in
+    if app.dropdown.is_none() {
+        app.ts.window.borrow_mut().show();
+        if config_value(&config, "window.start_fullscreen", false)? {
+            toggle_fullscreen(&mut app.ts);
+        } else if config_value(&config, "window.start_maximized", false)? || saved_geometry.is_some_and(|saved| saved.maximized) {
+            app.ts.window.borrow_mut().maximize();
+        }
+    }
 
-    println!("{}", app.ws.borrow().grid);
+    debug!("{}", app.ws.borrow().grid);
 
     // window.borrow_mut().set_key_callback({
     //     // let chars = characters.clone();
@@ -842,137 +3041,1422 @@ fn init() -> AppState {
     //     }
     // });
 
-    app
+    Ok(app)
 }
 
+/// Handle what happened since the last call and draw a frame if that
+/// changed anything. Events are waited for by the caller.
 fn tick(app: &mut AppState) {
-    app.ts.window.borrow_mut().swap_buffers();
-
-    app.ts.glfw.poll_events();
+    let frame_start = Instant::now();
 
-    for (_, event) in glfw::flush_messages(&app.ts.events) {
+    // Collected first, handling them needs the whole app
+    let events: Vec<_> = glfw::flush_messages(&app.ts.events).collect();
+    if !events.is_empty() {
+        app.pacing.request_redraw();
+    }
+    for (_, event) in events {
         match event {
-            glfw::WindowEvent::Key(glfw::Key::Escape, _, glfw::Action::Press, _) => {
-                app.ts.window.borrow_mut().set_should_close(true);
-            }
-
-            glfw::WindowEvent::Key(key, _, glfw::Action::Press | glfw::Action::Repeat, modifiers) => {
-                let mut ws = app.ws.borrow_mut();
-                let ch; 
-                if modifiers.contains(glfw::Modifiers::Shift) && modifiers.contains(glfw::Modifiers::CapsLock) {
-                    if key > glfw::Key::Z || key < glfw::Key::A { 
-                        ch = key_to_symbol(key); 
-                    } else {
-                        ch = key_to_char(key); 
+            glfw::WindowEvent::Key(key, _, action, modifiers) => {
+                // Seen once there's a key pressed, the log still has it
+                if action == glfw::Action::Press {
+                    app.config_errors.clear();
+                }
+                let bound = match action {
+                    glfw::Action::Release => None,
+                    _ => app.prefs.bindings.action(key, modifiers),
+                };
+                // Plain keys belong to search, copy mode and hints while
+                // they're open, chords with a modifier work everywhere
+                let modal = app.search.open
+                    || app.copy_mode.active
+                    || app.hints.active
+                    || app.pending_paste.is_some()
+                    || app.pending_close.is_some();
+                let chord = modifiers.intersects(glfw::Modifiers::Control | glfw::Modifiers::Alt | glfw::Modifiers::Super);
+                match bound {
+                    Some(bound) if !modal || chord => perform_action(app, bound),
+                    _ => handle_key(app, key, action, modifiers),
+                }
+            }
+
+            glfw::WindowEvent::MouseButton(button, action, modifiers) => {
+                let button = match button {
+                    glfw::MouseButton::Button1 => mouse::Button::Left,
+                    glfw::MouseButton::Button2 => mouse::Button::Right,
+                    glfw::MouseButton::Button3 => mouse::Button::Middle,
+                    _ => continue,
+                };
+                let kind = match action {
+                    glfw::Action::Press => mouse::EventKind::Press,
+                    glfw::Action::Release => mouse::EventKind::Release,
+                    glfw::Action::Repeat => continue,
+                };
+                // Clicking a link opens it and a middle click pastes the
+                // primary selection, unless the application wants the clicks
+                if kind == mouse::EventKind::Press && app.term.modes.mouse_tracking == term::MouseTracking::None {
+                    if button == mouse::Button::Left {
+                        if let Some((_, file_ref)) = hovered_file_ref(app) {
+                            open_in_editor(app, &file_ref);
+                            continue;
+                        }
+                        if let Some(link) = hovered_link(app) {
+                            open::open(&link.uri);
+                            continue;
+                        }
                     }
-                } else if modifiers.contains(glfw::Modifiers::Shift) || modifiers.contains(glfw::Modifiers::CapsLock) {
-                    if key > glfw::Key::Z || key < glfw::Key::A { 
-                        ch = key_to_symbol(key); 
-                    } else {
-                        ch = key_to_capital_char(key);
+                    if button == mouse::Button::Middle {
+                        if let Some(text) = app.primary.get() {
+                            paste(app, &text);
+                        }
+                        continue;
                     }
-                } else {
-                    if key > glfw::Key::Z || key < glfw::Key::A { 
-                        ch = key_to_symbol(key); 
-                    } else {
-                        ch = key_to_char(key);
+                }
+
+                // Dragging with the left button selects text, with shift
+                // held when the application wants the clicks, and a block
+                // of it with alt held
+                let selecting = app.term.modes.mouse_tracking == term::MouseTracking::None
+                    || modifiers.contains(glfw::Modifiers::Shift);
+                if button == mouse::Button::Left {
+                    if kind == mouse::EventKind::Press && selecting {
+                        if !select_word(app) {
+                            start_selection(app, modifiers.contains(glfw::Modifiers::Alt));
+                        }
+                        continue;
+                    }
+                    if kind == mouse::EventKind::Release && app.ts.mouse.drag_anchor.is_some() {
+                        app.ts.mouse.drag_anchor = None;
+                        copy_selection(app);
+                        continue;
+                    }
+                    // The release of a double click
+                    if kind == mouse::EventKind::Release && selecting {
+                        continue;
                     }
                 }
-                
-                if ch == None { 
-                    println!("Unrecognized key: {:?}", key);
-                    return 
-                };
 
-                let c = ch.unwrap();
+                let mouse = &mut app.ts.mouse;
+                mouse.pressed = if kind == mouse::EventKind::Press { Some(button) } else { None };
 
-                match key {
-                    glfw::Key::Backspace => {
-                        ws.backspace();
-                    }
-                    _ => {
-                        ws.buffer.push(c);
+                let (x, y) = mouse.position;
+                let cell = app.ws.borrow().cell_at(x, y);
+                if let Some(report) = mouse::encode(&app.term.modes, Some(button), kind, modifiers, cell) {
+                    app.pty.write(&report);
+                }
+            }
+
+            glfw::WindowEvent::CursorPos(x, y) => {
+                let mouse = &mut app.ts.mouse;
+                mouse.position = (x, y);
+                if mouse.hidden {
+                    mouse.hidden = false;
+                    app.ts.window.borrow_mut().set_cursor_mode(glfw::CursorMode::Normal);
+                }
+
+                let cell = app.ws.borrow().cell_at(x, y);
+                if mouse.last_cell == Some(cell) {
+                    continue;
+                }
+                mouse.last_cell = Some(cell);
+
+                let modifiers = glfw::Modifiers::empty();
+                let report = mouse::encode(&app.term.modes, mouse.pressed, mouse::EventKind::Motion, modifiers, cell);
+                if let Some(report) = report {
+                    app.pty.write(&report);
+                }
+            }
+
+            glfw::WindowEvent::Pos(..) | glfw::WindowEvent::Size(..) => {
+                let window = app.ts.window.borrow();
+                let fullscreen = window.with_window_mode(|mode| matches!(mode, glfw::WindowMode::FullScreen(_)));
+                if !window.is_maximized() && !fullscreen && window.is_visible() {
+                    let ((x, y), (width, height)) = (window.get_pos(), window.get_size());
+                    app.ts.restored_geometry = Some((x, y, width, height));
+                }
+            }
+
+            glfw::WindowEvent::FramebufferSize(width, height) => {
+                if width <= 0 || height <= 0 {
+                    continue;
+                }
+                app.ws.borrow_mut().update_size(width as f32, height as f32);
+                app.ws.borrow_mut().pixel_ratio = pixel_ratio(&app.ts.window.borrow());
+                resize_grid(app);
+            }
+
+            // Moved to a monitor with a different scale. On Wayland the
+            // framebuffer size can change before or after this arrives, so
+            // the mouse is mapped with the new ratio either way.
+            glfw::WindowEvent::ContentScale(_, _) => {
+                app.ws.borrow_mut().pixel_ratio = pixel_ratio(&app.ts.window.borrow());
+                let scale = content_scale(&mut app.ts.glfw, &app.ts.window.borrow());
+                if scale != app.ws.borrow().scale {
+                    app.ws.borrow_mut().scale = scale;
+                    if let Err(e) = reload_font(app) {
+                        error!("Could not rasterize the font at the new scale: {}", e);
                     }
                 }
-                
+            }
+
+            // Files dragged from a file manager are typed as their paths
+            glfw::WindowEvent::FileDrop(paths) => {
+                let text = paste::quote_paths(&paths);
+                paste(app, &text);
+            }
+
+            glfw::WindowEvent::Focus(focused) => {
+                app.ts.focused = focused;
+                if app.term.modes.focus_reporting {
+                    app.pty.write(if focused { b"\x1b[I" } else { b"\x1b[O" });
+                }
+            }
+
+            glfw::WindowEvent::Close => request_close(app),
+
+            glfw::WindowEvent::Scroll(_, y) => {
+                if y == 0.0 {
+                    continue;
+                }
+                // Applications that track the mouse get the wheel, otherwise
+                // the wheel scrolls through the scrollback
+                if app.term.modes.mouse_tracking == term::MouseTracking::None {
+                    app.term.scroll_display(y.signum() as isize * 3);
+                    continue;
+                }
+
+                let button = if y > 0.0 { mouse::Button::WheelUp } else { mouse::Button::WheelDown };
+                let (x, y) = app.ts.mouse.position;
+                let cell = app.ws.borrow().cell_at(x, y);
+                let modifiers = glfw::Modifiers::empty();
+                if let Some(report) = mouse::encode(&app.term.modes, Some(button), mouse::EventKind::Press, modifiers, cell) {
+                    app.pty.write(&report);
+                }
             }
             _ => {}
         }
     }
 
-    check_gl_errors();
-    unsafe {
-        //gl::PolygonMode(gl::FRONT_AND_BACK, gl::LINE);
-        gl::ClearColor(0.0, 0.0, 0.0, 1.0);
-        gl::Clear(gl::COLOR_BUFFER_BIT);
-
-        render_screen_buffer(&app.renderer, app.ws.clone());
-
-        let (cursor_vertices, cursor_indices) = calculate_cursor_vertices(
-            app.ws.borrow().width,
-            app.ws.borrow().height,
-            app.ws.borrow().grid.rows,
-            app.ws.borrow().grid.cols,
-            app.ws.borrow().get_next_cell(),
-        );
+    // Also keeps scrolling while the pointer rests past the grid
+    if app.ts.mouse.drag_anchor.is_some() {
+        drag_selection(app);
+    }
 
-        set_renderer_vertices(
-            app.renderer.cursor_vao,
-            app.renderer.cursor_vbo,
-            &cursor_vertices,
-            &cursor_indices,
-        );
-        render_cursor(&app.renderer.cursor_shader, app.renderer.cursor_vbo);
+    let mut played = false;
+    if let Some(player) = &mut app.player {
+        for (_, event) in player.due() {
+            event.apply(&mut app.term);
+            played = true;
+        }
+    }
+    if read_pty(app) || played {
+        app.pacing.request_redraw();
+        if app.search.open {
+            app.search.refresh(&mut app.term);
+        }
+    }
+    if let Some(text) = app.term.take_clipboard() {
+        app.ts.window.borrow_mut().set_clipboard_string(&text);
+    }
+    for notification in app.term.take_notifications() {
+        notify(&notification);
+    }
+    update_pointer(app);
+
+    // The application's title, with commands that failed flagged in it and
+    // the program running named when the title doesn't already
+    update_foreground_job(app);
+    let title = app.term.title.as_deref().unwrap_or(&app.ts.title);
+    let mut title = match app.term.shell.exit_status {
+        Some(status) if status != 0 => format!("{} [exit {}]", title, status),
+        _ => title.to_string(),
+    };
+    if let Some((_, program)) = app.foreground_job.as_ref().filter(|(_, program)| !title.contains(program.as_str())) {
+        title = format!("{} ({})", title, program);
+    }
+    if title != app.ts.shown_title {
+        app.ts.window.borrow_mut().set_title(&title);
+        app.ts.shown_title = title;
+    }
+
+    let blink_visible = blink_visible(app.ts.glfw.get_time());
+    // The overlay counts frames, it needs them to keep coming
+    if app.renderer.reload_shaders() || blink_visible != app.blink_visible || app.perf_hud.visible {
+        app.pacing.request_redraw();
+    }
+    // Output keeps being read while detached, there's just nothing to draw
+    if app.detached.is_some() || !app.pacing.due() {
+        return;
+    }
+    app.blink_visible = blink_visible;
+    if draw_frame(app) {
+        // Still scrolling into place, or fading the scrollbar out
+        app.pacing.request_redraw();
+    }
+    // Waiting for the swap isn't part of the frame
+    let frame_time = app.renderer.drawn_at().duration_since(frame_start);
+    app.pacing.drawn();
+    if app.perf_hud.visible {
+        let term = &app.term;
+        app.perf_hud.scrollback(term.scrollback_len(), term.scrollback_bytes(), term.scrollback_budget);
+        app.perf_hud.frame(frame_time, app.renderer.draw_calls(), app.renderer.cached_glyphs());
     }
 }
 
-fn read_from_fd(fd: RawFd) -> Option<Vec<u8>> {
-    let mut read_buffer = [0; 65536];
-    let read_result = read(fd, &mut read_buffer);
-    match read_result {
-        Ok(bytes_read) => Some(read_buffer[..bytes_read].to_vec()),
-        Err(_e) => None
+/// Draw and show the terminal, and return whether smooth scrolling or the
+/// scrollbar fading out need more frames
+fn draw_frame(app: &mut AppState) -> bool {
+    let time = app.ts.glfw.get_time();
+    let (scrollbar, fading) = app.scrollbar.update(&app.term, time, app.ws.borrow().scale);
+    let blink_visible = blink_visible(time);
+    // The highlights are found once the view is where it's drawn
+    let frame = render::Frame { focused: app.ts.focused, blink_visible, scrollbar, restyled: None };
+    let lag = app.smooth_scroll.update(&app.term, time);
+    if lag == 0.0 {
+        render(app, &frame, 0);
+    } else {
+        render_lagging(app, lag, &frame);
+    }
+    lag != 0.0 || fading
+}
+
+// Blinking text is shown for the first half of every period
+fn blink_visible(time: f64) -> bool {
+    time % BLINK_PERIOD < BLINK_PERIOD / 2.0
+}
+
+/// Rebuild the programs when the files in `shader_dir` change. A shader
+/// that doesn't compile leaves the programs that work in place.
+/// Returns whether the shaders were reloaded
+fn reload_shaders(renderer: &mut GlRenderer) -> bool {
+    let Some(watcher) = renderer.shader_watcher.as_mut() else {
+        return false;
+    };
+    if !watcher.changed() {
+        return false;
+    }
+    match init_shaders(Some(&watcher.dir)) {
+        Ok(shaders) => {
+            renderer.font_shader.delete();
+            renderer.rect_shader.delete();
+            renderer.image_shader.delete();
+            if let Some(lcd_shader) = &renderer.lcd_shader {
+                lcd_shader.delete();
+            }
+            renderer.font_shader = shaders.font;
+            renderer.rect_shader = shaders.rect;
+            renderer.image_shader = shaders.image;
+            renderer.lcd_shader = shaders.lcd;
+            renderer.retained.get_mut().setup = None;
+            info!("Reloaded the shaders from {}", watcher.dir.display());
+            true
+        }
+        Err(e) => {
+            warn!("Keeping the previous shaders: {}", e);
+            false
+        }
     }
 }
 
-fn spawn_pty_with_shell(default_shell: String) -> RawFd {
-    match forkpty(None, None) {
-        Ok(fork_pty_result) => {
-            match fork_pty_result {
-                ForkptyResult::Child => {
-                    // Secondary part of the pty, aka stdin pipe?
-                    Command::new(&default_shell).spawn().expect("Failed to spawn shell");
-                    std::thread::sleep(std::time::Duration::from_millis(2000));
-                    std::process::exit(0);
+/// Draw the view `lag` rows behind where the terminal has it while
+/// scrolling smoothly. It is drawn from the whole line at or above that
+/// point, moved up the rest of the way by shifting the viewport.
+fn render_lagging(app: &mut AppState, lag: f32, frame: &render::Frame) {
+    let display_offset = app.term.display_offset;
+    let shown = (display_offset as f32 + lag).clamp(0.0, app.term.scrollback_len() as f32);
+    let whole = shown.ceil();
+    let shift = ((whole - shown) * app.ws.borrow().grid.cell_height).round() as i32;
+
+    app.term.display_offset = whole as usize;
+    render(app, frame, shift);
+    app.term.display_offset = display_offset;
+}
+
+/// A key that isn't bound to an action: it goes to the active mode, or
+/// to the application
+fn handle_key(app: &mut AppState, key: glfw::Key, action: glfw::Action, modifiers: glfw::Modifiers) {
+    let ch = key_text(key, modifiers);
+    if app.exit_status.is_some() {
+        // Held open after the shell exited, there's nothing to type into
+        if action == glfw::Action::Press && matches!(key, glfw::Key::Enter | glfw::Key::KpEnter | glfw::Key::Escape) {
+            app.ts.window.borrow_mut().set_should_close(true);
+        }
+    } else if app.pending_close.is_some() {
+        match paste::answer(key, action, ch) {
+            Some(true) => app.ts.window.borrow_mut().set_should_close(true),
+            Some(false) => app.pending_close = None,
+            None => {}
+        }
+    } else if let Some(text) = &app.pending_paste {
+        match paste::answer(key, action, ch) {
+            Some(true) => {
+                let text = text.clone();
+                app.pending_paste = None;
+                send_paste(app, &text);
+            }
+            Some(false) => app.pending_paste = None,
+            None => {}
+        }
+    } else if app.search.open {
+        app.search.handle_key(key, action, ch, &mut app.term);
+    } else if app.copy_mode.active {
+        let selection = app.copy_mode.selection;
+        if let Some(text) = app.copy_mode.handle_key(key, action, ch, &mut app.term) {
+            app.ts.window.borrow_mut().set_clipboard_string(&text);
+        } else if app.copy_mode.selection != selection {
+            copy_selection(app);
+        }
+    } else if app.hints.active {
+        match app.hints.handle_key(key, action, ch) {
+            Some(HintAction::Open(target)) => open::open(&resolve_hint_target(&target, working_directory(app))),
+            Some(HintAction::Copy(target)) => app.ts.window.borrow_mut().set_clipboard_string(&target),
+            None => {}
+        }
+    } else {
+        match keyboard::encode(key, action, modifiers, ch, app.term.key_modes()) {
+            Some(bytes) => {
+                app.pty.write(&bytes);
+                // Typing snaps the view back to the bottom
+                app.term.display_offset = 0;
+                if app.prefs.hide_mouse_when_typing && !app.ts.mouse.hidden {
+                    app.ts.mouse.hidden = true;
+                    app.ts.window.borrow_mut().set_cursor_mode(glfw::CursorMode::Hidden);
                 }
-                ForkptyResult::Parent { master, child: _ } => {
-                    master.as_raw_fd();
+            }
+            None if ch.is_none() && action == glfw::Action::Press => {
+                debug!("Unrecognized key: {:?}", key);
+            }
+            None => {}
+        }
+    }
+}
+
+/// Start selecting text, or a block of it, where the left button went
+/// down, dropping the selection there was
+fn start_selection(app: &mut AppState, block: bool) {
+    let (x, y) = app.ts.mouse.position;
+    let (row, col) = app.ws.borrow().cell_at(x, y);
+    app.copy_mode.exit();
+    let mouse = &mut app.ts.mouse;
+    mouse.drag_anchor = Some(selection::Point { line: app.term.first_visible_line() + row, col });
+    mouse.drag_block = block;
+    mouse.autoscroll_lines = 0.0;
+    mouse.autoscroll_time = None;
+}
+
+/// Select the word, path, URL or quoted string under the pointer if this
+/// press makes a double click, and tell whether it did
+fn select_word(app: &mut AppState) -> bool {
+    let (x, y) = app.ts.mouse.position;
+    let (row, col) = app.ws.borrow().cell_at(x, y);
+    let point = selection::Point { line: app.term.first_visible_line() + row, col };
+    let time = app.ts.glfw.get_time();
+    let last_click = app.ts.mouse.last_click.replace((time, point));
+    if !last_click.is_some_and(|(at, last)| last == point && time - at <= DOUBLE_CLICK_TIME) {
+        return false;
+    }
+    // A third click starts over
+    app.ts.mouse.last_click = None;
+    app.copy_mode.exit();
+    app.ts.mouse.drag_anchor = None;
+    app.copy_mode.selection = app.prefs.word_rules.select(&app.term, point);
+    copy_selection(app);
+    app.pacing.request_redraw();
+    true
+}
+
+/// Stretch the selection to the pointer while the left button is held.
+/// Past the top or bottom of the grid the view scrolls through the
+/// scrollback, faster the further past it the pointer is.
+fn drag_selection(app: &mut AppState) {
+    let Some(anchor) = app.ts.mouse.drag_anchor else {
+        return;
+    };
+    let (x, y) = app.ts.mouse.position;
+    let (overshoot, cell_height, (row, col)) = {
+        let ws = app.ws.borrow();
+        (ws.vertical_overshoot(y), ws.grid.cell_height, ws.cell_at(x, y))
+    };
+    let time = app.ts.glfw.get_time();
+    let mouse = &mut app.ts.mouse;
+    if overshoot == 0.0 {
+        mouse.autoscroll_lines = 0.0;
+        mouse.autoscroll_time = None;
+    } else {
+        let elapsed = mouse.autoscroll_time.map_or(0.0, |last| time - last);
+        mouse.autoscroll_time = Some(time);
+        mouse.autoscroll_lines += elapsed * AUTOSCROLL_RATE * (overshoot / cell_height) as f64;
+        let lines = mouse.autoscroll_lines.trunc();
+        mouse.autoscroll_lines -= lines;
+        // Above the grid is back into the scrollback
+        app.term.scroll_display(-lines as isize);
+    }
+
+    let end = selection::Point { line: app.term.first_visible_line() + row, col };
+    // A click without a drag selects nothing
+    if end == anchor && app.copy_mode.selection.is_none() {
+        return;
+    }
+    let selection = Some(selection::Selection { anchor, end, block: app.ts.mouse.drag_block });
+    if app.copy_mode.selection != selection {
+        app.copy_mode.selection = selection;
+        app.pacing.request_redraw();
+    }
+}
+
+/// Mirror the selection onto the primary selection as it changes, and
+/// onto the clipboard too when the config asks for that
+fn copy_selection(app: &mut AppState) {
+    let Some(selection) = &app.copy_mode.selection else {
+        return;
+    };
+    let text = selection.text(&app.term);
+    app.primary.set(&text);
+    if app.prefs.copy_on_select {
+        app.ts.window.borrow_mut().set_clipboard_string(&text);
+    }
+}
+
+/// A region of the terminal in `format`, `None` when there's no selection
+/// or no command has finished
+fn export_region(app: &AppState, region: export::Region, format: export::Format) -> Option<String> {
+    let selection = match region {
+        export::Region::Screen => {
+            let top = app.term.first_visible_line();
+            selection::Selection {
+                anchor: selection::Point { line: top, col: 0 },
+                end: selection::Point { line: top + app.term.rows - 1, col: app.term.cols - 1 },
+                block: false,
+            }
+        }
+        export::Region::Scrollback => selection::Selection {
+            anchor: selection::Point { line: 0, col: 0 },
+            end: selection::Point { line: app.term.scrollback_len() + app.term.rows - 1, col: app.term.cols - 1 },
+            block: false,
+        },
+        export::Region::Selection => app.copy_mode.selection?,
+        export::Region::LastCommandOutput => app.term.shell.last_command_output(app.term.cols)?,
+    };
+    Some(export::export(&app.term, &selection, format))
+}
+
+fn export_to_clipboard(app: &mut AppState, region: export::Region) {
+    match export_region(app, region, app.prefs.export_format) {
+        Some(text) => app.ts.window.borrow_mut().set_clipboard_string(&text),
+        None => warn!("No {} to export", region.name()),
+    }
+}
+
+/// Open a new window running `pipe_command` with the region on its input
+fn pipe_to_command(app: &AppState, region: export::Region) {
+    let Some(text) = export_region(app, region, app.prefs.pipe_format) else {
+        warn!("No {} to pipe", region.name());
+        return;
+    };
+    // The window's shell reads it from a file, the PTY is the command's
+    // terminal. The file is gone as soon as the shell has it open.
+    let path = match pipe_file(&text) {
+        Ok(path) => path,
+        Err(e) => {
+            error!("Could not save the {} to pipe it: {}", region.name(), e);
+            return;
+        }
+    };
+    let script = format!("exec < \"$1\"; rm -f \"$1\"; {}", app.prefs.pipe_command);
+    let program = ["sh", "-c", &script, "sh", &path.to_string_lossy()].map(String::from);
+    spawn_new_window(working_directory(app), &program);
+}
+
+// A file only the user can read with `text` in it
+fn pipe_file(text: &str) -> std::io::Result<PathBuf> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+    let dir = env::var_os("XDG_RUNTIME_DIR").map_or_else(env::temp_dir, PathBuf::from);
+    let path = dir.join(format!("rush-pipe-{}-{}", std::process::id(), NEXT.fetch_add(1, Ordering::Relaxed)));
+    let mut file = std::fs::File::options().write(true).create_new(true).mode(0o600).open(&path)?;
+    file.write_all(text.as_bytes())?;
+    Ok(path)
+}
+
+fn perform_action(app: &mut AppState, action: Action) {
+    match action {
+        Action::Copy => {
+            if let Some(selection) = &app.copy_mode.selection {
+                app.ts.window.borrow_mut().set_clipboard_string(&selection.text(&app.term));
+            }
+        }
+        Action::Paste => {
+            let text = app.ts.window.borrow().get_clipboard_string();
+            if let Some(text) = text {
+                paste(app, &text);
+            }
+        }
+        Action::ScrollLineUp => app.term.scroll_display(1),
+        Action::ScrollLineDown => app.term.scroll_display(-1),
+        Action::ScrollPageUp => app.term.scroll_display(app.term.rows as isize),
+        Action::ScrollPageDown => app.term.scroll_display(-(app.term.rows as isize)),
+        Action::ScrollToTop => app.term.scroll_display(app.term.scrollback_len() as isize),
+        Action::ScrollToBottom => app.term.display_offset = 0,
+        Action::Search => app.search.start(),
+        Action::CopyMode => app.copy_mode.enter(&mut app.term),
+        Action::Hints => app.hints.start(&app.term),
+        Action::ScrollToPreviousPrompt => {
+            if let Some(line) = app.term.shell.previous_prompt(app.term.first_visible_line()) {
+                scroll_line_to_top(&mut app.term, line);
+            }
+        }
+        Action::ScrollToNextPrompt => {
+            if let Some(line) = app.term.shell.next_prompt(app.term.first_visible_line()) {
+                scroll_line_to_top(&mut app.term, line);
+            }
+        }
+        Action::SelectLastCommandOutput => {
+            if let Some(selection) = app.term.shell.last_command_output(app.term.cols) {
+                app.copy_mode.select(selection, &mut app.term);
+                copy_selection(app);
+            }
+        }
+        Action::ExportScreen => export_to_clipboard(app, export::Region::Screen),
+        Action::ExportSelection => export_to_clipboard(app, export::Region::Selection),
+        Action::ExportLastCommandOutput => export_to_clipboard(app, export::Region::LastCommandOutput),
+        Action::PipeScreen => pipe_to_command(app, export::Region::Screen),
+        Action::PipeScrollback => pipe_to_command(app, export::Region::Scrollback),
+        Action::PipeLastCommandOutput => pipe_to_command(app, export::Region::LastCommandOutput),
+        Action::SpawnNewWindow => {
+            let cwd = working_directory(app);
+            if app.daemon {
+                // Picked up by the daemon's loop on the next frame
+                if let Err(e) = control::send(&Message::NewWindow { cwd }) {
+                    error!("Could not reach the daemon: {}", e);
                 }
+            } else {
+                spawn_new_window(cwd, &[]);
+            }
+        }
+        Action::ClearScrollback => {
+            // The selection would point at lines that are gone
+            app.copy_mode.exit();
+            app.term.clear_history();
+        }
+        Action::NextTheme => {
+            if let Some(theme) = app.prefs.themes.next(app.theme.as_deref()).map(String::from) {
+                set_theme(app, &theme);
             }
         }
-        Err (e) => { panic!("Failed to fork {:?}", e); }
+        Action::Screenshot => {
+            let name = format!("rush-{}.png", dump::timestamp(std::time::SystemTime::now()));
+            app.screenshot_to = Some(app.prefs.screenshot_directory.join(name));
+        }
+        Action::TogglePerfHud => app.perf_hud.toggle(),
+        Action::ToggleFullscreen => toggle_fullscreen(&mut app.ts),
+        Action::ToggleAlwaysOnTop => {
+            let mut window = app.ts.window.borrow_mut();
+            let floating = window.is_floating();
+            window.set_floating(!floating);
+        }
+        Action::Detach => detach(app),
+        Action::Quit => request_close(app),
+        Action::IncreaseFontSize => {
+            // Steps of about a tenth so they feel the same at any size
+            let size = app.font_size_px;
+            set_font_size(app, size + (size / 10).max(1));
+        }
+        Action::DecreaseFontSize => {
+            let size = app.font_size_px;
+            set_font_size(app, size.saturating_sub((size / 11).max(1)));
+        }
+        Action::ResetFontSize => set_font_size(app, app.default_font_size_px),
+        Action::None => {}
     }
 }
 
-fn main() {
-    let default_shell = std::env::var("SHELL").expect("Could not find default shell");
-    let stdout_fd = spawn_pty_with_shell(default_shell);
-    let mut read_buffer = vec![];
+/// Scroll so the line at `index` is at the top of the window, or as close
+/// as the scrollback allows
+fn scroll_line_to_top(term: &mut Terminal, index: usize) {
+    term.display_offset = term.scrollback_len().saturating_sub(index);
+}
+
+/// Cover the monitor the window is on, or go back to where the window was
+/// before. The new size arrives as a framebuffer size event.
+fn toggle_fullscreen(ts: &mut TerminalState) {
+    let mut window = ts.window.borrow_mut();
+    if let Some((x, y, width, height)) = ts.windowed_geometry.take() {
+        window.set_monitor(glfw::WindowMode::Windowed, x, y, width as u32, height as u32, None);
+        return;
+    }
+    let (x, y) = window.get_pos();
+    let (width, height) = window.get_size();
+    let center = (x + width / 2, y + height / 2);
+    let fullscreen = ts.glfw.with_connected_monitors(|_, monitors| {
+        // Wayland doesn't tell windows where they are, the first monitor
+        // is used there
+        let under_center = monitors.iter().find(|monitor| {
+            let (monitor_x, monitor_y) = monitor.get_pos();
+            monitor.get_video_mode().is_some_and(|mode| {
+                (monitor_x..monitor_x + mode.width as i32).contains(&center.0)
+                    && (monitor_y..monitor_y + mode.height as i32).contains(&center.1)
+            })
+        });
+        let monitor = under_center.or(monitors.first())?;
+        let mode = monitor.get_video_mode()?;
+        window.set_monitor(glfw::WindowMode::FullScreen(monitor), 0, 0, mode.width, mode.height, Some(mode.refresh_rate));
+        Some(())
+    });
+    if fullscreen.is_some() {
+        ts.windowed_geometry = Some((x, y, width, height));
+    }
+}
+
+/// Rasterize the font again at a new size and fit the grid to the cells
+/// that come out of it
+fn set_font_size(app: &mut AppState, size_px: u32) {
+    let size_px = size_px.clamp(MIN_FONT_SIZE, MAX_FONT_SIZE);
+    if size_px == app.font_size_px {
+        return;
+    }
+    let previous = std::mem::replace(&mut app.font_size_px, size_px);
+    if let Err(e) = reload_font(app) {
+        error!("Could not change the font size: {}", e);
+        app.font_size_px = previous;
+    }
+}
+
+/// Rasterize the font again after its size or the content scale changed.
+/// The glyphs from before are kept when it fails.
+fn reload_font(app: &mut AppState) -> Result<(), RushError> {
+    let size_px = scaled_font_size(app.font_size_px, app.ws.borrow().scale);
+    let char_dim = app.renderer.load_font(size_px)?;
+    app.ws.borrow_mut().set_cell_size(char_dim);
+    resize_grid(app);
+    Ok(())
+}
+
+/// Fit the terminal to the grid after the window or the cells changed size
+fn resize_grid(app: &mut AppState) {
+    let ws = app.ws.borrow();
+    let cell_size = (ws.grid.cell_width as u32, ws.grid.cell_height as u32);
+    // A recording keeps the size it was made at
+    if app.player.is_none() && (app.term.rows, app.term.cols) != (ws.grid.rows, ws.grid.cols) {
+        app.term.resize(ws.grid.rows, ws.grid.cols);
+        if let Some(recorder) = &mut app.recorder {
+            if let Err(e) = recorder.resize(app.term.cols, app.term.rows) {
+                error!("Stopped recording: {}", e);
+                app.recorder = None;
+            }
+        }
+    }
+    app.term.cell_size = cell_size;
+    app.pty.resize(app.term.rows, app.term.cols, cell_size);
+    app.renderer.set_viewport(&ws);
+}
+
+/// The size glyphs are rasterized at, kept fractional so a scale of 1.25
+/// or 1.5 doesn't round the text to the nearest pixel size
+fn scaled_font_size(size_px: u32, scale: f32) -> f32 {
+    (size_px as f32 * scale).max(1.0)
+}
+
+/// How much larger than normal things should be drawn on the monitor the
+/// window is on. X11 only reports a content scale when Xft.dpi is set, so
+/// without one the scale is guessed from the monitor's DPI instead. macOS
+/// and Wayland always report it, 2 on Retina displays and whatever the
+/// output is set to on Wayland, fractions included.
+fn content_scale(glfw: &mut glfw::Glfw, window: &glfw::PWindow) -> f32 {
+    let (scale, _) = window.get_content_scale();
+    if scale != 1.0 || cfg!(target_os = "macos") || is_wayland(window) {
+        return scale;
+    }
+    let dpi = glfw.with_primary_monitor(|_, monitor| {
+        let monitor = monitor?;
+        let (width_mm, _) = monitor.get_physical_size();
+        let mode = monitor.get_video_mode()?;
+        (width_mm > 0).then(|| mode.width as f32 / (width_mm as f32 / 25.4))
+    });
+    match dpi {
+        // Only clearly HiDPI screens, physical sizes are often wrong
+        Some(dpi) if dpi >= 96.0 * 1.5 => (dpi / 96.0 * 4.0).round() / 4.0,
+        _ => 1.0,
+    }
+}
+
+fn pixel_ratio(window: &glfw::PWindow) -> f32 {
+    let (width, _) = window.get_size();
+    let (framebuffer_width, _) = window.get_framebuffer_size();
+    if width > 0 {
+        framebuffer_width as f32 / width as f32
+    } else {
+        1.0
+    }
+}
+
+/// Send `text` to the application as if it was typed, with its control
+/// characters dropped. Pastes that look risky wait for confirmation first.
+fn paste(app: &mut AppState, text: &str) {
+    let text = paste::sanitize(text);
+    if app.prefs.confirm_paste && paste::needs_confirmation(&text, app.term.modes.bracketed_paste) {
+        app.pending_paste = Some(text);
+    } else {
+        send_paste(app, &text);
+    }
+}
+
+fn send_paste(app: &mut AppState, text: &str) {
+    // Enter sends a carriage return, pasted lines should end the same way
+    let text = text.replace("\r\n", "\r").replace('\n', "\r");
+    if app.term.modes.bracketed_paste {
+        app.pty.write(b"\x1b[200~");
+        app.pty.write(text.as_bytes());
+        app.pty.write(b"\x1b[201~");
+    } else {
+        app.pty.write(text.as_bytes());
+    }
+    app.term.display_offset = 0;
+}
+
+/// Start another rush, in the directory the shell is in, running `program`
+/// instead of the shell when it's given
+fn spawn_new_window(working_directory: Option<PathBuf>, program: &[String]) {
+    let exe = match env::current_exe() {
+        Ok(exe) => exe,
+        Err(e) => {
+            error!("Could not find the rush executable: {}", e);
+            return;
+        }
+    };
+    let mut command = std::process::Command::new(exe);
+    if let Some(dir) = working_directory {
+        command.arg("--working-directory").arg(dir);
+    }
+    if !program.is_empty() {
+        command.arg("-e").args(program);
+    }
+    match command.spawn() {
+        Ok(mut child) => {
+            std::thread::spawn(move || child.wait());
+        }
+        Err(e) => error!("Could not start a new window: {}", e),
+    }
+}
+
+/// Turn a path picked with a hint into one the opener understands. Relative
+/// paths are relative to the shell's directory, not rush's.
+fn resolve_hint_target(target: &str, working_directory: Option<PathBuf>) -> String {
+    if target.contains("://") {
+        return target.to_string();
+    }
+    if let Some(rest) = target.strip_prefix("~/") {
+        if let Some(home) = env::var_os("HOME") {
+            return PathBuf::from(home).join(rest).to_string_lossy().into_owned();
+        }
+    }
+    match working_directory {
+        Some(cwd) if !target.starts_with('/') => cwd.join(target).to_string_lossy().into_owned(),
+        _ => target.to_string(),
+    }
+}
+
+/// Where screenshots are saved: `screenshot_directory` in the config,
+/// `$XDG_PICTURES_DIR` or else ~/Pictures
+fn screenshot_directory(config: &HashMap<String, String>) -> PathBuf {
+    if let Some(dir) = config.get("screenshot_directory") {
+        return PathBuf::from(dir);
+    }
+    match env::var_os("XDG_PICTURES_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => env::var_os("HOME").map_or_else(PathBuf::new, |home| Path::new(&home).join("Pictures")),
+    }
+}
+
+/// Close the window once the shell has exited, or keep it open with how
+/// the shell ended written under its output, as `exit_behavior` says
+fn reap_shell(app: &mut AppState) {
+    // Still running, the child that exited was another one
+    let Some(status) = app.pty.try_wait() else {
+        return;
+    };
+    app.exit_status = Some(status);
+    let hold = match app.prefs.exit_behavior {
+        ExitBehavior::Close => false,
+        ExitBehavior::Hold => true,
+        ExitBehavior::HoldOnFailure => !status.success(),
+    };
+    if !hold {
+        app.ts.window.borrow_mut().set_should_close(true);
+        return;
+    }
+    let message = format!("\r\n\x1b[0;7m The shell {}, press Enter to close \x1b[0m", status);
+    app.term.process(message.as_bytes());
+    app.term.display_offset = 0;
+    app.pacing.request_redraw();
+}
+
+/// Look the foreground job up again when the shell has started or
+/// finished one
+fn update_foreground_job(app: &mut AppState) {
+    let job = app.pty.foreground_job();
+    if job != app.foreground_job.as_ref().map(|&(pid, _)| pid) {
+        app.foreground_job = job.and_then(|pid| Some((pid, pty::process_name(pid)?)));
+    }
+}
+
+/// Show a notification a program sent on the desktop
+fn notify(notification: &term::Notification) {
+    let title = notification.title.as_deref().unwrap_or("rush");
+    match std::process::Command::new("notify-send").args(["--app-name", "rush", title, &notification.body]).spawn() {
+        // Reaped once it exits so it doesn't linger as a zombie
+        Ok(mut child) => {
+            std::thread::spawn(move || child.wait());
+        }
+        Err(e) => warn!("Could not run notify-send to show a notification: {}", e),
+    }
+}
+
+/// Hide the window and keep its shell running, until `rush msg attach`
+/// brings it back. Only the daemon outlives its windows.
+fn detach(app: &mut AppState) {
+    if !app.daemon || app.dropdown.is_some() {
+        warn!("Only windows rush --daemon opened can be detached, and not its dropdown");
+        return;
+    }
+    app.ts.window.borrow_mut().hide();
+    app.ts.focused = false;
+    app.detached = Some(Instant::now());
+    info!("Detached the session of shell {}", app.pty.child());
+}
+
+/// Show a detached window again, as it is now
+fn attach(app: &mut AppState) {
+    app.detached = None;
+    let mut window = app.ts.window.borrow_mut();
+    window.show();
+    window.focus();
+    app.pacing.request_redraw();
+}
+
+/// Close the window, once the user confirms it if a job is still running
+/// in it
+fn request_close(app: &mut AppState) {
+    update_foreground_job(app);
+    match &app.foreground_job {
+        Some((_, program)) if app.prefs.confirm_close => {
+            // The window manager's close button already set it
+            app.ts.window.borrow_mut().set_should_close(false);
+            app.pending_close = Some(program.clone());
+            app.pacing.request_redraw();
+        }
+        _ => app.ts.window.borrow_mut().set_should_close(true),
+    }
+}
+
+/// The directory the shell is in. What it reported with OSC 7 is preferred,
+/// the kernel only knows the directory of the process on the PTY.
+fn working_directory(app: &AppState) -> Option<PathBuf> {
+    app.term.working_directory.clone().or_else(|| app.pty.working_directory())
+}
+
+/// Give the pointer the shape for what is under it: a hand over links, an
+/// I-beam over text that can be selected, and an arrow over the padding or
+/// when the application takes the clicks
+fn update_pointer(app: &mut AppState) {
+    let (x, y) = app.ts.mouse.position;
+    let shape = if !app.ws.borrow().in_grid(x, y) || app.term.modes.mouse_tracking != term::MouseTracking::None {
+        glfw::StandardCursor::Arrow
+    } else if hovered_link(app).is_some() || hovered_file_ref(app).is_some() {
+        glfw::StandardCursor::Hand
+    } else {
+        glfw::StandardCursor::IBeam
+    };
+    if app.ts.mouse.shape != Some(shape) {
+        app.ts.mouse.shape = Some(shape);
+        app.ts.window.borrow_mut().set_cursor(Some(glfw::Cursor::standard(shape)));
+    }
+}
+
+/// The hyperlink under the mouse pointer, if any
+fn hovered_link(app: &AppState) -> Option<std::sync::Arc<Hyperlink>> {
+    let (x, y) = app.ts.mouse.position;
+    let (row, col) = app.ws.borrow().cell_at(x, y);
+    if row >= app.term.rows {
+        return None;
+    }
+    app.term.visible_row(row).get(col)?.hyperlink().cloned()
+}
+
+/// The row of the view and the file reference under the mouse pointer,
+/// while ctrl is held and the file is there to open
+fn hovered_file_ref(app: &AppState) -> Option<(usize, FileRef)> {
+    let window = app.ts.window.borrow();
+    let ctrl = [glfw::Key::LeftControl, glfw::Key::RightControl]
+        .iter()
+        .any(|&key| window.get_key(key) == glfw::Action::Press);
+    if !ctrl || app.term.modes.mouse_tracking != term::MouseTracking::None {
+        return None;
+    }
+    let (x, y) = app.ts.mouse.position;
+    if !app.ws.borrow().in_grid(x, y) {
+        return None;
+    }
+    let (row, col) = app.ws.borrow().cell_at(x, y);
+    let file_ref = app.file_refs.at(&app.term, row, col)?;
+    let path = resolve_hint_target(&file_ref.path, working_directory(app));
+    Path::new(&path).is_file().then_some((row, file_ref))
+}
+
+/// Open the file at the line a reference points to, with `editor` from the
+/// config or else `$VISUAL` or `$EDITOR` in a new window
+fn open_in_editor(app: &AppState, file_ref: &FileRef) {
+    let cwd = working_directory(app);
+    let path = resolve_hint_target(&file_ref.path, cwd.clone());
+    let Some(template) = &app.prefs.editor else {
+        let editor = env::var("VISUAL").or_else(|_| env::var("EDITOR")).unwrap_or_else(|_| "vi".to_string());
+        spawn_new_window(cwd, &file_ref.command(&format!("{} +{{line}} {{file}}", editor), &path));
+        return;
+    };
+    let command = file_ref.command(template, &path);
+    let Some((program, args)) = command.split_first() else {
+        return;
+    };
+    let mut process = std::process::Command::new(program);
+    process.args(args);
+    if let Some(dir) = cwd {
+        process.current_dir(dir);
+    }
+    match process.spawn() {
+        Ok(mut child) => {
+            std::thread::spawn(move || child.wait());
+        }
+        Err(e) => error!("Could not run {} to open {}: {}", program, path, e),
+    }
+}
+
+/// Drain everything the shell has written since the last frame into the
+/// terminal, and send back any replies the terminal produced. Returns
+/// whether there was anything.
+fn read_pty(app: &mut AppState) -> bool {
+    let _span = trace_span!("read_pty").entered();
+    if app.pty_closed {
+        return false;
+    }
+    let mut read_buffer = [0; 65536];
+    let mut read_any = false;
+    // Input the program wasn't reading yet, like the rest of a long paste
+    app.pty.flush();
     loop {
-        match read_from_fd(stdout_fd) {
-            Some(mut read_bytes) => {
-                read_buffer.append(&mut read_bytes);
+        match app.pty.read(&mut read_buffer) {
+            Some(0) => {
+                app.pty_closed = true;
+                break;
             }
-            None => {
-                println!("{:?}", String::from_utf8(read_buffer).unwrap());
-                std::process::exit(0);
+            // Nothing is expected from the idle program behind a recording
+            Some(_) if app.player.is_some() => {}
+            Some(bytes_read) => {
+                app.term.process(&read_buffer[..bytes_read]);
+                app.perf_hud.read(bytes_read);
+                if let Some(recorder) = &mut app.recorder {
+                    if let Err(e) = recorder.output(&read_buffer[..bytes_read]) {
+                        error!("Stopped recording: {}", e);
+                        app.recorder = None;
+                    }
+                }
+                if let Some(dump) = &mut app.dump {
+                    if let Err(e) = dump.write(&read_buffer[..bytes_read], &app.term.unhandled.take()) {
+                        error!("Stopped dumping the output to {}: {}", dump.path.display(), e);
+                        app.dump = None;
+                    }
+                }
+                read_any = true;
             }
+            None => break,
         }
     }
 
-    let mut app: AppState = init();
-    check_gl_errors();
+    let responses = app.term.take_responses();
+    if !responses.is_empty() {
+        app.pty.write(&responses);
+    }
+    read_any
+}
+
+fn main() {
+    let cli = Cli::parse();
+    logging::init(cli.log_level, cli.log_file.as_deref());
+    if cli.print_default_config {
+        print!("{}", yaml_parser::DEFAULT_CONFIG);
+        return;
+    }
+
+    let message = match &cli.subcommand {
+        Some(Subcommand::Msg { message }) => Some(message.clone()),
+        None if cli.toggle => Some(Message::Toggle),
+        None => None,
+    };
+    if let Some(message) = message {
+        match control::request(&message) {
+            Ok(reply) => print!("{}", reply),
+            Err(e) => {
+                eprintln!("Could not reach a rush daemon at {}: {}", control::socket_path().display(), e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    if cli.daemon {
+        if let Err(e) = run_daemon(&cli) {
+            eprintln!("rush: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if cli.headless {
+        run_headless(&cli);
+        return;
+    }
+    if let Some(capture) = &cli.bench_render {
+        run_bench_render(&cli, capture);
+        return;
+    }
+
+    let mut app: AppState = init(&cli).unwrap_or_else(|e| {
+        eprintln!("rush: {}", e);
+        std::process::exit(1);
+    });
+    let mut signals = SignalEvents::new().expect("Failed to set up signal handlers");
+    let color_scheme = color_scheme_watcher(&cli);
     while !app.ts.window.as_ref().borrow().should_close() {
+        wait_events(&mut app.ts.glfw, app.pacing.wait());
+        handle_signals(&mut signals, &cli, std::slice::from_mut(&mut app));
+        follow_color_scheme(color_scheme.as_ref(), std::slice::from_mut(&mut app));
         tick(&mut app);
     }
+    save_geometry(&app);
+}
+
+/// Act on the signals that came since the last call, in every window.
+/// Returns whether rush was asked to quit, the windows are closing then.
+fn handle_signals(signals: &mut SignalEvents, cli: &Cli, windows: &mut [AppState]) -> bool {
+    let mut terminate = false;
+    for event in signals.pending() {
+        debug!("Got {:?}", event);
+        match event {
+            SignalEvent::ChildExited => {
+                for app in windows.iter_mut().filter(|app| app.exit_status.is_none()) {
+                    reap_shell(app);
+                }
+            }
+            SignalEvent::Terminate => {
+                terminate = true;
+                for app in windows.iter() {
+                    app.ts.window.borrow_mut().set_should_close(true);
+                }
+            }
+            SignalEvent::ReloadConfig => reload_config(cli, windows),
+        }
+    }
+    terminate
+}
+
+/// Switch the window to the colors of `theme` from the config
+fn set_theme(app: &mut AppState, theme: &str) {
+    let Some(palette) = app.prefs.themes.get(theme) else {
+        warn!("There's no theme called {} in the config", theme);
+        return;
+    };
+    app.term.set_theme(palette.clone());
+    app.theme = Some(theme.to_string());
+    app.pacing.request_redraw();
+}
+
+/// Switch the windows following the desktop's color scheme to the theme
+/// for it, when it has changed
+fn follow_color_scheme(watcher: Option<&ColorSchemeWatcher>, windows: &mut [AppState]) {
+    let Some(scheme) = watcher.and_then(ColorSchemeWatcher::changed) else {
+        return;
+    };
+    for app in windows {
+        if let Some(theme) = app.prefs.themes.for_scheme(scheme).map(String::from) {
+            set_theme(app, &theme);
+        }
+    }
+}
+
+/// Start following the desktop's color scheme, when the config has a
+/// theme for light colors to follow it with
+fn color_scheme_watcher(cli: &Cli) -> Option<ColorSchemeWatcher> {
+    let config = yaml_parser::parse_config(cli.config.as_deref()).ok()?;
+    config.get("light_theme")?;
+    ColorSchemeWatcher::spawn()
+        .map_err(|e| warn!("Could not run gdbus to follow the desktop's color scheme: {}", e))
+        .ok()
+}
+
+/// Read the config again and apply the preferences in it to the open
+/// windows, and the colors of their theme. Fonts and the renderer are only
+/// read for new windows.
+fn reload_config(cli: &Cli, windows: &mut [AppState]) {
+    let reloaded = yaml_parser::parse_config(cli.config.as_deref()).and_then(|mut config| {
+        let errors = validate_config(cli, &mut config);
+        for app in windows.iter_mut() {
+            app.prefs = Preferences::from_config(&config)?;
+            // New highlight rules and selection colors can change any row
+            app.damage = Damage::new();
+            app.term.policy = escape_policy(cli, &config)?;
+            app.config_errors.clone_from(&errors);
+            app.pacing.request_redraw();
+            if let Some(theme) = app.theme.clone().or_else(|| app.prefs.themes.dark.clone()) {
+                set_theme(app, &theme);
+            }
+        }
+        Ok(())
+    });
+    match reloaded {
+        Ok(()) => info!("Reloaded the config"),
+        Err(e) => error!("Could not reload the config: {}", e),
+    }
+}
+
+/// Keep where the window was for the next one to open there
+fn save_geometry(app: &AppState) {
+    let Some((x, y, width, height)) = app.ts.restored_geometry.filter(|_| app.remember_geometry) else {
+        return;
+    };
+    let maximized = app.ts.window.borrow().is_maximized();
+    if let Err(e) = (WindowGeometry { x, y, width, height, maximized }).save() {
+        warn!("Could not save the window's geometry: {}", e);
+    }
+}
+
+/// Handle window events as they come in for up to `timeout`
+fn wait_events(glfw: &mut glfw::Glfw, timeout: Duration) {
+    // GLFW refuses a timeout of zero
+    if timeout.is_zero() {
+        glfw.poll_events();
+    } else {
+        glfw.wait_events_timeout(timeout.as_secs_f64());
+    }
+}
+
+/// Replay `capture` as fast as frames can be drawn, one read's worth of
+/// output per frame, then report how fast that went
+fn run_bench_render(cli: &Cli, capture: &Path) {
+    let output = std::fs::read(capture).unwrap_or_else(|e| {
+        eprintln!("rush: Could not read {}: {}", capture.display(), e);
+        std::process::exit(1);
+    });
+    let mut app = init(cli).unwrap_or_else(|e| {
+        eprintln!("rush: {}", e);
+        std::process::exit(1);
+    });
+    // How fast frames are made, not how often the monitor refreshes. wgpu
+    // goes by vsync in the config, it picks how frames are shown up front.
+    match app.renderer {
+        WindowRenderer::OpenGl(_) => app.ts.glfw.set_swap_interval(glfw::SwapInterval::None),
+        #[cfg(feature = "wgpu")]
+        WindowRenderer::Wgpu(_) => {}
+        WindowRenderer::Software(_) => {}
+    }
+
+    let start = Instant::now();
+    let (mut frames, mut cells, mut bytes) = (0u64, 0u64, 0usize);
+    'passes: for _ in 0..BENCH_PASSES {
+        for chunk in output.chunks(BENCH_READ_SIZE) {
+            app.term.process(chunk);
+            // Nobody is there to read the replies
+            app.term.take_responses();
+            draw_frame(&mut app);
+            app.ts.glfw.poll_events();
+            frames += 1;
+            cells += (app.term.rows * app.term.cols) as u64;
+            bytes += chunk.len();
+            if app.ts.window.borrow().should_close() {
+                break 'passes;
+            }
+        }
+    }
+    // Count the GPU's part of the last frames too
+    app.renderer.finish();
+
+    let seconds = start.elapsed().as_secs_f64();
+    println!(
+        "{} frames of {}x{} in {:.2} s: {:.1} frames/s, {:.0} cells/s, {:.2} MiB/s of output",
+        frames,
+        app.term.cols,
+        app.term.rows,
+        seconds,
+        frames as f64 / seconds,
+        cells as f64 / seconds,
+        bytes as f64 / seconds / (1024.0 * 1024.0)
+    );
+}
+
+/// Run the program without a window, or play a recording, then print the
+/// screen it left behind
+fn run_headless(cli: &Cli) {
+    if let Some(path) = &cli.play {
+        let cast = Cast::open(path).unwrap_or_else(|e| {
+            eprintln!("rush: {}", RushError::Play { path: path.clone(), source: e });
+            std::process::exit(1);
+        });
+        // No need to wait, the screen is the same once it's all applied
+        let mut term = Terminal::new(cast.rows, cast.cols);
+        for (_, event) in &cast.events {
+            event.apply(&mut term);
+        }
+        println!("{}", term.screen_text());
+        return;
+    }
+    let config = yaml_parser::parse_config(cli.config.as_deref()).unwrap_or_else(|e| {
+        eprintln!("rush: {}", e);
+        std::process::exit(1);
+    });
+    let (cols, rows) = cli.headless_size;
+    let mut headless = Headless::spawn(&shell_command(cli, &config, None), rows, cols);
+    headless.run(None);
+    println!("{}", headless.term.screen_text());
+}
+
+/// Slide the dropdown window in or out, opening it if there isn't one.
+/// Closing it, or its shell exiting, ends it like any other window.
+fn toggle_dropdown(cli: &Cli, glfw: &mut glfw::Glfw, windows: &mut Vec<AppState>) {
+    if !windows.iter().any(|app| app.dropdown.is_some()) {
+        let cli = Cli { toggle: true, ..cli.clone() };
+        match init(&cli) {
+            Ok(mut app) => {
+                app.daemon = true;
+                windows.push(app);
+            }
+            Err(e) => {
+                error!("Could not open the dropdown window: {}", e);
+                return;
+            }
+        }
+    }
+    for app in windows.iter_mut() {
+        if let Some(dropdown) = &mut app.dropdown {
+            dropdown.toggle(glfw, &mut app.ts.window.borrow_mut());
+        }
+    }
+}
+
+/// The window messages to the daemon are for: the focused one, or the
+/// last one opened that isn't detached
+fn target_window(windows: &[AppState]) -> Option<usize> {
+    let focused = windows.iter().position(|app| app.ts.focused);
+    focused.or_else(|| windows.iter().rposition(|app| app.detached.is_none()))
+}
+
+/// Host windows until SIGTERM, opening new ones and typing text into them
+/// as the control socket asks. It starts without a window and keeps
+/// running when the last one is closed, so the next one opens instantly.
+fn run_daemon(cli: &Cli) -> Result<(), RushError> {
+    let mut server =
+        control::Server::bind().map_err(|source| RushError::ControlSocket { path: control::socket_path(), source })?;
+    // The windows share GLFW, the decorations are picked once for them all
+    let decorations = yaml_parser::parse_config(cli.config.as_deref())
+        .ok()
+        .and_then(|config| config_value(&config, "wayland_decorations", WaylandDecorations::Client).ok())
+        .unwrap_or(WaylandDecorations::Client);
+    let mut glfw = init_glfw_library(decorations).map_err(|e| RushError::Glfw(format!("{:?}", e)))?;
+    let mut windows: Vec<AppState> = Vec::new();
+    let mut signals = SignalEvents::new().expect("Failed to set up signal handlers");
+    let color_scheme = color_scheme_watcher(cli);
+
+    loop {
+        follow_color_scheme(color_scheme.as_ref(), &mut windows);
+        for (message, reply) in server.poll() {
+            match message {
+                Message::NewWindow { cwd } => {
+                    let mut cli = cli.clone();
+                    cli.working_directory = cwd.or(cli.working_directory);
+                    match init(&cli) {
+                        Ok(mut app) => {
+                            app.daemon = true;
+                            windows.push(app);
+                        }
+                        Err(e) => error!("Could not open a window: {}", e),
+                    }
+                }
+                Message::SendText { text } => {
+                    match target_window(&windows) {
+                        Some(i) => windows[i].pty.write(text.as_bytes()),
+                        None => warn!("No window to send text to"),
+                    }
+                }
+                Message::Toggle => toggle_dropdown(cli, &mut glfw, &mut windows),
+                Message::Detach => match target_window(&windows) {
+                    Some(i) => detach(&mut windows[i]),
+                    None => warn!("No window to detach"),
+                },
+                Message::Attach { session } => {
+                    let mut detached = windows.iter_mut().filter(|app| app.detached.is_some());
+                    let app = match session {
+                        Some(pid) => detached.find(|app| app.pty.child().as_raw() == pid),
+                        None => detached.max_by_key(|app| app.detached),
+                    };
+                    match app {
+                        Some(app) => attach(app),
+                        None => reply.send("There's no such detached session\n"),
+                    }
+                }
+                Message::Sessions => {
+                    let mut sessions = String::new();
+                    for app in &windows {
+                        let state = if app.detached.is_some() { "detached" } else { "attached" };
+                        sessions += &format!("{}\t{}\t{}\n", app.pty.child(), state, app.ts.shown_title);
+                    }
+                    reply.send(&sessions);
+                }
+                Message::Theme { name } => {
+                    for app in windows.iter_mut() {
+                        set_theme(app, &name);
+                    }
+                }
+                Message::Screenshot { file } => {
+                    match target_window(&windows) {
+                        Some(i) => {
+                            windows[i].screenshot_to = Some(file);
+                            windows[i].pacing.request_redraw();
+                        }
+                        None => warn!("No window to take a screenshot of"),
+                    }
+                }
+                Message::Export { region, format, file } => {
+                    let Some(i) = target_window(&windows) else {
+                        warn!("No window to export from");
+                        continue;
+                    };
+                    match export_region(&windows[i], region, format) {
+                        Some(text) => {
+                            if let Err(e) = std::fs::write(&file, text) {
+                                error!("Could not export to {}: {}", file.display(), e);
+                            }
+                        }
+                        None => warn!("No {} to export", region.name()),
+                    }
+                }
+            }
+        }
+
+        // Until the window that needs it soonest
+        let wait = windows.iter().map(|app| app.pacing.wait()).min();
+        wait_events(&mut glfw, wait.unwrap_or(Duration::from_millis(100)));
+
+        // Each window draws with its own context
+        for app in windows.iter_mut() {
+            if let Some(dropdown) = &mut app.dropdown {
+                dropdown.step(&mut app.ts.window.borrow_mut());
+            }
+            app.renderer.make_current(&app.ts.window);
+            tick(app);
+        }
+        let terminate = handle_signals(&mut signals, cli, &mut windows);
+        for app in windows.iter().filter(|app| app.ts.window.borrow().should_close()) {
+            save_geometry(app);
+        }
+        windows.retain(|app| !app.ts.window.borrow().should_close());
+        if terminate {
+            return Ok(());
+        }
+    }
 }
@@ -0,0 +1,116 @@
+use crate::selection::Point;
+use crate::term::{Modes, MouseTracking};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Button {
+    Left,
+    Middle,
+    Right,
+    WheelUp,
+    WheelDown,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EventKind {
+    Press,
+    Release,
+    Motion,
+}
+
+#[derive(Default)]
+pub struct MouseState {
+    // Window coordinates of the pointer
+    pub position: (f64, f64),
+    pub pressed: Option<Button>,
+    // The last cell a motion event was reported for, so moving within
+    // a cell doesn't flood the application
+    pub last_cell: Option<(usize, usize)>,
+    // The shape the pointer was last given
+    pub shape: Option<glfw::StandardCursor>,
+    // Hidden while typing, until the mouse moves
+    pub hidden: bool,
+    // Where the left button went down to select text, while it's held
+    pub drag_anchor: Option<Point>,
+    // Whether the drag selects a block, alt was held when it started
+    pub drag_block: bool,
+    // Lines left over from scrolling the view while the drag is past the
+    // top or bottom of the grid, and the time that was last worked out at
+    pub autoscroll_lines: f64,
+    pub autoscroll_time: Option<f64>,
+    // When and where the left button last went down, to tell a double
+    // click from two clicks
+    pub last_click: Option<(f64, Point)>,
+}
+
+impl MouseState {
+    pub fn new() -> MouseState {
+        MouseState::default()
+    }
+}
+
+/// Encode a mouse event the way the application asked for it, or `None`
+/// if it isn't interested in this kind of event
+pub fn encode(
+    modes: &Modes,
+    button: Option<Button>,
+    kind: EventKind,
+    modifiers: glfw::Modifiers,
+    cell: (usize, usize),
+) -> Option<Vec<u8>> {
+    let tracking = modes.mouse_tracking;
+    match (tracking, kind) {
+        (MouseTracking::None, _) => return None,
+        (MouseTracking::X10, EventKind::Release | EventKind::Motion) => return None,
+        (MouseTracking::Normal, EventKind::Motion) => return None,
+        (MouseTracking::ButtonEvent, EventKind::Motion) if button.is_none() => return None,
+        _ => {}
+    }
+
+    let wheel = matches!(button, Some(Button::WheelUp | Button::WheelDown));
+    if wheel && kind == EventKind::Release {
+        return None;
+    }
+
+    let mut code: u32 = match button {
+        Some(Button::Left) => 0,
+        Some(Button::Middle) => 1,
+        Some(Button::Right) => 2,
+        Some(Button::WheelUp) => 64,
+        Some(Button::WheelDown) => 65,
+        None => 3,
+    };
+    if kind == EventKind::Motion {
+        code += 32;
+    }
+
+    // X10 mode doesn't report modifiers
+    if tracking != MouseTracking::X10 {
+        if modifiers.contains(glfw::Modifiers::Shift) {
+            code += 4;
+        }
+        if modifiers.contains(glfw::Modifiers::Alt) {
+            code += 8;
+        }
+        if modifiers.contains(glfw::Modifiers::Control) {
+            code += 16;
+        }
+    }
+
+    let (row, col) = (cell.0 + 1, cell.1 + 1);
+    if modes.sgr_mouse {
+        let end = if kind == EventKind::Release { 'm' } else { 'M' };
+        return Some(format!("\x1b[<{};{};{}{}", code, col, row, end).into_bytes());
+    }
+
+    // The legacy encoding can't say which button was released
+    if kind == EventKind::Release {
+        code = (code & !0b11) | 3;
+    }
+
+    // Coordinates are sent as single bytes offset by 32, so anything past
+    // column/row 223 can't be reported
+    if col > 223 || row > 223 {
+        return None;
+    }
+    Some(vec![0x1b, b'[', b'M', 32 + code as u8, 32 + col as u8, 32 + row as u8])
+}
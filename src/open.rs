@@ -0,0 +1,20 @@
+use std::process::Command;
+
+/// Open a URL or file with the desktop's handler for it
+pub fn open(target: &str) {
+    // `ls --hyperlink` and friends emit file://hostname/path, which the
+    // handlers don't all understand. Only local files can be opened anyway.
+    let target = match target.strip_prefix("file://") {
+        Some(rest) => rest.find('/').map_or(target, |slash| &rest[slash..]),
+        None => target,
+    };
+
+    let opener = if cfg!(target_os = "macos") { "open" } else { "xdg-open" };
+    match Command::new(opener).arg(target).spawn() {
+        // Reap the opener once it exits so it doesn't linger as a zombie
+        Ok(mut child) => {
+            std::thread::spawn(move || child.wait());
+        }
+        Err(e) => tracing::warn!("Could not run {} to open {}: {}", opener, target, e),
+    }
+}
@@ -0,0 +1,436 @@
+// A small VT500-style escape sequence parser, loosely following
+// Paul Williams' state machine (https://vt100.net/emu/dec_ansi_parser).
+// The parser only tokenizes, everything it recognizes is handed to a
+// `Perform` implementation which owns the actual terminal state.
+
+const MAX_PARAMS: usize = 32;
+const MAX_INTERMEDIATES: usize = 2;
+const MAX_STRING_LEN: usize = 1 << 20;
+
+pub trait Perform {
+    /// A printable character in the ground state
+    fn print(&mut self, c: char);
+
+    /// A C0 or C1 control character
+    fn execute(&mut self, byte: u8);
+
+    fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], action: char);
+
+    fn esc_dispatch(&mut self, intermediates: &[u8], byte: u8);
+
+    /// OSC strings, already split on ';'
+    fn osc_dispatch(&mut self, _params: &[&[u8]]) {}
+
+    /// A complete DCS string, dispatched once the terminator is seen
+    fn dcs_dispatch(&mut self, _params: &Params, _intermediates: &[u8], _action: char, _data: &[u8]) {}
+
+    /// A complete APC string, dispatched once the terminator is seen
+    fn apc_dispatch(&mut self, _data: &[u8]) {}
+}
+
+/// CSI/DCS parameters. Each parameter can carry colon separated
+/// subparameters (e.g. `38:2::255:0:0`), so every parameter is a slice.
+#[derive(Default, Debug, Clone)]
+pub struct Params {
+    values: Vec<u16>,
+    // Index into `values` at which each parameter starts
+    starts: Vec<usize>,
+}
+
+impl Params {
+    pub fn len(&self) -> usize {
+        self.starts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.starts.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&[u16]> {
+        let start = *self.starts.get(index)?;
+        let end = self.starts.get(index + 1).copied().unwrap_or(self.values.len());
+        Some(&self.values[start..end])
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &[u16]> {
+        (0..self.len()).filter_map(move |i| self.get(i))
+    }
+
+    /// The first value of parameter `index`, or `default` when it is
+    /// missing or zero, which is how most CSI sequences treat defaults
+    pub fn first_or(&self, index: usize, default: u16) -> u16 {
+        match self.get(index).and_then(|p| p.first()) {
+            Some(&v) if v != 0 => v,
+            _ => default,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.values.clear();
+        self.starts.clear();
+    }
+
+    fn push_param(&mut self) {
+        if self.starts.len() < MAX_PARAMS {
+            self.starts.push(self.values.len());
+            self.values.push(0);
+        }
+    }
+
+    fn push_subparam(&mut self) {
+        if self.starts.is_empty() {
+            self.push_param();
+        }
+        self.values.push(0);
+    }
+
+    fn push_digit(&mut self, digit: u8) {
+        if self.starts.is_empty() {
+            self.push_param();
+        }
+        if let Some(last) = self.values.last_mut() {
+            *last = last.saturating_mul(10).saturating_add((digit - b'0') as u16);
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+enum State {
+    #[default]
+    Ground,
+    Escape,
+    EscapeIntermediate,
+    CsiEntry,
+    CsiParam,
+    CsiIntermediate,
+    CsiIgnore,
+    DcsEntry,
+    DcsParam,
+    DcsIntermediate,
+    DcsPassthrough,
+    DcsIgnore,
+    OscString,
+    ApcString,
+    // SOS and PM strings are consumed and thrown away
+    IgnoredString,
+}
+
+#[derive(Default)]
+pub struct Parser {
+    state: State,
+    params: Params,
+    intermediates: Vec<u8>,
+    ignoring: bool,
+    // DCS final byte, needed when the string is dispatched
+    dcs_action: char,
+    // OSC, DCS and APC payloads
+    string: Vec<u8>,
+    // Set when an ESC was seen inside a string, the next byte decides
+    // whether it was a string terminator
+    string_esc: bool,
+    utf8: Utf8Decoder,
+}
+
+impl Parser {
+    pub fn new() -> Parser {
+        Parser::default()
+    }
+
+    pub fn advance<P: Perform>(&mut self, performer: &mut P, bytes: &[u8]) {
+        for &byte in bytes {
+            self.advance_byte(performer, byte);
+        }
+    }
+
+    fn advance_byte<P: Perform>(&mut self, performer: &mut P, byte: u8) {
+        match self.state {
+            State::OscString | State::ApcString | State::DcsPassthrough | State::IgnoredString => {
+                return self.advance_string(performer, byte);
+            }
+            _ => {}
+        }
+
+        // These are handled the same way from every non-string state
+        match byte {
+            0x18 | 0x1a => {
+                self.utf8.reset();
+                performer.execute(byte);
+                self.state = State::Ground;
+                return;
+            }
+            0x1b => {
+                self.utf8.reset();
+                self.enter_escape();
+                return;
+            }
+            _ => {}
+        }
+
+        match self.state {
+            State::Ground => self.advance_ground(performer, byte),
+            State::Escape => self.advance_escape(performer, byte),
+            State::EscapeIntermediate => match byte {
+                0x00..=0x1f => performer.execute(byte),
+                0x20..=0x2f => self.collect(byte),
+                0x30..=0x7e => {
+                    performer.esc_dispatch(&self.intermediates, byte);
+                    self.state = State::Ground;
+                }
+                _ => {}
+            },
+            State::CsiEntry | State::CsiParam | State::CsiIntermediate | State::CsiIgnore => {
+                self.advance_csi(performer, byte)
+            }
+            State::DcsEntry | State::DcsParam | State::DcsIntermediate | State::DcsIgnore => {
+                self.advance_dcs(byte)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn enter_escape(&mut self) {
+        self.params.clear();
+        self.intermediates.clear();
+        self.ignoring = false;
+        self.state = State::Escape;
+    }
+
+    fn collect(&mut self, byte: u8) {
+        if self.intermediates.len() < MAX_INTERMEDIATES {
+            self.intermediates.push(byte);
+        } else {
+            self.ignoring = true;
+        }
+    }
+
+    fn advance_ground<P: Perform>(&mut self, performer: &mut P, byte: u8) {
+        if self.utf8.is_pending() && byte & 0xc0 != 0x80 {
+            // The sequence was cut short, the new byte starts over
+            self.utf8.reset();
+            performer.print(char::REPLACEMENT_CHARACTER);
+        }
+
+        if self.utf8.is_pending() || byte >= 0x80 {
+            if let Some(c) = self.utf8.push(byte) {
+                performer.print(c);
+            }
+            return;
+        }
+
+        match byte {
+            0x00..=0x1f => performer.execute(byte),
+            0x7f => {}
+            _ => performer.print(byte as char),
+        }
+    }
+
+    fn advance_escape<P: Perform>(&mut self, performer: &mut P, byte: u8) {
+        match byte {
+            0x00..=0x1f => performer.execute(byte),
+            0x20..=0x2f => {
+                self.collect(byte);
+                self.state = State::EscapeIntermediate;
+            }
+            b'[' => self.state = State::CsiEntry,
+            b']' => self.enter_string(State::OscString),
+            b'P' => self.state = State::DcsEntry,
+            b'_' => self.enter_string(State::ApcString),
+            b'X' | b'^' => self.enter_string(State::IgnoredString),
+            0x30..=0x7e => {
+                performer.esc_dispatch(&self.intermediates, byte);
+                self.state = State::Ground;
+            }
+            _ => {}
+        }
+    }
+
+    fn advance_csi<P: Perform>(&mut self, performer: &mut P, byte: u8) {
+        match byte {
+            0x00..=0x1f => performer.execute(byte),
+            0x7f => {}
+            b'0'..=b'9' if self.state != State::CsiIntermediate => {
+                self.params.push_digit(byte);
+                self.state = State::CsiParam;
+            }
+            b';' if self.state != State::CsiIntermediate => {
+                if self.params.is_empty() {
+                    // An omitted first parameter still counts
+                    self.params.push_param();
+                }
+                self.params.push_param();
+                self.state = State::CsiParam;
+            }
+            b':' if self.state != State::CsiIntermediate => {
+                self.params.push_subparam();
+                self.state = State::CsiParam;
+            }
+            // Private markers are only valid right after the CSI
+            b'<'..=b'?' => {
+                if self.state == State::CsiEntry {
+                    self.collect(byte);
+                    self.state = State::CsiParam;
+                } else {
+                    self.state = State::CsiIgnore;
+                }
+            }
+            0x20..=0x2f => {
+                self.collect(byte);
+                self.state = State::CsiIntermediate;
+            }
+            0x40..=0x7e => {
+                if self.state != State::CsiIgnore && !self.ignoring {
+                    performer.csi_dispatch(&self.params, &self.intermediates, byte as char);
+                }
+                self.state = State::Ground;
+            }
+            _ => self.state = State::CsiIgnore,
+        }
+    }
+
+    fn advance_dcs(&mut self, byte: u8) {
+        match byte {
+            0x00..=0x1f | 0x7f => {}
+            b'0'..=b'9' if self.state != State::DcsIntermediate => {
+                self.params.push_digit(byte);
+                self.state = State::DcsParam;
+            }
+            b';' if self.state != State::DcsIntermediate => {
+                if self.params.is_empty() {
+                    self.params.push_param();
+                }
+                self.params.push_param();
+                self.state = State::DcsParam;
+            }
+            b':' if self.state != State::DcsIntermediate => {
+                self.params.push_subparam();
+                self.state = State::DcsParam;
+            }
+            b'<'..=b'?' => {
+                if self.state == State::DcsEntry {
+                    self.collect(byte);
+                    self.state = State::DcsParam;
+                } else {
+                    self.state = State::DcsIgnore;
+                }
+            }
+            0x20..=0x2f => {
+                self.collect(byte);
+                self.state = State::DcsIntermediate;
+            }
+            0x40..=0x7e => {
+                if self.state == State::DcsIgnore || self.ignoring {
+                    self.enter_string(State::IgnoredString);
+                } else {
+                    self.dcs_action = byte as char;
+                    self.enter_string(State::DcsPassthrough);
+                }
+            }
+            _ => self.state = State::DcsIgnore,
+        }
+    }
+
+    fn enter_string(&mut self, state: State) {
+        self.string.clear();
+        self.string_esc = false;
+        self.state = state;
+    }
+
+    fn advance_string<P: Perform>(&mut self, performer: &mut P, byte: u8) {
+        if self.string_esc {
+            self.string_esc = false;
+            self.finish_string(performer);
+            // ESC \ is the string terminator, anything else after the ESC
+            // starts a new escape sequence
+            self.enter_escape();
+            if byte != b'\\' {
+                self.advance_byte(performer, byte);
+            } else {
+                self.state = State::Ground;
+            }
+            return;
+        }
+
+        match byte {
+            0x1b => self.string_esc = true,
+            // BEL terminates OSC strings for xterm compatibility
+            0x07 if self.state == State::OscString => {
+                self.finish_string(performer);
+                self.state = State::Ground;
+            }
+            0x18 | 0x1a => {
+                self.string.clear();
+                self.state = State::Ground;
+            }
+            _ => {
+                if self.state != State::IgnoredString && self.string.len() < MAX_STRING_LEN {
+                    self.string.push(byte);
+                }
+            }
+        }
+    }
+
+    fn finish_string<P: Perform>(&mut self, performer: &mut P) {
+        match self.state {
+            State::OscString => {
+                let params: Vec<&[u8]> = self.string.split(|&b| b == b';').collect();
+                performer.osc_dispatch(&params);
+            }
+            State::DcsPassthrough => {
+                performer.dcs_dispatch(&self.params, &self.intermediates, self.dcs_action, &self.string);
+            }
+            State::ApcString => performer.apc_dispatch(&self.string),
+            _ => {}
+        }
+        self.string.clear();
+    }
+}
+
+/// Incremental UTF-8 decoder, invalid sequences decode to U+FFFD
+#[derive(Default)]
+struct Utf8Decoder {
+    codepoint: u32,
+    remaining: u8,
+    min: u32,
+}
+
+impl Utf8Decoder {
+    fn is_pending(&self) -> bool {
+        self.remaining > 0
+    }
+
+    fn reset(&mut self) {
+        self.remaining = 0;
+    }
+
+    /// Feed one byte, `byte` must be a continuation byte if a sequence
+    /// is pending
+    fn push(&mut self, byte: u8) -> Option<char> {
+        if self.remaining > 0 {
+            self.codepoint = (self.codepoint << 6) | (byte & 0x3f) as u32;
+            self.remaining -= 1;
+            if self.remaining > 0 {
+                return None;
+            }
+            if self.codepoint < self.min {
+                return Some(char::REPLACEMENT_CHARACTER);
+            }
+            return Some(char::from_u32(self.codepoint).unwrap_or(char::REPLACEMENT_CHARACTER));
+        }
+
+        self.start(byte)
+    }
+
+    fn start(&mut self, byte: u8) -> Option<char> {
+        let (codepoint, remaining, min) = match byte {
+            0x00..=0x7f => return Some(byte as char),
+            0xc2..=0xdf => ((byte & 0x1f) as u32, 1, 0x80),
+            0xe0..=0xef => ((byte & 0x0f) as u32, 2, 0x800),
+            0xf0..=0xf4 => ((byte & 0x07) as u32, 3, 0x10000),
+            _ => return Some(char::REPLACEMENT_CHARACTER),
+        };
+        self.codepoint = codepoint;
+        self.remaining = remaining;
+        self.min = min;
+        None
+    }
+}
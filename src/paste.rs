@@ -0,0 +1,63 @@
+// Pastes bigger than this are confirmed whatever they hold
+const LARGE_PASTE: usize = 64 * 1024;
+
+/// Drop the control characters from pasted text, keeping tabs and line
+/// breaks. Escape sequences in the clipboard could otherwise end a
+/// bracketed paste early and have the rest run as typed commands.
+pub fn sanitize(text: &str) -> String {
+    text.chars()
+        .filter(|&c| matches!(c, '\t' | '\n' | '\r') || !c.is_control())
+        .collect()
+}
+
+/// Whether pasting `text` should be confirmed first: it is big, or it has
+/// line breaks that would run commands because the application doesn't
+/// use bracketed paste
+pub fn needs_confirmation(text: &str, bracketed: bool) -> bool {
+    text.len() > LARGE_PASTE || (!bracketed && text.contains(['\n', '\r']))
+}
+
+/// What the prompt asking to confirm `text` says
+pub fn prompt(text: &str) -> String {
+    let lines = text.lines().count();
+    format!(
+        "Paste {} line{} ({} bytes)? [y/n]",
+        lines,
+        if lines == 1 { "" } else { "s" },
+        text.len()
+    )
+}
+
+/// The answer to the prompt a key gives, `None` for keys that don't answer
+pub fn answer(key: glfw::Key, action: glfw::Action, text: Option<char>) -> Option<bool> {
+    if action == glfw::Action::Release {
+        return None;
+    }
+    match (key, text) {
+        (glfw::Key::Enter, _) | (_, Some('y' | 'Y')) => Some(true),
+        (glfw::Key::Escape, _) | (_, Some('n' | 'N')) => Some(false),
+        _ => None,
+    }
+}
+
+/// Dropped files as they'd be typed at a shell prompt: quoted where
+/// needed, separated by spaces, with a space after the last one
+pub fn quote_paths(paths: &[std::path::PathBuf]) -> String {
+    let mut text = String::new();
+    for path in paths {
+        let path = path.to_string_lossy();
+        let plain = !path.is_empty()
+            && path.chars().all(|c| c.is_alphanumeric() || "/._-+,:@%=".contains(c));
+        if plain {
+            text.push_str(&path);
+        } else {
+            // Nothing is special inside single quotes, a quote itself has
+            // to be closed, escaped and reopened
+            text.push('\'');
+            text.push_str(&path.replace('\'', r"'\''"));
+            text.push('\'');
+        }
+        text.push(' ');
+    }
+    text
+}
@@ -0,0 +1,119 @@
+use std::time::{Duration, Instant};
+
+// How often the numbers change, every frame would be unreadable
+const UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Frame times, draw calls, cached glyphs, rows that changed, PTY
+/// throughput and the memory the scrollback takes, shown in a corner of
+/// the window while toggled on. Frame times only count the work
+/// of a frame, not the wait for the next vblank.
+pub struct PerfHud {
+    pub visible: bool,
+    // Since the numbers shown were last updated
+    since: Instant,
+    frames: u32,
+    frame_time: Duration,
+    max_frame_time: Duration,
+    pty_bytes: u64,
+    // What the last frame drew
+    draw_calls: u32,
+    glyphs: usize,
+    // Rows of the view the last frame changed, out of how many
+    damage: (usize, usize),
+    // Lines in the scrollback, the bytes they take and the most they may
+    scrollback: (usize, usize, usize),
+    text: String,
+}
+
+impl PerfHud {
+    pub fn new() -> PerfHud {
+        PerfHud {
+            visible: false,
+            since: Instant::now(),
+            frames: 0,
+            frame_time: Duration::ZERO,
+            max_frame_time: Duration::ZERO,
+            pty_bytes: 0,
+            draw_calls: 0,
+            glyphs: 0,
+            damage: (0, 0),
+            scrollback: (0, 0, 0),
+            text: String::new(),
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+        // Start over rather than show what was counted while hidden
+        self.reset();
+        self.text = "Measuring...".to_string();
+    }
+
+    /// Count bytes read from the PTY
+    pub fn read(&mut self, bytes: usize) {
+        self.pty_bytes += bytes as u64;
+    }
+
+    /// Note how big the scrollback is: `lines` taking `bytes` out of the
+    /// `budget` it may take
+    pub fn scrollback(&mut self, lines: usize, bytes: usize, budget: usize) {
+        self.scrollback = (lines, bytes, budget);
+    }
+
+    /// Note that the last frame changed `damaged` of the `rows` in view
+    pub fn damage(&mut self, damaged: usize, rows: usize) {
+        self.damage = (damaged, rows);
+    }
+
+    /// Count a frame that took `time` and `draw_calls` draws, with
+    /// `glyphs` glyphs rasterized and cached
+    pub fn frame(&mut self, time: Duration, draw_calls: u32, glyphs: usize) {
+        self.frames += 1;
+        self.frame_time += time;
+        self.max_frame_time = self.max_frame_time.max(time);
+        self.draw_calls = draw_calls;
+        self.glyphs = glyphs;
+
+        let elapsed = self.since.elapsed();
+        if elapsed < UPDATE_INTERVAL {
+            return;
+        }
+        let average = self.frame_time / self.frames;
+        self.text = format!(
+            "{:.2} ms avg {:.2} max | {:.0} fps | {} draws | {} glyphs | {}/{} rows damaged | pty {}/s | scrollback {} lines {}/{}",
+            average.as_secs_f64() * 1000.0,
+            self.max_frame_time.as_secs_f64() * 1000.0,
+            self.frames as f64 / elapsed.as_secs_f64(),
+            self.draw_calls,
+            self.glyphs,
+            self.damage.0,
+            self.damage.1,
+            format_bytes(self.pty_bytes as f64 / elapsed.as_secs_f64()),
+            self.scrollback.0,
+            format_bytes(self.scrollback.1 as f64),
+            format_bytes(self.scrollback.2 as f64)
+        );
+        self.reset();
+    }
+
+    /// What the overlay says
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    fn reset(&mut self) {
+        self.since = Instant::now();
+        self.frames = 0;
+        self.frame_time = Duration::ZERO;
+        self.max_frame_time = Duration::ZERO;
+        self.pty_bytes = 0;
+    }
+}
+
+fn format_bytes(bytes: f64) -> String {
+    match bytes {
+        b if b >= 1024.0 * 1024.0 => format!("{:.1} MiB", b / (1024.0 * 1024.0)),
+        b if b >= 1024.0 => format!("{:.1} KiB", b / 1024.0),
+        b => format!("{:.0} B", b),
+    }
+}
@@ -0,0 +1,92 @@
+/// Whether programs may use a feature
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Permission {
+    Allow,
+    Deny,
+}
+
+impl std::str::FromStr for Permission {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allow" => Ok(Permission::Allow),
+            "deny" => Ok(Permission::Deny),
+            _ => Err(format!("expected allow or deny, not {:?}", s)),
+        }
+    }
+}
+
+/// What programs can do past the screen with escape sequences
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Feature {
+    // OSC 52, putting text on the clipboard
+    ClipboardWrite,
+    // OSC 0 and 2, and restoring a title saved with XTWINOPS
+    Title,
+    // OSC 9 and OSC 777's notify
+    Notifications,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 3] = [Feature::ClipboardWrite, Feature::Title, Feature::Notifications];
+
+    /// Its name in the config and on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            Feature::ClipboardWrite => "clipboard_write",
+            Feature::Title => "title",
+            Feature::Notifications => "notifications",
+        }
+    }
+}
+
+impl std::str::FromStr for Feature {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Feature::ALL
+            .into_iter()
+            .find(|feature| feature.name() == s)
+            .ok_or_else(|| format!("expected clipboard_write, title or notifications, not {:?}", s))
+    }
+}
+
+/// Which features the programs in a window may use. Whatever runs in it
+/// gets the same say, a program on another machine over ssh as much as
+/// the shell, so the features it could be abused for can be turned off.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Policy {
+    clipboard_write: Permission,
+    title: Permission,
+    notifications: Permission,
+}
+
+impl Default for Policy {
+    fn default() -> Policy {
+        Policy { clipboard_write: Permission::Allow, title: Permission::Allow, notifications: Permission::Allow }
+    }
+}
+
+impl Policy {
+    pub fn allows(&self, feature: Feature) -> bool {
+        self.get(feature) == Permission::Allow
+    }
+
+    pub fn get(&self, feature: Feature) -> Permission {
+        match feature {
+            Feature::ClipboardWrite => self.clipboard_write,
+            Feature::Title => self.title,
+            Feature::Notifications => self.notifications,
+        }
+    }
+
+    pub fn set(&mut self, feature: Feature, permission: Permission) {
+        let field = match feature {
+            Feature::ClipboardWrite => &mut self.clipboard_write,
+            Feature::Title => &mut self.title,
+            Feature::Notifications => &mut self.notifications,
+        };
+        *field = permission;
+    }
+}
@@ -0,0 +1,79 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// The PRIMARY selection of X11 and Wayland: whatever was selected last,
+/// pasted with a middle click. GLFW only knows the clipboard, so it goes
+/// through wl-copy/wl-paste or xclip. Where neither is there the selection
+/// is only shared between rush's own windows.
+#[derive(Default)]
+pub struct PrimarySelection {
+    // What was selected last, for when the tools aren't available
+    local: Option<String>,
+}
+
+impl PrimarySelection {
+    pub fn new() -> PrimarySelection {
+        PrimarySelection::default()
+    }
+
+    pub fn set(&mut self, text: &str) {
+        self.local = Some(text.to_string());
+        let Some((program, args)) = copy_command() else {
+            return;
+        };
+        let child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(e) => {
+                tracing::warn!("Could not run {} to set the primary selection: {}", program, e);
+                return;
+            }
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            let _ = stdin.write_all(text.as_bytes());
+        }
+        // They fork to keep serving the selection, reap what's left
+        std::thread::spawn(move || child.wait());
+    }
+
+    pub fn get(&self) -> Option<String> {
+        let Some((program, args)) = paste_command() else {
+            return self.local.clone();
+        };
+        match Command::new(program).args(args).stderr(Stdio::null()).output() {
+            Ok(output) if output.status.success() => Some(String::from_utf8_lossy(&output.stdout).into_owned()),
+            // Nothing is selected anywhere
+            Ok(_) => None,
+            Err(_) => self.local.clone(),
+        }
+    }
+}
+
+fn wayland() -> bool {
+    std::env::var_os("WAYLAND_DISPLAY").is_some()
+}
+
+fn copy_command() -> Option<(&'static str, &'static [&'static str])> {
+    if !cfg!(target_os = "linux") {
+        None
+    } else if wayland() {
+        Some(("wl-copy", &["--primary"]))
+    } else {
+        Some(("xclip", &["-selection", "primary", "-in"]))
+    }
+}
+
+fn paste_command() -> Option<(&'static str, &'static [&'static str])> {
+    if !cfg!(target_os = "linux") {
+        None
+    } else if wayland() {
+        Some(("wl-paste", &["--primary", "--no-newline"]))
+    } else {
+        Some(("xclip", &["-selection", "primary", "-out"]))
+    }
+}
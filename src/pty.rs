@@ -0,0 +1,251 @@
+use nix::errno::Errno;
+use nix::fcntl::{fcntl, FcntlArg, OFlag};
+use nix::libc;
+use nix::pty::{forkpty, ForkptyResult, Winsize};
+use nix::sys::signal::{killpg, Signal};
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::{execvp, read, tcgetpgrp, write, Pid};
+use std::ffi::CString;
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::path::PathBuf;
+
+use crate::terminfo;
+
+/// The program to run on the secondary side and the environment to run it in
+pub struct Command {
+    pub program: String,
+    pub args: Vec<String>,
+    // Inherited from rush when not set
+    pub working_directory: Option<PathBuf>,
+    // Variables set on top of the inherited environment
+    pub env: Vec<(String, String)>,
+}
+
+/// Arguments written as a shell would take them: split on whitespace but
+/// not inside single or double quotes, with a backslash keeping the
+/// character after it as it is, except inside single quotes
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Args(pub Vec<String>);
+
+impl std::str::FromStr for Args {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut args = Vec::new();
+        // The argument being read, None between arguments
+        let mut arg: Option<String> = None;
+        let mut quote = None;
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            match (quote, c) {
+                (Some(open), c) if c == open => quote = None,
+                (None, '\'' | '"') => {
+                    quote = Some(c);
+                    arg.get_or_insert_with(String::new);
+                }
+                (None | Some('"'), '\\') => {
+                    let escaped = chars.next().ok_or_else(|| "ends with a lone backslash".to_string())?;
+                    arg.get_or_insert_with(String::new).push(escaped);
+                }
+                (None, c) if c.is_whitespace() => args.extend(arg.take()),
+                (_, c) => arg.get_or_insert_with(String::new).push(c),
+            }
+        }
+        if let Some(open) = quote {
+            return Err(format!("the {} quote isn't closed", open));
+        }
+        args.extend(arg);
+        Ok(Args(args))
+    }
+}
+
+/// How the child ended
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ExitStatus {
+    Exited(i32),
+    Killed(Signal),
+}
+
+impl ExitStatus {
+    pub fn success(self) -> bool {
+        self == ExitStatus::Exited(0)
+    }
+}
+
+impl std::fmt::Display for ExitStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ExitStatus::Exited(code) => write!(f, "exited with status {}", code),
+            ExitStatus::Killed(signal) => write!(f, "killed by {}", signal.as_str()),
+        }
+    }
+}
+
+/// The primary side of a pseudoterminal with a shell running on the
+/// secondary side. Dropping it hangs up on the shell, as closing a
+/// terminal does.
+pub struct Pty {
+    master: OwnedFd,
+    child: Pid,
+    // Once the child has been reaped, its pid may belong to another process
+    exit_status: Option<ExitStatus>,
+    // Input the child hasn't taken yet, written as it reads
+    pending: Vec<u8>,
+}
+
+impl Pty {
+    pub fn spawn(command: &Command, rows: usize, cols: usize, cell_size: (u32, u32)) -> Pty {
+        let winsize = winsize(rows, cols, cell_size);
+        match unsafe { forkpty(&winsize, None) } {
+            Ok(ForkptyResult::Child) => {
+                // stderr is the terminal now, these show up in the window
+                if let Some(dir) = &command.working_directory {
+                    if let Err(e) = std::env::set_current_dir(dir) {
+                        eprintln!("Could not change to {:?}: {}", dir, e);
+                    }
+                }
+                for (key, value) in &command.env {
+                    std::env::set_var(key, value);
+                }
+
+                let program = CString::new(command.program.as_str()).expect("Invalid program path");
+                let args: Vec<CString> = std::iter::once(program.clone())
+                    .chain(command.args.iter().map(|arg| CString::new(arg.as_str()).expect("Invalid argument")))
+                    .collect();
+                let _ = execvp(&program, &args);
+                eprintln!("Failed to exec {:?}", program);
+                std::process::exit(1);
+            }
+            Ok(ForkptyResult::Parent { master, child }) => {
+                // Reads happen once per frame, they must not block the render loop
+                let flags = fcntl(master.as_raw_fd(), FcntlArg::F_GETFL).expect("Failed to get pty flags");
+                let flags = OFlag::from_bits_truncate(flags) | OFlag::O_NONBLOCK;
+                fcntl(master.as_raw_fd(), FcntlArg::F_SETFL(flags)).expect("Failed to set pty flags");
+
+                Pty { master, child, exit_status: None, pending: Vec::new() }
+            }
+            Err(e) => panic!("Failed to fork {:?}", e),
+        }
+    }
+
+    pub fn child(&self) -> Pid {
+        self.child
+    }
+
+    /// The directory the child is currently in
+    pub fn working_directory(&self) -> Option<PathBuf> {
+        std::fs::read_link(format!("/proc/{}/cwd", self.child)).ok()
+    }
+
+    /// The process group in the foreground of the terminal, the one keys
+    /// are sent to, if it isn't the child's own. Shells put each command
+    /// they run in a group of its own.
+    pub fn foreground_job(&self) -> Option<Pid> {
+        // 0 while no group is in the foreground
+        tcgetpgrp(&self.master).ok().filter(|&group| group != self.child && group.as_raw() > 0)
+    }
+
+    /// Reap the child if it has exited, and say how it ended. `None` while
+    /// it's still running.
+    pub fn try_wait(&mut self) -> Option<ExitStatus> {
+        if self.exit_status.is_none() {
+            self.exit_status = match waitpid(self.child, Some(WaitPidFlag::WNOHANG)) {
+                Ok(WaitStatus::Exited(_, code)) => Some(ExitStatus::Exited(code)),
+                Ok(WaitStatus::Signaled(_, signal, _)) => Some(ExitStatus::Killed(signal)),
+                _ => None,
+            };
+        }
+        self.exit_status
+    }
+
+    /// Read whatever the child has written. Returns `None` if there is
+    /// nothing to read right now and `Some(0)` once the child is gone.
+    pub fn read(&self, buf: &mut [u8]) -> Option<usize> {
+        match read(self.master.as_raw_fd(), buf) {
+            Ok(n) => Some(n),
+            Err(Errno::EAGAIN) | Err(Errno::EINTR) => None,
+            // Linux reports EIO once the secondary side has been closed
+            Err(_) => Some(0),
+        }
+    }
+
+    /// Send input to the child. What doesn't fit while the child isn't
+    /// reading is kept for `flush`, rather than waiting on it.
+    pub fn write(&mut self, bytes: &[u8]) {
+        self.pending.extend_from_slice(bytes);
+        self.flush();
+    }
+
+    /// Write as much of the input kept by `write` as the child takes now
+    pub fn flush(&mut self) {
+        let mut written = 0;
+        while written < self.pending.len() {
+            match write(&self.master, &self.pending[written..]) {
+                Ok(n) => written += n,
+                Err(Errno::EINTR) => continue,
+                Err(Errno::EAGAIN) => break,
+                Err(e) => {
+                    tracing::error!("Failed to write to pty: {:?}", e);
+                    written = self.pending.len();
+                }
+            }
+        }
+        self.pending.drain(..written);
+    }
+
+    pub fn resize(&self, rows: usize, cols: usize, cell_size: (u32, u32)) {
+        let winsize = winsize(rows, cols, cell_size);
+        unsafe {
+            libc::ioctl(self.master.as_raw_fd(), libc::TIOCSWINSZ, &winsize);
+        }
+    }
+}
+
+/// The name of the program process `pid` runs, as `ps` shows it
+pub fn process_name(pid: Pid) -> Option<String> {
+    let comm = std::fs::read_to_string(format!("/proc/{}/comm", pid)).ok()?;
+    Some(comm.trim_end().to_string())
+}
+
+impl Drop for Pty {
+    fn drop(&mut self) {
+        if self.exit_status.is_some() {
+            return;
+        }
+        // The job in the foreground too, a shell doesn't always pass the
+        // hangup on to it
+        for group in [self.foreground_job(), Some(self.child)].into_iter().flatten() {
+            let _ = killpg(group, Signal::SIGHUP);
+        }
+        // Reaped whenever it gets around to exiting, so it isn't left a
+        // zombie
+        let child = self.child;
+        std::thread::spawn(move || waitpid(child, None));
+    }
+}
+
+// The pixel size lets programs drawing images work out how big a cell is
+fn winsize(rows: usize, cols: usize, cell_size: (u32, u32)) -> Winsize {
+    Winsize {
+        ws_row: rows as u16,
+        ws_col: cols as u16,
+        ws_xpixel: (cols as u32 * cell_size.0) as u16,
+        ws_ypixel: (rows as u32 * cell_size.1) as u16,
+    }
+}
+
+/// The environment given to the child: `TERM` describing rush, `COLORTERM`
+/// advertising truecolor, and `WINDOWID` when running under X11
+pub fn child_env(window_id: Option<u64>) -> Vec<(String, String)> {
+    // Programs misbehave with a TERM their terminfo database doesn't know,
+    // so only claim to be rush when its entry is installed
+    let term = if terminfo::is_installed("rush") { "rush" } else { "xterm-256color" };
+    let mut env = vec![
+        ("TERM".to_string(), term.to_string()),
+        ("COLORTERM".to_string(), "truecolor".to_string()),
+    ];
+    if let Some(id) = window_id {
+        env.push(("WINDOWID".to_string(), id.to_string()));
+    }
+    env
+}
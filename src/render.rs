@@ -0,0 +1,193 @@
+use std::borrow::Cow;
+use std::io;
+use std::path::Path;
+
+use crate::color::{self, Palette, Rgb};
+use crate::highlight::Restyled;
+use crate::term::{self, Terminal};
+
+/// What a frame depends on besides the terminal
+#[derive(Clone, Copy, Debug)]
+pub struct Frame<'a> {
+    pub focused: bool,
+    // Blinking text is shown for half of every blink
+    pub blink_visible: bool,
+    // Drawn while the view is in the scrollback, and for a moment after
+    pub scrollbar: Option<Scrollbar>,
+    // The rows the highlight rules restyled, drawn instead of the
+    // terminal's own
+    pub restyled: Option<&'a Restyled>,
+}
+
+impl Frame<'_> {
+    /// The cells of the row at `index` in view, as they are drawn
+    pub fn row<'b>(&'b self, term: &'b Terminal, index: usize) -> Cow<'b, [term::Cell]> {
+        match self.restyled.and_then(|restyled| restyled.row(index)) {
+            Some(row) => Cow::Borrowed(row),
+            None => term.visible_row(index),
+        }
+    }
+}
+
+/// How the scrollbar over the right edge of the grid looks this frame
+#[derive(Clone, Copy, Debug)]
+pub struct Scrollbar {
+    // In framebuffer pixels
+    pub width: f32,
+    // Its alpha fades the scrollbar in and out
+    pub color: [f32; 4],
+}
+
+/// Where the view is in the scrollback and the screen below it: the top
+/// of the scrollbar's thumb and its height, as fractions of the grid's
+/// height from the top. `None` without a scrollback to scroll through.
+pub fn scrollbar_thumb(term: &Terminal) -> Option<(f32, f32)> {
+    let scrollback = term.scrollback_len();
+    if scrollback == 0 {
+        return None;
+    }
+    let lines = (scrollback + term.rows) as f32;
+    let top = scrollback.saturating_sub(term.display_offset) as f32;
+    Some((top / lines, term.rows as f32 / lines))
+}
+
+/// The cursor as it is drawn
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Cursor {
+    pub row: usize,
+    pub col: usize,
+    // In columns, two on a wide character
+    pub width: usize,
+    // An outline instead of a block, while the window doesn't have focus
+    pub hollow: bool,
+}
+
+impl Cursor {
+    /// Where the cursor of `term` is drawn, if the application didn't hide
+    /// it and it isn't scrolled out of view
+    pub fn of(term: &Terminal, focused: bool) -> Option<Cursor> {
+        let row = term.cursor_view_row().filter(|_| term.modes.cursor_visible)?;
+        Some(Cursor { row, col: term.cursor.col, width: term.cursor_width(), hollow: !focused })
+    }
+}
+
+// Below this contrast ratio the cursor is taken for the cell it's on, or
+// the text under it for the cursor
+const MIN_CURSOR_CONTRAST: f32 = 1.5;
+
+/// How the cursor is colored, `cursor.*` in the config
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct CursorStyle {
+    // `None` swaps the colors of the cell under the cursor. A color the
+    // application sets with OSC 12 takes its place.
+    pub color: Option<Rgb>,
+    // The text under a block cursor, the cell's background when unset
+    pub text_color: Option<Rgb>,
+    // Below 1 the cell shows through the cursor, and its text isn't drawn
+    // again on top
+    pub opacity: f32,
+}
+
+impl Default for CursorStyle {
+    fn default() -> CursorStyle {
+        CursorStyle { color: None, text_color: None, opacity: 1.0 }
+    }
+}
+
+impl CursorStyle {
+    /// The color of the cursor over `cell` and that of the text under it.
+    /// Either is swapped for black or white when it would be hard to tell
+    /// from what is behind it.
+    pub fn colors(&self, palette: &Palette, cell: &term::Cell) -> (Rgb, Rgb) {
+        let (fg, bg) = palette.cell_colors(cell);
+        let mut cursor = palette.cursor.or(self.color).unwrap_or(fg);
+        if color::contrast(cursor, bg) < MIN_CURSOR_CONTRAST {
+            cursor = color::contrasting(bg);
+        }
+        let mut text = self.text_color.unwrap_or(bg);
+        if color::contrast(text, cursor) < MIN_CURSOR_CONTRAST {
+            text = color::contrasting(cursor);
+        }
+        (cursor, text)
+    }
+
+    /// Whether the text under a block cursor is drawn over it
+    pub fn opaque(&self) -> bool {
+        self.opacity >= 1.0
+    }
+}
+
+/// Draws frames of the terminal, the grid logic doesn't care with what.
+/// A frame is begun, its cells, the cursor and overlays drawn in that
+/// order, and then it is presented.
+pub trait Renderer {
+    /// Start a frame, cleared to the default background
+    fn begin_frame(&mut self, term: &Terminal);
+
+    /// The cells in view: their backgrounds, text and its decorations
+    fn draw_cells(&mut self, term: &Terminal, frame: &Frame);
+
+    /// The cursor over the cells, with the text under a block cursor drawn
+    /// again on top of it so it stays readable
+    fn draw_cursor(&mut self, term: &Terminal, frame: &Frame, cursor: &Cursor);
+
+    /// Whatever the window shows over the terminal, nothing by default
+    fn draw_overlays(&mut self, _term: &Terminal, _frame: &Frame) {}
+
+    /// The finished frame before it is shown, `None` from renderers that
+    /// can't read it back
+    fn read_pixels(&mut self) -> Option<Screenshot> {
+        None
+    }
+
+    /// Show the finished frame
+    fn present(&mut self);
+}
+
+/// Draw a whole frame of `term`
+pub fn draw(renderer: &mut impl Renderer, term: &Terminal, frame: &Frame) {
+    compose(renderer, term, frame);
+    renderer.present();
+}
+
+/// Draw a whole frame of `term` and keep a copy of it
+pub fn capture(renderer: &mut impl Renderer, term: &Terminal, frame: &Frame) -> Option<Screenshot> {
+    compose(renderer, term, frame);
+    let screenshot = renderer.read_pixels();
+    renderer.present();
+    screenshot
+}
+
+fn compose(renderer: &mut impl Renderer, term: &Terminal, frame: &Frame) {
+    renderer.begin_frame(term);
+    renderer.draw_cells(term, frame);
+    if let Some(cursor) = Cursor::of(term, frame.focused) {
+        renderer.draw_cursor(term, frame, &cursor);
+    }
+    renderer.draw_overlays(term, frame);
+}
+
+/// A frame as it was drawn, in opaque RGBA pixels in rows from the top
+pub struct Screenshot {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+impl Screenshot {
+    pub fn write_png(&self, path: &Path) -> io::Result<()> {
+        let file = io::BufWriter::new(std::fs::File::create(path)?);
+        let mut encoder = png::Encoder::new(file, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(io::Error::other)?;
+        writer.write_image_data(&self.rgba).map_err(io::Error::other)?;
+        writer.finish().map_err(io::Error::other)
+    }
+}
+
+/// Whether the text of a cell is drawn this frame, concealed cells and
+/// blinking cells in the off half of the blink are left blank
+pub fn text_visible(cell: &term::Cell, blink_visible: bool) -> bool {
+    !cell.flags.contains(term::Flags::HIDDEN) && (blink_visible || !cell.flags.contains(term::Flags::BLINK))
+}
@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+
+use rush::color;
+use rush::render::Scrollbar;
+use rush::term::Terminal;
+
+use crate::error::RushError;
+
+// Seconds the scrollbar stays once the view is back at the bottom, and how
+// long it then takes to fade out
+const LINGER: f64 = 0.6;
+const FADE_OUT: f64 = 0.3;
+
+// Lets the text under it show through
+const OPACITY: f32 = 0.6;
+
+/// The scrollbar, `scrollbar:` in the config. It shows while the view is
+/// in the scrollback and fades out after it comes back to the bottom.
+pub struct ScrollbarFade {
+    // Pixels before scaling for the monitor, 0 turns the scrollbar off
+    width: f32,
+    color: color::Rgb,
+    // When the view came back to the bottom, `None` while it's scrolled up
+    at_bottom_since: Option<f64>,
+}
+
+impl ScrollbarFade {
+    pub fn from_config(config: &HashMap<String, String>) -> Result<ScrollbarFade, RushError> {
+        Ok(ScrollbarFade {
+            width: crate::config_value(config, "scrollbar.width", 4.0f32)?.max(0.0),
+            color: crate::config_color(config, "scrollbar.color")?.unwrap_or((0x88, 0x88, 0x88)),
+            // Nothing to fade out when the window opens
+            at_bottom_since: Some(f64::NEG_INFINITY),
+        })
+    }
+
+    /// The scrollbar to draw at `time` in seconds, if any, and whether it
+    /// is fading out and needs more frames
+    pub fn update(&mut self, term: &Terminal, time: f64, scale: f32) -> (Option<Scrollbar>, bool) {
+        if self.width == 0.0 {
+            return (None, false);
+        }
+        let alpha = if term.display_offset > 0 {
+            self.at_bottom_since = None;
+            1.0
+        } else {
+            let elapsed = time - *self.at_bottom_since.get_or_insert(time);
+            (1.0 - (elapsed - LINGER) / FADE_OUT).clamp(0.0, 1.0) as f32
+        };
+        if alpha == 0.0 {
+            return (None, false);
+        }
+        let mut color = color::to_gl(self.color);
+        color[3] = OPACITY * alpha;
+        let scrollbar = Scrollbar { width: (self.width * scale).round().max(1.0), color };
+        (Some(scrollbar), term.display_offset == 0)
+    }
+}
@@ -0,0 +1,211 @@
+use crate::selection::Point;
+use crate::span::{Span, SpanKind};
+use crate::term::{Flags, Terminal};
+
+/// A match of the query, from column `start` of `line` to before column
+/// `end` of `end_line`. That's a later line than `line` when the match
+/// goes on across lines the text wrapped over.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Match {
+    pub line: usize,
+    pub start: usize,
+    pub end_line: usize,
+    pub end: usize,
+}
+
+/// Searching the scrollback. While it is open it takes over the keyboard:
+/// keys edit the query until Enter, then `n` and `N` jump between matches.
+#[derive(Default)]
+pub struct Search {
+    pub open: bool,
+    // Whether keys are going to the query rather than moving between matches
+    pub editing: bool,
+    pub query: String,
+    // Sorted from the oldest line to the newest. Matches in the history
+    // spilled to disk come first, numbered by line of the spill.
+    pub matches: Vec<Match>,
+    pub current: Option<usize>,
+    // The text of the lines the matches on disk are in, one per match
+    pub older: Vec<String>,
+    // Lines of the spill searched for the query so far
+    older_searched: usize,
+}
+
+impl Search {
+    pub fn new() -> Search {
+        Search::default()
+    }
+
+    pub fn start(&mut self) {
+        self.open = true;
+        self.editing = true;
+    }
+
+    pub fn close(&mut self, term: &mut Terminal) {
+        *self = Search::default();
+        self.publish(term);
+    }
+
+    /// Handle a key event while the search is open
+    pub fn handle_key(&mut self, key: glfw::Key, action: glfw::Action, text: Option<char>, term: &mut Terminal) {
+        if action == glfw::Action::Release {
+            return;
+        }
+        match key {
+            glfw::Key::Escape => self.close(term),
+            glfw::Key::Enter | glfw::Key::KpEnter if self.editing => self.editing = false,
+            glfw::Key::Backspace if self.editing => {
+                self.query.pop();
+                self.update(term);
+            }
+            _ if self.editing => {
+                if let Some(c) = text.filter(|c| !c.is_control()) {
+                    self.query.push(c);
+                    self.update(term);
+                }
+            }
+            // Searching starts from the bottom, so `n` goes up towards older
+            // output and `N` back down
+            _ => match text {
+                Some('n') => self.jump(term, false),
+                Some('N') => self.jump(term, true),
+                _ => {}
+            },
+        }
+    }
+
+    /// Search again after the query changed, the newest match becomes the
+    /// current one
+    pub fn update(&mut self, term: &mut Terminal) {
+        self.matches.clear();
+        self.older.clear();
+        self.older_searched = 0;
+        self.find_matches(term);
+        self.current = self.matches.len().checked_sub(1);
+        self.publish(term);
+        self.scroll_to_current(term);
+    }
+
+    /// Search again after new output arrived, staying on the current match
+    /// where possible
+    pub fn refresh(&mut self, term: &mut Terminal) {
+        let current = self.current;
+        self.find_matches(term);
+        self.current = current.zip(self.matches.len().checked_sub(1)).map(|(c, last)| c.min(last));
+        self.publish(term);
+    }
+
+    // Lay the matches in the scrollback and on the screen over them, for
+    // the renderer
+    fn publish(&self, term: &mut Terminal) {
+        term.spans.remove(|kind| matches!(kind, SpanKind::SearchMatch { .. }));
+        let on_screen = self.matches.iter().enumerate().skip(self.older.len());
+        term.spans.extend(on_screen.map(|(i, m)| Span {
+            kind: SpanKind::SearchMatch { current: Some(i) == self.current },
+            start: Point { line: m.line, col: m.start },
+            end: Point { line: m.end_line, col: m.end },
+        }));
+    }
+
+    fn find_matches(&mut self, term: &Terminal) {
+        // Lines on disk don't change, only the ones spilled since the last
+        // search are looked at
+        self.matches.truncate(self.older.len());
+        let spilled = term.spilled().map_or(0, |spill| spill.len());
+        if spilled < self.older_searched {
+            self.matches.clear();
+            self.older.clear();
+            self.older_searched = 0;
+        }
+        if self.query.is_empty() {
+            return;
+        }
+
+        // Smart case: only case sensitive if the query has capitals in it
+        let case_sensitive = self.query.chars().any(char::is_uppercase);
+        let fold = |c: char| if case_sensitive { c } else { c.to_lowercase().next().unwrap_or(c) };
+        let query: Vec<char> = self.query.chars().map(fold).collect();
+
+        let mut chars = Vec::new();
+        if let Some(spill) = term.spilled() {
+            let searched = spill.for_each_line(self.older_searched, |line, text| {
+                chars.clear();
+                chars.extend(text.chars().map(fold));
+                for found in occurrences(&chars, &query) {
+                    self.matches.push(Match { line, start: found.start, end_line: line, end: found.end });
+                    self.older.push(text.to_string());
+                }
+            });
+            match searched {
+                Ok(()) => self.older_searched = spilled,
+                Err(e) => tracing::warn!("Could not search the scrollback spilled to disk: {}", e),
+            }
+        }
+
+        // Where each character came from
+        let mut points = Vec::new();
+        let mut line = 0;
+        while line < term.total_lines() {
+            chars.clear();
+            points.clear();
+            // The lines a line of text was wrapped over are searched as one
+            let lines = term.logical_line(line);
+            line = *lines.end() + 1;
+            for line in lines {
+                for (col, cell) in term.line(line).iter().enumerate() {
+                    if !cell.flags.intersects(Flags::WIDE_CHAR_SPACER | Flags::LEADING_WIDE_CHAR_SPACER) {
+                        chars.push(fold(cell.c));
+                        points.push((line, col));
+                    }
+                }
+            }
+
+            for found in occurrences(&chars, &query) {
+                let (line, start) = points[found.start];
+                let (end_line, last) = points[found.end - 1];
+                let wide = term.line(end_line)[last].flags.contains(Flags::WIDE_CHAR);
+                self.matches.push(Match { line, start, end_line, end: last + if wide { 2 } else { 1 } });
+            }
+        }
+    }
+
+    /// The line the current match is in when it's in the history spilled
+    /// to disk, which isn't on the screen to scroll to
+    pub fn current_older_line(&self) -> Option<&str> {
+        self.older.get(self.current?).map(String::as_str)
+    }
+
+    fn jump(&mut self, term: &mut Terminal, newer: bool) {
+        let Some(current) = self.current else {
+            return;
+        };
+        let count = self.matches.len();
+        self.current = Some(if newer { (current + 1) % count } else { (current + count - 1) % count });
+        self.publish(term);
+        self.scroll_to_current(term);
+    }
+
+    fn scroll_to_current(&self, term: &mut Terminal) {
+        match self.current {
+            // As close to it as the scrollback goes
+            Some(current) if current < self.older.len() => term.scroll_to_line(0),
+            Some(current) => term.scroll_to_line(self.matches[current].line),
+            None => {}
+        }
+    }
+}
+
+// Where `query` is in `chars`, as ranges of indices that don't overlap
+fn occurrences(chars: &[char], query: &[char]) -> Vec<std::ops::Range<usize>> {
+    let mut found = Vec::new();
+    let mut start = 0;
+    while start + query.len() <= chars.len() {
+        if chars[start..start + query.len()] == query[..] {
+            found.push(start..start + query.len());
+            start += query.len();
+        } else {
+            start += 1;
+        }
+    }
+    found
+}
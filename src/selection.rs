@@ -0,0 +1,197 @@
+use regex::Regex;
+
+use crate::term::{Cell, Flags, Terminal};
+
+/// Letters, digits and these make up the words a double click selects,
+/// `word_characters` in the config
+pub const DEFAULT_WORD_CHARACTERS: &str = "_-";
+
+/// URLs, then absolute, home relative and relative paths with at least one
+/// slash in them
+pub const URL_AND_PATH_PATTERN: &str = r#"(?x)
+    (?:https?|ftp|file)://[^\s<>"'`]+
+    | (?:~|\.{1,2})?(?:/[\w.\-@+~]+)+/?
+    | [\w.\-@+]+(?:/[\w.\-@+]+)+/?
+"#;
+
+// Sentence punctuation and closing quotes after a URL or path aren't part
+// of it
+pub const TRAILING_PUNCTUATION: [char; 9] = ['.', ',', ';', ':', '!', '?', ')', '\'', '"'];
+
+const QUOTES: [char; 3] = ['"', '\'', '`'];
+
+/// A position in the scrollback or on the screen, lines are indexed from the
+/// oldest one in the scrollback
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+pub struct Point {
+    pub line: usize,
+    pub col: usize,
+}
+
+/// Text selected from one point to another, in reading order, or the
+/// rectangle of cells with the two points at its corners
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Selection {
+    // Where the selection was started, it stays put while `end` moves
+    pub anchor: Point,
+    pub end: Point,
+    pub block: bool,
+}
+
+impl Selection {
+    pub fn new(anchor: Point) -> Selection {
+        Selection { anchor, end: anchor, block: false }
+    }
+
+    fn ordered(&self) -> (Point, Point) {
+        if self.anchor <= self.end {
+            (self.anchor, self.end)
+        } else {
+            (self.end, self.anchor)
+        }
+    }
+
+    // The first and last column of a block
+    fn columns(&self) -> (usize, usize) {
+        (self.anchor.col.min(self.end.col), self.anchor.col.max(self.end.col))
+    }
+
+    pub fn contains(&self, point: Point) -> bool {
+        let (start, end) = self.ordered();
+        if self.block {
+            let (left, right) = self.columns();
+            (start.line..=end.line).contains(&point.line) && (left..=right).contains(&point.col)
+        } else {
+            start <= point && point <= end
+        }
+    }
+
+    /// The selected cells of each line the selection spans. A block is
+    /// the same columns of every line.
+    pub fn lines<'a>(&self, term: &'a Terminal) -> Vec<&'a [Cell]> {
+        let (start, end) = self.ordered();
+        let mut lines = Vec::new();
+        for line in start.line..=end.line.min(term.total_lines().saturating_sub(1)) {
+            let row = term.line(line);
+            let (from, to) = if self.block {
+                let (left, right) = self.columns();
+                (left, right + 1)
+            } else {
+                let from = if line == start.line { start.col } else { 0 };
+                let to = if line == end.line { end.col + 1 } else { row.len() };
+                (from, to)
+            };
+            let to = to.min(row.len());
+            lines.push(&row[from.min(to)..to]);
+        }
+        lines
+    }
+
+    /// The selected text, with trailing blanks trimmed from every line.
+    /// Lines the text wrapped over are joined back into one.
+    pub fn text(&self, term: &Terminal) -> String {
+        let (start, _) = self.ordered();
+        let lines = self.lines(term);
+        let mut text = String::new();
+        let mut wrapped = false;
+        for (i, cells) in lines.iter().enumerate() {
+            let mut line_text = String::new();
+            for cell in cells.iter() {
+                if cell.flags.intersects(Flags::WIDE_CHAR_SPACER | Flags::LEADING_WIDE_CHAR_SPACER) {
+                    continue;
+                }
+                line_text.push(cell.c);
+                line_text.extend(cell.zerowidth());
+            }
+
+            if i > 0 && !wrapped {
+                text.push('\n');
+            }
+            // Blanks before a wrap are part of the text, a block is copied
+            // as it is on the screen
+            wrapped = !self.block && i + 1 < lines.len() && term.wraps(start.line + i);
+            text.push_str(if wrapped { &line_text } else { line_text.trim_end() });
+        }
+        text
+    }
+}
+
+/// Decides what a double click selects
+pub struct WordRules {
+    word_characters: String,
+    url_or_path: Regex,
+}
+
+impl WordRules {
+    pub fn new(word_characters: &str) -> WordRules {
+        WordRules {
+            word_characters: word_characters.to_string(),
+            url_or_path: Regex::new(URL_AND_PATH_PATTERN).expect("Invalid URL and path pattern"),
+        }
+    }
+
+    /// What a double click on `point` selects: the quoted string a quote
+    /// starts or ends, the URL or path under it, or else the word it is
+    /// on. Nothing on a blank.
+    pub fn select(&self, term: &Terminal, point: Point) -> Option<Selection> {
+        let row = term.line(point.line);
+        // The column each character came from, wide characters once
+        let mut chars = Vec::new();
+        let mut text = String::new();
+        let mut byte_cols = Vec::new();
+        for (col, cell) in row.iter().enumerate() {
+            if !cell.flags.contains(Flags::WIDE_CHAR_SPACER) {
+                chars.push((col, cell.c));
+                text.push(cell.c);
+                byte_cols.resize(text.len(), col);
+            }
+        }
+        // On the spacer of a wide character is on the character
+        let index = chars.iter().rposition(|&(col, _)| col <= point.col)?;
+        let clicked = chars[index].1;
+        if clicked == ' ' {
+            return None;
+        }
+        let span = |start: usize, end: usize| {
+            let end =
+                row.get(end + 1).filter(|cell| cell.flags.contains(Flags::WIDE_CHAR_SPACER)).map_or(end, |_| end + 1);
+            Some(Selection {
+                anchor: Point { line: point.line, col: start },
+                end: Point { line: point.line, col: end },
+                block: false,
+            })
+        };
+
+        if QUOTES.contains(&clicked) {
+            // An even number of the same quote before it opens a string
+            let before = chars[..index].iter().filter(|&&(_, c)| c == clicked).count();
+            let other = if before % 2 == 0 {
+                chars[index + 1..].iter().find(|&&(_, c)| c == clicked)
+            } else {
+                chars[..index].iter().rfind(|&&(_, c)| c == clicked)
+            };
+            if let Some(&(other, _)) = other {
+                return span(other.min(chars[index].0), other.max(chars[index].0));
+            }
+        }
+
+        for found in self.url_or_path.find_iter(&text) {
+            let target = found.as_str().trim_end_matches(TRAILING_PUNCTUATION);
+            let (start, end) = (found.start(), found.start() + target.len());
+            if !target.is_empty() && (byte_cols[start]..=byte_cols[end - 1]).contains(&chars[index].0) {
+                return span(byte_cols[start], byte_cols[end - 1]);
+            }
+        }
+
+        if !self.is_word_char(clicked) {
+            return span(chars[index].0, chars[index].0);
+        }
+        let start = chars[..index].iter().rposition(|&(_, c)| !self.is_word_char(c)).map_or(0, |i| i + 1);
+        let end = chars[index..].iter().position(|&(_, c)| !self.is_word_char(c)).map_or(chars.len(), |i| index + i);
+        span(chars[start].0, chars[end - 1].0)
+    }
+
+    fn is_word_char(&self, c: char) -> bool {
+        c.is_alphanumeric() || self.word_characters.contains(c)
+    }
+}
@@ -1,16 +1,87 @@
 use gl::types::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::CString;
 use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Why a shader program couldn't be built, with the driver's full log when
+/// it was the driver that refused it
+#[derive(Debug, thiserror::Error)]
+pub enum ShaderError {
+    #[error("Could not read the shader {}: {source}", .path.display())]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("The shader {} contains a NUL byte", .path.display())]
+    InvalidSource { path: PathBuf },
+    #[error("Could not compile the shader {}:\n{log}", .path.display())]
+    Compile { path: PathBuf, log: String },
+    #[error("Could not link {} with {}:\n{log}", .vertex_path.display(), .fragment_path.display())]
+    Link {
+        vertex_path: PathBuf,
+        fragment_path: PathBuf,
+        log: String,
+    },
+}
 
 #[derive(Clone)]
 pub struct Shader {
     id: u32,
+    // Looked up on first use, -1 for names the program doesn't have
+    uniform_locations: RefCell<HashMap<String, GLint>>,
 }
 
 impl Shader {
-    pub fn new(vertex_path: &str, fragment_path: &str) -> Self {
-        let shader_program: u32 =
-            unsafe { Self::create_shader_program(vertex_path, fragment_path) };
-        Shader { id: shader_program }
+    /// Build a program from GLSL files on disk
+    pub fn new(vertex_path: &Path, fragment_path: &Path) -> Result<Self, ShaderError> {
+        let read = |path: &Path| fs::read_to_string(path).map_err(|source| ShaderError::Read { path: path.to_path_buf(), source });
+        Self::from_source(vertex_path, read(vertex_path)?, fragment_path, read(fragment_path)?)
+    }
+
+    /// Build a program from GLSL source, the paths only name the stages in
+    /// errors
+    pub fn from_source(
+        vertex_path: &Path,
+        vertex_source: String,
+        fragment_path: &Path,
+        fragment_source: String,
+    ) -> Result<Self, ShaderError> {
+        unsafe {
+            let vertex_shader = Self::compile(vertex_path, vertex_source, gl::VERTEX_SHADER)?;
+            let fragment_shader = match Self::compile(fragment_path, fragment_source, gl::FRAGMENT_SHADER) {
+                Ok(shader) => shader,
+                Err(e) => {
+                    gl::DeleteShader(vertex_shader);
+                    return Err(e);
+                }
+            };
+
+            // Link shaders and create shader program
+            let shader_program = gl::CreateProgram();
+            gl::AttachShader(shader_program, vertex_shader);
+            gl::AttachShader(shader_program, fragment_shader);
+            gl::LinkProgram(shader_program);
+
+            // Cleanup, the program keeps what it needs
+            gl::DeleteShader(vertex_shader);
+            gl::DeleteShader(fragment_shader);
+
+            if let Some(log) = Self::link_error(shader_program) {
+                gl::DeleteProgram(shader_program);
+                return Err(ShaderError::Link {
+                    vertex_path: vertex_path.to_path_buf(),
+                    fragment_path: fragment_path.to_path_buf(),
+                    log,
+                });
+            }
+
+            Ok(Shader {
+                id: shader_program,
+                uniform_locations: RefCell::new(HashMap::new()),
+            })
+        }
     }
 
     pub fn get_id(&self) -> &u32 {
@@ -23,100 +94,111 @@ impl Shader {
         };
     }
 
-    unsafe fn create_shader_program(vertex_shader_path: &str, fragment_shader_path: &str) -> u32 {
-        let shader_program: u32;
-
-        let vertex_shader_source =
-            fs::read_to_string(vertex_shader_path).expect("Failed to read vertex shader source");
-        let fragment_shader_source = fs::read_to_string(fragment_shader_path)
-            .expect("Failed to read fragment shader source");
-
-        let vertex_shader_cstr = std::ffi::CString::new(vertex_shader_source)
-            .expect("Failed to create vertex shader CString");
-        let fragment_shader_cstr = std::ffi::CString::new(fragment_shader_source)
-            .expect("Failed to create fragment shader CString");
-
-        // Compile vertex shader
-        let vertex_shader = gl::CreateShader(gl::VERTEX_SHADER);
-        gl::ShaderSource(
-            vertex_shader,
-            1,
-            &vertex_shader_cstr.as_ptr(),
-            std::ptr::null(),
-        );
-        gl::CompileShader(vertex_shader);
-        Self::check_shader_compile_status(vertex_shader);
-
-        // Compile fragment shader
-        let fragment_shader = gl::CreateShader(gl::FRAGMENT_SHADER);
-        gl::ShaderSource(
-            fragment_shader,
-            1,
-            &fragment_shader_cstr.as_ptr(),
-            std::ptr::null(),
-        );
-        gl::CompileShader(fragment_shader);
-        Self::check_shader_compile_status(fragment_shader);
-
-        // Link shaders and create shader program
-        shader_program = gl::CreateProgram();
-        gl::AttachShader(shader_program, vertex_shader);
-        gl::AttachShader(shader_program, fragment_shader);
-        gl::LinkProgram(shader_program);
-        Self::check_shader_link_status(shader_program);
-
-        // Cleanup
-        gl::DeleteShader(vertex_shader);
-        gl::DeleteShader(fragment_shader);
-
-        shader_program
+    /// Free the program, it must not be used afterwards
+    pub fn delete(&self) {
+        unsafe {
+            gl::DeleteProgram(self.id);
+        }
     }
 
-    fn check_shader_link_status(shader: u32) {
+    /// Set a uniform of this program, which must be in use
+    pub fn set_mat4(&self, name: &str, matrix: &[[f32; 4]; 4]) {
+        unsafe {
+            gl::UniformMatrix4fv(self.uniform_location(name), 1, gl::FALSE, matrix.as_ptr() as *const f32);
+        }
+    }
+
+    /// Set a uniform of this program, which must be in use
+    pub fn set_vec4(&self, name: &str, value: [f32; 4]) {
+        unsafe {
+            gl::Uniform4f(self.uniform_location(name), value[0], value[1], value[2], value[3]);
+        }
+    }
+
+    /// Set a uniform of this program, which must be in use
+    pub fn set_float(&self, name: &str, value: f32) {
+        unsafe {
+            gl::Uniform1f(self.uniform_location(name), value);
+        }
+    }
+
+    /// Set a uniform of this program, which must be in use
+    pub fn set_int(&self, name: &str, value: i32) {
+        unsafe {
+            gl::Uniform1i(self.uniform_location(name), value);
+        }
+    }
+
+    // Setting location -1 is silently ignored by GL, like a uniform the
+    // compiler optimized away
+    fn uniform_location(&self, name: &str) -> GLint {
+        if let Some(&location) = self.uniform_locations.borrow().get(name) {
+            return location;
+        }
+        let location = match CString::new(name) {
+            Ok(c_name) => unsafe { gl::GetUniformLocation(self.id, c_name.as_ptr()) },
+            Err(_) => -1,
+        };
+        self.uniform_locations.borrow_mut().insert(name.to_string(), location);
+        location
+    }
+
+    /// Compile one stage of a program
+    unsafe fn compile(path: &Path, source: String, kind: GLenum) -> Result<u32, ShaderError> {
+        let source = CString::new(crate::gl_context::adapt_shader(source)).map_err(|_| ShaderError::InvalidSource { path: path.to_path_buf() })?;
+
+        let shader = gl::CreateShader(kind);
+        gl::ShaderSource(shader, 1, &source.as_ptr(), std::ptr::null());
+        gl::CompileShader(shader);
+
+        if let Some(log) = Self::compile_error(shader) {
+            gl::DeleteShader(shader);
+            return Err(ShaderError::Compile { path: path.to_path_buf(), log });
+        }
+        Ok(shader)
+    }
+
+    /// The info log of a shader that failed to compile
+    fn compile_error(shader: u32) -> Option<String> {
         let mut success = gl::FALSE as GLint;
-        let mut info_log = vec![0u8; 512];
+        let mut length = 0;
         unsafe {
-            gl::GetProgramiv(shader, gl::LINK_STATUS, &mut success);
-            if success == gl::FALSE as GLint {
-                gl::GetProgramInfoLog(
-                    shader,
-                    info_log.len() as GLsizei,
-                    std::ptr::null_mut(),
-                    info_log.as_mut_ptr() as *mut GLchar,
-                );
-                let error_message = std::ffi::CStr::from_ptr(info_log.as_ptr() as *const _)
-                    .to_string_lossy()
-                    .into_owned();
-                eprintln!("ERROR::PROGRAM::LINKING_FAILED\n{}", error_message);
+            gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut success);
+            if success != gl::FALSE as GLint {
+                return None;
             }
+            gl::GetShaderiv(shader, gl::INFO_LOG_LENGTH, &mut length);
+            let mut info_log = vec![0u8; length.max(1) as usize];
+            gl::GetShaderInfoLog(
+                shader,
+                info_log.len() as GLsizei,
+                &mut length,
+                info_log.as_mut_ptr() as *mut GLchar,
+            );
+            info_log.truncate(length.max(0) as usize);
+            Some(String::from_utf8_lossy(&info_log).trim_end().to_string())
         }
     }
 
-    fn check_shader_compile_status(shader: u32) {
+    /// The info log of a program that failed to link
+    fn link_error(program: u32) -> Option<String> {
         let mut success = gl::FALSE as GLint;
-        let mut info_log = vec![0u8; 512];
-
+        let mut length = 0;
         unsafe {
-            // Check compile status
-            gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut success);
-            if success == gl::FALSE as GLint {
-                // Retrieve error log
-                gl::GetShaderInfoLog(
-                    shader,
-                    info_log.len() as GLsizei,
-                    std::ptr::null_mut(),
-                    info_log.as_mut_ptr() as *mut GLchar,
-                );
-
-                // Convert error log to a Rust string
-                let error_message =
-                    std::ffi::CStr::from_ptr(info_log.as_ptr() as *const gl::types::GLchar)
-                        .to_string_lossy()
-                        .into_owned();
-
-                // Print error message
-                eprintln!("ERROR::SHADER::COMPILATION_FAILED\n{}", error_message);
+            gl::GetProgramiv(program, gl::LINK_STATUS, &mut success);
+            if success != gl::FALSE as GLint {
+                return None;
             }
+            gl::GetProgramiv(program, gl::INFO_LOG_LENGTH, &mut length);
+            let mut info_log = vec![0u8; length.max(1) as usize];
+            gl::GetProgramInfoLog(
+                program,
+                info_log.len() as GLsizei,
+                &mut length,
+                info_log.as_mut_ptr() as *mut GLchar,
+            );
+            info_log.truncate(length.max(0) as usize);
+            Some(String::from_utf8_lossy(&info_log).trim_end().to_string())
         }
     }
 }
@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+// How often the files are looked at, stat'ing them every frame is wasteful
+const CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Notices when the shader files in the configured `shader_dir` change, so
+/// the programs can be rebuilt without restarting
+pub struct ShaderWatcher {
+    pub dir: PathBuf,
+    modified: HashMap<PathBuf, SystemTime>,
+    last_check: Instant,
+}
+
+impl ShaderWatcher {
+    pub fn new(dir: &Path) -> ShaderWatcher {
+        let mut watcher = ShaderWatcher {
+            dir: dir.to_path_buf(),
+            modified: HashMap::new(),
+            last_check: Instant::now(),
+        };
+        watcher.scan();
+        watcher
+    }
+
+    /// Whether any shader was written since the last call that said so
+    pub fn changed(&mut self) -> bool {
+        if self.last_check.elapsed() < CHECK_INTERVAL {
+            return false;
+        }
+        self.last_check = Instant::now();
+        self.scan()
+    }
+
+    // Record the modification time of every shader, true if one differs
+    // from what was recorded before
+    fn scan(&mut self) -> bool {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return false;
+        };
+        let mut changed = false;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let is_shader = matches!(path.extension().and_then(|ext| ext.to_str()), Some("vs" | "fs"));
+            let Some(modified) = entry.metadata().ok().and_then(|metadata| metadata.modified().ok()) else {
+                continue;
+            };
+            if is_shader && self.modified.insert(path, modified) != Some(modified) {
+                changed = true;
+            }
+        }
+        changed
+    }
+}
@@ -0,0 +1,90 @@
+use harfbuzz_rs::{shape, Face, Feature, Font, Owned, Tag, UnicodeBuffer};
+
+use crate::term::{Cell, Flags};
+
+/// A glyph coming out of the shaper, placed on the grid
+pub struct ShapedGlyph {
+    // Glyph index in the font, not a codepoint
+    pub glyph_id: u32,
+    pub col: usize,
+    // Number of grid columns the glyph covers, more than one for ligatures
+    pub columns: usize,
+}
+
+/// Runs rows of cells through HarfBuzz so fonts with programming ligatures
+/// (`=>`, `!=`, `->`) can substitute their ligature glyphs
+pub struct Shaper {
+    font: Owned<Font<'static>>,
+    features: Vec<Feature>,
+}
+
+impl Shaper {
+    pub fn new(font_path: &str) -> Shaper {
+        let face = Face::from_file(font_path, 0).expect("Could not open font for shaping");
+        let font = Font::new(face);
+        // Most coding fonts implement their ligatures as contextual alternates
+        let features = vec![
+            Feature::new(Tag::new('l', 'i', 'g', 'a'), 1, ..),
+            Feature::new(Tag::new('c', 'a', 'l', 't'), 1, ..),
+        ];
+        Shaper { font, features }
+    }
+
+    /// Shape a row of cells. No ligature will span `break_at`, which keeps
+    /// the character under the cursor readable on its own.
+    pub fn shape_row(&self, row: &[Cell], break_at: Option<usize>) -> Vec<ShapedGlyph> {
+        let mut glyphs = Vec::new();
+        let mut boundaries = Vec::new();
+        if let Some(col) = break_at {
+            boundaries.push(col);
+            boundaries.push(col + 1);
+        }
+        boundaries.push(row.len());
+
+        let mut start = 0;
+        for end in boundaries {
+            let end = end.min(row.len());
+            if end > start {
+                self.shape_run(row, start, end, &mut glyphs);
+                start = end;
+            }
+        }
+
+        glyphs
+    }
+
+    fn shape_run(&self, row: &[Cell], start: usize, end: usize, glyphs: &mut Vec<ShapedGlyph>) {
+        let mut text = String::new();
+        // The column each byte of `text` came from, HarfBuzz reports
+        // clusters as byte offsets
+        let mut byte_cols = Vec::new();
+        for (col, cell) in row.iter().enumerate().take(end).skip(start) {
+            if cell.flags.contains(Flags::WIDE_CHAR_SPACER) {
+                continue;
+            }
+            for c in std::iter::once(cell.c).chain(cell.zerowidth().iter().copied()) {
+                text.push(c);
+                byte_cols.resize(text.len(), col);
+            }
+        }
+
+        let buffer = UnicodeBuffer::new().add_str(&text);
+        let output = shape(&self.font, buffer, &self.features);
+        let infos = output.get_glyph_infos();
+
+        for (i, info) in infos.iter().enumerate() {
+            let col = byte_cols[info.cluster as usize];
+            // A glyph covers every cell up to where the next cluster starts
+            let next_col = infos[i + 1..]
+                .iter()
+                .map(|next| byte_cols[next.cluster as usize])
+                .find(|&next| next != col)
+                .unwrap_or(end);
+            glyphs.push(ShapedGlyph {
+                glyph_id: info.codepoint,
+                col,
+                columns: next_col.saturating_sub(col).max(1),
+            });
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use crate::selection::{Point, Selection};
+use crate::span::{Span, SpanKind, Zone};
+use tracing::debug;
+
+/// What an OSC 133 mark from the shell says starts at its position
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MarkKind {
+    // A: the prompt is about to be printed
+    PromptStart,
+    // B: the prompt is done, what follows is the command being typed
+    CommandStart,
+    // C: the command was entered, what follows is its output
+    OutputStart,
+    // D: the command finished, with its exit status if the shell said
+    CommandEnd(Option<i32>),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Mark {
+    pub kind: MarkKind,
+    pub point: Point,
+}
+
+/// Semantic prompt marks the shell sends around prompts and commands, for
+/// jumping between prompts and picking out the output of a command
+#[derive(Default)]
+pub struct ShellIntegration {
+    // Oldest first
+    pub marks: Vec<Mark>,
+    // Of the last command that finished
+    pub exit_status: Option<i32>,
+}
+
+impl ShellIntegration {
+    /// Handle the parameters of an OSC 133 after the `133`
+    pub fn mark(&mut self, params: &[&[u8]], point: Point) {
+        let kind = match params.first().copied() {
+            Some(b"A") => MarkKind::PromptStart,
+            Some(b"B") => MarkKind::CommandStart,
+            Some(b"C") => MarkKind::OutputStart,
+            Some(b"D") => {
+                let status = params
+                    .get(1)
+                    .and_then(|status| std::str::from_utf8(status).ok())
+                    .and_then(|status| status.parse().ok());
+                self.exit_status = status;
+                MarkKind::CommandEnd(status)
+            }
+            _ => {
+                debug!("Unhandled OSC 133: {:?}", params.iter().map(|p| String::from_utf8_lossy(p)).collect::<Vec<_>>());
+                return;
+            }
+        };
+        // Redrawing the prompt sends its marks again. A command's end and
+        // the next prompt's start usually share a position.
+        self.marks.retain(|mark| mark.point < point || (mark.point == point && mark.kind != kind));
+        self.marks.push(Mark { kind, point });
+    }
+
+    /// The line of the last prompt above `line`
+    pub fn previous_prompt(&self, line: usize) -> Option<usize> {
+        self.prompts().rev().find(|&prompt| prompt < line)
+    }
+
+    /// The line of the first prompt below `line`
+    pub fn next_prompt(&self, line: usize) -> Option<usize> {
+        self.prompts().find(|&prompt| prompt > line)
+    }
+
+    fn prompts(&self) -> impl DoubleEndedIterator<Item = usize> + '_ {
+        self.marks
+            .iter()
+            .filter(|mark| mark.kind == MarkKind::PromptStart)
+            .map(|mark| mark.point.line)
+    }
+
+    /// The output of the last command that finished, `cols` being the width
+    /// of the screen. `None` if it didn't print anything.
+    pub fn last_command_output(&self, cols: usize) -> Option<Selection> {
+        let end = self.marks.iter().rposition(|mark| matches!(mark.kind, MarkKind::CommandEnd(_)))?;
+        let start = self.marks[..end].iter().rposition(|mark| mark.kind == MarkKind::OutputStart)?;
+        let (start, end) = (self.marks[start].point, self.marks[end].point);
+        let output = Span { kind: SpanKind::Zone(Zone::Output), start, end };
+        // The output ends just before the end mark
+        output.selection(cols)
+    }
+
+    /// The part of a command `point` is in, going by the last mark before
+    /// it. `None` between a command's end and the next prompt, and where
+    /// the shell doesn't send marks.
+    pub fn zone_at(&self, point: Point) -> Option<Zone> {
+        let before = self.marks.partition_point(|mark| mark.point <= point);
+        match self.marks[..before].last()?.kind {
+            MarkKind::PromptStart => Some(Zone::Prompt),
+            MarkKind::CommandStart => Some(Zone::Input),
+            MarkKind::OutputStart => Some(Zone::Output),
+            MarkKind::CommandEnd(_) => None,
+        }
+    }
+
+    /// Forget lines that fell off the top of the scrollback and move the
+    /// marks up with the rest
+    pub fn lines_dropped(&mut self, count: usize) {
+        self.marks.retain_mut(|mark| {
+            if mark.point.line < count {
+                return false;
+            }
+            mark.point.line -= count;
+            true
+        });
+    }
+}
@@ -0,0 +1,39 @@
+use std::io;
+
+use signal_hook::consts::{SIGCHLD, SIGTERM, SIGUSR1};
+use signal_hook::iterator::Signals;
+
+/// What a signal rush got asks of it
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SignalEvent {
+    // A shell may have exited and be waiting to be reaped
+    ChildExited,
+    // Close every window and quit
+    Terminate,
+    // Read the config again
+    ReloadConfig,
+}
+
+/// The signals rush handles, turned into events the loop picks up with
+/// the window events. The handlers only note that a signal came, all the
+/// work happens on the loop's thread, between GLFW calls.
+pub struct SignalEvents {
+    signals: Signals,
+}
+
+impl SignalEvents {
+    pub fn new() -> io::Result<SignalEvents> {
+        Ok(SignalEvents { signals: Signals::new([SIGCHLD, SIGTERM, SIGUSR1])? })
+    }
+
+    /// The signals that came since the last call, once each however many
+    /// times they came
+    pub fn pending(&mut self) -> impl Iterator<Item = SignalEvent> + '_ {
+        self.signals.pending().filter_map(|signal| match signal {
+            SIGCHLD => Some(SignalEvent::ChildExited),
+            SIGTERM => Some(SignalEvent::Terminate),
+            SIGUSR1 => Some(SignalEvent::ReloadConfig),
+            _ => None,
+        })
+    }
+}
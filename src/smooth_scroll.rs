@@ -0,0 +1,55 @@
+use crate::term::Terminal;
+
+// Seconds for the remaining distance to shrink by a factor of e
+const TIME_CONSTANT: f64 = 0.04;
+
+// Below this many rows the animation snaps to the real position
+const SNAP_DISTANCE: f32 = 0.01;
+
+/// Animates the view moving through the lines, whether the user scrolled
+/// the scrollback or output scrolled the screen. Jumps of a whole screen
+/// or more aren't animated.
+pub struct SmoothScroll {
+    pub enabled: bool,
+    // Where the top of the view was last frame, counted in lines that
+    // have scrolled by since the start
+    last_position: Option<i64>,
+    // How many rows the drawn view lags behind the real one, positive
+    // when it is showing older lines
+    lag: f32,
+    last_time: f64,
+}
+
+impl SmoothScroll {
+    pub fn new(enabled: bool) -> SmoothScroll {
+        SmoothScroll {
+            enabled,
+            last_position: None,
+            lag: 0.0,
+            last_time: 0.0,
+        }
+    }
+
+    /// Advance the animation to `time` in seconds and return how many rows
+    /// the view should be drawn behind the terminal's
+    pub fn update(&mut self, term: &Terminal, time: f64) -> f32 {
+        let position = term.scrolled_lines as i64 - term.display_offset as i64;
+        let moved = position - self.last_position.unwrap_or(position);
+        self.last_position = Some(position);
+
+        if !self.enabled || moved.unsigned_abs() as usize >= term.rows {
+            self.lag = 0.0;
+        } else {
+            let rows = term.rows as f32;
+            self.lag = (self.lag + moved as f32).clamp(-rows, rows);
+        }
+
+        let elapsed = (time - self.last_time).max(0.0);
+        self.last_time = time;
+        self.lag *= (-elapsed / TIME_CONSTANT).exp() as f32;
+        if self.lag.abs() < SNAP_DISTANCE {
+            self.lag = 0.0;
+        }
+        self.lag
+    }
+}
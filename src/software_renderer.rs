@@ -0,0 +1,293 @@
+use std::cell::RefCell;
+use std::num::NonZeroU32;
+use std::rc::Rc;
+use std::time::Instant;
+
+use freetype::freetype as ft;
+use raw_window_handle::{DisplayHandle, HandleError, HasDisplayHandle, HasWindowHandle, WindowHandle};
+use rush::canvas::Canvas;
+use tracing::warn;
+
+use crate::color;
+use crate::error::RushError;
+use crate::glyph_store::GlyphStore;
+use crate::render::{self, Cursor, Frame};
+use crate::term::{self, Terminal};
+use crate::{box_drawing, Character, CharacterDimensions, RenderSettings, WindowState};
+use crate::RECT_VERTEX_SIZE;
+
+/// The window, as softbuffer wants it to draw into. Holding on to it keeps
+/// the window around for as long as the surface.
+#[derive(Clone)]
+struct SharedWindow(Rc<RefCell<glfw::PWindow>>);
+
+impl HasWindowHandle for SharedWindow {
+    fn window_handle(&self) -> Result<WindowHandle<'_>, HandleError> {
+        let raw = self.0.borrow().window_handle()?.as_raw();
+        // The window lives as long as self
+        Ok(unsafe { WindowHandle::borrow_raw(raw) })
+    }
+}
+
+impl HasDisplayHandle for SharedWindow {
+    fn display_handle(&self) -> Result<DisplayHandle<'_>, HandleError> {
+        let raw = self.0.borrow().display_handle()?.as_raw();
+        Ok(unsafe { DisplayHandle::borrow_raw(raw) })
+    }
+}
+
+/// A glyph's coverage, one byte a pixel in rows from the top
+struct Glyph {
+    character: Character,
+    mask: Vec<u8>,
+}
+
+/// Draws on the CPU into a buffer that is copied to the window, for when
+/// there's no OpenGL context to be had, in virtual machines and some
+/// remote sessions. It draws what the wgpu renderer does: the cells, the
+/// cursor and the dimming of an unfocused window, without ligatures,
+/// images or the search, hint and paste bars. The window is opaque.
+pub struct SoftwareRenderer {
+    surface: softbuffer::Surface<SharedWindow, SharedWindow>,
+    canvas: Canvas,
+    // `None` for characters the font doesn't have
+    glyphs: GlyphStore<Option<Glyph>>,
+    face: ft::FT_Face,
+    settings: RenderSettings,
+    // The part of the canvas the grid is drawn in: x and y of its top left
+    // corner, width and height
+    grid_rect: (i32, i32, i32, i32),
+    // Pixels the grid is moved up by while scrolling smoothly
+    pub shift: i32,
+    // Kept from frame to frame
+    rects: Vec<f32>,
+    shapes: Vec<box_drawing::Shape>,
+    pub drawn_at: Instant,
+}
+
+impl SoftwareRenderer {
+    /// Set up drawing into `window`, which was made without an OpenGL
+    /// context, and rasterize the font at `font_size_px`
+    pub fn new(
+        window: Rc<RefCell<glfw::PWindow>>,
+        face: ft::FT_Face,
+        font_size_px: f32,
+        settings: RenderSettings,
+    ) -> Result<(SoftwareRenderer, CharacterDimensions), RushError> {
+        let window = SharedWindow(window);
+        let context = softbuffer::Context::new(window.clone()).map_err(|e| RushError::Software(e.to_string()))?;
+        let surface = softbuffer::Surface::new(&context, window).map_err(|e| RushError::Software(e.to_string()))?;
+        let mut renderer = SoftwareRenderer {
+            surface,
+            canvas: Canvas::new(0, 0),
+            glyphs: GlyphStore::new(),
+            face,
+            settings,
+            grid_rect: (0, 0, 1, 1),
+            shift: 0,
+            rects: Vec::new(),
+            shapes: Vec::new(),
+            drawn_at: Instant::now(),
+        };
+        let char_dim = renderer.load_font(font_size_px)?;
+        Ok((renderer, char_dim))
+    }
+
+    /// Rasterize the font again at `font_size_px`, and return the size of
+    /// a cell
+    pub fn load_font(&mut self, font_size_px: f32) -> Result<CharacterDimensions, RushError> {
+        let options = self.settings.glyph_options;
+        let (glyphs, max_width, max_height) =
+            crate::load_font_chars(self.face, font_size_px, options, |glyph| unsafe { Some(copy_glyph(glyph)) })?;
+        self.glyphs = glyphs;
+        Ok(CharacterDimensions { width: max_width as u32, height: max_height as u32 })
+    }
+
+    /// Glyphs rasterized so far
+    pub fn cached_glyphs(&self) -> usize {
+        self.glyphs.len()
+    }
+
+    /// Follow the window to its new size
+    pub fn resize(&mut self, ws: &WindowState) {
+        let (width, height) = (ws.width as u32, ws.height as u32);
+        // Minimized
+        let (Some(nonzero_width), Some(nonzero_height)) = (NonZeroU32::new(width), NonZeroU32::new(height)) else {
+            return;
+        };
+        if (width as usize, height as usize) != (self.canvas.width, self.canvas.height) {
+            if let Err(e) = self.surface.resize(nonzero_width, nonzero_height) {
+                warn!("Could not resize the window's buffer: {}", e);
+                return;
+            }
+            self.canvas.resize(width as usize, height as usize);
+        }
+        // From the top left corner rather than the bottom left OpenGL wants
+        let (x, y, grid_width, grid_height) = ws.grid_viewport();
+        self.grid_rect = (x, height as i32 - y - grid_height, grid_width.max(1), grid_height.max(1));
+    }
+
+    /// Rasterize `c` the first time it's drawn
+    fn load_glyph(&mut self, c: char) {
+        if self.glyphs.get(c).is_some() {
+            return;
+        }
+        let glyph = unsafe {
+            let options = self.settings.glyph_options;
+            let found = ft::FT_Get_Char_Index(self.face, c as ft::FT_ULong) != 0;
+            let loaded = found
+                && ft::FT_Load_Char(self.face, c as ft::FT_ULong, options.load_flags()) == 0
+                && ft::FT_Render_Glyph((*self.face).glyph, options.render_mode()) == 0;
+            loaded.then(|| copy_glyph(&*(*self.face).glyph))
+        };
+        self.glyphs.insert(c, glyph);
+    }
+
+    /// A point of the grid in normalized device coordinates, in pixels of
+    /// the canvas
+    fn to_pixels(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        let (left, top, width, height) = self.grid_rect;
+        (left as f32 + (x + 1.0) / 2.0 * width as f32, top as f32 + (1.0 - y) / 2.0 * height as f32 - self.shift as f32)
+    }
+
+    /// Fill the triangles of a rect batch, and empty it
+    fn fill_rects(&mut self) {
+        for triangle in self.rects.chunks_exact(3 * RECT_VERTEX_SIZE) {
+            let corner = |i: usize| self.to_pixels((triangle[i * RECT_VERTEX_SIZE], triangle[i * RECT_VERTEX_SIZE + 1]));
+            let corners = [corner(0), corner(1), corner(2)];
+            self.canvas.fill_triangle(corners, [triangle[2], triangle[3], triangle[4], triangle[5]]);
+        }
+        self.rects.clear();
+    }
+
+    /// Blend the glyph of `c` into the cell at `(row, col)`, spanning
+    /// `columns` columns
+    fn draw_glyph(&mut self, term: &Terminal, c: char, (row, col): (usize, usize), columns: usize, fg: [f32; 4]) {
+        self.load_glyph(c);
+        let Some(Some(glyph)) = self.glyphs.get(c) else {
+            return;
+        };
+        let (_, _, width, height) = self.grid_rect;
+        let (x, y, _, quad_height) =
+            crate::glyph_quad((row, col), columns, &glyph.character, width as f32, height as f32, term.rows, term.cols);
+        let (left, top) = self.to_pixels((x, y + quad_height));
+        let mask_width = glyph.character.size.0 as usize;
+        self.canvas.blend_mask((left.round() as i32, top.round() as i32), mask_width, &glyph.mask, fg);
+    }
+
+    /// The text of the cell at `(row, col)` in `fg`: its glyphs, box
+    /// drawing and decorations
+    fn draw_cell_text(&mut self, term: &Terminal, cell: &term::Cell, (row, col): (usize, usize), fg: [f32; 4]) {
+        if !cell.flags.contains(term::Flags::WIDE_CHAR_SPACER) {
+            let columns = if cell.flags.contains(term::Flags::WIDE_CHAR) { 2 } else { 1 };
+            if cell.c != ' ' && !box_drawing::is_box_drawing(cell.c) {
+                self.load_glyph(cell.c);
+                let c = if matches!(self.glyphs.get(cell.c), Some(Some(_))) { cell.c } else { '?' };
+                self.draw_glyph(term, c, (row, col), columns, fg);
+            }
+            // Combining marks are drawn over the base character in the same cell
+            for &mark in cell.zerowidth() {
+                self.draw_glyph(term, mark, (row, col), columns, fg);
+            }
+        }
+        if box_drawing::is_box_drawing(cell.c) {
+            let (_, _, width, height) = self.grid_rect;
+            let viewport = (width as f32, height as f32);
+            crate::push_box_drawing(&mut self.rects, &mut self.shapes, cell.c, (row, col), term, viewport, fg);
+        }
+        crate::push_decorations(&mut self.rects, term, cell, cell.flags, (row, col), fg);
+        self.fill_rects();
+    }
+}
+
+impl render::Renderer for SoftwareRenderer {
+    fn begin_frame(&mut self, term: &Terminal) {
+        self.canvas.clear(term.palette.background);
+        let (x, y, width, height) = self.grid_rect;
+        // Keep the grid out of the padding while it's moved
+        self.canvas.set_clip((x.max(0) as usize, y.max(0) as usize), width as usize, height as usize);
+        self.rects.clear();
+    }
+
+    fn draw_cells(&mut self, term: &Terminal, frame: &Frame) {
+        crate::background_pass(term, frame, 0..term.rows, &mut self.rects);
+        self.fill_rects();
+        for row in 0..term.rows {
+            for (col, cell) in frame.row(term, row).iter().enumerate() {
+                if render::text_visible(cell, frame.blink_visible) {
+                    let fg = color::to_gl(term.palette.cell_colors(cell).0);
+                    self.draw_cell_text(term, cell, (row, col), fg);
+                }
+            }
+        }
+    }
+
+    fn draw_cursor(&mut self, term: &Terminal, frame: &Frame, cursor: &Cursor) {
+        let (_, _, width, height) = self.grid_rect;
+        let style = self.settings.cursor;
+        crate::cursor_pass((width as f32, height as f32), term, frame, cursor, &style, &mut self.rects);
+        self.fill_rects();
+        if !cursor.hollow && style.opaque() {
+            // The text under a block cursor in a color that stands out on it
+            let cell = &frame.row(term, cursor.row)[cursor.col];
+            let fg = color::to_gl(style.colors(&term.palette, cell).1);
+            self.draw_cell_text(term, cell, (cursor.row, cursor.col), fg);
+        }
+    }
+
+    fn draw_overlays(&mut self, term: &Terminal, frame: &Frame) {
+        if !frame.focused && self.settings.unfocused_dim > 0.0 {
+            crate::dim_pass(term, self.settings.unfocused_dim, &mut self.rects);
+            self.fill_rects();
+        }
+        if let Some(scrollbar) = &frame.scrollbar {
+            let (_, _, width, height) = self.grid_rect;
+            crate::scrollbar_pass((width as f32, height as f32), term, scrollbar, &mut self.rects);
+            self.fill_rects();
+        }
+    }
+
+    fn read_pixels(&mut self) -> Option<render::Screenshot> {
+        let rgba = self.canvas.pixels.iter().flat_map(|&pixel| [(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8, 255]);
+        let (width, height) = (self.canvas.width as u32, self.canvas.height as u32);
+        Some(render::Screenshot { width, height, rgba: rgba.collect() })
+    }
+
+    fn present(&mut self) {
+        self.drawn_at = Instant::now();
+        let mut buffer = match self.surface.buffer_mut() {
+            Ok(buffer) => buffer,
+            Err(e) => {
+                warn!("Could not draw a frame: {}", e);
+                return;
+            }
+        };
+        // Resized since the frame was drawn
+        if buffer.len() != self.canvas.pixels.len() {
+            return;
+        }
+        buffer.copy_from_slice(&self.canvas.pixels);
+        if let Err(e) = buffer.present() {
+            warn!("Could not show a frame: {}", e);
+        }
+    }
+}
+
+/// The coverage of the glyph FreeType just rendered, without the padding
+/// its rows can have
+unsafe fn copy_glyph(glyph: &ft::FT_GlyphSlotRec) -> Glyph {
+    let bitmap = &glyph.bitmap;
+    let (width, rows) = (bitmap.width as usize, bitmap.rows as usize);
+    let mut mask = Vec::with_capacity(width * rows);
+    for row in 0..rows {
+        let start = bitmap.buffer.offset(row as isize * bitmap.pitch as isize);
+        mask.extend_from_slice(std::slice::from_raw_parts(start, width));
+    }
+    let character = Character {
+        texture_id: 0,
+        size: (width as i32, rows as i32),
+        bearing: (glyph.bitmap_left, glyph.bitmap_top),
+        advance: glyph.advance.x,
+    };
+    Glyph { character, mask }
+}
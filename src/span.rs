@@ -0,0 +1,121 @@
+use std::sync::Arc;
+
+use crate::selection::{Point, Selection};
+use crate::term::Hyperlink;
+
+/// The part of a command the shell's OSC 133 marks say text is in
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Zone {
+    Prompt,
+    // What was typed at the prompt
+    Input,
+    Output,
+}
+
+/// What a range of cells is besides its text and colors
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum SpanKind {
+    Hyperlink(Arc<Hyperlink>),
+    Zone(Zone),
+    // A match of the search, `current` when it's the one jumped to
+    SearchMatch { current: bool },
+}
+
+/// `kind` from `start` to before `end`, in reading order
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Span {
+    pub kind: SpanKind,
+    pub start: Point,
+    pub end: Point,
+}
+
+impl Span {
+    pub fn contains(&self, point: Point) -> bool {
+        self.start <= point && point < self.end
+    }
+
+    /// The cells of the span as a selection, `cols` being the width of the
+    /// screen. `None` if it's empty.
+    pub fn selection(&self, cols: usize) -> Option<Selection> {
+        if self.end <= self.start {
+            return None;
+        }
+        let last = if self.end.col > 0 {
+            Point { line: self.end.line, col: self.end.col - 1 }
+        } else {
+            Point { line: self.end.line - 1, col: cols - 1 }
+        };
+        Some(Selection { anchor: self.start, end: last, block: false })
+    }
+}
+
+/// Spans laid over the grid rather than kept in its cells, for metadata
+/// that covers a range however long it is without costing the cells under
+/// it anything. What has to follow the text when it's overwritten, like a
+/// hyperlink, goes in the cells instead.
+#[derive(Default, Debug)]
+pub struct Spans {
+    // Sorted by start
+    spans: Vec<Span>,
+    // The most lines a span goes over, so looking up a point only goes back
+    // as far as a span reaching it could start
+    longest: usize,
+}
+
+impl Spans {
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    pub fn insert(&mut self, span: Span) {
+        self.longest = self.longest.max(span.end.line.saturating_sub(span.start.line));
+        let index = self.spans.partition_point(|other| other.start <= span.start);
+        self.spans.insert(index, span);
+    }
+
+    /// Drop the spans `remove` picks by their kind
+    pub fn remove(&mut self, remove: impl Fn(&SpanKind) -> bool) {
+        self.spans.retain(|span| !remove(&span.kind));
+        if self.spans.is_empty() {
+            self.longest = 0;
+        }
+    }
+
+    /// The spans covering `point`, the one starting last first
+    pub fn at(&self, point: Point) -> impl Iterator<Item = &Span> + '_ {
+        let after = self.spans.partition_point(|span| span.start <= point);
+        self.spans[..after]
+            .iter()
+            .rev()
+            .take_while(move |span| span.start.line + self.longest >= point.line)
+            .filter(move |span| span.contains(point))
+    }
+
+    /// Forget spans on lines that fell off the top of the scrollback and
+    /// move the rest up with the lines
+    pub fn lines_dropped(&mut self, count: usize) {
+        self.spans.retain_mut(|span| {
+            if span.end.line < count || (span.end.line == count && span.end.col == 0) {
+                return false;
+            }
+            span.start = if span.start.line < count {
+                Point::default()
+            } else {
+                Point { line: span.start.line - count, col: span.start.col }
+            };
+            span.end.line -= count;
+            true
+        });
+    }
+}
+
+impl Extend<Span> for Spans {
+    fn extend<T: IntoIterator<Item = Span>>(&mut self, spans: T) {
+        for span in spans {
+            self.longest = self.longest.max(span.end.line.saturating_sub(span.start.line));
+            self.spans.push(span);
+        }
+        // Stable, spans starting together stay in the order they came in
+        self.spans.sort_by_key(|span| span.start);
+    }
+}
@@ -0,0 +1,104 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::fs::FileExt;
+use std::path::PathBuf;
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+
+// Lines compressed together, enough for deflate to find repetition in
+const BLOCK_LINES: usize = 1000;
+
+// Where a compressed block is in the file
+struct Block {
+    offset: u64,
+    len: usize,
+}
+
+/// The text of lines that fell off the top of the scrollback, compressed
+/// into a temporary file so the history can still be searched. The file
+/// is unlinked as soon as it's made, it goes away with the process however
+/// that ends.
+pub struct Spill {
+    file: File,
+    blocks: Vec<Block>,
+    // Lines not yet compressed into a block
+    pending: Vec<String>,
+    end: u64,
+}
+
+impl Spill {
+    pub fn new() -> io::Result<Spill> {
+        let dir = std::env::var_os("XDG_RUNTIME_DIR").map_or_else(std::env::temp_dir, PathBuf::from);
+        let path = dir.join(format!("rush-scrollback-{}-{}", std::process::id(), next_id()));
+        let file = File::options().read(true).write(true).create_new(true).open(&path)?;
+        std::fs::remove_file(&path)?;
+        Ok(Spill { file, blocks: Vec::new(), pending: Vec::new(), end: 0 })
+    }
+
+    /// Lines spilled so far
+    pub fn len(&self) -> usize {
+        self.blocks.len() * BLOCK_LINES + self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Add the text of the next line to fall off
+    pub fn push(&mut self, line: String) -> io::Result<()> {
+        self.pending.push(line);
+        if self.pending.len() < BLOCK_LINES {
+            return Ok(());
+        }
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::fast());
+        encoder.write_all(self.pending.join("\n").as_bytes())?;
+        let compressed = encoder.finish()?;
+        self.file.write_all_at(&compressed, self.end)?;
+        self.blocks.push(Block { offset: self.end, len: compressed.len() });
+        self.end += compressed.len() as u64;
+        self.pending.clear();
+        Ok(())
+    }
+
+    /// Forget every line, and give the disk space back
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.blocks.clear();
+        self.pending.clear();
+        self.end = 0;
+        self.file.set_len(0)
+    }
+
+    /// Call `f` with the number and text of every line from `first` on,
+    /// oldest first
+    pub fn for_each_line(&self, first: usize, mut f: impl FnMut(usize, &str)) -> io::Result<()> {
+        let mut text = String::new();
+        for (i, block) in self.blocks.iter().enumerate().skip(first / BLOCK_LINES) {
+            let mut compressed = vec![0; block.len];
+            self.file.read_exact_at(&mut compressed, block.offset)?;
+            text.clear();
+            DeflateDecoder::new(&compressed[..]).read_to_string(&mut text)?;
+            for (j, line) in text.split('\n').enumerate() {
+                let number = i * BLOCK_LINES + j;
+                if number >= first {
+                    f(number, line);
+                }
+            }
+        }
+        let pending_start = self.blocks.len() * BLOCK_LINES;
+        for (j, line) in self.pending.iter().enumerate() {
+            if pending_start + j >= first {
+                f(pending_start + j, line);
+            }
+        }
+        Ok(())
+    }
+}
+
+// Tells apart the files of the terminals in one process
+fn next_id() -> usize {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
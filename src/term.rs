@@ -0,0 +1,1874 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::os::unix::ffi::OsStringExt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use unicode_width::UnicodeWidthChar;
+
+use crate::color::{self, Palette, Rgb};
+use crate::graphics::{self, Graphics, Placement};
+use crate::keyboard;
+use crate::parser::{Params, Parser, Perform};
+use crate::policy::{Feature, Policy};
+use crate::selection::{Point, Selection};
+use crate::shell_integration::ShellIntegration;
+use crate::span::{Span, SpanKind, Spans};
+use crate::spill::Spill;
+use crate::terminfo::{self, Capability};
+use tracing::{debug, trace_span, warn};
+
+// How many lines that scrolled off the top of the screen are kept around
+const SCROLLBACK_LIMIT: usize = 10_000;
+// And how much memory they may take, unless set otherwise
+const DEFAULT_SCROLLBACK_BUDGET: usize = 64 << 20;
+const TAB_WIDTH: usize = 8;
+// Entries kept on the kitty keyboard flags stack before the oldest is dropped
+const KEYBOARD_STACK_LIMIT: usize = 16;
+// Titles kept on the XTWINOPS title stack, as many as xterm keeps
+const TITLE_STACK_LIMIT: usize = 10;
+// Most columns or rows an image may be stretched over
+const MAX_IMAGE_CELLS: u32 = 1000;
+// Combining marks kept on a cell, further ones are dropped. Text needs a
+// few at most, a stream of them mustn't grow a cell without end.
+const MAX_ZEROWIDTH: usize = 8;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Color {
+    Default,
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Flags(u16);
+
+impl Flags {
+    pub const BOLD: Flags = Flags(1 << 0);
+    pub const ITALIC: Flags = Flags(1 << 1);
+    pub const UNDERLINE: Flags = Flags(1 << 2);
+    // A character two columns wide, the cell to its right is a spacer
+    pub const WIDE_CHAR: Flags = Flags(1 << 3);
+    // The right half of a wide character, never rendered on its own
+    pub const WIDE_CHAR_SPACER: Flags = Flags(1 << 4);
+    pub const DOUBLE_UNDERLINE: Flags = Flags(1 << 5);
+    pub const UNDERCURL: Flags = Flags(1 << 6);
+    pub const STRIKEOUT: Flags = Flags(1 << 7);
+    pub const DIM: Flags = Flags(1 << 8);
+    pub const INVERSE: Flags = Flags(1 << 9);
+    pub const HIDDEN: Flags = Flags(1 << 10);
+    pub const BLINK: Flags = Flags(1 << 11);
+    // On the last cell of a row whose text went on onto the next one, as
+    // opposed to the program breaking the line
+    pub const WRAPLINE: Flags = Flags(1 << 12);
+    // Left blank in the last column by a wide character that didn't fit
+    // there and went on the next line
+    pub const LEADING_WIDE_CHAR_SPACER: Flags = Flags(1 << 13);
+    pub const ALL_UNDERLINES: Flags = Flags(Flags::UNDERLINE.0 | Flags::DOUBLE_UNDERLINE.0 | Flags::UNDERCURL.0);
+
+    pub fn contains(self, other: Flags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn intersects(self, other: Flags) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    pub fn insert(&mut self, other: Flags) {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: Flags) {
+        self.0 &= !other.0;
+    }
+}
+
+impl std::ops::BitOr for Flags {
+    type Output = Flags;
+
+    fn bitor(self, other: Flags) -> Flags {
+        Flags(self.0 | other.0)
+    }
+}
+
+/// A desktop notification a program asked for with OSC 9 or OSC 777
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Notification {
+    pub title: Option<String>,
+    pub body: String,
+}
+
+/// An OSC 8 hyperlink
+#[derive(PartialEq, Eq, Debug)]
+pub struct Hyperlink {
+    // Cells with the same id and URI are one link, even when they aren't
+    // next to each other
+    pub id: Option<String>,
+    pub uri: String,
+}
+
+impl Hyperlink {
+    /// Whether two cells' links are the same link. Links without an id are
+    /// only the same if they came from the same OSC 8 sequence.
+    pub fn is_same(&self, other: &Hyperlink) -> bool {
+        std::ptr::eq(self, other) || (self.id.is_some() && self == other)
+    }
+}
+
+/// Data only a few cells need, boxed so it doesn't grow every cell
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct CellExtra {
+    // Combining marks and other zero width characters drawn on top of `c`
+    pub zerowidth: Vec<char>,
+    // SGR 58, underlines use the foreground color when unset
+    pub underline_color: Option<Color>,
+    pub hyperlink: Option<Arc<Hyperlink>>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Cell {
+    pub c: char,
+    pub fg: Color,
+    pub bg: Color,
+    pub flags: Flags,
+    pub extra: Option<Box<CellExtra>>,
+}
+
+impl Default for Cell {
+    fn default() -> Cell {
+        Cell {
+            c: ' ',
+            fg: Color::Default,
+            bg: Color::Default,
+            flags: Flags::default(),
+            extra: None,
+        }
+    }
+}
+
+impl Cell {
+    pub fn zerowidth(&self) -> &[char] {
+        match &self.extra {
+            Some(extra) => &extra.zerowidth,
+            None => &[],
+        }
+    }
+
+    pub fn underline_color(&self) -> Option<Color> {
+        self.extra.as_ref().and_then(|extra| extra.underline_color)
+    }
+
+    pub fn hyperlink(&self) -> Option<&Arc<Hyperlink>> {
+        self.extra.as_ref().and_then(|extra| extra.hyperlink.as_ref())
+    }
+
+    fn set_hyperlink(&mut self, link: Arc<Hyperlink>) {
+        self.extra.get_or_insert_with(Default::default).hyperlink = Some(link);
+    }
+
+    fn push_zerowidth(&mut self, c: char) {
+        let zerowidth = &mut self.extra.get_or_insert_with(Default::default).zerowidth;
+        if zerowidth.len() < MAX_ZEROWIDTH {
+            zerowidth.push(c);
+        }
+    }
+
+    fn set_underline_color(&mut self, color: Option<Color>) {
+        match (&mut self.extra, color) {
+            (Some(extra), _) => {
+                extra.underline_color = color;
+                if **extra == CellExtra::default() {
+                    self.extra = None;
+                }
+            }
+            (None, Some(_)) => {
+                self.extra = Some(Box::new(CellExtra {
+                    underline_color: color,
+                    ..Default::default()
+                }))
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+pub type Row = Vec<Cell>;
+
+/// Which mouse events get reported to the application, set with
+/// DECSET 9/1000/1002/1003
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MouseTracking {
+    None,
+    // Button presses only
+    X10,
+    // Presses, releases and the wheel
+    Normal,
+    // Normal, plus motion while a button is held
+    ButtonEvent,
+    // Normal, plus all motion
+    AnyEvent,
+}
+
+pub struct Modes {
+    pub mouse_tracking: MouseTracking,
+    // DECSET 1006, report mouse events as `CSI < b ; x ; y M/m`
+    pub sgr_mouse: bool,
+    // DECSET 1004, send `CSI I` / `CSI O` when the window gains or loses focus
+    pub focus_reporting: bool,
+    // DECSET 2004, pasted text is wrapped in `CSI 200~` and `CSI 201~`
+    pub bracketed_paste: bool,
+    pub cursor_visible: bool,
+    // DECCKM, the cursor keys send `ESC O A` rather than `CSI A`
+    pub app_cursor: bool,
+    // DECKPAM/DECKPNM, the keypad sends SS3 sequences rather than digits
+    pub app_keypad: bool,
+    // DECAWM, printing past the last column continues on the next line
+    pub autowrap: bool,
+    // Reverse wraparound, backspace at the first column goes to the end of
+    // the line above
+    pub reverse_wrap: bool,
+    // IRM, printing shifts the rest of the line right instead of
+    // overwriting it
+    pub insert: bool,
+}
+
+impl Default for Modes {
+    fn default() -> Modes {
+        Modes {
+            mouse_tracking: MouseTracking::None,
+            sgr_mouse: false,
+            focus_reporting: false,
+            bracketed_paste: false,
+            cursor_visible: true,
+            app_cursor: false,
+            app_keypad: false,
+            autowrap: true,
+            reverse_wrap: false,
+            insert: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum Charset {
+    #[default]
+    Ascii,
+    // The VT100 line drawing set, selected with `ESC ( 0`
+    DecSpecialGraphics,
+}
+
+impl Charset {
+    fn map(self, c: char) -> char {
+        if self == Charset::Ascii {
+            return c;
+        }
+        match c {
+            '_' => '\u{a0}',
+            '`' => '◆',
+            'a' => '▒',
+            'b' => '␉',
+            'c' => '␌',
+            'd' => '␍',
+            'e' => '␊',
+            'f' => '°',
+            'g' => '±',
+            'h' => '␤',
+            'i' => '␋',
+            'j' => '┘',
+            'k' => '┐',
+            'l' => '┌',
+            'm' => '└',
+            'n' => '┼',
+            'o' => '⎺',
+            'p' => '⎻',
+            'q' => '─',
+            'r' => '⎼',
+            's' => '⎽',
+            't' => '├',
+            'u' => '┤',
+            'v' => '┴',
+            'w' => '┬',
+            'x' => '│',
+            'y' => '≤',
+            'z' => '≥',
+            '{' => 'π',
+            '|' => '≠',
+            '}' => '£',
+            '~' => '·',
+            _ => c,
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Cursor {
+    pub row: usize,
+    pub col: usize,
+    // Attributes given to newly printed cells
+    pub template: Cell,
+    // The G0 and G1 character sets, and which one SI/SO made active.
+    // They're part of the cursor so DECSC/DECRC save them too.
+    pub charsets: [Charset; 2],
+    pub active_charset: usize,
+    // The OSC 8 link printed text belongs to. Kept apart from the template
+    // so SGR resets don't end it.
+    pub hyperlink: Option<Arc<Hyperlink>>,
+    // A character was printed in the last column and the cursor stays on
+    // it until the next one, which goes on the next line. Moving the
+    // cursor first cancels the wrap.
+    pub pending_wrap: bool,
+}
+
+/// The sequences the terminal didn't understand or found invalid. They're
+/// logged, and kept while `recording` to annotate dumps of the output.
+#[derive(Default)]
+pub struct Unhandled {
+    pub recording: bool,
+    messages: Vec<String>,
+}
+
+impl Unhandled {
+    fn note(&mut self, message: String) {
+        debug!("{}", message);
+        if self.recording {
+            self.messages.push(message);
+        }
+    }
+
+    /// What was noted since the last call
+    pub fn take(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.messages)
+    }
+}
+
+/// The terminal emulator proper: the cell grid, the cursor and the modes
+/// set by the application, driven by the bytes read from the PTY.
+pub struct Terminal {
+    pub rows: usize,
+    pub cols: usize,
+    lines: Vec<Row>,
+    scrollback: VecDeque<Row>,
+    // Bytes the scrollback takes, as `row_bytes` counts them, and how many
+    // it may take before the oldest lines are dropped
+    scrollback_bytes: usize,
+    pub scrollback_budget: usize,
+    // Where the text of lines dropped from the scrollback goes, when that
+    // is turned on
+    spill: Option<Spill>,
+    pub cursor: Cursor,
+    saved_cursor: Option<Cursor>,
+    // Scrolling region, inclusive
+    scroll_top: usize,
+    scroll_bottom: usize,
+    pub modes: Modes,
+    pub palette: Palette,
+    // The colors of the theme in use, what the application's changes to
+    // the palette are reset to
+    theme: Palette,
+    // Kitty keyboard protocol flags pushed by the application, the last
+    // entry is the one in effect
+    keyboard_stack: Vec<u8>,
+    // How many lines the view is scrolled back into the scrollback
+    pub display_offset: usize,
+    // Lines that have scrolled into the scrollback since the start. Unlike
+    // the scrollback's length it keeps counting once the scrollback is
+    // full, so it tells how far the contents moved.
+    pub scrolled_lines: u64,
+    parser: Parser,
+    // Replies to the application, to be written back to the PTY
+    responses: Vec<u8>,
+    // Images from the kitty graphics protocol
+    pub graphics: Graphics,
+    // Size of a cell in pixels, images are measured in it
+    pub cell_size: (u32, u32),
+    // Prompt and command marks from the shell
+    pub shell: ShellIntegration,
+    // Metadata laid over ranges of lines, like the matches of a search
+    pub spans: Spans,
+    // The shell's directory as it last reported it with OSC 7
+    pub working_directory: Option<PathBuf>,
+    // The window title the application set with OSC 0 or 2
+    pub title: Option<String>,
+    // Titles saved with XTWINOPS 22 to be restored with 23
+    title_stack: Vec<Option<String>>,
+    // What programs may do past the screen, set by the window
+    pub policy: Policy,
+    // What the last OSC 52 put on the clipboard, for the window to pass on
+    clipboard: Option<String>,
+    notifications: Vec<Notification>,
+    // The last character printed, for REP to repeat
+    last_char: Option<char>,
+    // Sequences that weren't understood
+    pub unhandled: Unhandled,
+}
+
+impl Terminal {
+    pub fn new(rows: usize, cols: usize) -> Terminal {
+        let rows = rows.max(1);
+        let cols = cols.max(1);
+        Terminal {
+            rows,
+            cols,
+            lines: vec![vec![Cell::default(); cols]; rows],
+            scrollback: VecDeque::new(),
+            scrollback_bytes: 0,
+            scrollback_budget: DEFAULT_SCROLLBACK_BUDGET,
+            spill: None,
+            cursor: Cursor::default(),
+            saved_cursor: None,
+            scroll_top: 0,
+            scroll_bottom: rows - 1,
+            modes: Modes::default(),
+            palette: Palette::default(),
+            theme: Palette::default(),
+            keyboard_stack: Vec::new(),
+            display_offset: 0,
+            scrolled_lines: 0,
+            parser: Parser::new(),
+            responses: Vec::new(),
+            graphics: Graphics::default(),
+            cell_size: (1, 1),
+            shell: ShellIntegration::default(),
+            spans: Spans::default(),
+            working_directory: None,
+            title: None,
+            title_stack: Vec::new(),
+            policy: Policy::default(),
+            clipboard: None,
+            notifications: Vec::new(),
+            last_char: None,
+            unhandled: Unhandled::default(),
+        }
+    }
+
+    /// Feed output of the child process through the parser
+    pub fn process(&mut self, bytes: &[u8]) {
+        let _span = trace_span!("parse", bytes = bytes.len()).entered();
+        let mut parser = std::mem::take(&mut self.parser);
+        parser.advance(self, bytes);
+        self.parser = parser;
+    }
+
+    /// Switch to the colors of another theme. Colors the application
+    /// changed are replaced too, the way a program picks colors to go with
+    /// the theme is by asking for the theme's.
+    pub fn set_theme(&mut self, theme: Palette) {
+        self.palette = theme.clone();
+        self.theme = theme;
+    }
+
+    /// Bytes the terminal wants to send back to the application
+    pub fn take_responses(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.responses)
+    }
+
+    /// Text the application put on the clipboard since the last call
+    pub fn take_clipboard(&mut self) -> Option<String> {
+        self.clipboard.take()
+    }
+
+    /// Notifications the application sent since the last call
+    pub fn take_notifications(&mut self) -> Vec<Notification> {
+        std::mem::take(&mut self.notifications)
+    }
+
+    /// Row `index` of the screen as it should be displayed, taking the
+    /// scrollback position into account. Rows of the scrollback don't keep
+    /// the blank cells at their end, they're filled back in so the row is
+    /// as wide as the screen.
+    pub fn visible_row(&self, index: usize) -> Cow<'_, [Cell]> {
+        let row = if index < self.display_offset {
+            &self.scrollback[self.scrollback.len() - self.display_offset + index]
+        } else {
+            &self.lines[index - self.display_offset]
+        };
+        if row.len() >= self.cols {
+            return Cow::Borrowed(row);
+        }
+        let mut padded = row.clone();
+        padded.resize(self.cols, Cell::default());
+        Cow::Owned(padded)
+    }
+
+    /// The row of the view the cursor is on, if it isn't scrolled out of
+    /// view
+    pub fn cursor_view_row(&self) -> Option<usize> {
+        let row = self.cursor.row + self.display_offset;
+        (row < self.rows).then_some(row)
+    }
+
+    /// How many columns the cursor covers, two when it sits on a wide
+    /// character
+    pub fn cursor_width(&self) -> usize {
+        let cell = &self.lines[self.cursor.row][self.cursor.col];
+        if cell.flags.contains(Flags::WIDE_CHAR) {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// The text on the screen, a line per row with trailing blanks trimmed,
+    /// to look at the grid without drawing it
+    pub fn screen_text(&self) -> String {
+        let top = self.scrollback.len();
+        // A block of the whole screen, which keeps wrapped rows apart
+        let selection = Selection {
+            anchor: Point { line: top, col: 0 },
+            end: Point { line: top + self.rows - 1, col: self.cols - 1 },
+            block: true,
+        };
+        selection.text(self)
+    }
+
+    /// The keyboard enhancement flags currently in effect
+    pub fn keyboard_flags(&self) -> u8 {
+        self.keyboard_stack.last().copied().unwrap_or(0)
+    }
+
+    /// What the keys should send, for `keyboard::encode`
+    pub fn key_modes(&self) -> keyboard::Modes {
+        keyboard::Modes {
+            flags: self.keyboard_flags(),
+            app_cursor: self.modes.app_cursor,
+            app_keypad: self.modes.app_keypad,
+        }
+    }
+
+    pub fn scrollback_len(&self) -> usize {
+        self.scrollback.len()
+    }
+
+    /// Lines in the scrollback and on the screen together, lines are
+    /// indexed from the oldest one in the scrollback
+    pub fn total_lines(&self) -> usize {
+        self.scrollback.len() + self.rows
+    }
+
+    pub fn line(&self, index: usize) -> &Row {
+        if index < self.scrollback.len() {
+            &self.scrollback[index]
+        } else {
+            &self.lines[index - self.scrollback.len()]
+        }
+    }
+
+    /// What the cell at `point` is part of: its hyperlink, the part of a
+    /// command it's in, and the spans laid over it
+    pub fn spans_at(&self, point: Point) -> Vec<SpanKind> {
+        let mut kinds = Vec::new();
+        if let Some(link) = self.line(point.line).get(point.col).and_then(Cell::hyperlink) {
+            kinds.push(SpanKind::Hyperlink(link.clone()));
+        }
+        kinds.extend(self.shell.zone_at(point).map(SpanKind::Zone));
+        kinds.extend(self.spans.at(point).map(|span| span.kind.clone()));
+        kinds
+    }
+
+    /// The run of cells around `point` in its line that are the same
+    /// hyperlink as it
+    pub fn hyperlink_span(&self, point: Point) -> Option<Span> {
+        let row = self.line(point.line);
+        let link = row.get(point.col)?.hyperlink()?;
+        let same = |col: &usize| row[*col].hyperlink().is_some_and(|other| other.is_same(link));
+        let start = (0..point.col).rev().take_while(same).last().unwrap_or(point.col);
+        let end = (point.col..row.len()).take_while(same).last().map_or(point.col, |last| last + 1);
+        Some(Span {
+            kind: SpanKind::Hyperlink(link.clone()),
+            start: Point { line: point.line, col: start },
+            end: Point { line: point.line, col: end },
+        })
+    }
+
+    /// Whether line `index` goes on into the next one because its text
+    /// wrapped there. Lines only stay wrapped at the width they wrapped at.
+    pub fn wraps(&self, index: usize) -> bool {
+        index + 1 < self.total_lines() && self.line(index).last().is_some_and(|cell| cell.flags.contains(Flags::WRAPLINE))
+    }
+
+    /// The lines a line of text was wrapped over that line `index` is part
+    /// of, first to last
+    pub fn logical_line(&self, index: usize) -> std::ops::RangeInclusive<usize> {
+        let mut first = index;
+        while first > 0 && self.wraps(first - 1) {
+            first -= 1;
+        }
+        let mut last = index;
+        while self.wraps(last) {
+            last += 1;
+        }
+        first..=last
+    }
+
+    /// The index of the line shown at the top of the window
+    pub fn first_visible_line(&self) -> usize {
+        self.scrollback.len() - self.display_offset
+    }
+
+    /// Scroll the display just enough to bring the line at `index` into view
+    pub fn scroll_to_line(&mut self, index: usize) {
+        let top = self.first_visible_line();
+        if index < top {
+            self.display_offset = self.scrollback.len() - index;
+        } else if index >= top + self.rows {
+            self.display_offset = (self.scrollback.len() + self.rows).saturating_sub(index + 1);
+        }
+    }
+
+    /// Move the view `delta` lines into (positive) or out of (negative)
+    /// the scrollback
+    pub fn scroll_display(&mut self, delta: isize) {
+        let offset = self.display_offset as isize + delta;
+        self.display_offset = offset.clamp(0, self.scrollback.len() as isize) as usize;
+    }
+
+    pub fn resize(&mut self, rows: usize, cols: usize) {
+        let rows = rows.max(1);
+        let cols = cols.max(1);
+        for line in self.lines.iter_mut() {
+            line.resize(cols, Cell::default());
+            // Don't leave half a wide character at the edge
+            if line[cols - 1].flags.contains(Flags::WIDE_CHAR) {
+                line[cols - 1] = Cell::default();
+            }
+        }
+        // Shrinking pushes lines above the cursor into the scrollback,
+        // growing pulls them back out
+        while self.lines.len() > rows {
+            if self.cursor.row > 0 {
+                let line = self.lines.remove(0);
+                self.push_scrollback(line);
+                self.cursor.row -= 1;
+            } else {
+                self.lines.pop();
+            }
+        }
+        while self.lines.len() < rows {
+            match self.scrollback.pop_back() {
+                Some(mut line) => {
+                    self.scrollback_bytes -= row_bytes(&line);
+                    line.resize(cols, Cell::default());
+                    self.lines.insert(0, line);
+                    self.cursor.row += 1;
+                }
+                None => self.lines.push(vec![Cell::default(); cols]),
+            }
+        }
+        self.rows = rows;
+        self.cols = cols;
+        self.scroll_top = 0;
+        self.scroll_bottom = rows - 1;
+        self.display_offset = self.display_offset.min(self.scrollback.len());
+        self.cursor.row = self.cursor.row.min(rows - 1);
+        self.cursor.col = self.cursor.col.min(cols - 1);
+        self.cursor.pending_wrap = false;
+    }
+
+    /// Keep the text of lines that fall off the top of the scrollback in a
+    /// compressed temporary file, for searching
+    pub fn spill_to_disk(&mut self) -> std::io::Result<()> {
+        self.spill = Some(Spill::new()?);
+        Ok(())
+    }
+
+    /// The history older than the scrollback, if it is kept
+    pub fn spilled(&self) -> Option<&Spill> {
+        self.spill.as_ref()
+    }
+
+    /// Memory the scrollback takes, in bytes
+    pub fn scrollback_bytes(&self) -> usize {
+        self.scrollback_bytes
+    }
+
+    fn push_scrollback(&mut self, mut line: Row) {
+        // Most lines end well before the right edge, the blank cells after
+        // the end aren't kept
+        let used = line.iter().rposition(|cell| *cell != Cell::default()).map_or(0, |last| last + 1);
+        line.truncate(used);
+        line.shrink_to_fit();
+        self.scrollback_bytes += row_bytes(&line);
+        self.scrollback.push_back(line);
+        self.scrolled_lines += 1;
+
+        let mut dropped = 0;
+        while self.scrollback.len() > SCROLLBACK_LIMIT
+            || (self.scrollback_bytes > self.scrollback_budget && self.scrollback.len() > 1)
+        {
+            let line = self.scrollback.pop_front().expect("Scrollback over its limits is empty");
+            self.scrollback_bytes -= row_bytes(&line);
+            if let Some(spill) = &mut self.spill {
+                if let Err(e) = spill.push(row_text(&line)) {
+                    warn!("Could not spill the scrollback to disk, only keeping it in memory: {}", e);
+                    self.spill = None;
+                }
+            }
+            dropped += 1;
+        }
+        if dropped > 0 {
+            self.graphics.lines_dropped(dropped);
+            self.shell.lines_dropped(dropped);
+            self.spans.lines_dropped(dropped);
+        }
+        if self.display_offset > 0 {
+            // Keep the view still while the user is reading the scrollback,
+            // as far as the lines being read are still there
+            self.display_offset = (self.display_offset + 1).saturating_sub(dropped).clamp(1, self.scrollback.len());
+        }
+    }
+
+    fn blank_cell(&self) -> Cell {
+        Cell {
+            bg: self.cursor.template.bg,
+            ..Cell::default()
+        }
+    }
+
+    fn scroll_up(&mut self, count: usize) {
+        let count = count.min(self.scroll_bottom - self.scroll_top + 1);
+        for _ in 0..count {
+            let blank = vec![self.blank_cell(); self.cols];
+            let line = self.lines.remove(self.scroll_top);
+            self.lines.insert(self.scroll_bottom, blank);
+            if self.scroll_top == 0 {
+                self.push_scrollback(line);
+            }
+        }
+    }
+
+    fn scroll_down(&mut self, count: usize) {
+        let count = count.min(self.scroll_bottom - self.scroll_top + 1);
+        for _ in 0..count {
+            let blank = vec![self.blank_cell(); self.cols];
+            self.lines.remove(self.scroll_bottom);
+            self.lines.insert(self.scroll_top, blank);
+        }
+    }
+
+    fn linefeed(&mut self) {
+        self.cursor.pending_wrap = false;
+        if self.cursor.row == self.scroll_bottom {
+            self.scroll_up(1);
+        } else if self.cursor.row < self.rows - 1 {
+            self.cursor.row += 1;
+        }
+    }
+
+    fn reverse_index(&mut self) {
+        self.cursor.pending_wrap = false;
+        if self.cursor.row == self.scroll_top {
+            self.scroll_down(1);
+        } else if self.cursor.row > 0 {
+            self.cursor.row -= 1;
+        }
+    }
+
+    fn goto(&mut self, row: usize, col: usize) {
+        self.cursor.row = row.min(self.rows - 1);
+        self.cursor.col = col.min(self.cols - 1);
+    }
+
+    fn backspace(&mut self) {
+        let reverse_wrap = self.modes.reverse_wrap && self.modes.autowrap;
+        // The cursor is already on the character a pending wrap left it at
+        if std::mem::take(&mut self.cursor.pending_wrap) && reverse_wrap {
+            return;
+        }
+        if self.cursor.col > 0 {
+            self.cursor.col -= 1;
+        } else if reverse_wrap && self.cursor.row > 0 {
+            self.cursor.row -= 1;
+            self.cursor.col = self.cols - 1;
+        }
+    }
+
+    fn move_up(&mut self, count: usize) {
+        let top = if self.cursor.row >= self.scroll_top { self.scroll_top } else { 0 };
+        self.cursor.row = self.cursor.row.saturating_sub(count).max(top);
+    }
+
+    fn move_down(&mut self, count: usize) {
+        let bottom = if self.cursor.row <= self.scroll_bottom { self.scroll_bottom } else { self.rows - 1 };
+        self.cursor.row = (self.cursor.row + count).min(bottom);
+    }
+
+    /// Clear the other half of a wide character when one half is about to
+    /// be overwritten, so no orphaned halves are left behind
+    fn clear_wide_char(&mut self, row: usize, col: usize) {
+        let blank = self.blank_cell();
+        let line = &mut self.lines[row];
+        if line[col].flags.contains(Flags::WIDE_CHAR_SPACER) && col > 0 {
+            line[col - 1] = blank.clone();
+        }
+        if line[col].flags.contains(Flags::WIDE_CHAR) && col + 1 < line.len() {
+            line[col + 1] = blank;
+        }
+    }
+
+    fn erase_cells(&mut self, row: usize, from: usize, to: usize) {
+        let blank = self.blank_cell();
+        let to = to.min(self.cols);
+        if from < to {
+            self.clear_wide_char(row, from);
+            self.clear_wide_char(row, to - 1);
+        }
+        for cell in &mut self.lines[row][from.min(to)..to] {
+            *cell = blank.clone();
+        }
+    }
+
+    fn erase_in_display(&mut self, mode: u16) {
+        let (row, col) = (self.cursor.row, self.cursor.col);
+        match mode {
+            0 => {
+                self.erase_cells(row, col, self.cols);
+                for r in row + 1..self.rows {
+                    self.erase_cells(r, 0, self.cols);
+                }
+            }
+            1 => {
+                for r in 0..row {
+                    self.erase_cells(r, 0, self.cols);
+                }
+                self.erase_cells(row, 0, col + 1);
+            }
+            2 => {
+                for r in 0..self.rows {
+                    self.erase_cells(r, 0, self.cols);
+                }
+                // Images on the screen go with the text
+                let top = self.scrollback.len();
+                self.graphics.delete(false, None, |p| p.line + p.rows > top);
+            }
+            3 => self.clear_scrollback(),
+            _ => {}
+        }
+    }
+
+    /// ED 3, drop the scrollback and the images and marks in it
+    fn clear_scrollback(&mut self) {
+        let dropped = self.scrollback.len();
+        self.graphics.lines_dropped(dropped);
+        self.shell.lines_dropped(dropped);
+        self.spans.lines_dropped(dropped);
+        // A new deque rather than clearing, so the memory is given back
+        self.scrollback = VecDeque::new();
+        self.scrollback_bytes = 0;
+        self.display_offset = 0;
+        if let Some(spill) = &mut self.spill {
+            if let Err(e) = spill.clear() {
+                warn!("Could not clear the scrollback spilled to disk: {}", e);
+            }
+        }
+    }
+
+    /// Clear the scrollback and the screen, except for the line the cursor
+    /// is on which moves to the top, so a prompt stays in view
+    pub fn clear_history(&mut self) {
+        let row = self.cursor.row;
+        self.lines.drain(..row);
+        self.lines.resize(self.rows, vec![Cell::default(); self.cols]);
+        self.cursor.row = 0;
+        // Screen lines are numbered after the scrollback's
+        self.graphics.lines_dropped(row);
+        self.shell.lines_dropped(row);
+        self.spans.lines_dropped(row);
+        self.clear_scrollback();
+    }
+
+    fn erase_in_line(&mut self, mode: u16) {
+        let (row, col) = (self.cursor.row, self.cursor.col);
+        match mode {
+            0 => self.erase_cells(row, col, self.cols),
+            1 => self.erase_cells(row, 0, col + 1),
+            2 => self.erase_cells(row, 0, self.cols),
+            _ => {}
+        }
+    }
+
+    fn insert_lines(&mut self, count: usize) {
+        if self.cursor.row < self.scroll_top || self.cursor.row > self.scroll_bottom {
+            return;
+        }
+        let count = count.min(self.scroll_bottom - self.cursor.row + 1);
+        for _ in 0..count {
+            self.lines.remove(self.scroll_bottom);
+            self.lines.insert(self.cursor.row, vec![self.blank_cell(); self.cols]);
+        }
+        self.cursor.col = 0;
+    }
+
+    fn delete_lines(&mut self, count: usize) {
+        if self.cursor.row < self.scroll_top || self.cursor.row > self.scroll_bottom {
+            return;
+        }
+        let count = count.min(self.scroll_bottom - self.cursor.row + 1);
+        for _ in 0..count {
+            self.lines.remove(self.cursor.row);
+            self.lines.insert(self.scroll_bottom, vec![self.blank_cell(); self.cols]);
+        }
+        self.cursor.col = 0;
+    }
+
+    fn insert_chars(&mut self, count: usize) {
+        let blank = self.blank_cell();
+        let col = self.cursor.col;
+        self.clear_wide_char(self.cursor.row, col);
+        let line = &mut self.lines[self.cursor.row];
+        let count = count.min(line.len() - col);
+        line.truncate(line.len() - count);
+        for _ in 0..count {
+            line.insert(col, blank.clone());
+        }
+        // The spacer of the last wide character may have been pushed off
+        let last = line.len() - 1;
+        if line[last].flags.contains(Flags::WIDE_CHAR) {
+            line[last] = blank;
+        }
+    }
+
+    fn delete_chars(&mut self, count: usize) {
+        let blank = self.blank_cell();
+        let col = self.cursor.col;
+        let count = count.min(self.cols - col);
+        self.clear_wide_char(self.cursor.row, col);
+        self.clear_wide_char(self.cursor.row, col + count - 1);
+        let line = &mut self.lines[self.cursor.row];
+        line.drain(col..col + count);
+        line.resize(self.cols, blank);
+    }
+
+    fn next_tab_stop(&mut self, count: usize) {
+        for _ in 0..count {
+            let next = (self.cursor.col / TAB_WIDTH + 1) * TAB_WIDTH;
+            self.cursor.col = next.min(self.cols - 1);
+        }
+    }
+
+    fn set_scroll_region(&mut self, top: usize, bottom: usize) {
+        let bottom = bottom.min(self.rows - 1);
+        if top >= bottom {
+            return;
+        }
+        self.scroll_top = top;
+        self.scroll_bottom = bottom;
+        self.goto(0, 0);
+    }
+
+    fn save_cursor(&mut self) {
+        self.saved_cursor = Some(self.cursor.clone());
+    }
+
+    fn restore_cursor(&mut self) {
+        if let Some(saved) = self.saved_cursor.clone() {
+            let (row, col) = (saved.row, saved.col);
+            self.cursor = saved;
+            self.goto(row, col);
+        }
+    }
+
+    fn reset(&mut self) {
+        let cell_size = self.cell_size;
+        let scrolled_lines = self.scrolled_lines;
+        let working_directory = self.working_directory.take();
+        let title = self.title.take();
+        let policy = self.policy;
+        *self = Terminal::new(self.rows, self.cols);
+        self.cell_size = cell_size;
+        // Or a program could reset its way out of it
+        self.policy = policy;
+        self.scrolled_lines = scrolled_lines;
+        self.working_directory = working_directory;
+        // Like xterm, a reset leaves the title alone
+        self.title = title;
+    }
+
+    /// APC G, the kitty graphics protocol: `data` is the control data, then
+    /// a semicolon and the base64 payload
+    fn kitty_graphics(&mut self, data: &[u8]) {
+        let (control, payload) = match data.iter().position(|&b| b == b';') {
+            Some(i) => (&data[..i], &data[i + 1..]),
+            None => (data, &[][..]),
+        };
+        let command = match graphics::Command::parse(control) {
+            Ok(command) => command,
+            Err(e) => {
+                self.unhandled.note(format!("Invalid graphics command: {}", e.message));
+                return;
+            }
+        };
+        let Some((mut command, payload)) = self.graphics.receive(command, payload) else {
+            return;
+        };
+
+        let result = match command.action {
+            b't' | b'T' | b'q' => self.transmit_image(&mut command, &payload),
+            b'p' => {
+                let id = match command.image_id {
+                    0 => self.graphics.id_for_number(command.image_number),
+                    id => Some(id),
+                };
+                id.ok_or_else(|| graphics::Error::new("ENOENT", "No such image"))
+                    .and_then(|id| self.place_image(id, &command))
+            }
+            // Deleting never gets a reply
+            b'd' => {
+                self.delete_images(&command);
+                return;
+            }
+            action => Err(graphics::Error::invalid(format!("Unsupported action {}", action as char))),
+        };
+        if let Some(reply) = command.response(&result) {
+            self.responses.extend(reply);
+        }
+    }
+
+    fn transmit_image(&mut self, command: &mut graphics::Command, payload: &[u8]) -> Result<(), graphics::Error> {
+        let data = graphics::load_data(command, payload)?;
+        let (width, height, rgba) = graphics::decode(command, &data)?;
+        // A query only checks the image could be loaded
+        if command.action == b'q' {
+            return Ok(());
+        }
+
+        let id = match command.image_id {
+            0 => self.graphics.free_id(),
+            id => id,
+        };
+        // Images sent with only a number learn their id from the reply
+        if command.image_number != 0 {
+            command.image_id = id;
+        }
+        self.graphics.add_image(id, command.image_number, width, height, rgba);
+        if command.action == b'T' {
+            self.place_image(id, command)?;
+        }
+        Ok(())
+    }
+
+    /// Show an image at the cursor
+    fn place_image(&mut self, id: u32, command: &graphics::Command) -> Result<(), graphics::Error> {
+        let image = self.graphics.images.get(&id).ok_or_else(|| graphics::Error::new("ENOENT", "No such image"))?;
+        let x = command.source_x.min(image.width);
+        let y = command.source_y.min(image.height);
+        let source_width = match command.source_width {
+            0 => image.width - x,
+            w => w.min(image.width - x),
+        };
+        let source_height = match command.source_height {
+            0 => image.height - y,
+            h => h.min(image.height - y),
+        };
+
+        // Columns and rows given together stretch the image over them, given
+        // alone the other side keeps the aspect ratio
+        let (cell_width, cell_height) = (self.cell_size.0.max(1) as u64, self.cell_size.1.max(1) as u64);
+        let (cols, rows) = (command.cols.min(MAX_IMAGE_CELLS) as u64, command.rows.min(MAX_IMAGE_CELLS) as u64);
+        let (source_w, source_h) = (source_width.max(1) as u64, source_height.max(1) as u64);
+        let (width, height) = match (cols, rows) {
+            (0, 0) => (source_width as u64, source_height as u64),
+            (cols, 0) => (cols * cell_width, source_h * cols * cell_width / source_w),
+            (0, rows) => (source_w * rows * cell_height / source_h, rows * cell_height),
+            (cols, rows) => (cols * cell_width, rows * cell_height),
+        };
+        let offset = (command.cell_x_offset.min(cell_width as u32 - 1), command.cell_y_offset.min(cell_height as u32 - 1));
+        let cols = (offset.0 as u64 + width).div_ceil(cell_width).max(1) as usize;
+        let rows = (offset.1 as u64 + height).div_ceil(cell_height).max(1) as usize;
+
+        self.graphics.add_placement(Placement {
+            image_id: id,
+            placement_id: command.placement_id,
+            line: self.scrollback.len() + self.cursor.row,
+            col: self.cursor.col,
+            cols,
+            rows,
+            offset,
+            source: (x, y, source_width, source_height),
+            width: width as u32,
+            height: height as u32,
+            z: command.z,
+        });
+
+        // The cursor ends up just after the image, on its last row
+        if command.move_cursor {
+            for _ in 1..rows {
+                self.linefeed();
+            }
+            self.cursor.col = (self.cursor.col + cols).min(self.cols - 1);
+        }
+        Ok(())
+    }
+
+    /// `d` picks what to delete. Lowercase only removes placements,
+    /// uppercase frees the images left without any too.
+    fn delete_images(&mut self, command: &graphics::Command) {
+        let free = command.delete.is_ascii_uppercase();
+        let top = self.scrollback.len();
+        // `x` and `y` are 1 based cell coordinates here
+        let line = top + (command.source_y as usize).saturating_sub(1);
+        let col = (command.source_x as usize).saturating_sub(1);
+        let graphics = &mut self.graphics;
+        match command.delete.to_ascii_lowercase() {
+            b'a' => graphics.delete(free, None, |p| p.line + p.rows > top),
+            b'i' => {
+                let (id, placement_id) = (command.image_id, command.placement_id);
+                graphics.delete(free, Some(id), |p| {
+                    p.image_id == id && (placement_id == 0 || p.placement_id == placement_id)
+                });
+            }
+            b'n' => {
+                if let Some(id) = graphics.id_for_number(command.image_number) {
+                    graphics.delete(free, Some(id), |p| p.image_id == id);
+                }
+            }
+            b'c' => {
+                let (line, col) = (top + self.cursor.row, self.cursor.col);
+                graphics.delete(free, None, |p| p.intersects(line, col));
+            }
+            b'p' => graphics.delete(free, None, |p| p.intersects(line, col)),
+            b'x' => graphics.delete(free, None, |p| (p.col..p.col + p.cols).contains(&col)),
+            b'y' => graphics.delete(free, None, |p| (p.line..p.line + p.rows).contains(&line)),
+            b'z' => graphics.delete(free, None, |p| p.z == command.z),
+            d => self.unhandled.note(format!("Unhandled graphics delete: {}", d as char)),
+        }
+    }
+
+    // Whether the policy lets programs use `feature`, noting it when not
+    fn permitted(&self, feature: Feature) -> bool {
+        let allowed = self.policy.allows(feature);
+        if !allowed {
+            debug!("Ignored a program's use of {}, the policy denies it", feature.name());
+        }
+        allowed
+    }
+
+    /// OSC 52: the text is base64. Whichever selection it names, it goes on
+    /// the clipboard.
+    fn clipboard_write(&mut self, data: &[u8]) {
+        use base64::Engine;
+        match base64::engine::general_purpose::STANDARD.decode(data) {
+            Ok(text) => self.clipboard = Some(String::from_utf8_lossy(&text).into_owned()),
+            Err(e) => self.unhandled.note(format!("Invalid base64 in OSC 52: {}", e)),
+        }
+    }
+
+    /// OSC 8: `params` are colon separated `key=value` pairs, an empty URI
+    /// ends the link
+    fn set_hyperlink(&mut self, params: &[u8], uri: &[u8]) {
+        if uri.is_empty() {
+            self.cursor.hyperlink = None;
+            return;
+        }
+        let id = params
+            .split(|&b| b == b':')
+            .find_map(|param| param.strip_prefix(b"id="))
+            .map(|id| String::from_utf8_lossy(id).into_owned());
+        self.cursor.hyperlink = Some(Arc::new(Hyperlink {
+            id,
+            uri: String::from_utf8_lossy(uri).into_owned(),
+        }));
+    }
+
+    /// OSC 4: pairs of a palette index and a color to set it to, or `?` to
+    /// report it
+    fn palette_colors(&mut self, pairs: &[&[u8]]) {
+        for pair in pairs.chunks(2) {
+            let [index, spec] = pair else {
+                break;
+            };
+            let Some(index) = std::str::from_utf8(index).ok().and_then(|index| index.parse::<u8>().ok()) else {
+                self.unhandled.note(format!("Invalid OSC 4 color index: {:?}", String::from_utf8_lossy(index)));
+                continue;
+            };
+            if *spec == b"?" {
+                let reply = format!("\x1b]4;{};{}\x1b\\", index, color::format_spec(self.palette.indexed(index)));
+                self.responses.extend(reply.as_bytes());
+            } else if let Some(rgb) = parse_color(spec) {
+                self.palette.set_indexed(index, rgb);
+            }
+        }
+    }
+
+    /// OSC 104: put the listed palette entries back, all of them when none
+    /// are listed
+    fn reset_palette_colors(&mut self, indices: &[&[u8]]) {
+        let default = self.theme.clone();
+        let mut indices: Vec<u8> = indices
+            .iter()
+            .filter_map(|index| std::str::from_utf8(index).ok()?.parse().ok())
+            .collect();
+        if indices.is_empty() {
+            indices = (0..=255).collect();
+        }
+        for index in indices {
+            self.palette.set_indexed(index, default.indexed(index));
+        }
+    }
+
+    /// OSC 10, 11 and 12: the default foreground, the default background and
+    /// the cursor color
+    fn dynamic_color(&mut self, code: u8, spec: &[u8]) {
+        if spec == b"?" {
+            let rgb = match code {
+                10 => self.palette.foreground,
+                11 => self.palette.background,
+                // Reported as the text it takes the color of by default
+                _ => self.palette.cursor.unwrap_or(self.palette.foreground),
+            };
+            let reply = format!("\x1b]{};{}\x1b\\", code, color::format_spec(rgb));
+            self.responses.extend(reply.as_bytes());
+            return;
+        }
+        let Some(rgb) = parse_color(spec) else {
+            return;
+        };
+        match code {
+            10 => self.palette.foreground = rgb,
+            11 => self.palette.background = rgb,
+            _ => self.palette.cursor = Some(rgb),
+        }
+    }
+
+    /// XTGETTCAP: `data` is a semicolon separated list of hex encoded
+    /// terminfo names, each is answered on its own, found or not
+    fn request_termcap(&mut self, data: &[u8]) {
+        for encoded in data.split(|&b| b == b';') {
+            let capability = hex_decode(encoded)
+                .and_then(|name| String::from_utf8(name).ok())
+                .and_then(|name| terminfo::capability(&name));
+            let reply = match capability {
+                Some(Capability::Boolean) => format!("\x1bP1+r{}\x1b\\", String::from_utf8_lossy(encoded)),
+                Some(Capability::Value(value)) => {
+                    format!("\x1bP1+r{}={}\x1b\\", String::from_utf8_lossy(encoded), hex_encode(value.as_bytes()))
+                }
+                None => format!("\x1bP0+r{}\x1b\\", String::from_utf8_lossy(encoded)),
+            };
+            self.responses.extend(reply.as_bytes());
+        }
+    }
+
+    fn set_ansi_mode(&mut self, mode: u16, enabled: bool) {
+        match mode {
+            4 => self.modes.insert = enabled,
+            _ => self.unhandled.note(format!("Unhandled mode: {} {}", mode, enabled)),
+        }
+    }
+
+    fn set_private_mode(&mut self, mode: u16, enabled: bool) {
+        let tracking = |m: MouseTracking| if enabled { m } else { MouseTracking::None };
+        match mode {
+            1 => self.modes.app_cursor = enabled,
+            7 => {
+                self.modes.autowrap = enabled;
+                self.cursor.pending_wrap = false;
+            }
+            9 => self.modes.mouse_tracking = tracking(MouseTracking::X10),
+            25 => self.modes.cursor_visible = enabled,
+            45 => self.modes.reverse_wrap = enabled,
+            1000 => self.modes.mouse_tracking = tracking(MouseTracking::Normal),
+            1002 => self.modes.mouse_tracking = tracking(MouseTracking::ButtonEvent),
+            1003 => self.modes.mouse_tracking = tracking(MouseTracking::AnyEvent),
+            1004 => self.modes.focus_reporting = enabled,
+            1006 => self.modes.sgr_mouse = enabled,
+            2004 => self.modes.bracketed_paste = enabled,
+            _ => self.unhandled.note(format!("Unhandled private mode: {} {}", mode, enabled)),
+        }
+    }
+
+    /// DSR, the terminal's status or the cursor position
+    fn device_status_report(&mut self, report: u16) {
+        match report {
+            // Nothing's wrong
+            5 => self.responses.extend(b"\x1b[0n"),
+            6 => {
+                let reply = format!("\x1b[{};{}R", self.cursor.row + 1, self.cursor.col + 1);
+                self.responses.extend(reply.as_bytes());
+            }
+            _ => self.unhandled.note(format!("Unhandled DSR: {}", report)),
+        }
+    }
+
+    /// XTWINOPS, the reports of the window's size and the title stack.
+    /// Operations that would move or resize the window are ignored.
+    fn window_op(&mut self, params: &Params) {
+        let (cell_width, cell_height) = self.cell_size;
+        let reply = match params.first_or(0, 0) {
+            // Never iconified
+            11 => "\x1b[1t".to_string(),
+            // The text area in pixels
+            14 => format!("\x1b[4;{};{}t", self.rows as u32 * cell_height, self.cols as u32 * cell_width),
+            16 => format!("\x1b[6;{};{}t", cell_height, cell_width),
+            // The text area in cells, and the screen, which is the same
+            18 => format!("\x1b[8;{};{}t", self.rows, self.cols),
+            19 => format!("\x1b[9;{};{}t", self.rows, self.cols),
+            // 0 and 2 are the title with or without the icon name, there
+            // is no icon name to save apart from it
+            22 if params.first_or(1, 0) != 1 => {
+                if self.title_stack.len() == TITLE_STACK_LIMIT {
+                    self.title_stack.remove(0);
+                }
+                self.title_stack.push(self.title.clone());
+                return;
+            }
+            23 if params.first_or(1, 0) != 1 => {
+                if let Some(title) = self.title_stack.pop().filter(|_| self.policy.allows(Feature::Title)) {
+                    self.title = title;
+                }
+                return;
+            }
+            22 | 23 => return,
+            op => {
+                self.unhandled.note(format!("Unhandled XTWINOPS: {}", op));
+                return;
+            }
+        };
+        self.responses.extend(reply.as_bytes());
+    }
+
+    /// Whether a DEC private mode is set, `None` for modes that aren't
+    /// supported
+    fn private_mode(&self, mode: u16) -> Option<bool> {
+        let tracking = |m: MouseTracking| self.modes.mouse_tracking == m;
+        let set = match mode {
+            1 => self.modes.app_cursor,
+            7 => self.modes.autowrap,
+            9 => tracking(MouseTracking::X10),
+            25 => self.modes.cursor_visible,
+            45 => self.modes.reverse_wrap,
+            1000 => tracking(MouseTracking::Normal),
+            1002 => tracking(MouseTracking::ButtonEvent),
+            1003 => tracking(MouseTracking::AnyEvent),
+            1004 => self.modes.focus_reporting,
+            1006 => self.modes.sgr_mouse,
+            2004 => self.modes.bracketed_paste,
+            _ => return None,
+        };
+        Some(set)
+    }
+
+    /// Whether an ANSI mode is set, `None` for modes that aren't supported
+    fn ansi_mode(&self, mode: u16) -> Option<bool> {
+        match mode {
+            4 => Some(self.modes.insert),
+            // Automatic newline, known but never set
+            20 => Some(false),
+            _ => None,
+        }
+    }
+
+    fn push_keyboard_flags(&mut self, flags: u8) {
+        if self.keyboard_stack.len() == KEYBOARD_STACK_LIMIT {
+            self.keyboard_stack.remove(0);
+        }
+        self.keyboard_stack.push(flags & keyboard::ALL_FLAGS);
+    }
+
+    fn pop_keyboard_flags(&mut self, count: usize) {
+        let len = self.keyboard_stack.len();
+        self.keyboard_stack.truncate(len.saturating_sub(count));
+    }
+
+    fn set_keyboard_flags(&mut self, flags: u8, mode: u16) {
+        let flags = flags & keyboard::ALL_FLAGS;
+        let current = self.keyboard_flags();
+        let new = match mode {
+            1 => flags,
+            2 => current | flags,
+            3 => current & !flags,
+            _ => return,
+        };
+        match self.keyboard_stack.last_mut() {
+            Some(last) => *last = new,
+            None => self.keyboard_stack.push(new),
+        }
+    }
+
+    /// Attach a combining mark to the character printed before it
+    fn print_zerowidth(&mut self, c: char) {
+        let (mut row, mut col) = (self.cursor.row, self.cursor.col);
+        // The character a pending wrap waits after is under the cursor
+        if self.cursor.pending_wrap {
+            col += 1;
+        }
+        if col == 0 {
+            // The previous character wrapped the cursor onto this line
+            if row == 0 {
+                return;
+            }
+            row -= 1;
+            col = self.cols;
+        }
+        col -= 1;
+        if self.lines[row][col].flags.contains(Flags::WIDE_CHAR_SPACER) && col > 0 {
+            col -= 1;
+        }
+        self.lines[row][col].push_zerowidth(c);
+    }
+
+    fn set_attributes(&mut self, params: &Params) {
+        let template = &mut self.cursor.template;
+        if params.is_empty() {
+            *template = Cell::default();
+            return;
+        }
+
+        let mut iter = params.iter();
+        while let Some(param) = iter.next() {
+            match param[0] {
+                0 => *template = Cell::default(),
+                1 => template.flags.insert(Flags::BOLD),
+                2 => template.flags.insert(Flags::DIM),
+                3 => template.flags.insert(Flags::ITALIC),
+                4 => {
+                    // 4:n selects the underline style
+                    template.flags.remove(Flags::ALL_UNDERLINES);
+                    match param.get(1) {
+                        Some(0) => {}
+                        Some(2) => template.flags.insert(Flags::DOUBLE_UNDERLINE),
+                        Some(3) => template.flags.insert(Flags::UNDERCURL),
+                        // Dotted and dashed are drawn as single underlines
+                        _ => template.flags.insert(Flags::UNDERLINE),
+                    }
+                }
+                // Rapid blink blinks at the same rate as slow blink
+                5 | 6 => template.flags.insert(Flags::BLINK),
+                7 => template.flags.insert(Flags::INVERSE),
+                8 => template.flags.insert(Flags::HIDDEN),
+                9 => template.flags.insert(Flags::STRIKEOUT),
+                21 => {
+                    template.flags.remove(Flags::ALL_UNDERLINES);
+                    template.flags.insert(Flags::DOUBLE_UNDERLINE);
+                }
+                22 => template.flags.remove(Flags::BOLD | Flags::DIM),
+                23 => template.flags.remove(Flags::ITALIC),
+                24 => template.flags.remove(Flags::ALL_UNDERLINES),
+                25 => template.flags.remove(Flags::BLINK),
+                27 => template.flags.remove(Flags::INVERSE),
+                28 => template.flags.remove(Flags::HIDDEN),
+                29 => template.flags.remove(Flags::STRIKEOUT),
+                n @ 30..=37 => template.fg = Color::Indexed((n - 30) as u8),
+                38 => {
+                    if let Some(color) = parse_extended_color(param, &mut iter) {
+                        template.fg = color;
+                    }
+                }
+                39 => template.fg = Color::Default,
+                n @ 40..=47 => template.bg = Color::Indexed((n - 40) as u8),
+                48 => {
+                    if let Some(color) = parse_extended_color(param, &mut iter) {
+                        template.bg = color;
+                    }
+                }
+                49 => template.bg = Color::Default,
+                58 => {
+                    if let Some(color) = parse_extended_color(param, &mut iter) {
+                        template.set_underline_color(Some(color));
+                    }
+                }
+                59 => template.set_underline_color(None),
+                n @ 90..=97 => template.fg = Color::Indexed((n - 90 + 8) as u8),
+                n @ 100..=107 => template.bg = Color::Indexed((n - 100 + 8) as u8),
+                n => self.unhandled.note(format!("Unhandled SGR attribute: {}", n)),
+            }
+        }
+    }
+}
+
+/// Parse the color following SGR 38/48, either in the colon form
+/// (`38:5:n`, `38:2::r:g:b`) or the legacy semicolon form (`38;5;n`)
+fn parse_extended_color<'a>(param: &[u16], iter: &mut impl Iterator<Item = &'a [u16]>) -> Option<Color> {
+    if param.len() > 1 {
+        return match param[1] {
+            5 => param.get(2).map(|&n| Color::Indexed(n as u8)),
+            2 => {
+                // The colorspace id is optional
+                let rgb = if param.len() >= 6 { &param[3..6] } else { param.get(2..5)? };
+                Some(Color::Rgb(rgb[0] as u8, rgb[1] as u8, rgb[2] as u8))
+            }
+            _ => None,
+        };
+    }
+
+    match iter.next()?[0] {
+        5 => iter.next().map(|n| Color::Indexed(n[0] as u8)),
+        2 => {
+            let r = iter.next()?[0];
+            let g = iter.next()?[0];
+            let b = iter.next()?[0];
+            Some(Color::Rgb(r as u8, g as u8, b as u8))
+        }
+        _ => None,
+    }
+}
+
+impl Perform for Terminal {
+    fn print(&mut self, c: char) {
+        let c = self.cursor.charsets[self.cursor.active_charset].map(c);
+        let width = c.width().unwrap_or(1);
+        if width == 0 {
+            self.print_zerowidth(c);
+            return;
+        }
+        if width == 2 && self.cols < 2 {
+            return;
+        }
+
+        if self.cursor.pending_wrap {
+            let last = self.cols - 1;
+            self.lines[self.cursor.row][last].flags.insert(Flags::WRAPLINE);
+            self.cursor.col = 0;
+            self.linefeed();
+        }
+
+        // A wide character doesn't fit in the last column, so it goes on
+        // the next line instead, or nowhere without autowrap
+        if width == 2 && self.cursor.col == self.cols - 1 {
+            if !self.modes.autowrap {
+                return;
+            }
+            let (row, col) = (self.cursor.row, self.cursor.col);
+            self.erase_cells(row, col, col + 1);
+            self.lines[row][col].flags.insert(Flags::WRAPLINE | Flags::LEADING_WIDE_CHAR_SPACER);
+            self.cursor.col = 0;
+            self.linefeed();
+        }
+
+        if self.modes.insert {
+            self.insert_chars(width);
+        }
+
+        let (row, col) = (self.cursor.row, self.cursor.col);
+        self.clear_wide_char(row, col);
+        self.last_char = Some(c);
+        let mut cell = Cell { c, ..self.cursor.template.clone() };
+        if let Some(link) = &self.cursor.hyperlink {
+            cell.set_hyperlink(link.clone());
+        }
+        if width == 2 {
+            self.clear_wide_char(row, col + 1);
+            cell.flags.insert(Flags::WIDE_CHAR);
+            let mut spacer = Cell { c: ' ', ..self.cursor.template.clone() };
+            spacer.flags.insert(Flags::WIDE_CHAR_SPACER);
+            self.lines[row][col + 1] = spacer;
+        }
+        self.lines[row][col] = cell;
+
+        // Without autowrap the last column is overwritten again and again
+        if col + width >= self.cols {
+            self.cursor.col = self.cols - 1;
+            self.cursor.pending_wrap = self.modes.autowrap;
+        } else {
+            self.cursor.col += width;
+        }
+    }
+
+    fn execute(&mut self, byte: u8) {
+        match byte {
+            // BEL
+            0x07 => {}
+            // BS
+            0x08 => self.backspace(),
+            // HT
+            0x09 => {
+                self.cursor.pending_wrap = false;
+                self.next_tab_stop(1);
+            }
+            // LF, VT, FF
+            0x0a..=0x0c => self.linefeed(),
+            // CR
+            0x0d => {
+                self.cursor.pending_wrap = false;
+                self.cursor.col = 0;
+            }
+            // SO, shift out to G1
+            0x0e => self.cursor.active_charset = 1,
+            // SI, shift in to G0
+            0x0f => self.cursor.active_charset = 0,
+            _ => {}
+        }
+    }
+
+    fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], action: char) {
+        let count = params.first_or(0, 1) as usize;
+        // Whatever moves the cursor or edits the screen cancels a pending
+        // wrap. Attributes, modes and reports leave it, and saving and
+        // restoring the cursor carries it along.
+        if !matches!(action, 'm' | 'h' | 'l' | 'n' | 'c' | 'p' | 't' | 's' | 'u' | 'b') {
+            self.cursor.pending_wrap = false;
+        }
+        match (intermediates, action) {
+            (b"?", 'h') => params.iter().for_each(|p| self.set_private_mode(p[0], true)),
+            (b"?", 'l') => params.iter().for_each(|p| self.set_private_mode(p[0], false)),
+            ([], 'h') => params.iter().for_each(|p| self.set_ansi_mode(p[0], true)),
+            ([], 'l') => params.iter().for_each(|p| self.set_ansi_mode(p[0], false)),
+            ([], '@') => self.insert_chars(count),
+            ([], 'A') => self.move_up(count),
+            ([], 'B') | ([], 'e') => self.move_down(count),
+            ([], 'C') | ([], 'a') => self.cursor.col = (self.cursor.col + count).min(self.cols - 1),
+            ([], 'D') => self.cursor.col = self.cursor.col.saturating_sub(count),
+            ([], 'E') => {
+                self.move_down(count);
+                self.cursor.col = 0;
+            }
+            ([], 'F') => {
+                self.move_up(count);
+                self.cursor.col = 0;
+            }
+            ([], 'G') | ([], '`') => self.goto(self.cursor.row, count - 1),
+            ([], 'H') | ([], 'f') => {
+                let row = params.first_or(0, 1) as usize;
+                let col = params.first_or(1, 1) as usize;
+                self.goto(row - 1, col - 1);
+            }
+            ([], 'I') => self.next_tab_stop(count),
+            ([], 'J') => self.erase_in_display(params.first_or(0, 0)),
+            ([], 'K') => self.erase_in_line(params.first_or(0, 0)),
+            ([], 'L') => self.insert_lines(count),
+            ([], 'M') => self.delete_lines(count),
+            ([], 'P') => self.delete_chars(count),
+            ([], 'S') => self.scroll_up(count),
+            ([], 'T') => self.scroll_down(count),
+            // REP, the character goes through print so it wraps, inserts
+            // and scrolls like the original
+            ([], 'b') => {
+                if let Some(c) = self.last_char {
+                    (0..count).for_each(|_| self.print(c));
+                }
+            }
+            ([], 'X') => {
+                let col = self.cursor.col;
+                self.erase_cells(self.cursor.row, col, col + count);
+            }
+            ([], 'd') => self.goto(count - 1, self.cursor.col),
+            ([], 'm') => self.set_attributes(params),
+            ([], 'r') => {
+                let top = params.first_or(0, 1) as usize;
+                let bottom = params.first_or(1, self.rows as u16) as usize;
+                self.set_scroll_region(top - 1, bottom - 1);
+            }
+            ([], 's') => self.save_cursor(),
+            ([], 'u') => self.restore_cursor(),
+            (b">", 'u') => self.push_keyboard_flags(params.first_or(0, 0) as u8),
+            (b"<", 'u') => self.pop_keyboard_flags(count),
+            (b"=", 'u') => self.set_keyboard_flags(params.first_or(0, 0) as u8, params.first_or(1, 1)),
+            (b"?", 'u') => {
+                let reply = format!("\x1b[?{}u", self.keyboard_flags());
+                self.responses.extend(reply.as_bytes());
+            }
+            ([], 'n') => self.device_status_report(params.first_or(0, 0)),
+            ([], 't') => self.window_op(params),
+            // DECRQM, whether a mode is set
+            (b"?$", 'p') => {
+                let mode = params.first_or(0, 0);
+                let reply = format!("\x1b[?{};{}$y", mode, mode_status(self.private_mode(mode)));
+                self.responses.extend(reply.as_bytes());
+            }
+            (b"$", 'p') => {
+                let mode = params.first_or(0, 0);
+                let reply = format!("\x1b[{};{}$y", mode, mode_status(self.ansi_mode(mode)));
+                self.responses.extend(reply.as_bytes());
+            }
+            // DA1: a VT220 with ANSI color
+            ([], 'c') if params.first_or(0, 0) == 0 => self.responses.extend(b"\x1b[?62;22c"),
+            // DA2: the terminal type, a VT220 again, and the version
+            (b">", 'c') if params.first_or(0, 0) == 0 => {
+                let reply = format!("\x1b[>1;{};0c", version_number());
+                self.responses.extend(reply.as_bytes());
+            }
+            _ => self.unhandled.note(format!("Unhandled CSI: {:?} {:?} {}", params, intermediates, action)),
+        }
+    }
+
+    fn osc_dispatch(&mut self, params: &[&[u8]]) {
+        match params {
+            // The URI may itself contain semicolons
+            [b"8", link_params, uri @ ..] if !uri.is_empty() => {
+                self.set_hyperlink(link_params, &uri.join(&b';'));
+            }
+            // The title may itself contain semicolons. The icon name isn't
+            // shown anywhere.
+            [b"0" | b"2", title @ ..] => {
+                if self.permitted(Feature::Title) {
+                    self.title = Some(String::from_utf8_lossy(&title.join(&b';')).into_owned());
+                }
+            }
+            // Reading the clipboard back isn't supported, anything running
+            // in the window could read what was copied elsewhere
+            [b"52", _, data] if *data != b"?" => {
+                if self.permitted(Feature::ClipboardWrite) {
+                    self.clipboard_write(data);
+                }
+            }
+            // The body may itself contain semicolons. OSC 9;4 is ConEmu's
+            // progress report rather than a notification.
+            [b"9", body @ ..] if body.first() != Some(&&b"4"[..]) => {
+                if self.permitted(Feature::Notifications) {
+                    let body = String::from_utf8_lossy(&body.join(&b';')).into_owned();
+                    self.notifications.push(Notification { title: None, body });
+                }
+            }
+            [b"777", b"notify", title, body @ ..] => {
+                if self.permitted(Feature::Notifications) {
+                    self.notifications.push(Notification {
+                        title: Some(String::from_utf8_lossy(title).into_owned()),
+                        body: String::from_utf8_lossy(&body.join(&b';')).into_owned(),
+                    });
+                }
+            }
+            [b"1", ..] => {}
+            [b"4", pairs @ ..] => self.palette_colors(pairs),
+            [b"104", indices @ ..] => self.reset_palette_colors(indices),
+            // Each further color sets the next one along
+            [code @ (b"10" | b"11" | b"12"), specs @ ..] => {
+                let first = 10 + code[1] - b'0';
+                for (code, spec) in (first..=12).zip(specs) {
+                    self.dynamic_color(code, spec);
+                }
+            }
+            [b"110"] => self.palette.foreground = self.theme.foreground,
+            [b"111"] => self.palette.background = self.theme.background,
+            [b"112"] => self.palette.cursor = self.theme.cursor,
+            [b"7", uri @ ..] => {
+                self.working_directory = parse_file_uri(&uri.join(&b';'));
+            }
+            [b"133", params @ ..] => {
+                let point = Point { line: self.scrollback.len() + self.cursor.row, col: self.cursor.col };
+                self.shell.mark(params, point);
+            }
+            _ => self.unhandled.note(format!(
+                "Unhandled OSC: {:?}",
+                params.iter().map(|p| String::from_utf8_lossy(p)).collect::<Vec<_>>()
+            )),
+        }
+    }
+
+    fn dcs_dispatch(&mut self, _params: &Params, intermediates: &[u8], action: char, data: &[u8]) {
+        match (intermediates, action) {
+            (b"+", 'q') => self.request_termcap(data),
+            _ => self.unhandled.note(format!(
+                "Unhandled DCS: {:?} {} {:?}",
+                intermediates,
+                action,
+                String::from_utf8_lossy(data)
+            )),
+        }
+    }
+
+    fn apc_dispatch(&mut self, data: &[u8]) {
+        match data.strip_prefix(b"G") {
+            Some(data) => self.kitty_graphics(data),
+            None => self.unhandled.note(format!("Unhandled APC: {:?}", String::from_utf8_lossy(data))),
+        }
+    }
+
+    fn esc_dispatch(&mut self, intermediates: &[u8], byte: u8) {
+        match (intermediates, byte) {
+            ([], b'7') => self.save_cursor(),
+            ([], b'8') => self.restore_cursor(),
+            ([], b'D') => self.linefeed(),
+            ([], b'E') => {
+                self.linefeed();
+                self.cursor.col = 0;
+            }
+            ([], b'M') => self.reverse_index(),
+            ([], b'c') => self.reset(),
+            ([], b'=') => self.modes.app_keypad = true,
+            ([], b'>') => self.modes.app_keypad = false,
+            ([slot @ (b'(' | b')')], charset) => {
+                let index = (*slot - b'(') as usize;
+                self.cursor.charsets[index] = match charset {
+                    b'0' => Charset::DecSpecialGraphics,
+                    // Everything else, UK and the national sets included,
+                    // is treated as ASCII
+                    _ => Charset::Ascii,
+                };
+            }
+            _ => self.unhandled.note(format!("Unhandled ESC: {:?} {}", intermediates, byte as char)),
+        }
+    }
+}
+
+// Roughly what a row takes on the heap: its cells and what they box
+fn row_bytes(row: &Row) -> usize {
+    let extras: usize = row
+        .iter()
+        .filter_map(|cell| cell.extra.as_ref())
+        .map(|extra| std::mem::size_of::<CellExtra>() + extra.zerowidth.capacity() * std::mem::size_of::<char>())
+        .sum();
+    row.capacity() * std::mem::size_of::<Cell>() + extras
+}
+
+// The text of a row, with the blanks at its end trimmed
+fn row_text(row: &Row) -> String {
+    let mut text = String::new();
+    for cell in row.iter().filter(|cell| !cell.flags.contains(Flags::WIDE_CHAR_SPACER)) {
+        text.push(cell.c);
+        text.extend(cell.zerowidth());
+    }
+    text.truncate(text.trim_end().len());
+    text
+}
+
+fn hex_decode(hex: &[u8]) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    hex.chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+fn parse_color(spec: &[u8]) -> Option<Rgb> {
+    let rgb = std::str::from_utf8(spec).ok().and_then(color::parse_spec);
+    if rgb.is_none() {
+        debug!("Invalid OSC color: {:?}", String::from_utf8_lossy(spec));
+    }
+    rgb
+}
+
+/// The DECRPM code for a mode: 1 set, 2 reset, 0 not recognized
+fn mode_status(mode: Option<bool>) -> u8 {
+    match mode {
+        Some(true) => 1,
+        Some(false) => 2,
+        None => 0,
+    }
+}
+
+/// The crate version as one number, 0.1.2 becoming 102 like xterm's patch
+/// numbers, for DA2
+fn version_number() -> u32 {
+    env!("CARGO_PKG_VERSION")
+        .split('.')
+        .take(3)
+        .fold(0, |number, part| number * 100 + part.parse::<u32>().unwrap_or(0))
+}
+
+/// The path of a `file://host/path` URI as sent with OSC 7. The host isn't
+/// checked, so a directory reported from a remote shell is taken to be
+/// local.
+fn parse_file_uri(uri: &[u8]) -> Option<PathBuf> {
+    let rest = uri.strip_prefix(b"file://").or_else(|| uri.strip_prefix(b"kitty-shell-cwd://"))?;
+    let path = &rest[rest.iter().position(|&b| b == b'/')?..];
+
+    // Percent encoded bytes are decoded, anything malformed is kept as is
+    let mut decoded = Vec::with_capacity(path.len());
+    let mut i = 0;
+    while i < path.len() {
+        let hex = path.get(i + 1..i + 3).and_then(|hex| std::str::from_utf8(hex).ok());
+        match (path[i], hex.and_then(|hex| u8::from_str_radix(hex, 16).ok())) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    Some(PathBuf::from(std::ffi::OsString::from_vec(decoded)))
+}
@@ -0,0 +1,151 @@
+// rush's own terminfo entry, so ncurses applications know exactly what it
+// supports instead of guessing from xterm's
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+const SOURCE: &str = include_str!("../terminfo/rush.ti");
+
+/// Whether ncurses would find a terminfo entry called `name`
+pub fn is_installed(name: &str) -> bool {
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    if let Some(dir) = std::env::var_os("TERMINFO") {
+        dirs.push(dir.into());
+    }
+    if let Some(home) = std::env::var_os("HOME") {
+        dirs.push(Path::new(&home).join(".terminfo"));
+    }
+    if let Some(list) = std::env::var_os("TERMINFO_DIRS") {
+        dirs.extend(std::env::split_paths(&list));
+    }
+    dirs.extend(["/etc/terminfo", "/lib/terminfo", "/usr/share/terminfo"].map(PathBuf::from));
+
+    let first = name.chars().next().unwrap_or('_');
+    dirs.iter().any(|dir| {
+        // Entries live under their first letter, or its hex code on
+        // case-insensitive filesystems such as macOS
+        dir.join(first.to_string()).join(name).exists()
+            || dir.join(format!("{:x}", first as u32)).join(name).exists()
+    })
+}
+
+/// Compile the bundled entry into `~/.terminfo`, unless the one there was
+/// already compiled from it. A stamp next to it records which source that
+/// was, so a newer rush replaces what an older one installed. Needs `tic`
+/// from ncurses, if it can't be installed the child gets a fallback TERM
+/// instead.
+pub fn install() {
+    let Some(home) = std::env::var_os("HOME") else {
+        return;
+    };
+    let dir = Path::new(&home).join(".terminfo");
+    let stamp = dir.join(".rush-source");
+    let hash = format!("{:016x}", source_hash());
+    if std::fs::read_to_string(&stamp).is_ok_and(|installed| installed.trim() == hash) {
+        return;
+    }
+
+    // The source goes in on stdin rather than through a file others could
+    // swap out first
+    let child = Command::new("tic").arg("-x").arg("-o").arg(&dir).arg("-").stdin(Stdio::piped()).spawn();
+    let status = child.and_then(|mut child| {
+        let written = child.stdin.take().map_or(Ok(()), |mut stdin| stdin.write_all(SOURCE.as_bytes()));
+        let status = child.wait()?;
+        written.map(|_| status)
+    });
+
+    match status {
+        Ok(status) if status.success() => {
+            if let Err(e) = std::fs::write(&stamp, hash) {
+                tracing::warn!("Could not record the installed terminfo entry: {}", e);
+            }
+        }
+        Ok(status) => tracing::warn!("tic failed to install the terminfo entry: {}", status),
+        Err(e) => tracing::warn!("Could not run tic to install the terminfo entry: {}", e),
+    }
+}
+
+// FNV-1a of the bundled source, the same from one build to the next unlike
+// std's hasher
+fn source_hash() -> u64 {
+    SOURCE.bytes().fold(0xcbf29ce484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+/// A capability of the bundled entry
+#[derive(Debug)]
+pub enum Capability {
+    Boolean,
+    /// A number or a string, with the string's escapes already expanded
+    Value(String),
+}
+
+/// Look up a capability by its terminfo name, as XTGETTCAP asks for them.
+/// `TN` and `RGB` aren't in the entry: the first names the terminal, the
+/// second tells tmux and neovim direct color works, which `Tc` says too
+/// but only some programs read.
+pub fn capability(name: &str) -> Option<Capability> {
+    match name {
+        "TN" | "name" => return Some(Capability::Value("rush".to_string())),
+        "RGB" => return Some(Capability::Boolean),
+        // The termcap name some programs still ask for
+        "Co" => return capability("colors"),
+        _ => {}
+    }
+    fields().find_map(|field| {
+        if let Some(value) = field.strip_prefix(name).and_then(|rest| rest.strip_prefix('=')) {
+            Some(Capability::Value(unescape(value)))
+        } else if let Some(value) = field.strip_prefix(name).and_then(|rest| rest.strip_prefix('#')) {
+            Some(Capability::Value(value.to_string()))
+        } else {
+            (field == name).then_some(Capability::Boolean)
+        }
+    })
+}
+
+// The capabilities of the entry, the names line and comments skipped
+fn fields() -> impl Iterator<Item = String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut escaped = false;
+    let body = SOURCE.lines().filter(|line| !line.starts_with('#')).skip(1);
+    for line in body {
+        for c in line.trim().chars() {
+            if c == ',' && !escaped {
+                fields.push(std::mem::take(&mut field));
+                continue;
+            }
+            escaped = c == '\\' && !escaped;
+            field.push(c);
+        }
+    }
+    fields.into_iter().map(|field| field.trim().to_string()).filter(|field| !field.is_empty())
+}
+
+// Expand the escapes of a terminfo string into the bytes it stands for
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('E' | 'e') => unescaped.push('\x1b'),
+                Some('n' | 'l') => unescaped.push('\n'),
+                Some('r') => unescaped.push('\r'),
+                Some('t') => unescaped.push('\t'),
+                Some('b') => unescaped.push('\x08'),
+                Some('f') => unescaped.push('\x0c'),
+                Some('s') => unescaped.push(' '),
+                Some(c) => unescaped.push(c),
+                None => unescaped.push('\\'),
+            },
+            '^' => match chars.next() {
+                Some('?') => unescaped.push('\x7f'),
+                Some(c) => unescaped.push(((c as u8) & 0x1f) as char),
+                None => unescaped.push('^'),
+            },
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
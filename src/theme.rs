@@ -0,0 +1,176 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+
+use rush::color::Palette;
+
+use crate::error::RushError;
+
+/// The color themes in the config, a `theme.NAME` section each with the
+/// `foreground`, `background` and `cursor` colors and `color0` to
+/// `color255`. What a theme leaves out is xterm's.
+pub struct Themes {
+    palettes: BTreeMap<String, Palette>,
+    // `theme`, the one windows open with
+    pub dark: Option<String>,
+    // `light_theme`, set to follow the desktop from `dark` to it when the
+    // desktop prefers light colors
+    pub light: Option<String>,
+}
+
+impl Themes {
+    pub fn from_config(config: &HashMap<String, String>) -> Result<Themes, RushError> {
+        let mut palettes = BTreeMap::new();
+        for key in config.keys() {
+            let Some((name, _)) = key.strip_prefix("theme.").and_then(|rest| rest.split_once('.')) else {
+                continue;
+            };
+            if !palettes.contains_key(name) {
+                palettes.insert(name.to_string(), palette(config, name)?);
+            }
+        }
+        let themes = Themes { palettes, dark: config.get("theme").cloned(), light: config.get("light_theme").cloned() };
+        for (key, name) in [("theme", &themes.dark), ("light_theme", &themes.light)] {
+            if let Some(name) = name.as_ref().filter(|name| themes.get(name).is_none()) {
+                return Err(RushError::InvalidConfigValue {
+                    key: key.to_string(),
+                    value: name.clone(),
+                    reason: format!("there's no theme.{} section", name),
+                });
+            }
+        }
+        Ok(themes)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Palette> {
+        self.palettes.get(name)
+    }
+
+    /// The theme after `current` by name, the first after the last
+    pub fn next(&self, current: Option<&str>) -> Option<&str> {
+        let mut names = self.palettes.keys().map(String::as_str);
+        let after = current.and_then(|current| names.clone().find(|&name| name > current));
+        after.or_else(|| names.next())
+    }
+
+    /// The theme to use for the desktop's color scheme, when following it
+    pub fn for_scheme(&self, scheme: ColorScheme) -> Option<&str> {
+        let light = self.light.as_deref()?;
+        match scheme {
+            ColorScheme::Light => Some(light),
+            ColorScheme::Dark | ColorScheme::NoPreference => self.dark.as_deref(),
+        }
+    }
+}
+
+// The colors of `theme.NAME`, over xterm's
+fn palette(config: &HashMap<String, String>, name: &str) -> Result<Palette, RushError> {
+    let mut palette = Palette::default();
+    let color = |key: &str| crate::config_color(config, &format!("theme.{}.{}", name, key));
+    if let Some(rgb) = color("foreground")? {
+        palette.foreground = rgb;
+    }
+    if let Some(rgb) = color("background")? {
+        palette.background = rgb;
+    }
+    palette.cursor = color("cursor")?;
+    for index in 0..=255u8 {
+        if let Some(rgb) = color(&format!("color{}", index))? {
+            palette.set_indexed(index, rgb);
+        }
+    }
+    Ok(palette)
+}
+
+/// Whether the desktop prefers dark or light colors
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColorScheme {
+    NoPreference,
+    Dark,
+    Light,
+}
+
+impl ColorScheme {
+    // The `color-scheme` setting of the XDG settings portal
+    fn from_portal(value: u32) -> ColorScheme {
+        match value {
+            1 => ColorScheme::Dark,
+            2 => ColorScheme::Light,
+            _ => ColorScheme::NoPreference,
+        }
+    }
+}
+
+// Where the desktop's settings are read, by `gdbus` so there's no D-Bus
+// library to build rush with
+const PORTAL_ARGS: [&str; 6] = [
+    "--session",
+    "--dest",
+    "org.freedesktop.portal.Desktop",
+    "--object-path",
+    "/org/freedesktop/portal/desktop",
+    "--method",
+];
+
+/// Follows the desktop's color scheme through the XDG settings portal:
+/// its scheme when it starts, then every change to it
+pub struct ColorSchemeWatcher {
+    changes: Receiver<ColorScheme>,
+    monitor: Child,
+}
+
+impl ColorSchemeWatcher {
+    pub fn spawn() -> io::Result<ColorSchemeWatcher> {
+        // Watching starts before the scheme is read, so no change is missed
+        let mut monitor = Command::new("gdbus")
+            .args(["monitor", "--session", "--dest", "org.freedesktop.portal.Desktop"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdout = monitor.stdout.take().expect("The monitor's output is piped");
+        let (sender, changes) = mpsc::channel();
+        std::thread::spawn(move || {
+            let read = Command::new("gdbus")
+                .arg("call")
+                .args(PORTAL_ARGS)
+                .args(["org.freedesktop.portal.Settings.Read", "org.freedesktop.appearance", "color-scheme"])
+                .stderr(Stdio::null())
+                .output();
+            match read.ok().and_then(|output| last_uint32(&String::from_utf8_lossy(&output.stdout))) {
+                Some(value) => {
+                    let _ = sender.send(ColorScheme::from_portal(value));
+                }
+                None => tracing::info!("The desktop doesn't say which color scheme it prefers"),
+            }
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                let changed = line.contains("SettingChanged") && line.contains("'color-scheme'");
+                if let Some(value) = last_uint32(&line).filter(|_| changed) {
+                    if sender.send(ColorScheme::from_portal(value)).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        Ok(ColorSchemeWatcher { changes, monitor })
+    }
+
+    /// The scheme the desktop changed to since the last call, if it did
+    pub fn changed(&self) -> Option<ColorScheme> {
+        self.changes.try_iter().last()
+    }
+}
+
+impl Drop for ColorSchemeWatcher {
+    fn drop(&mut self) {
+        let _ = self.monitor.kill();
+        let _ = self.monitor.wait();
+    }
+}
+
+// The value of the last `uint32 N` in what gdbus printed
+fn last_uint32(text: &str) -> Option<u32> {
+    let (_, rest) = text.rsplit_once("uint32 ")?;
+    let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+    rest[..digits].parse().ok()
+}
@@ -0,0 +1,612 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Instant;
+
+use freetype::freetype as ft;
+use tracing::info;
+
+use crate::color;
+use crate::error::RushError;
+use crate::glyph_store::GlyphStore;
+use crate::render::{self, Cursor, Frame};
+use crate::term::{self, Terminal};
+use crate::{box_drawing, Character, CharacterDimensions, GlyphBatch, RenderSettings, WindowState};
+use crate::{GLYPH_VERTEX_SIZE, RECT_VERTEX_SIZE};
+
+// Glyphs are packed into one texture this many pixels wide and high. When
+// it fills up it starts over, and glyphs are rasterized again as needed.
+const ATLAS_SIZE: u32 = 2048;
+// Between glyphs in the atlas, so sampling one doesn't bleed into the next
+const ATLAS_PADDING: u32 = 1;
+
+#[derive(Clone, Copy)]
+struct AtlasGlyph {
+    character: Character,
+    // Top left and bottom right corners in the atlas
+    uv: [f32; 4],
+}
+
+/// Rendered glyphs packed into one texture, left to right on shelves as
+/// tall as the tallest glyph on them
+struct Atlas {
+    texture: wgpu::Texture,
+    // Where the next glyph goes, and the height of its shelf
+    x: u32,
+    y: u32,
+    shelf_height: u32,
+    // A glyph didn't fit, the atlas starts over with the next frame
+    full: bool,
+}
+
+impl Atlas {
+    /// Copy the glyph FreeType just rendered into the atlas, `None` when
+    /// there's no room left for it
+    unsafe fn insert(&mut self, queue: &wgpu::Queue, glyph: &ft::FT_GlyphSlotRec) -> Option<AtlasGlyph> {
+        let bitmap = &glyph.bitmap;
+        let (width, height) = (bitmap.width, bitmap.rows);
+        if self.x + width > ATLAS_SIZE {
+            self.x = 0;
+            self.y += self.shelf_height + ATLAS_PADDING;
+            self.shelf_height = 0;
+        }
+        if self.x + width > ATLAS_SIZE || self.y + height > ATLAS_SIZE {
+            self.full = true;
+            return None;
+        }
+
+        if width > 0 && height > 0 {
+            // Rows of FreeType bitmaps can be padded
+            let mut pixels = Vec::with_capacity((width * height) as usize);
+            for row in 0..height as isize {
+                let start = bitmap.buffer.offset(row * bitmap.pitch as isize);
+                pixels.extend_from_slice(std::slice::from_raw_parts(start, width as usize));
+            }
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &self.texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d { x: self.x, y: self.y, z: 0 },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                &pixels,
+                wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(width), rows_per_image: None },
+                wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+            );
+        }
+
+        let size = ATLAS_SIZE as f32;
+        let uv = [
+            self.x as f32 / size,
+            self.y as f32 / size,
+            (self.x + width) as f32 / size,
+            (self.y + height) as f32 / size,
+        ];
+        self.x += width + ATLAS_PADDING;
+        self.shelf_height = self.shelf_height.max(height);
+        let character = Character {
+            texture_id: 0,
+            size: (width as i32, height as i32),
+            bearing: (glyph.bitmap_left, glyph.bitmap_top),
+            advance: glyph.advance.x,
+        };
+        Some(AtlasGlyph { character, uv })
+    }
+
+    fn clear(&mut self) {
+        self.x = 0;
+        self.y = 0;
+        self.shelf_height = 0;
+        self.full = false;
+    }
+}
+
+/// Draws with wgpu, on Vulkan, Metal or DX12, for platforms where an
+/// OpenGL context is hard to come by. It draws the cells, the cursor and
+/// the dimming of an unfocused window. Ligatures, images, and the search,
+/// hint and paste overlays are only drawn with OpenGL so far.
+///
+/// A frame is collected in layers, each drawn as its rectangles and then
+/// its glyphs, and handed to the GPU all at once when it's presented.
+pub struct WgpuRenderer {
+    surface: wgpu::Surface<'static>,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    config: wgpu::SurfaceConfiguration,
+    rect_pipeline: wgpu::RenderPipeline,
+    glyph_pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+    uniforms: wgpu::Buffer,
+    // Grows to what a frame needs and stays that size
+    vertex_buffer: wgpu::Buffer,
+    atlas: Atlas,
+    // `None` for characters the font doesn't have or that didn't fit
+    glyphs: GlyphStore<Option<AtlasGlyph>>,
+    face: ft::FT_Face,
+    settings: RenderSettings,
+    // The part of the framebuffer the grid is drawn in: x and y of its
+    // top left corner, width and height
+    grid_rect: (u32, u32, u32, u32),
+    // Pixels the grid is moved up by while scrolling smoothly
+    pub shift: i32,
+    // The frame being built, cleared to `clear`
+    clear: wgpu::Color,
+    rects: Vec<f32>,
+    batch: GlyphBatch,
+    // Where each layer's rectangle vertices and glyph quads start
+    layers: Vec<(usize, usize)>,
+    shapes: Vec<box_drawing::Shape>,
+    vertices: Vec<f32>,
+    // For the performance overlay
+    pub draw_calls: u32,
+    pub drawn_at: Instant,
+    // The surface draws into the window, which has to outlive it
+    _window: Rc<RefCell<glfw::PWindow>>,
+}
+
+impl WgpuRenderer {
+    /// Set up drawing into `window`, which was made without an OpenGL
+    /// context, and rasterize the font at `font_size_px`
+    pub fn new(
+        window: Rc<RefCell<glfw::PWindow>>,
+        face: ft::FT_Face,
+        font_size_px: f32,
+        settings: RenderSettings,
+    ) -> Result<(WgpuRenderer, CharacterDimensions), RushError> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor { backends: wgpu::Backends::PRIMARY, ..Default::default() });
+        // The window is kept alive for as long as the surface
+        let surface = unsafe {
+            let target = wgpu::SurfaceTargetUnsafe::from_window(&*window.borrow())
+                .map_err(|e| RushError::Wgpu(e.to_string()))?;
+            instance.create_surface_unsafe(target)
+        }
+        .map_err(|e| RushError::Wgpu(e.to_string()))?;
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            compatible_surface: Some(&surface),
+            ..Default::default()
+        }))
+        .ok_or_else(|| RushError::Wgpu("no GPU can draw into the window".to_string()))?;
+        let adapter_info = adapter.get_info();
+        info!("Drawing with {} through {:?}", adapter_info.name, adapter_info.backend);
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("rush"),
+                required_limits: wgpu::Limits::downlevel_defaults().using_resolution(adapter.limits()),
+                ..Default::default()
+            },
+            None,
+        ))
+        .map_err(|e| RushError::Wgpu(e.to_string()))?;
+
+        let capabilities = surface.get_capabilities(&adapter);
+        // Colors are blended as they are, like they are with OpenGL
+        let format = capabilities
+            .formats
+            .iter()
+            .copied()
+            .find(|format| !format.is_srgb())
+            .or(capabilities.formats.first().copied())
+            .ok_or_else(|| RushError::Wgpu("the GPU can't draw into the window".to_string()))?;
+        let present_mode = match settings.vsync {
+            true => wgpu::PresentMode::Fifo,
+            false => [wgpu::PresentMode::Immediate, wgpu::PresentMode::Mailbox]
+                .into_iter()
+                .find(|mode| capabilities.present_modes.contains(mode))
+                .unwrap_or(wgpu::PresentMode::Fifo),
+        };
+        // Premultiplied like the clear color, for a see through background
+        let alpha_mode = match capabilities.alpha_modes.contains(&wgpu::CompositeAlphaMode::PreMultiplied) {
+            true if settings.background_opacity < 1.0 => wgpu::CompositeAlphaMode::PreMultiplied,
+            _ => capabilities.alpha_modes[0],
+        };
+        let (width, height) = window.borrow().get_framebuffer_size();
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format,
+            width: width.max(1) as u32,
+            height: height.max(1) as u32,
+            present_mode,
+            desired_maximum_frame_latency: 2,
+            alpha_mode,
+            view_formats: Vec::new(),
+        };
+        surface.configure(&device, &config);
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("glyph atlas"),
+            size: wgpu::Extent3d { width: ATLAS_SIZE, height: ATLAS_SIZE, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        // The offset of the grid and the text gamma, padded to 16 bytes
+        let uniforms = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("uniforms"),
+            size: 16,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&view) },
+                wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::Sampler(&sampler) },
+                wgpu::BindGroupEntry { binding: 2, resource: uniforms.as_entire_binding() },
+            ],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("wgpu_shader.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../wgpu_shader.wgsl").into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |vertex: &str, fragment: &str, buffer: wgpu::VertexBufferLayout| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(vertex),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: vertex,
+                    compilation_options: Default::default(),
+                    buffers: &[buffer],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: fragment,
+                    compilation_options: Default::default(),
+                    // The alpha channel adds up rather than being blended
+                    // like the colors, as with OpenGL
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(wgpu::BlendState {
+                            color: wgpu::BlendComponent {
+                                src_factor: wgpu::BlendFactor::SrcAlpha,
+                                dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                                operation: wgpu::BlendOperation::Add,
+                            },
+                            alpha: wgpu::BlendComponent::OVER,
+                        }),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+                cache: None,
+            })
+        };
+        let rect_pipeline = pipeline(
+            "vs_rect",
+            "fs_rect",
+            wgpu::VertexBufferLayout {
+                array_stride: (RECT_VERTEX_SIZE * 4) as u64,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x4],
+            },
+        );
+        let glyph_pipeline = pipeline(
+            "vs_glyph",
+            "fs_glyph",
+            wgpu::VertexBufferLayout {
+                array_stride: (GLYPH_VERTEX_SIZE * 4) as u64,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Float32x4],
+            },
+        );
+        let vertex_buffer = vertex_buffer(&device, 1 << 16);
+
+        let mut renderer = WgpuRenderer {
+            surface,
+            device,
+            queue,
+            config,
+            rect_pipeline,
+            glyph_pipeline,
+            bind_group,
+            uniforms,
+            vertex_buffer,
+            atlas: Atlas { texture, x: 0, y: 0, shelf_height: 0, full: false },
+            glyphs: GlyphStore::new(),
+            face,
+            settings,
+            grid_rect: (0, 0, width.max(1) as u32, height.max(1) as u32),
+            shift: 0,
+            clear: wgpu::Color::BLACK,
+            rects: Vec::new(),
+            batch: GlyphBatch::default(),
+            layers: Vec::new(),
+            shapes: Vec::new(),
+            vertices: Vec::new(),
+            draw_calls: 0,
+            drawn_at: Instant::now(),
+            _window: window,
+        };
+        let char_dim = renderer.load_font(font_size_px)?;
+        Ok((renderer, char_dim))
+    }
+
+    /// Rasterize the font again at `font_size_px`, and return the size of
+    /// a cell
+    pub fn load_font(&mut self, font_size_px: f32) -> Result<CharacterDimensions, RushError> {
+        self.atlas.clear();
+        // What was in the atlas is gone, even if the new glyphs don't make it
+        self.glyphs = GlyphStore::new();
+        let (atlas, queue) = (&mut self.atlas, &self.queue);
+        let options = self.settings.glyph_options;
+        let (glyphs, max_width, max_height) =
+            crate::load_font_chars(self.face, font_size_px, options, |glyph| unsafe { atlas.insert(queue, glyph) })?;
+        self.glyphs = glyphs;
+        Ok(CharacterDimensions { width: max_width as u32, height: max_height as u32 })
+    }
+
+    /// Glyphs cached in the atlas
+    pub fn cached_glyphs(&self) -> usize {
+        self.glyphs.len()
+    }
+
+    /// Follow the window to its new size
+    pub fn resize(&mut self, ws: &WindowState) {
+        let (width, height) = (ws.width as u32, ws.height as u32);
+        // Minimized
+        if width == 0 || height == 0 {
+            return;
+        }
+        if (width, height) != (self.config.width, self.config.height) {
+            self.config.width = width;
+            self.config.height = height;
+            self.surface.configure(&self.device, &self.config);
+        }
+        // From the bottom left corner, as OpenGL has it
+        let (x, y, grid_width, grid_height) = ws.grid_viewport();
+        let x = (x.max(0) as u32).min(width - 1);
+        let top = (height as i32 - y - grid_height).max(0) as u32;
+        let top = top.min(height - 1);
+        self.grid_rect = (x, top, (grid_width.max(1) as u32).min(width - x), (grid_height.max(1) as u32).min(height - top));
+    }
+
+    /// Wait for the GPU to be done with every frame handed to it
+    pub fn finish(&self) {
+        self.device.poll(wgpu::Maintain::Wait);
+    }
+
+    fn viewport(&self) -> (f32, f32) {
+        (self.grid_rect.2 as f32, self.grid_rect.3 as f32)
+    }
+
+    // What is drawn from here on goes over everything before
+    fn begin_layer(&mut self) {
+        self.layers.push((self.rects.len(), self.batch.quads.len()));
+    }
+
+    /// The glyph of `c` from the atlas, rasterized the first time it's
+    /// drawn
+    fn glyph(&mut self, c: char) -> Option<AtlasGlyph> {
+        if let Some(&glyph) = self.glyphs.get(c) {
+            return glyph;
+        }
+        let glyph = unsafe {
+            let options = self.settings.glyph_options;
+            let found = ft::FT_Get_Char_Index(self.face, c as ft::FT_ULong) != 0;
+            let loaded = found
+                && ft::FT_Load_Char(self.face, c as ft::FT_ULong, options.load_flags()) == 0
+                && ft::FT_Render_Glyph((*self.face).glyph, options.render_mode()) == 0;
+            if loaded {
+                self.atlas.insert(&self.queue, &*(*self.face).glyph)
+            } else {
+                None
+            }
+        };
+        self.glyphs.insert(c, glyph);
+        glyph
+    }
+
+    /// The text of the cell at `(row, col)` in `fg`: its glyphs, box
+    /// drawing and decorations
+    fn push_cell_text(&mut self, term: &Terminal, cell: &term::Cell, (row, col): (usize, usize), fg: [f32; 4]) {
+        let viewport = self.viewport();
+        if !cell.flags.contains(term::Flags::WIDE_CHAR_SPACER) {
+            let columns = if cell.flags.contains(term::Flags::WIDE_CHAR) { 2 } else { 1 };
+            let base = (cell.c != ' ' && !box_drawing::is_box_drawing(cell.c)).then_some(cell.c);
+            let base = base.map(|c| self.glyph(c).or_else(|| self.glyph('?')));
+            // Combining marks are drawn over the base character in the same cell
+            let marks = cell.zerowidth().iter().map(|&mark| self.glyph(mark));
+            for glyph in base.into_iter().chain(marks.collect::<Vec<_>>()).flatten() {
+                let quad = crate::glyph_quad((row, col), columns, &glyph.character, viewport.0, viewport.1, term.rows, term.cols);
+                self.batch.push(0, quad, glyph.uv, fg);
+            }
+        }
+        if box_drawing::is_box_drawing(cell.c) {
+            crate::push_box_drawing(&mut self.rects, &mut self.shapes, cell.c, (row, col), term, viewport, fg);
+        }
+        crate::push_decorations(&mut self.rects, term, cell, cell.flags, (row, col), fg);
+    }
+
+    fn upload(&mut self) {
+        self.vertices.clear();
+        self.vertices.extend_from_slice(&self.rects);
+        for (_, quad) in &self.batch.quads {
+            self.vertices.extend_from_slice(quad);
+        }
+        let bytes = as_bytes(&self.vertices);
+        if bytes.len() as u64 > self.vertex_buffer.size() {
+            self.vertex_buffer = vertex_buffer(&self.device, (bytes.len() as u64).next_power_of_two());
+        }
+        if !bytes.is_empty() {
+            self.queue.write_buffer(&self.vertex_buffer, 0, bytes);
+        }
+
+        let offset_y = 2.0 * self.shift as f32 / self.grid_rect.3 as f32;
+        self.queue.write_buffer(&self.uniforms, 0, as_bytes(&[0.0, offset_y, self.settings.text_gamma, 0.0]));
+    }
+}
+
+impl render::Renderer for WgpuRenderer {
+    fn begin_frame(&mut self, term: &Terminal) {
+        if self.atlas.full {
+            info!("The glyph atlas is full, starting over");
+            self.atlas.clear();
+            self.glyphs = GlyphStore::new();
+        }
+        // Premultiplied, which is what compositors expect
+        let alpha = self.settings.background_opacity;
+        let [r, g, b, _] = color::to_gl(term.palette.background);
+        self.clear = wgpu::Color { r: (r * alpha) as f64, g: (g * alpha) as f64, b: (b * alpha) as f64, a: alpha as f64 };
+        self.rects.clear();
+        self.batch.clear();
+        self.layers.clear();
+        self.draw_calls = 0;
+    }
+
+    fn draw_cells(&mut self, term: &Terminal, frame: &Frame) {
+        self.begin_layer();
+        crate::background_pass(term, frame, 0..term.rows, &mut self.rects);
+
+        self.begin_layer();
+        for row in 0..term.rows {
+            for (col, cell) in frame.row(term, row).iter().enumerate() {
+                if render::text_visible(cell, frame.blink_visible) {
+                    let fg = color::to_gl(term.palette.cell_colors(cell).0);
+                    self.push_cell_text(term, cell, (row, col), fg);
+                }
+            }
+        }
+    }
+
+    fn draw_cursor(&mut self, term: &Terminal, frame: &Frame, cursor: &Cursor) {
+        self.begin_layer();
+        let style = self.settings.cursor;
+        crate::cursor_pass(self.viewport(), term, frame, cursor, &style, &mut self.rects);
+        if !cursor.hollow && style.opaque() {
+            // The text under a block cursor in a color that stands out on it
+            self.begin_layer();
+            let cell = &frame.row(term, cursor.row)[cursor.col];
+            let fg = color::to_gl(style.colors(&term.palette, cell).1);
+            self.push_cell_text(term, cell, (cursor.row, cursor.col), fg);
+        }
+    }
+
+    fn draw_overlays(&mut self, term: &Terminal, frame: &Frame) {
+        if !frame.focused && self.settings.unfocused_dim > 0.0 {
+            self.begin_layer();
+            crate::dim_pass(term, self.settings.unfocused_dim, &mut self.rects);
+        }
+        if let Some(scrollbar) = &frame.scrollbar {
+            self.begin_layer();
+            crate::scrollbar_pass(self.viewport(), term, scrollbar, &mut self.rects);
+        }
+    }
+
+    fn present(&mut self) {
+        self.upload();
+        self.drawn_at = Instant::now();
+        let output = match self.surface.get_current_texture() {
+            Ok(output) => output,
+            // The window changed size, this frame is dropped
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                self.surface.configure(&self.device, &self.config);
+                return;
+            }
+            Err(e) => {
+                tracing::warn!("Could not draw a frame: {}", e);
+                return;
+            }
+        };
+        let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("frame"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Clear(self.clear), store: wgpu::StoreOp::Store },
+                })],
+                ..Default::default()
+            });
+            let (x, y, width, height) = self.grid_rect;
+            pass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+            // Keep the grid out of the padding while it's moved
+            pass.set_scissor_rect(x, y, width, height);
+            pass.set_bind_group(0, &self.bind_group, &[]);
+
+            let glyphs_offset = (self.rects.len() * 4) as u64;
+            let end = (self.rects.len(), self.batch.quads.len());
+            for (i, &(rects_start, quads_start)) in self.layers.iter().enumerate() {
+                let (rects_end, quads_end) = self.layers.get(i + 1).copied().unwrap_or(end);
+                if rects_end > rects_start {
+                    pass.set_pipeline(&self.rect_pipeline);
+                    pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+                    pass.draw((rects_start / RECT_VERTEX_SIZE) as u32..(rects_end / RECT_VERTEX_SIZE) as u32, 0..1);
+                    self.draw_calls += 1;
+                }
+                if quads_end > quads_start {
+                    pass.set_pipeline(&self.glyph_pipeline);
+                    pass.set_vertex_buffer(0, self.vertex_buffer.slice(glyphs_offset..));
+                    pass.draw(quads_start as u32 * 6..quads_end as u32 * 6, 0..1);
+                    self.draw_calls += 1;
+                }
+            }
+        }
+        self.queue.submit([encoder.finish()]);
+        output.present();
+    }
+}
+
+fn vertex_buffer(device: &wgpu::Device, size: u64) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("vertices"),
+        size,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn as_bytes(floats: &[f32]) -> &[u8] {
+    // Any f32 is four valid bytes
+    unsafe { std::slice::from_raw_parts(floats.as_ptr() as *const u8, std::mem::size_of_val(floats)) }
+}
@@ -0,0 +1,84 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Where a window was on the screen and how big, saved when it closes so
+/// the next one opens the same way. Sizes are in screen coordinates, the
+/// unit window managers place windows in.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct WindowGeometry {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    // The size and position are the ones it goes back to when it's
+    // restored
+    pub maximized: bool,
+}
+
+/// `$XDG_STATE_HOME/rush/window`, or `~/.local/state/rush/window`
+pub fn state_path() -> Option<PathBuf> {
+    let state_home = std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local").join("state")))?;
+    Some(state_home.join("rush").join("window"))
+}
+
+impl WindowGeometry {
+    /// The geometry the last window closed with, if one was saved
+    pub fn load() -> Option<WindowGeometry> {
+        std::fs::read_to_string(state_path()?).ok()?.parse().ok()
+    }
+
+    /// Save over what the last window left, in one go so windows closing
+    /// at the same time don't leave half a file
+    pub fn save(&self) -> io::Result<()> {
+        let path = state_path().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no home directory"))?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let temp = path.with_extension(format!("{}", std::process::id()));
+        std::fs::write(&temp, self.to_string())?;
+        std::fs::rename(&temp, &path)
+    }
+}
+
+// `key: value` lines, like the config
+
+impl fmt::Display for WindowGeometry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "x: {}", self.x)?;
+        writeln!(f, "y: {}", self.y)?;
+        writeln!(f, "width: {}", self.width)?;
+        writeln!(f, "height: {}", self.height)?;
+        writeln!(f, "maximized: {}", self.maximized)
+    }
+}
+
+impl std::str::FromStr for WindowGeometry {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (mut x, mut y, mut width, mut height, mut maximized) = (None, None, None, None, false);
+        for line in s.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim() {
+                "x" => x = value.parse().ok(),
+                "y" => y = value.parse().ok(),
+                "width" => width = value.parse().ok(),
+                "height" => height = value.parse().ok(),
+                "maximized" => maximized = value == "true",
+                // Written by a newer rush
+                _ => {}
+            }
+        }
+        let (width, height) = (width.ok_or(())?, height.ok_or(())?);
+        if width <= 0 || height <= 0 {
+            return Err(());
+        }
+        Ok(WindowGeometry { x: x.ok_or(())?, y: y.ok_or(())?, width, height, maximized })
+    }
+}
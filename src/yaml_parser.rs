@@ -1,21 +1,194 @@
 use std::collections::HashMap;
 use std::fs::File;
-use std::io::{self, BufRead};
-
-pub fn parse_config() -> HashMap<String, String> {
-    let mut config: HashMap<String, String> = HashMap::new();
-    let file = File::open("/home/bobby/code/apps/rush/config.yaml").expect("Unable to read config file: Does not exist");
-    let reader = io::BufReader::new(file);
-    for line in reader.lines() {
-        let line = line.expect("Could not read line");
-        let settings: Vec<&str> = line.split(":").collect();
-        if settings.len() < 2 { continue };
-        println!("{:?}", settings);
-        config.insert(
-            settings[0].trim().to_string(),
-            settings[1].trim().to_string()
-        );
+use std::io::{self, BufRead, Read};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+use crate::error::RushError;
+
+/// The config rush ships with, used when the user doesn't have one
+pub const DEFAULT_CONFIG: &str = include_str!("../config.yaml");
+
+/// `$XDG_CONFIG_HOME/rush/config.yaml`, or `~/.config/rush/config.yaml`
+pub fn default_config_path() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config_home.join("rush").join("config.yaml"))
+}
+
+/// Where a value was set, lines and columns counted from 1
+#[derive(Clone)]
+struct Position {
+    // None for the built in config
+    path: Option<PathBuf>,
+    line: usize,
+    column: usize,
+}
+
+/// The settings of a config file and the files it imports by key, and
+/// where each one was set
+pub struct Config {
+    values: HashMap<String, String>,
+    positions: HashMap<String, Position>,
+}
+
+impl Config {
+    /// Put the location `error` is about in front of it, when it is about a
+    /// value set in a file
+    pub fn locate(&self, error: RushError) -> RushError {
+        let RushError::InvalidConfigValue { key, .. } = &error else {
+            return error;
+        };
+        match self.positions.get(key) {
+            Some(&Position { path: Some(ref path), line, column }) => {
+                RushError::ConfigDiagnostic { path: path.clone(), line, column, source: Box::new(error) }
+            }
+            _ => error,
+        }
+    }
+
+    /// Go back to the built in value of `key`, or to leaving it unset when
+    /// it has that already
+    pub fn reset(&mut self, key: &str) {
+        let default = parse(DEFAULT_CONFIG.as_bytes(), None).expect("Reading from memory can't fail");
+        match default.values.get(key).filter(|&value| self.values.get(key) != Some(value)) {
+            Some(value) => self.values.insert(key.to_string(), value.clone()),
+            None => self.values.remove(key),
+        };
+        self.positions.remove(key);
+    }
+
+    // Take the settings of `other`, over the ones set already
+    fn merge(&mut self, other: Config) {
+        self.values.extend(other.values);
+        self.positions.extend(other.positions);
+    }
+}
+
+impl Deref for Config {
+    type Target = HashMap<String, String>;
+
+    fn deref(&self) -> &HashMap<String, String> {
+        &self.values
+    }
+}
+
+/// Read the config at `path`, or the one at the default location. Falls
+/// back to the built in defaults when no path is given and the user has
+/// no config file.
+pub fn parse_config(path: Option<&Path>) -> Result<Config, RushError> {
+    let path = match path {
+        Some(path) => Some(path.to_path_buf()),
+        None => default_config_path().filter(|path| path.exists()),
+    };
+    let Some(path) = path else {
+        return Ok(parse(DEFAULT_CONFIG.as_bytes(), None).expect("Reading from memory can't fail"));
+    };
+    parse_file(&path, &mut Vec::new())
+}
+
+// Read the file at `path` and the ones it imports. `importing` are the
+// files whose imports are being read, which it can't import again.
+fn parse_file(path: &Path, importing: &mut Vec<PathBuf>) -> Result<Config, RushError> {
+    let read_error = |source| RushError::ConfigRead { path: path.to_path_buf(), source };
+    let file = File::open(path).map_err(read_error)?;
+    let mut own = parse(file, Some(path.to_path_buf())).map_err(read_error)?;
+    let Some(imports) = own.values.remove("import") else {
+        return Ok(own);
+    };
+    own.positions.remove("import");
+
+    // Imports are read in order, each over the ones before it, and the
+    // file's own settings go over them all
+    let mut config = Config { values: HashMap::new(), positions: HashMap::new() };
+    importing.push(path.canonicalize().unwrap_or_else(|_| path.to_path_buf()));
+    for import in imports.split(',').map(str::trim).filter(|import| !import.is_empty()) {
+        let import = match import.strip_prefix("~/").zip(std::env::var_os("HOME")) {
+            Some((rest, home)) => Path::new(&home).join(rest),
+            None => path.parent().unwrap_or(Path::new("")).join(import),
+        };
+        if importing.contains(&import.canonicalize().unwrap_or_else(|_| import.clone())) {
+            tracing::warn!("{} imports itself, through {}", import.display(), path.display());
+            continue;
+        }
+        // The file for a machine that doesn't need one, most often
+        if !import.exists() {
+            tracing::info!("{} imports {}, which doesn't exist", path.display(), import.display());
+            continue;
+        }
+        config.merge(parse_file(&import, importing)?);
+    }
+    importing.pop();
+    config.merge(own);
+    Ok(config)
+}
+
+/// Settings are `key: value` lines. A key with no value starts a section,
+/// the indented lines under it are stored as `section.key`. `${NAME}` in a
+/// value is replaced with the environment variable, `${NAME:-default}`
+/// with `default` when it's unset or empty.
+fn parse(source: impl Read, path: Option<PathBuf>) -> io::Result<Config> {
+    let mut config = Config { values: HashMap::new(), positions: HashMap::new() };
+    let mut section: Option<String> = None;
+    let reader = io::BufReader::new(source);
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim_start().starts_with('#') { continue };
+        // Values may have colons of their own, in a regex or a command
+        let Some((key, value)) = line.split_once(':') else { continue };
+        tracing::trace!("Config line: {:?}", (key, value));
+
+        let key = key.trim();
+        let column = line.len() - value.trim_start().len() + 1;
+        let value = value.trim();
+        let indented = line.starts_with(char::is_whitespace);
+        if !indented {
+            section = if value.is_empty() { Some(key.to_string()) } else { None };
+        }
+        let key = match &section {
+            Some(section) if indented => format!("{}.{}", section, key),
+            _ => key.to_string(),
+        };
+        config.positions.insert(key.clone(), Position { path: path.clone(), line: number + 1, column });
+        config.values.insert(key, interpolate(value));
+    }
+
+    Ok(config)
+}
+
+// `value` with the environment variables in it filled in
+fn interpolate(value: &str) -> String {
+    let mut interpolated = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        interpolated.push_str(&rest[..start]);
+        let reference = &rest[start + 2..start + len];
+        let (name, default) = match reference.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (reference, None),
+        };
+        match (variable(name), default) {
+            (Some(value), _) => interpolated.push_str(&value),
+            (None, Some(default)) => interpolated.push_str(default),
+            (None, None) => tracing::warn!("${{{}}} in the config isn't set", name),
+        }
+        rest = &rest[start + len + 1..];
     }
+    interpolated.push_str(rest);
+    interpolated
+}
 
-    config
+// The environment variable `name` when it's set and not empty. Shells
+// don't export HOSTNAME, the machine's name is looked up instead, for
+// configs to import the file of the machine they're on.
+fn variable(name: &str) -> Option<String> {
+    let value = match std::env::var(name) {
+        Err(_) if name == "HOSTNAME" => nix::unistd::gethostname().ok()?.into_string().ok()?,
+        value => value.ok()?,
+    };
+    (!value.is_empty()).then_some(value)
 }
@@ -0,0 +1,68 @@
+// Recordings: what the recorder writes reads back as the same events, and
+// a cast made by asciinema plays onto the grid as it would in a window.
+
+use rush::asciicast::{Cast, Event, Recorder};
+use rush::term::Terminal;
+
+fn play(cast: &Cast) -> Terminal {
+    let mut term = Terminal::new(cast.rows, cast.cols);
+    for (_, event) in &cast.events {
+        event.apply(&mut term);
+    }
+    term
+}
+
+#[test]
+fn recording_reads_back() {
+    let mut file = Vec::new();
+    let mut recorder = Recorder::new(&mut file, 80, 24).unwrap();
+    recorder.output(b"\x1b[1mbold\x1b[0m \"quoted\" back\\slash\r\n").unwrap();
+    // A character split between two reads is recorded once it's whole
+    let snowman = "\u{2603}".as_bytes();
+    recorder.output(&snowman[..1]).unwrap();
+    recorder.output(&snowman[1..]).unwrap();
+    recorder.resize(100, 30).unwrap();
+    drop(recorder);
+
+    let cast = Cast::parse(&String::from_utf8(file).unwrap()).unwrap();
+    assert_eq!((cast.cols, cast.rows), (80, 24));
+    let events: Vec<_> = cast.events.into_iter().map(|(_, event)| event).collect();
+    assert_eq!(
+        events,
+        [
+            Event::Output("\x1b[1mbold\x1b[0m \"quoted\" back\\slash\r\n".to_string()),
+            Event::Output("\u{2603}".to_string()),
+            Event::Resize { cols: 100, rows: 30 },
+        ]
+    );
+}
+
+#[test]
+fn asciinema_cast_plays() {
+    let cast = Cast::parse(concat!(
+        r#"{"version": 2, "width": 20, "height": 4, "timestamp": 1700000000, "env": {"TERM": "xterm-256color"}}"#,
+        "\n",
+        r#"[0.1, "o", "$ echo hi\r\n"]"#,
+        "\n",
+        r#"[0.2, "i", "ignored"]"#,
+        "\n",
+        r#"[0.3, "o", "hi \u001b[31m\ud83d\ude00\u001b[0m\r\n$ "]"#,
+        "\n",
+        r#"[0.4, "m", ""]"#,
+        "\n",
+    ))
+    .unwrap();
+    assert_eq!(cast.events.len(), 2);
+    let term = play(&cast);
+    let text = term.screen_text();
+    let lines: Vec<_> = text.lines().collect();
+    assert_eq!(lines[..3], ["$ echo hi", "hi \u{1f600}", "$"]);
+}
+
+#[test]
+fn invalid_casts_are_refused() {
+    assert!(Cast::parse("").is_err());
+    assert!(Cast::parse(r#"{"version": 1, "width": 80, "height": 24}"#).is_err());
+    assert!(Cast::parse("{\"version\": 2, \"width\": 80, \"height\": 24}\n[0.1, \"o\"]").is_err());
+    assert!(Cast::parse("{\"version\": 2, \"width\": 80, \"height\": 24}\n[0.1, \"r\", \"80\"]").is_err());
+}
@@ -0,0 +1,24 @@
+// Key chords and the actions they're bound to
+
+use std::collections::HashMap;
+
+use glfw::{Key, Modifiers};
+use rush::bindings::{Action, Bindings};
+
+#[test]
+fn plain_escape_goes_to_the_application() {
+    let bindings = Bindings::from_config(&HashMap::new());
+    assert_eq!(bindings.action(Key::Escape, Modifiers::empty()), None);
+    assert_eq!(bindings.action(Key::Q, Modifiers::Control | Modifiers::Shift), Some(Action::Quit));
+}
+
+#[test]
+fn the_config_overrides_the_defaults() {
+    let config = HashMap::from([
+        ("keybindings.ctrl+shift+q".to_string(), "None".to_string()),
+        ("keybindings.alt+f4".to_string(), "Quit".to_string()),
+    ]);
+    let bindings = Bindings::from_config(&config);
+    assert_eq!(bindings.action(Key::Q, Modifiers::Control | Modifiers::Shift), None);
+    assert_eq!(bindings.action(Key::F4, Modifiers::Alt | Modifiers::NumLock), Some(Action::Quit));
+}
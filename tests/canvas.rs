@@ -0,0 +1,45 @@
+// The CPU rasterizer the software renderer draws with
+
+use rush::canvas::Canvas;
+
+const HALF_RED: [f32; 4] = [1.0, 0.0, 0.0, 0.5];
+
+#[test]
+fn quads_blend_every_pixel_once() {
+    let mut canvas = Canvas::new(8, 8);
+    // A square as two triangles, the diagonal going through pixel centers
+    let (tl, tr, bl, br) = ((2.0, 2.0), (6.0, 2.0), (2.0, 6.0), (6.0, 6.0));
+    canvas.fill_triangle([tl, tr, bl], HALF_RED);
+    canvas.fill_triangle([tr, bl, br], HALF_RED);
+    for y in 0..8 {
+        for x in 0..8 {
+            let expected = if (2..6).contains(&x) && (2..6).contains(&y) { (128, 0, 0) } else { (0, 0, 0) };
+            assert_eq!(canvas.pixel(x, y), expected, "at {}, {}", x, y);
+        }
+    }
+}
+
+#[test]
+fn masks_blend_by_coverage() {
+    let mut canvas = Canvas::new(3, 1);
+    canvas.clear((0, 0, 255));
+    canvas.blend_mask((0, 0), 3, &[0, 128, 255], [1.0, 1.0, 1.0, 1.0]);
+    assert_eq!(canvas.pixel(0, 0), (0, 0, 255));
+    assert_eq!(canvas.pixel(1, 0), (128, 128, 255));
+    assert_eq!(canvas.pixel(2, 0), (255, 255, 255));
+}
+
+#[test]
+fn drawing_stays_inside_the_clip() {
+    let mut canvas = Canvas::new(4, 4);
+    canvas.set_clip((1, 1), 2, 2);
+    canvas.fill_triangle([(-10.0, -10.0), (20.0, -10.0), (-10.0, 20.0)], [0.0, 1.0, 0.0, 1.0]);
+    canvas.blend_mask((-1, 2), 5, &[255; 10], [0.0, 0.0, 1.0, 1.0]);
+    let green: Vec<_> = (0..16).filter(|i| canvas.pixel(i % 4, i / 4) == (0, 255, 0)).collect();
+    let blue: Vec<_> = (0..16).filter(|i| canvas.pixel(i % 4, i / 4) == (0, 0, 255)).collect();
+    assert_eq!(green, [5, 6]);
+    assert_eq!(blue, [9, 10]);
+    // Clearing ignores it
+    canvas.clear((1, 2, 3));
+    assert_eq!(canvas.pixel(0, 0), (1, 2, 3));
+}
@@ -0,0 +1,142 @@
+// Escape sequence conformance: every case in tests/conformance is a byte
+// stream fed through a fresh terminal, and the grid it leaves behind is
+// compared with the snapshot next to it. Run with UPDATE_SNAPSHOTS=1 to
+// write the snapshots of new cases, or rewrite changed ones once the
+// change is known to be right.
+
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use rush::term::{Cell, Color, Flags, Terminal};
+
+const ROWS: usize = 24;
+const COLS: usize = 80;
+
+const FLAG_NAMES: &[(Flags, &str)] = &[
+    (Flags::BOLD, "bold"),
+    (Flags::DIM, "dim"),
+    (Flags::ITALIC, "italic"),
+    (Flags::UNDERLINE, "underline"),
+    (Flags::DOUBLE_UNDERLINE, "double-underline"),
+    (Flags::UNDERCURL, "undercurl"),
+    (Flags::STRIKEOUT, "strikeout"),
+    (Flags::INVERSE, "inverse"),
+    (Flags::HIDDEN, "hidden"),
+    (Flags::BLINK, "blink"),
+];
+
+fn case_path(name: &str, extension: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/conformance")
+        .join(name)
+        .with_extension(extension)
+}
+
+fn color_name(color: Color) -> String {
+    match color {
+        Color::Default => "default".to_string(),
+        Color::Indexed(index) => index.to_string(),
+        Color::Rgb(r, g, b) => format!("#{:02x}{:02x}{:02x}", r, g, b),
+    }
+}
+
+// What sets a cell apart from a blank one, empty when nothing does
+fn style(cell: &Cell) -> String {
+    let mut style = String::new();
+    if cell.fg != Color::Default {
+        write!(style, " fg={}", color_name(cell.fg)).unwrap();
+    }
+    if cell.bg != Color::Default {
+        write!(style, " bg={}", color_name(cell.bg)).unwrap();
+    }
+    for (flag, name) in FLAG_NAMES {
+        if cell.flags.contains(*flag) {
+            write!(style, " {}", name).unwrap();
+        }
+    }
+    style
+}
+
+/// The cursor, the text of every row and the runs of styled cells, in a
+/// form that diffs well
+fn snapshot(term: &Terminal) -> String {
+    let mut snapshot = format!("cursor {} {}\n", term.cursor.row, term.cursor.col);
+    if term.cursor.pending_wrap {
+        snapshot.push_str("pending wrap\n");
+    }
+    if !term.modes.cursor_visible {
+        snapshot.push_str("cursor hidden\n");
+    }
+
+    snapshot.push_str("screen\n");
+    for row in 0..term.rows {
+        let mut text = String::new();
+        for cell in term.visible_row(row).iter() {
+            if !cell.flags.contains(Flags::WIDE_CHAR_SPACER) {
+                text.push(cell.c);
+                text.extend(cell.zerowidth());
+            }
+        }
+        writeln!(snapshot, "|{}", text.trim_end()).unwrap();
+    }
+
+    snapshot.push_str("styles\n");
+    for row in 0..term.rows {
+        let cells = term.visible_row(row);
+        let mut start = 0;
+        for col in 1..=cells.len() {
+            let current = style(&cells[start]);
+            if col < cells.len() && style(&cells[col]) == current {
+                continue;
+            }
+            if !current.is_empty() {
+                writeln!(snapshot, "{} {}..{}{}", row, start, col, current).unwrap();
+            }
+            start = col;
+        }
+    }
+    snapshot
+}
+
+fn check(name: &str) {
+    let input = std::fs::read(case_path(name, "in")).unwrap_or_else(|e| panic!("Could not read {}.in: {}", name, e));
+    let mut term = Terminal::new(ROWS, COLS);
+    term.process(&input);
+    let actual = snapshot(&term);
+
+    let path = case_path(name, "snap");
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        std::fs::write(&path, &actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("Could not read {}.snap, run with UPDATE_SNAPSHOTS=1 to write it: {}", name, e));
+    assert!(actual == expected, "{} differs from its snapshot\nexpected:\n{}\nactual:\n{}", name, expected, actual);
+}
+
+macro_rules! conformance {
+    ($($name:ident),* $(,)?) => {
+        $(
+            #[test]
+            fn $name() {
+                check(stringify!($name));
+            }
+        )*
+    };
+}
+
+conformance! {
+    cursor_movement,
+    erase,
+    scroll_region,
+    insert_delete,
+    autowrap,
+    insert_mode_and_rep,
+    sgr,
+    wide_and_combining,
+    combining_flood,
+    charsets,
+    tabs,
+    save_restore,
+    ls_color,
+}
@@ -0,0 +1,21 @@
+// Which rows a frame has to redraw
+
+use rush::damage::Damage;
+use rush::term::Terminal;
+
+#[test]
+fn only_changed_rows_and_the_cursor_are_damaged() {
+    let mut term = Terminal::new(5, 20);
+    let mut damage = Damage::new();
+    assert_eq!(damage.update(&term), vec![0, 1, 2, 3, 4]);
+    assert!(damage.update(&term).is_empty());
+
+    term.process(b"\x1b[3;1Hx");
+    // The cursor left row 0 for row 2, and row 2 has the new text
+    assert_eq!(damage.update(&term), vec![0, 2]);
+    term.process(b"y");
+    assert_eq!(damage.update(&term), vec![2]);
+
+    term.resize(4, 20);
+    assert_eq!(damage.update(&term), vec![0, 1, 2, 3]);
+}
@@ -0,0 +1,38 @@
+// The screen exported with its colors, as escape sequences and as HTML
+
+use rush::export::{self, Format};
+use rush::selection::{Point, Selection};
+use rush::term::Terminal;
+
+fn screen(term: &Terminal) -> Selection {
+    let top = term.first_visible_line();
+    Selection {
+        anchor: Point { line: top, col: 0 },
+        end: Point { line: top + term.rows - 1, col: term.cols - 1 },
+        block: false,
+    }
+}
+
+#[test]
+fn ansi_keeps_the_colors_as_they_were_set() {
+    let mut term = Terminal::new(2, 20);
+    term.process(b"\x1b[1;31merror\x1b[0m: no\r\n\x1b[38;2;1;2;3mrgb");
+    let ansi = export::export(&term, &screen(&term), Format::Ansi);
+    assert_eq!(ansi, "\x1b[0;1;31merror\x1b[0m: no\n\x1b[0;38;2;1;2;3mrgb\x1b[0m");
+}
+
+#[test]
+fn html_escapes_text_and_styles_runs() {
+    let mut term = Terminal::new(1, 20);
+    term.process(b"a<b \x1b[1mbold\x1b[0m");
+    let html = export::export(&term, &screen(&term), Format::Html);
+    assert!(html.starts_with("<pre style=\"color:#"));
+    assert!(html.contains("a&lt;b <span style=\"font-weight:bold\">bold</span></pre>"));
+}
+
+#[test]
+fn text_is_what_copying_gives() {
+    let mut term = Terminal::new(2, 10);
+    term.process(b"\x1b[32mone\x1b[0m\r\ntwo");
+    assert_eq!(export::export(&term, &screen(&term), Format::Text), "one\ntwo");
+}
@@ -0,0 +1,27 @@
+// Finding file:line references on the screen
+
+use rush::file_ref::{FileRef, FileRefs};
+use rush::term::Terminal;
+
+#[test]
+fn references_are_found_under_the_pointer() {
+    let mut term = Terminal::new(3, 60);
+    term.process(b"  --> src/main.rs:12:5\r\nat 10:30 see ./lib/a.py:7.\r\n");
+    let refs = FileRefs::new();
+
+    let found = refs.at(&term, 0, 10).unwrap();
+    assert_eq!(found, FileRef { path: "src/main.rs".to_string(), line: 12, column: Some(5), cols: 6..22 });
+    assert_eq!(refs.at(&term, 0, 3), None);
+
+    let found = refs.at(&term, 1, 16).unwrap();
+    assert_eq!((found.path.as_str(), found.line, found.column), ("./lib/a.py", 7, None));
+    // A time isn't a file
+    assert_eq!(refs.at(&term, 1, 4), None);
+}
+
+#[test]
+fn editor_commands_are_filled_in() {
+    let found = FileRef { path: "a.rs".to_string(), line: 3, column: None, cols: 0..6 };
+    let command = found.command("code --goto {file}:{line}:{column}", "/home/me/my files/a.rs");
+    assert_eq!(command, ["code", "--goto", "/home/me/my files/a.rs:3:1"]);
+}
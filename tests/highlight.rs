@@ -0,0 +1,48 @@
+// Regex rules restyling text on the screen
+
+use rush::highlight::{Highlighter, Rule};
+use rush::term::{Color, Flags, Terminal};
+
+#[test]
+fn matches_are_restyled_in_copies_of_their_rows() {
+    let mut term = Terminal::new(3, 30);
+    term.process("ERROR: 世界 TICKET-42\r\nfine\r\n".as_bytes());
+    let before = term.visible_row(0).into_owned();
+    let rules = ["#ff0000 bold /ERROR/", "underline on #0000ff /[A-Z]+-[0-9]+/"];
+    let highlights = Highlighter::new(rules.iter().map(|rule| rule.parse::<Rule>().unwrap()).collect());
+
+    let restyled = highlights.apply(&term);
+    let row = restyled.row(0).unwrap();
+    assert!(row[..5].iter().all(|cell| cell.fg == Color::Rgb(255, 0, 0) && cell.flags.contains(Flags::BOLD)));
+    assert_eq!(row[5].fg, Color::Default);
+    // The wide characters take two columns each, the ticket starts at 12
+    assert!(!row[11].flags.contains(Flags::UNDERLINE));
+    assert!(row[12..21].iter().all(|cell| cell.bg == Color::Rgb(0, 0, 255) && cell.flags.contains(Flags::UNDERLINE)));
+    assert!(!row[21].flags.contains(Flags::UNDERLINE));
+    assert!(restyled.row(1).is_none());
+    assert_eq!(term.visible_row(0), before);
+}
+
+#[test]
+fn highlighting_the_scrollback_leaves_it_alone() {
+    let mut term = Terminal::new(2, 30);
+    term.process(b"ERROR\r\nfine\r\nfine\r\nfine");
+    term.scroll_display(2);
+    let bytes = term.scrollback_bytes();
+    let highlights = Highlighter::new(vec!["bold /ERROR/".parse::<Rule>().unwrap()]);
+
+    let restyled = highlights.apply(&term);
+    assert!(restyled.row(0).unwrap()[..5].iter().all(|cell| cell.flags.contains(Flags::BOLD)));
+    assert_eq!(term.scrollback_bytes(), bytes);
+    assert!(term.visible_row(0).iter().all(|cell| cell.flags == Flags::default()));
+}
+
+#[test]
+fn invalid_rules_are_rejected() {
+    assert!("bold ERROR".parse::<Rule>().is_err());
+    assert!("bold /ERROR".parse::<Rule>().is_err());
+    assert!("sparkly /ERROR/".parse::<Rule>().is_err());
+    assert!("on /ERROR/".parse::<Rule>().is_err());
+    assert!("/(/".parse::<Rule>().is_err());
+    assert!("/a/b/".parse::<Rule>().unwrap().regex.is_match("a/b"));
+}
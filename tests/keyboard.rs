@@ -0,0 +1,22 @@
+// What keys send to the application
+
+use glfw::{Action, Key, Modifiers};
+use rush::keyboard::{self, Modes};
+
+fn press(key: Key, modifiers: Modifiers, modes: Modes) -> Vec<u8> {
+    keyboard::encode(key, Action::Press, modifiers, None, modes).unwrap()
+}
+
+#[test]
+fn function_keys_match_the_terminfo_entry() {
+    let modes = Modes::default();
+    assert_eq!(press(Key::F1, Modifiers::empty(), modes), b"\x1bOP");
+    assert_eq!(press(Key::F3, Modifiers::empty(), modes), b"\x1bOR");
+    assert_eq!(press(Key::F3, Modifiers::Shift, modes), b"\x1b[1;2R");
+    assert_eq!(press(Key::F5, Modifiers::empty(), modes), b"\x1b[15~");
+
+    // The keyboard protocol keeps F3 apart from cursor position reports
+    let modes = Modes { flags: keyboard::REPORT_ALL_KEYS, ..modes };
+    assert_eq!(press(Key::F3, Modifiers::empty(), modes), b"\x1b[13~");
+    assert_eq!(press(Key::F3, Modifiers::Shift, modes), b"\x1b[13;2~");
+}
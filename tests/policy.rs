@@ -0,0 +1,47 @@
+// What programs may do past the screen with escape sequences
+
+use rush::policy::{Feature, Permission, Policy};
+use rush::term::{Notification, Terminal};
+
+#[test]
+fn allowed_features_reach_the_window() {
+    let mut term = Terminal::new(2, 20);
+    term.process(b"\x1b]2;build\x07\x1b]52;c;aGVsbG8=\x07\x1b]777;notify;Done;make; 0 errors\x07\x1b]9;4;1;50\x07");
+    assert_eq!(term.title.as_deref(), Some("build"));
+    assert_eq!(term.take_clipboard().as_deref(), Some("hello"));
+    assert_eq!(term.take_clipboard(), None);
+    let done = Notification { title: Some("Done".to_string()), body: "make; 0 errors".to_string() };
+    assert_eq!(term.take_notifications(), [done]);
+
+    // Reading the clipboard back is never answered
+    term.process(b"\x1b]52;c;?\x07");
+    assert!(term.take_responses().is_empty());
+}
+
+#[test]
+fn denied_features_are_ignored_even_after_a_reset() {
+    let mut term = Terminal::new(2, 20);
+    term.process(b"\x1b]2;saved\x07\x1b[22t\x1b]2;local\x07");
+    let mut policy = Policy::default();
+    for feature in Feature::ALL {
+        policy.set(feature, Permission::Deny);
+    }
+    term.policy = policy;
+
+    // Not even a title saved while titles were allowed
+    term.process(b"\x1b[23t");
+    assert_eq!(term.title.as_deref(), Some("local"));
+
+    term.process(b"\x1bc\x1b]0;remote\x07\x1b]52;c;aGVsbG8=\x07\x1b]9;hi\x07");
+    assert_eq!(term.title.as_deref(), Some("local"));
+    assert_eq!(term.take_clipboard(), None);
+    assert!(term.take_notifications().is_empty());
+}
+
+#[test]
+fn features_are_named_as_in_the_config() {
+    assert_eq!("clipboard_write".parse(), Ok(Feature::ClipboardWrite));
+    assert!("clipboard".parse::<Feature>().is_err());
+    assert_eq!("deny".parse(), Ok(Permission::Deny));
+    assert!("no".parse::<Permission>().is_err());
+}
@@ -0,0 +1,150 @@
+// End to end: real programs run on a real PTY through the headless
+// terminal, and the grid they leave behind is checked. Unlike the
+// conformance cases this covers the whole way from the child's writes to
+// the cells, replies to its queries included.
+
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use rush::headless::Headless;
+use rush::pty::{self, Command};
+use rush::term::{Color, Flags};
+
+// Generous, a loaded CI machine can be slow to start a shell
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn sh(script: &str) -> Command {
+    Command {
+        program: "/bin/sh".to_string(),
+        args: vec!["-c".to_string(), script.to_string()],
+        working_directory: None,
+        env: pty::child_env(None),
+    }
+}
+
+/// Run `command` on a 24x80 screen until it exits
+fn run(command: &Command) -> Headless {
+    let mut headless = Headless::spawn(command, 24, 80);
+    assert!(headless.run(Some(TIMEOUT)), "{:?} didn't exit within {:?}", command.args, TIMEOUT);
+    headless
+}
+
+fn lines(headless: &Headless) -> Vec<String> {
+    headless.term.screen_text().lines().map(String::from).collect()
+}
+
+#[test]
+fn printf_text_and_colors() {
+    let headless = run(&sh(r"printf 'one\ntwo \033[1;31mred\033[0m plain\n'"));
+    assert_eq!(lines(&headless)[..2], ["one", "two red plain"]);
+
+    let row = headless.term.visible_row(1);
+    assert_eq!(row[4].fg, Color::Indexed(1));
+    assert!(row[4].flags.contains(Flags::BOLD));
+    assert_eq!(row[8].fg, Color::Default);
+    // The shell's newlines went through the line discipline as CR LF
+    assert_eq!((headless.term.cursor.row, headless.term.cursor.col), (2, 0));
+}
+
+#[test]
+fn long_lines_wrap() {
+    let headless = run(&sh("printf '%0100d' 0"));
+    let lines = lines(&headless);
+    assert_eq!(lines[0], "0".repeat(80));
+    assert_eq!(lines[1], "0".repeat(20));
+}
+
+#[test]
+fn child_sees_the_screen_size() {
+    let headless = run(&sh("stty size"));
+    assert_eq!(lines(&headless)[0], "24 80");
+}
+
+#[test]
+fn cursor_position_report_reaches_the_child() {
+    // The reply is read back without echo and printed escaped by od
+    let script = r"stty -echo -icanon
+        printf '\033[3;7H\033[6n'
+        reply=$(dd bs=1 count=6 2>/dev/null)
+        stty sane
+        printf '\033[H'
+        printf '%s' $reply | od -An -c";
+    let headless = run(&sh(script));
+    let lines = lines(&headless);
+    let report: Vec<&str> = lines[0].split_whitespace().collect();
+    assert_eq!(report, ["033", "[", "3", ";", "7", "R"]);
+}
+
+#[test]
+fn ls_colors_directories() {
+    let dir = std::env::temp_dir().join(format!("rush-pty-test-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("subdir")).unwrap();
+    std::fs::write(dir.join("file.txt"), "").unwrap();
+
+    let mut command = sh("ls --color=always -1");
+    command.working_directory = Some(PathBuf::from(&dir));
+    command.env.push(("LS_COLORS".to_string(), "di=01;34:fi=00".to_string()));
+    let headless = run(&command);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(lines(&headless)[..2], ["file.txt", "subdir"]);
+    let subdir = &headless.term.visible_row(1)[0];
+    assert_eq!(subdir.fg, Color::Indexed(4));
+    assert!(subdir.flags.contains(Flags::BOLD));
+    assert_eq!(headless.term.visible_row(0)[0].fg, Color::Default);
+}
+
+#[test]
+fn foreground_job_is_named() {
+    // Job control puts the command in a process group of its own
+    let pty = pty::Pty::spawn(&sh("set -m; sleep 5"), 24, 80, (8, 16));
+    let start = Instant::now();
+    // The job's process is the shell until it execs
+    while pty.foreground_job().and_then(pty::process_name).as_deref() != Some("sleep") {
+        assert!(start.elapsed() < TIMEOUT, "sleep didn't come to the foreground within {:?}", TIMEOUT);
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn exit_status_is_reaped() {
+    let mut pty = pty::Pty::spawn(&sh("exit 3"), 24, 80, (8, 16));
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = pty.try_wait() {
+            break status;
+        }
+        assert!(start.elapsed() < TIMEOUT, "The shell didn't exit within {:?}", TIMEOUT);
+        std::thread::sleep(Duration::from_millis(10));
+    };
+    assert_eq!(status, pty::ExitStatus::Exited(3));
+    assert!(!status.success());
+    assert_eq!(status.to_string(), "exited with status 3");
+}
+
+#[test]
+fn input_waits_for_a_program_that_isnt_reading() {
+    let mut headless = Headless::spawn(&sh("stty raw -echo; echo ready; sleep 1; head -c 200000 | wc -c"), 24, 80);
+    let start = Instant::now();
+    while !headless.term.screen_text().contains("ready") {
+        assert!(start.elapsed() < TIMEOUT, "The program didn't get ready within {:?}", TIMEOUT);
+        headless.pump();
+    }
+
+    // Far more than the PTY holds, the rest is written as the program reads
+    let start = Instant::now();
+    headless.write(&[b'a'; 200000]);
+    assert!(start.elapsed() < Duration::from_millis(500));
+    assert!(headless.run(Some(TIMEOUT)));
+    assert!(headless.term.screen_text().contains("200000"));
+}
+
+#[test]
+fn arguments_are_split_like_a_shell_would() {
+    let args = |s: &str| s.parse::<pty::Args>().map(|pty::Args(args)| args);
+    let split = args(r#"-c 'echo "$HOME"'  a\ b "x\"y" '' "#).unwrap();
+    assert_eq!(split, ["-c", "echo \"$HOME\"", "a b", "x\"y", ""]);
+    assert_eq!(args("  "), Ok(Vec::new()));
+    assert!(args("-c 'exit").is_err());
+    assert!(args("a\\").is_err());
+}
@@ -0,0 +1,166 @@
+// Frames drawn without a GPU: a renderer that draws the screen as text
+// shows what any backend is asked to draw.
+
+use rush::color::{self, Palette};
+use rush::render::{self, Cursor, CursorStyle, Frame, Renderer};
+use rush::term::{Flags, Terminal};
+
+// The screen as lines of text, the cursor's cell replaced with a block or
+// an outline
+#[derive(Default)]
+struct TextRenderer {
+    lines: Vec<String>,
+    presented: usize,
+}
+
+impl Renderer for TextRenderer {
+    fn begin_frame(&mut self, term: &Terminal) {
+        self.lines = vec![" ".repeat(term.cols); term.rows];
+    }
+
+    fn draw_cells(&mut self, term: &Terminal, frame: &Frame) {
+        for (row, line) in self.lines.iter_mut().enumerate() {
+            *line = frame
+                .row(term, row)
+                .iter()
+                .filter(|cell| !cell.flags.contains(Flags::WIDE_CHAR_SPACER))
+                .map(|cell| if render::text_visible(cell, frame.blink_visible) { cell.c } else { ' ' })
+                .collect();
+        }
+    }
+
+    fn draw_cursor(&mut self, _term: &Terminal, _frame: &Frame, cursor: &Cursor) {
+        let line = &mut self.lines[cursor.row];
+        let (start, c) = line.char_indices().nth(cursor.col).unwrap();
+        line.replace_range(start..start + c.len_utf8(), if cursor.hollow { "□" } else { "█" });
+    }
+
+    fn present(&mut self) {
+        self.presented += 1;
+    }
+}
+
+fn draw(term: &Terminal, focused: bool, blink_visible: bool) -> TextRenderer {
+    let mut renderer = TextRenderer::default();
+    render::draw(&mut renderer, term, &Frame { focused, blink_visible, scrollbar: None, restyled: None });
+    assert_eq!(renderer.presented, 1);
+    renderer
+}
+
+#[test]
+fn cursor_is_drawn_over_the_cells() {
+    let mut term = Terminal::new(3, 10);
+    term.process(b"$ ls\r\nfile\r\n$ ");
+    assert_eq!(draw(&term, true, true).lines, ["$ ls      ", "file      ", "$ █       "]);
+    // Unfocused windows show an outline
+    assert_eq!(draw(&term, false, true).lines[2], "$ □       ");
+
+    // Hidden by the application
+    term.process(b"\x1b[?25l");
+    assert_eq!(Cursor::of(&term, true), None);
+    assert_eq!(draw(&term, true, true).lines[2], "$         ");
+}
+
+#[test]
+fn cursor_scrolls_out_of_view() {
+    let mut term = Terminal::new(2, 10);
+    term.process(b"one\r\ntwo\r\nthree");
+    assert_eq!(Cursor::of(&term, true), Some(Cursor { row: 1, col: 5, width: 1, hollow: false }));
+    term.scroll_display(1);
+    assert_eq!(Cursor::of(&term, true), None);
+    assert_eq!(draw(&term, true, true).lines, ["one       ", "two       "]);
+}
+
+#[test]
+fn cursor_covers_wide_characters() {
+    let mut term = Terminal::new(1, 6);
+    term.process("a\u{4e2d}b\x1b[2G".as_bytes());
+    assert_eq!(Cursor::of(&term, true), Some(Cursor { row: 0, col: 1, width: 2, hollow: false }));
+}
+
+#[test]
+fn blinking_and_concealed_text() {
+    let mut term = Terminal::new(1, 20);
+    term.process(b"\x1b[5mblink\x1b[0m \x1b[8msecret\x1b[0m shown");
+    term.process(b"\x1b[?25l");
+    assert_eq!(draw(&term, true, true).lines, ["blink        shown  "]);
+    assert_eq!(draw(&term, true, false).lines, ["             shown  "]);
+}
+
+#[test]
+fn scrollbar_follows_the_view() {
+    let mut term = Terminal::new(2, 10);
+    assert_eq!(render::scrollbar_thumb(&term), None);
+
+    term.process(b"1\r\n2\r\n3\r\n4\r\n5\r\n6\r\n7\r\n8");
+    // Six lines of scrollback above the two on the screen
+    assert_eq!(render::scrollbar_thumb(&term), Some((0.75, 0.25)));
+    term.scroll_display(3);
+    assert_eq!(render::scrollbar_thumb(&term), Some((0.375, 0.25)));
+    term.scroll_display(10);
+    assert_eq!(render::scrollbar_thumb(&term), Some((0.0, 0.25)));
+}
+
+#[test]
+fn screenshots_are_written_as_png() {
+    // Renderers that can't read frames back still show them
+    let term = Terminal::new(1, 4);
+    let mut renderer = TextRenderer::default();
+    let frame = Frame { focused: true, blink_visible: true, scrollbar: None, restyled: None };
+    assert!(render::capture(&mut renderer, &term, &frame).is_none());
+    assert_eq!(renderer.presented, 1);
+
+    let screenshot = render::Screenshot { width: 2, height: 1, rgba: vec![255, 0, 0, 255, 0, 0, 255, 255] };
+    let path = std::env::temp_dir().join(format!("rush-screenshot-test-{}.png", std::process::id()));
+    screenshot.write_png(&path).unwrap();
+    let mut reader = png::Decoder::new(std::fs::File::open(&path).unwrap()).read_info().unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!((info.width, info.height), (2, 1));
+    assert_eq!(pixels, screenshot.rgba);
+}
+
+#[test]
+fn cursor_stays_visible_on_any_background() {
+    let mut term = Terminal::new(1, 10);
+    // White on black, and black on black
+    term.process(b"a\x1b[30mb");
+    let row = term.visible_row(0);
+    let invert = CursorStyle::default();
+    assert_eq!(invert.colors(&term.palette, &row[0]), ((0xff, 0xff, 0xff), (0, 0, 0)));
+    assert_eq!(invert.colors(&term.palette, &row[1]), ((0xff, 0xff, 0xff), (0, 0, 0)));
+
+    let dark = CursorStyle { color: Some((0x10, 0x10, 0x10)), text_color: Some((0x20, 0x20, 0x20)), opacity: 1.0 };
+    assert_eq!(dark.colors(&term.palette, &row[0]), ((0xff, 0xff, 0xff), (0x20, 0x20, 0x20)));
+    // Colors set with OSC 12 win over the config's
+    term.process(b"\x1b]12;#00ff00\x07");
+    assert_eq!(dark.colors(&term.palette, &term.visible_row(0)[0]).0, (0, 0xff, 0));
+}
+
+#[test]
+fn resetting_colors_goes_back_to_the_theme() {
+    let mut term = Terminal::new(2, 10);
+    let mut theme = Palette::default();
+    theme.background = (0xfa, 0xfa, 0xfa);
+    theme.set_indexed(1, (0x80, 0, 0));
+    term.set_theme(theme);
+    assert_eq!((term.palette.background, term.palette.indexed(1)), ((0xfa, 0xfa, 0xfa), (0x80, 0, 0)));
+
+    term.process(b"\x1b]11;#000000\x07\x1b]4;1;#ff0000\x07");
+    assert_eq!((term.palette.background, term.palette.indexed(1)), ((0, 0, 0), (0xff, 0, 0)));
+    term.process(b"\x1b]111\x07\x1b]104;1\x07");
+    assert_eq!((term.palette.background, term.palette.indexed(1)), ((0xfa, 0xfa, 0xfa), (0x80, 0, 0)));
+}
+
+#[test]
+fn color_specs_with_overlong_channels_are_ignored() {
+    assert_eq!(color::parse_spec("rgb:ffff/8/0"), Some((0xff, 0x88, 0)));
+    assert_eq!(color::parse_spec("rgb:ffffffff/0/0"), None);
+    assert_eq!(color::parse_spec("rgb:/0/0"), None);
+
+    let mut term = Terminal::new(2, 10);
+    let before = term.palette.indexed(1);
+    term.process(b"\x1b]4;1;rgb:ffffffffffffffff/0/0\x07");
+    assert_eq!(term.palette.indexed(1), before);
+}
@@ -0,0 +1,71 @@
+// What a double click selects, and what a block of the screen copies
+
+use rush::selection::{Point, Selection, WordRules, DEFAULT_WORD_CHARACTERS};
+use rush::term::Terminal;
+
+fn double_click(text: &str, col: usize, word_characters: &str) -> Option<String> {
+    let mut term = Terminal::new(1, 60);
+    term.process(text.as_bytes());
+    let point = Point { line: term.first_visible_line(), col };
+    let selection = WordRules::new(word_characters).select(&term, point)?;
+    Some(selection.text(&term))
+}
+
+#[test]
+fn words_stop_at_characters_outside_them() {
+    assert_eq!(double_click("cargo build-std --release", 8, DEFAULT_WORD_CHARACTERS).as_deref(), Some("build-std"));
+    assert_eq!(double_click("cargo build-std --release", 8, "").as_deref(), Some("build"));
+    assert_eq!(double_click("a=b", 1, "").as_deref(), Some("="));
+    assert_eq!(double_click("one  two", 4, DEFAULT_WORD_CHARACTERS), None);
+}
+
+#[test]
+fn paths_and_urls_are_selected_whole() {
+    assert_eq!(double_click("tail /var/log/syslog", 10, DEFAULT_WORD_CHARACTERS).as_deref(), Some("/var/log/syslog"));
+    assert_eq!(
+        double_click("see https://example.com/a?b=c.", 12, DEFAULT_WORD_CHARACTERS).as_deref(),
+        Some("https://example.com/a?b=c")
+    );
+}
+
+#[test]
+fn quotes_select_the_string_they_open_or_close() {
+    let line = r#"echo "hello world" 'x'"#;
+    assert_eq!(double_click(line, 5, DEFAULT_WORD_CHARACTERS).as_deref(), Some(r#""hello world""#));
+    assert_eq!(double_click(line, 17, DEFAULT_WORD_CHARACTERS).as_deref(), Some(r#""hello world""#));
+    assert_eq!(double_click(line, 21, DEFAULT_WORD_CHARACTERS).as_deref(), Some("'x'"));
+}
+
+#[test]
+fn blocks_copy_the_same_columns_of_every_line() {
+    let mut term = Terminal::new(3, 20);
+    term.process(b"PID  CMD\r\n1    init\r\n42   sshd");
+    let top = term.first_visible_line();
+    let selection = Selection {
+        anchor: Point { line: top + 2, col: 8 },
+        end: Point { line: top, col: 5 },
+        block: true,
+    };
+    assert_eq!(selection.text(&term), "CMD\ninit\nsshd");
+    assert!(selection.contains(Point { line: top + 1, col: 6 }));
+    assert!(!selection.contains(Point { line: top + 1, col: 0 }));
+}
+
+#[test]
+fn wrapped_lines_are_copied_as_one() {
+    let mut term = Terminal::new(4, 10);
+    term.process(b"0123456789abcde  fghij\r\nnext");
+    let top = term.first_visible_line();
+    assert_eq!(term.logical_line(top + 1), top..=top + 2);
+    assert!(!term.wraps(top + 2));
+
+    let selection = Selection { anchor: Point { line: top, col: 5 }, end: Point { line: top + 3, col: 1 }, block: false };
+    assert_eq!(selection.text(&term), "56789abcde  fghij\nne");
+
+    // A wide character that doesn't fit leaves the last column out
+    let mut term = Terminal::new(2, 4);
+    term.process("abc中".as_bytes());
+    let top = term.first_visible_line();
+    let selection = Selection { anchor: Point { line: top, col: 0 }, end: Point { line: top + 1, col: 1 }, block: false };
+    assert_eq!(selection.text(&term), "abc中");
+}
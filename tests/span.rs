@@ -0,0 +1,55 @@
+// Metadata attached to ranges of cells
+
+use rush::selection::Point;
+use rush::span::{Span, SpanKind, Zone};
+use rush::term::Terminal;
+
+fn point(line: usize, col: usize) -> Point {
+    Point { line, col }
+}
+
+#[test]
+fn cells_know_their_hyperlink_and_zone() {
+    let mut term = Terminal::new(4, 20);
+    term.process(b"\x1b]133;A\x07$ \x1b]133;B\x07ls\r\n\x1b]133;C\x07a \x1b]8;;file:///a\x07link\x1b]8;;\x07 b\r\n");
+    term.process(b"\x1b]133;D;0\x07");
+
+    assert_eq!(term.spans_at(point(0, 0)), [SpanKind::Zone(Zone::Prompt)]);
+    assert_eq!(term.spans_at(point(0, 2)), [SpanKind::Zone(Zone::Input)]);
+    let kinds = term.spans_at(point(1, 3));
+    assert!(matches!(&kinds[..], [SpanKind::Hyperlink(link), SpanKind::Zone(Zone::Output)] if link.uri == "file:///a"));
+    assert_eq!(term.spans_at(point(2, 0)), []);
+
+    let link = term.hyperlink_span(point(1, 4)).unwrap();
+    assert_eq!((link.start, link.end), (point(1, 2), point(1, 6)));
+    assert!(term.hyperlink_span(point(1, 1)).is_none());
+}
+
+#[test]
+fn spans_laid_over_lines_move_with_them() {
+    let mut term = Terminal::new(3, 10);
+    let found = |start, end, current| Span { kind: SpanKind::SearchMatch { current }, start, end };
+    term.spans.extend([found(point(2, 5), point(2, 8), true), found(point(0, 8), point(1, 2), false)]);
+
+    let kinds = |term: &Terminal, point| term.spans.at(point).map(|span| span.kind.clone()).collect::<Vec<_>>();
+    assert_eq!(kinds(&term, point(1, 0)), [SpanKind::SearchMatch { current: false }]);
+    assert_eq!(kinds(&term, point(1, 2)), []);
+    assert_eq!(kinds(&term, point(2, 7)), [SpanKind::SearchMatch { current: true }]);
+
+    // With the cursor on the last row, the two lines above it go
+    term.process(b"\x1b[3;1H");
+    term.clear_history();
+    assert_eq!(kinds(&term, point(0, 6)), [SpanKind::SearchMatch { current: true }]);
+    assert_eq!(kinds(&term, point(0, 0)), []);
+
+    term.spans.remove(|kind| matches!(kind, SpanKind::SearchMatch { .. }));
+    assert!(term.spans.is_empty());
+}
+
+#[test]
+fn spans_select_up_to_their_end() {
+    let span = Span { kind: SpanKind::Zone(Zone::Output), start: point(1, 0), end: point(3, 0) };
+    let selection = span.selection(80).unwrap();
+    assert_eq!((selection.anchor, selection.end), (point(1, 0), point(2, 79)));
+    assert!(Span { end: point(1, 0), ..span }.selection(80).is_none());
+}
@@ -0,0 +1,64 @@
+// How much of the history the scrollback keeps, and the rest kept on disk
+
+use rush::spill::Spill;
+use rush::term::Terminal;
+
+#[test]
+fn spilled_lines_read_back_in_order() {
+    let mut spill = Spill::new().unwrap();
+    for i in 0..2500 {
+        spill.push(format!("line {}", i)).unwrap();
+    }
+    assert_eq!(spill.len(), 2500);
+
+    let mut lines = Vec::new();
+    spill.for_each_line(998, |number, text| lines.push((number, text.to_string()))).unwrap();
+    assert_eq!(lines.len(), 1502);
+    assert_eq!(lines[0], (998, "line 998".to_string()));
+    assert_eq!(lines[1501], (2499, "line 2499".to_string()));
+
+    spill.clear().unwrap();
+    assert!(spill.is_empty());
+}
+
+#[test]
+fn lines_past_the_scrollback_limit_are_spilled() {
+    let mut term = Terminal::new(2, 20);
+    term.spill_to_disk().unwrap();
+    // The last line printed stays on the screen above the empty one
+    for i in 0..10_006 {
+        term.process(format!("{}\r\n", i).as_bytes());
+    }
+    let mut spilled = Vec::new();
+    term.spilled().unwrap().for_each_line(0, |_, text| spilled.push(text.to_string())).unwrap();
+    assert_eq!(spilled, ["0", "1", "2", "3", "4"]);
+}
+
+#[test]
+fn scrollback_stays_within_its_memory_budget() {
+    let mut term = Terminal::new(2, 80);
+    term.scrollback_budget = 256 * 1024;
+    for i in 0..5000 {
+        term.process(format!("{}\r\n", "x".repeat(i % 80)).as_bytes());
+    }
+    assert!(term.scrollback_bytes() <= term.scrollback_budget);
+    assert!(term.scrollback_len() > 100);
+    assert!(term.scrollback_len() < 5000);
+
+    term.process(b"\x1b[3J");
+    assert_eq!((term.scrollback_len(), term.scrollback_bytes()), (0, 0));
+}
+
+#[test]
+fn rows_of_the_scrollback_are_as_wide_as_the_screen() {
+    let mut term = Terminal::new(2, 10);
+    term.process(b"ab\r\n\r\n\r\ncd");
+    let bytes = term.scrollback_bytes();
+    term.display_offset = 2;
+    let row = term.visible_row(0);
+    assert_eq!(row.len(), 10);
+    assert_eq!(row.iter().map(|cell| cell.c).collect::<String>(), "ab        ");
+    assert_eq!(term.visible_row(1).len(), 10);
+    // Padded for the view only, the scrollback keeps the trimmed rows
+    assert_eq!(term.scrollback_bytes(), bytes);
+}
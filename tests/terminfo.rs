@@ -0,0 +1,30 @@
+// Installing the bundled terminfo entry
+
+use rush::terminfo;
+
+#[test]
+fn an_entry_from_an_older_source_is_replaced() {
+    if std::process::Command::new("tic").arg("-V").output().is_err() {
+        return;
+    }
+    let home = std::env::temp_dir().join(format!("rush-terminfo-{}", std::process::id()));
+    let entry = home.join(".terminfo/r/rush");
+    let stamp = home.join(".terminfo/.rush-source");
+    std::fs::create_dir_all(&home).unwrap();
+    std::env::set_var("HOME", &home);
+
+    terminfo::install();
+    let installed = std::fs::read_to_string(&stamp).unwrap();
+    assert!(entry.exists());
+
+    std::fs::remove_file(&entry).unwrap();
+    terminfo::install();
+    assert!(!entry.exists(), "installed again from the same source");
+
+    std::fs::write(&stamp, "older").unwrap();
+    terminfo::install();
+    assert!(entry.exists());
+    assert_eq!(std::fs::read_to_string(&stamp).unwrap(), installed);
+
+    std::fs::remove_dir_all(&home).unwrap();
+}
@@ -0,0 +1,18 @@
+use rush::window_geometry::WindowGeometry;
+
+#[test]
+fn geometry_survives_a_round_trip() {
+    let geometry = WindowGeometry { x: -20, y: 40, width: 1024, height: 640, maximized: true };
+    assert_eq!(geometry.to_string().parse(), Ok(geometry));
+}
+
+#[test]
+fn unusable_state_files_are_ignored() {
+    // Keys a newer version added don't matter
+    let geometry: Result<WindowGeometry, ()> = "x: 1\ny: 2\nwidth: 3\nheight: 4\nmonitor: DP-1\n".parse();
+    assert_eq!(geometry, Ok(WindowGeometry { x: 1, y: 2, width: 3, height: 4, maximized: false }));
+
+    assert!("x: 1\ny: 2\nwidth: 3\n".parse::<WindowGeometry>().is_err());
+    assert!("x: 1\ny: 2\nwidth: 0\nheight: 4\n".parse::<WindowGeometry>().is_err());
+    assert!("".parse::<WindowGeometry>().is_err());
+}
@@ -0,0 +1,58 @@
+// The shaders of the wgpu backend: flat colored rectangles like
+// rect_shader.fs, and glyphs sampled from the atlas like font_shader.fs
+
+struct Uniforms {
+    // Moves the grid while scrolling smoothly, in normalized device
+    // coordinates
+    offset: vec2<f32>,
+    // See font_shader.fs
+    gamma: f32,
+}
+
+@group(0) @binding(0) var atlas: texture_2d<f32>;
+@group(0) @binding(1) var atlas_sampler: sampler;
+@group(0) @binding(2) var<uniform> uniforms: Uniforms;
+
+struct RectOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+}
+
+@vertex
+fn vs_rect(@location(0) position: vec2<f32>, @location(1) color: vec4<f32>) -> RectOutput {
+    var out: RectOutput;
+    out.position = vec4<f32>(position + uniforms.offset, 0.0, 1.0);
+    out.color = color;
+    return out;
+}
+
+@fragment
+fn fs_rect(in: RectOutput) -> @location(0) vec4<f32> {
+    return in.color;
+}
+
+struct GlyphOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+@vertex
+fn vs_glyph(
+    @location(0) position: vec2<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) color: vec4<f32>,
+) -> GlyphOutput {
+    var out: GlyphOutput;
+    out.position = vec4<f32>(position + uniforms.offset, 0.0, 1.0);
+    out.uv = uv;
+    out.color = color;
+    return out;
+}
+
+@fragment
+fn fs_glyph(in: GlyphOutput) -> @location(0) vec4<f32> {
+    let luminance = dot(in.color.rgb, vec3<f32>(0.2126, 0.7152, 0.0722));
+    let coverage = pow(textureSample(atlas, atlas_sampler, in.uv).r, pow(uniforms.gamma, 1.0 - 2.0 * luminance));
+    return vec4<f32>(in.color.rgb, in.color.a * coverage);
+}